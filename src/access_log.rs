@@ -0,0 +1,571 @@
+// ==================== 访问日志 ====================
+//
+// 每个请求结束后按配置的模板输出一行访问日志，模板使用 nginx 风格的变量，
+// 如 `$remote_addr "$request" $status $request_time $upstream_addr`，便于对接已有的日志解析器，
+// 也可以直接使用与 Apache/nginx 相同的 combined 或 common 格式；
+// 配置了文件时独立于 env_logger 直接写入文件，并按大小或时间滚动；
+// 流量较大时可以只记录部分成功请求，出错和慢请求始终记录；
+// 超过慢请求阈值的请求另外输出一条带耗时分解的 WARN 日志；有流量订阅者时同时推送请求摘要，
+// 出错的请求记入状态页
+
+use crate::rotating_file::{RotatingFile, RotationConfig};
+use crate::{redact, request_id, tap};
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, HttpRequest, web};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// 访问日志配置：对应配置文件中的 [access_log]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AccessLogConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool, // 是否输出访问日志
+    #[serde(default = "default_format")]
+    pub format: String, // 日志模板，或预置格式 combined、common
+    #[serde(default)]
+    pub file: Option<String>, // 写入的文件，缺省输出到应用日志
+    #[serde(default)]
+    pub rotation: RotationConfig, // 文件滚动与保留策略
+    #[serde(default)]
+    pub sampling: SamplingConfig, // 采样策略
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        AccessLogConfig {
+            enabled: true,
+            format: default_format(),
+            file: None,
+            rotation: RotationConfig::default(),
+            sampling: SamplingConfig::default(),
+        }
+    }
+}
+
+// 采样配置：对应配置文件中的 [access_log.sampling]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SamplingConfig {
+    #[serde(default = "default_success_every")]
+    pub success_every: u64, // 成功请求每N个记录1个，1表示全部记录
+    #[serde(default = "default_error_status")]
+    pub error_status: u16, // 状态码不低于该值的请求视为出错，始终记录
+    #[serde(default)]
+    pub slow_ms: Option<u64>, // 处理时间超过该值(毫秒)的请求始终记录
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        SamplingConfig {
+            success_every: default_success_every(),
+            error_status: default_error_status(),
+            slow_ms: None,
+        }
+    }
+}
+
+// 以下函数为访问日志配置提供默认值
+fn default_true() -> bool {
+    true
+}
+
+fn default_success_every() -> u64 {
+    1
+}
+
+fn default_error_status() -> u16 {
+    400
+}
+
+fn default_format() -> String {
+    r#"$remote_addr "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent" $request_time $request_id"#
+        .to_string()
+}
+
+// 预置格式：与 Apache/nginx 的 combined、common 日志格式相同，GoAccess、AWStats 等工具可直接解析
+fn preset(format: &str) -> &str {
+    match format {
+        "combined" => {
+            r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent""#
+        }
+        "common" => {
+            r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent"#
+        }
+        _ => format,
+    }
+}
+
+// 模板中支持的变量
+#[derive(Debug, Clone)]
+enum Variable {
+    RemoteAddr,             // 客户端IP
+    RemotePort,             // 客户端端口
+    RemoteUser,             // Basic认证的用户名
+    Request,                // 请求行，如 "GET /a?b=1 HTTP/1.1"
+    RequestMethod,          // 请求方法
+    RequestUri,             // 路径和查询参数
+    Uri,                    // 路径
+    Args,                   // 查询参数
+    ServerProtocol,         // 协议版本
+    Host,                   // Host请求头
+    Status,                 // 响应状态码
+    BodyBytesSent,          // 响应体大小
+    RequestTime,            // 处理时间(秒，毫秒精度)
+    Msec,                   // 日志时间(Unix秒，毫秒精度)
+    TimeIso8601,            // 日志时间(ISO 8601)
+    TimeLocal,              // 日志时间(通用日志格式)
+    RequestId,              // 请求ID
+    Route,                  // 匹配的路由
+    UpstreamAddr,           // 上游地址，多次调用以逗号分隔
+    UpstreamStatus,         // 上游状态码
+    UpstreamResponseTime,   // 上游响应头延迟(秒)
+    RequestHeader(String),  // $http_<名称>：请求头
+    ResponseHeader(String), // $sent_http_<名称>：响应头
+}
+
+impl Variable {
+    fn parse(name: &str) -> Option<Self> {
+        // 变量名中的下划线对应请求头中的连字符
+        let header = |rest: &str| rest.replace('_', "-").to_ascii_lowercase();
+        Some(match name {
+            "remote_addr" => Variable::RemoteAddr,
+            "remote_port" => Variable::RemotePort,
+            "remote_user" => Variable::RemoteUser,
+            "request" => Variable::Request,
+            "request_method" => Variable::RequestMethod,
+            "request_uri" => Variable::RequestUri,
+            "uri" => Variable::Uri,
+            "args" | "query_string" => Variable::Args,
+            "server_protocol" => Variable::ServerProtocol,
+            "host" => Variable::Host,
+            "status" => Variable::Status,
+            "body_bytes_sent" => Variable::BodyBytesSent,
+            "request_time" => Variable::RequestTime,
+            "msec" => Variable::Msec,
+            "time_iso8601" => Variable::TimeIso8601,
+            "time_local" => Variable::TimeLocal,
+            "request_id" => Variable::RequestId,
+            "route" => Variable::Route,
+            "upstream_addr" => Variable::UpstreamAddr,
+            "upstream_status" => Variable::UpstreamStatus,
+            "upstream_response_time" => Variable::UpstreamResponseTime,
+            _ => {
+                if let Some(rest) = name.strip_prefix("sent_http_") {
+                    Variable::ResponseHeader(header(rest))
+                } else if let Some(rest) = name.strip_prefix("http_") {
+                    Variable::RequestHeader(header(rest))
+                } else {
+                    return None;
+                }
+            }
+        })
+    }
+}
+
+// 模板片段
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Variable(Variable),
+}
+
+// 解析后的日志模板
+#[derive(Debug, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    // 解析模板，变量写作 $name 或 ${name}；$$ 表示字面的 $
+    pub fn parse(format: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '$' {
+                literal.push(c);
+                continue;
+            }
+            let name: String = match chars.peek() {
+                Some('$') => {
+                    chars.next();
+                    literal.push('$');
+                    continue;
+                }
+                Some('{') => {
+                    chars.next();
+                    let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                    name
+                }
+                _ => {
+                    let mut name = String::new();
+                    while let Some(c) = chars
+                        .peek()
+                        .filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+                    {
+                        name.push(*c);
+                        chars.next();
+                    }
+                    name
+                }
+            };
+            let variable = Variable::parse(&name)
+                .ok_or_else(|| format!("访问日志模板中有未知变量: ${}", name))?;
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+            }
+            segments.push(Segment::Variable(variable));
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Template { segments })
+    }
+
+    // 按模板渲染一行日志，缺失的值输出为 -；与 nginx 相同，值中的引号、反斜杠和控制字符转义为 \xHH，
+    // 请求头中的引号不会破坏带引号的字段
+    fn render(&self, entry: &Entry) -> String {
+        let mut line = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => line.push_str(s),
+                Segment::Variable(v) => {
+                    let value = entry.value(v);
+                    if value.is_empty() {
+                        line.push('-');
+                    }
+                    for c in value.chars() {
+                        if c == '"' || c == '\\' || c.is_ascii_control() {
+                            let _ = write!(line, "\\x{:02X}", c as u32);
+                        } else {
+                            line.push(c);
+                        }
+                    }
+                }
+            }
+        }
+        line
+    }
+}
+
+// 一次上游调用的信息
+#[derive(Debug, Clone)]
+pub struct UpstreamAttempt {
+    pub addr: String,        // 上游地址
+    pub status: Option<u16>, // 上游状态码，调用失败时为None
+    pub time: Duration,      // 到达响应头或失败的时间
+}
+
+// 请求处理过程中收集的信息，保存在请求扩展中
+#[derive(Debug, Clone, Default)]
+pub struct RequestInfo {
+    pub route: Option<String>,           // 匹配的路由
+    pub route_match: Option<Duration>,   // 匹配路由的时间
+    pub upstreams: Vec<UpstreamAttempt>, // 上游调用记录
+    pub queue: Option<Duration>,         // 等待并发许可的时间
+    pub body: Option<Duration>,          // 读取上游响应体的时间
+}
+
+// 记录请求匹配的路由与匹配耗时
+pub fn set_route(req: &HttpRequest, route: &str, time: Duration) {
+    let mut extensions = req.extensions_mut();
+    let info = extensions.get_or_insert_with(RequestInfo::default);
+    info.route = Some(route.to_string());
+    info.route_match = Some(time);
+}
+
+// 记录一次上游调用
+pub fn record_upstream(req: &HttpRequest, attempt: UpstreamAttempt) {
+    req.extensions_mut()
+        .get_or_insert_with(RequestInfo::default)
+        .upstreams
+        .push(attempt);
+}
+
+// 记录等待并发许可的时间
+pub fn record_queue(req: &HttpRequest, time: Duration) {
+    req.extensions_mut()
+        .get_or_insert_with(RequestInfo::default)
+        .queue = Some(time);
+}
+
+// 记录读取上游响应体的时间
+pub fn record_body(req: &HttpRequest, time: Duration) {
+    req.extensions_mut()
+        .get_or_insert_with(RequestInfo::default)
+        .body = Some(time);
+}
+
+// 渲染一行日志所需的数据
+struct Entry<'a> {
+    req: &'a HttpRequest,
+    status: u16,
+    body_bytes: Option<u64>,
+    elapsed: Duration,
+    now: chrono::DateTime<chrono::Local>,
+    info: RequestInfo,
+    response_headers: &'a actix_web::http::header::HeaderMap,
+}
+
+impl Entry<'_> {
+    fn value(&self, variable: &Variable) -> String {
+        let req = self.req;
+        let join = |f: &dyn Fn(&UpstreamAttempt) -> String| {
+            self.info
+                .upstreams
+                .iter()
+                .map(f)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match variable {
+            Variable::RemoteAddr => crate::addr::peer_ip(req)
+                .map(|ip| ip.to_string())
+                .unwrap_or_default(),
+            Variable::RemotePort => req
+                .peer_addr()
+                .map(|a| a.port().to_string())
+                .unwrap_or_default(),
+            Variable::RemoteUser => remote_user(req.headers()).unwrap_or_default(),
+            Variable::Request => {
+                format!("{} {} {:?}", req.method(), request_uri(req), req.version())
+            }
+            Variable::RequestMethod => req.method().to_string(),
+            Variable::RequestUri => request_uri(req),
+            Variable::Uri => req.path().to_string(),
+            Variable::Args => req.query_string().to_string(),
+            Variable::ServerProtocol => format!("{:?}", req.version()),
+            Variable::Host => header_value(req.headers(), "host"),
+            Variable::Status => self.status.to_string(),
+            Variable::BodyBytesSent => self.body_bytes.map(|n| n.to_string()).unwrap_or_default(),
+            Variable::RequestTime => format!("{:.3}", self.elapsed.as_secs_f64()),
+            Variable::Msec => format!("{:.3}", self.now.timestamp_millis() as f64 / 1000.0),
+            Variable::TimeIso8601 => self.now.to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+            Variable::TimeLocal => self.now.format("%d/%b/%Y:%H:%M:%S %z").to_string(),
+            Variable::RequestId => request_id::current().unwrap_or_default(),
+            Variable::Route => self.info.route.clone().unwrap_or_default(),
+            Variable::UpstreamAddr => join(&|u| u.addr.clone()),
+            Variable::UpstreamStatus => join(&|u| {
+                u.status
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| "-".to_string())
+            }),
+            Variable::UpstreamResponseTime => join(&|u| format!("{:.3}", u.time.as_secs_f64())),
+            Variable::RequestHeader(name) => header_value(req.headers(), name),
+            Variable::ResponseHeader(name) => header_value(self.response_headers, name),
+        }
+    }
+}
+
+// 请求头的值，敏感请求头替换为 [REDACTED]
+fn header_value(headers: &actix_web::http::header::HeaderMap, name: &str) -> String {
+    match headers.get(name) {
+        Some(_) if redact::is_sensitive(name) => redact::REDACTED.to_string(),
+        Some(value) => value.to_str().unwrap_or_default().to_string(),
+        None => String::new(),
+    }
+}
+
+// Basic认证请求头中的用户名
+fn remote_user(headers: &actix_web::http::header::HeaderMap) -> Option<String> {
+    use base64::Engine;
+    let value = headers.get("authorization")?.to_str().ok()?;
+    let (scheme, credentials) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(credentials.trim())
+        .ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    decoded.split_once(':').map(|(user, _)| user.to_string())
+}
+
+fn request_uri(req: &HttpRequest) -> String {
+    req.uri()
+        .path_and_query()
+        .map(|pq| pq.as_str().to_string())
+        .unwrap_or_else(|| req.path().to_string())
+}
+
+// 访问日志
+pub struct AccessLog {
+    enabled: bool,
+    template: Template,
+    file: Option<RotatingFile>, // 访问日志文件，None表示输出到应用日志
+    sampling: SamplingConfig,
+    successes: AtomicU64,   // 已完成的成功请求数，用于按比例采样
+    slow: Option<Duration>, // 慢请求阈值，来自 [log] slow_request_ms
+}
+
+impl AccessLog {
+    // 根据配置创建访问日志，模板无效或文件无法打开时返回错误
+    pub fn new(config: &AccessLogConfig, slow: Option<Duration>) -> Result<Self, String> {
+        let file = match (&config.file, config.enabled) {
+            (Some(path), true) => {
+                let file = RotatingFile::open(path, &config.rotation)
+                    .map_err(|e| format!("打开访问日志文件 {} 失败: {}", path, e))?;
+                log::info!("访问日志: {}", path);
+                Some(file)
+            }
+            _ => None,
+        };
+        Ok(AccessLog {
+            enabled: config.enabled,
+            template: Template::parse(preset(&config.format))?,
+            file,
+            sampling: config.sampling.clone(),
+            successes: AtomicU64::new(0),
+            slow,
+        })
+    }
+
+    // 是否记录该请求：出错和慢请求始终记录，成功请求按 success_every 采样
+    fn sampled(&self, status: u16, elapsed: Duration) -> bool {
+        let sampling = &self.sampling;
+        if sampling.success_every <= 1 || status >= sampling.error_status {
+            return true;
+        }
+        if sampling
+            .slow_ms
+            .is_some_and(|ms| elapsed >= Duration::from_millis(ms))
+        {
+            return true;
+        }
+        self.successes
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(sampling.success_every)
+    }
+
+    // 输出一行访问日志，写入文件失败只记录告警
+    fn write(&self, line: String) {
+        match &self.file {
+            Some(file) => {
+                if let Err(e) = file.write_line(&line) {
+                    log::warn!("写入访问日志失败: {}", e);
+                }
+            }
+            None => log::info!(target: "access_log", "{}", line),
+        }
+    }
+}
+
+// 输出慢请求日志：总耗时及排队、各次上游调用（到达响应头，含建立连接）和读取响应体的耗时
+fn log_slow(req: &HttpRequest, status: u16, total: Duration, info: &RequestInfo) {
+    let ms = |d: Duration| format!("{}ms", d.as_millis());
+    let optional = |d: Option<Duration>| d.map(ms).unwrap_or_else(|| "-".to_string());
+    let upstreams = info
+        .upstreams
+        .iter()
+        .map(|u| {
+            let status = u
+                .status
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| "失败".to_string());
+            format!("{} {} {}", u.addr, status, ms(u.time))
+        })
+        .collect::<Vec<_>>();
+    log::warn!(
+        target: "slow_request",
+        "慢请求: {} {} 状态 {} 路由 {} 总耗时 {} [排队 {}, 上游 {} ({}), 响应体 {}]",
+        req.method(),
+        request_uri(req),
+        status,
+        info.route.as_deref().unwrap_or("-"),
+        ms(total),
+        optional(info.queue),
+        ms(info.upstreams.iter().map(|u| u.time).sum()),
+        if upstreams.is_empty() { "-".to_string() } else { upstreams.join(", ") },
+        optional(info.body),
+    );
+}
+
+// 访问日志中间件：需要注册在请求ID中间件的内层，日志行才能取到请求ID
+pub async fn middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let Some(state) = req.app_data::<web::Data<crate::AppState>>().cloned() else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_boxed_body);
+    };
+    let started = Instant::now();
+    // 内层中间件返回的错误（如降载拒绝）在这里转换为响应，保证同样被记录
+    let http_req = req.request().clone();
+    let res = match next.call(req).await {
+        Ok(res) => res.map_into_boxed_body(),
+        Err(e) => ServiceResponse::from_err(e, http_req),
+    };
+    let elapsed = started.elapsed();
+    let info = res
+        .request()
+        .extensions()
+        .get::<RequestInfo>()
+        .cloned()
+        .unwrap_or_default();
+    if let Some(error) = res.response().error()
+        && let Some(error) = error.as_error::<crate::ProxyError>()
+    {
+        crate::sentry::capture_error(
+            res.request(),
+            &info,
+            res.status().as_u16(),
+            &error.kind(),
+            &error.to_string(),
+        );
+    }
+    if state.access_log.slow.is_some_and(|slow| elapsed >= slow) {
+        log_slow(res.request(), res.status().as_u16(), elapsed, &info);
+    }
+    if res.status().is_server_error() {
+        let req = res.request();
+        state.dashboard.record_error(
+            req.method().as_str(),
+            &request_uri(req),
+            info.route.as_deref(),
+            res.status().as_u16(),
+            elapsed,
+        );
+    }
+    if state.tap.active() {
+        let req = res.request();
+        let path = request_uri(req);
+        state.tap.publish(&tap::Event {
+            method: req.method().as_str(),
+            path: &path,
+            status: res.status().as_u16(),
+            duration_ms: elapsed.as_millis(),
+            route: info.route.as_deref(),
+            upstream: info.upstreams.last().map(|u| u.addr.as_str()),
+            client_ip: crate::addr::peer_ip(req).map(|ip| ip.to_string()),
+        });
+    }
+    let access_log = &state.access_log;
+    if !access_log.enabled
+        || !state.features.enabled(crate::Feature::AccessLog)
+        || !access_log.sampled(res.status().as_u16(), elapsed)
+    {
+        return Ok(res);
+    }
+    let body_bytes = match res.response().body().size() {
+        BodySize::Sized(n) => Some(n),
+        BodySize::None => Some(0),
+        BodySize::Stream => None,
+    };
+    let entry = Entry {
+        req: res.request(),
+        status: res.status().as_u16(),
+        body_bytes,
+        elapsed,
+        now: chrono::Local::now(),
+        info,
+        response_headers: res.headers(),
+    };
+    access_log.write(access_log.template.render(&entry));
+    Ok(res)
+}
@@ -0,0 +1,86 @@
+// ==================== 访问控制列表(ACL) ====================
+//
+// 按客户端IP地址放行或拒绝请求：先匹配deny列表，再要求命中allow列表（allow为空表示全部放行）
+
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+// ACL配置：CIDR或单个IP地址的列表，同时支持IPv4与IPv6
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AclConfig {
+    #[serde(default)]
+    pub allow: Vec<String>, // 允许访问的网段，为空表示不限制
+    #[serde(default)]
+    pub deny: Vec<String>, // 拒绝访问的网段，优先于allow
+}
+
+// 单个网段，如 10.0.0.0/8、::1/128
+#[derive(Debug, Clone)]
+pub(crate) struct IpNet {
+    addr: IpAddr, // 网络地址
+    prefix: u8,   // 前缀长度
+}
+
+impl IpNet {
+    // 解析 "地址/前缀" 或单个地址
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (
+                addr.parse::<IpAddr>().ok()?,
+                Some(prefix.parse::<u8>().ok()?),
+            ),
+            None => (s.parse::<IpAddr>().ok()?, None),
+        };
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        (prefix <= max).then_some(IpNet { addr, prefix })
+    }
+
+    // 判断地址是否落在该网段内
+    pub(crate) fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, crate::addr::canonical(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false, // 地址族不同，不匹配
+        }
+    }
+}
+
+// 编译后的ACL
+#[derive(Debug, Clone)]
+pub struct Acl {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+}
+
+impl Acl {
+    // 从配置构建ACL，任何无法解析的网段都会导致启动失败
+    pub fn new(config: &AclConfig) -> Result<Self, String> {
+        let parse = |list: &[String]| {
+            list.iter()
+                .map(|s| IpNet::parse(s).ok_or_else(|| format!("无效的ACL网段: {}", s)))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Acl {
+            allow: parse(&config.allow)?,
+            deny: parse(&config.deny)?,
+        })
+    }
+
+    // 检查客户端地址；被拒绝时返回命中的规则ID（如 acl.deny[0]、acl.allow）
+    pub fn check(&self, ip: IpAddr) -> Result<(), String> {
+        if let Some(index) = self.deny.iter().position(|net| net.contains(ip)) {
+            return Err(format!("acl.deny[{}]", index));
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|net| net.contains(ip)) {
+            return Err("acl.allow".to_string());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+// ==================== 地址与双栈 ====================
+//
+// 监听地址可以写成逗号分隔的多个主机，如 "0.0.0.0, ::"，IPv6 地址带不带方括号都可以；
+// 只监听 :: 时为双栈，同时接受 IPv4 连接，与 IPv4 地址一起监听时 :: 只接受 IPv6 连接。
+// 双栈监听时 IPv4 客户端的地址是映射的 IPv6 地址(::ffff:a.b.c.d)，这里统一还原为 IPv4，
+// 日志、访问控制、审计与追踪中的客户端地址与单栈监听时一致
+
+use actix_web::HttpRequest;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+
+// 拼接 host:port，IPv6 地址加上方括号，用于 URL 与日志
+pub fn join(host: &str, port: u16) -> String {
+    let bare = host.trim_start_matches('[').trim_end_matches(']');
+    match bare.contains(':') {
+        true => format!("[{}]:{}", bare, port),
+        false => format!("{}:{}", host, port),
+    }
+}
+
+// 逗号分隔的监听主机列表，去掉 IPv6 地址的方括号
+pub fn hosts(hosts: &str) -> Vec<&str> {
+    hosts
+        .split(',')
+        .map(|h| h.trim().trim_start_matches('[').trim_end_matches(']'))
+        .filter(|h| !h.is_empty())
+        .collect()
+}
+
+// 解析监听主机列表与端口得到的所有地址，去掉重复的地址
+pub fn resolve(host: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+    let mut addrs: Vec<SocketAddr> = Vec::new();
+    for host in hosts(host) {
+        for addr in (host, port).to_socket_addrs()? {
+            if !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+        }
+    }
+    Ok(addrs)
+}
+
+// 将IPv4映射的IPv6地址(::ffff:a.b.c.d)还原为IPv4，保证双栈监听时规则依然生效
+pub fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        v4 => v4,
+    }
+}
+
+// 客户端的地址与端口，IPv4 映射的地址已还原
+pub fn peer_addr(req: &HttpRequest) -> Option<SocketAddr> {
+    req.peer_addr()
+        .map(|peer| SocketAddr::new(canonical(peer.ip()), peer.port()))
+}
+
+// 客户端 IP
+pub fn peer_ip(req: &HttpRequest) -> Option<IpAddr> {
+    peer_addr(req).map(|peer| peer.ip())
+}
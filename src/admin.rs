@@ -0,0 +1,516 @@
+// ==================== 管理接口 ====================
+//
+// 独立监听地址上的管理服务，与代理流量隔离，提供 /status 状态页、/metrics 指标导出、
+// /healthz 与 /readyz 探针、/captures 报文捕获查看、/tap 实时流量查看、/log-level 日志级别调整、
+// /cache/purge 响应缓存清除，以及 /config 配置查看、/routes 路由表、/routes/{name}/targets 运行时增减上游、
+// /upstreams 上游状态、/drain 流量排空、/maintenance 维护模式与 /features 功能开关。设置 token 后除探针外的接口都需要
+// Authorization: Bearer <token>
+
+use crate::{
+    AppState, TargetConfig, cache, health, log_level, maintenance, metrics, redact, routes, tap,
+};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+// 管理服务配置：对应配置文件中的 [admin]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AdminConfig {
+    #[serde(default)]
+    pub enabled: bool, // 是否启用管理服务
+    #[serde(default = "default_admin_host")]
+    pub host: String, // 监听地址
+    #[serde(default = "default_admin_port")]
+    pub port: u16, // 监听端口
+    #[serde(default)]
+    pub token: Option<String>, // 访问令牌，可以写成 "env:ADMIN_TOKEN"；不设置则不认证
+    #[serde(default)]
+    pub readiness: health::ReadinessConfig, // 就绪检查配置
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        AdminConfig {
+            enabled: false,
+            host: default_admin_host(),
+            port: default_admin_port(),
+            token: None,
+            readiness: health::ReadinessConfig::default(),
+        }
+    }
+}
+
+fn default_admin_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_admin_port() -> u16 {
+    9090
+}
+
+// 认证中间件：设置了令牌时，除存活与就绪探针外的请求都需要携带令牌，失败的请求写入审计日志。
+// 令牌取自当前配置，热加载后立即生效
+pub async fn auth<B: MessageBody + 'static>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let expected = req
+        .app_data::<web::Data<AppState>>()
+        .and_then(|state| state.config.load().admin.token.clone());
+    let Some(expected) = expected else {
+        return next.call(req).await.map(|resp| resp.map_into_left_body());
+    };
+    if matches!(req.path(), "/healthz" | "/readyz") {
+        return next.call(req).await.map(|resp| resp.map_into_left_body());
+    }
+    let provided = req
+        .headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or("");
+    if constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+        return next.call(req).await.map(|resp| resp.map_into_left_body());
+    }
+    log::warn!("管理接口认证失败: {} {}", req.method(), req.path());
+    if let Some(state) = req.app_data::<web::Data<AppState>>() {
+        state
+            .audit
+            .record(req.request(), "admin_auth", "admin.token", "-", 401);
+    }
+    let resp = HttpResponse::Unauthorized()
+        .insert_header(("WWW-Authenticate", "Bearer"))
+        .body("需要有效的管理接口令牌");
+    Ok(req.into_response(resp).map_into_right_body())
+}
+
+// 比较令牌，耗时与不相同的位置无关
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// 注册管理接口的路由
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/status", web::get().to(status_handler))
+        .route("/metrics", web::get().to(metrics_handler))
+        .route("/healthz", web::get().to(healthz_handler))
+        .route("/readyz", web::get().to(readyz_handler))
+        .route("/captures", web::get().to(captures_handler))
+        .route("/tap", web::get().to(tap_handler))
+        .route("/log-level", web::get().to(log_level_handler))
+        .route("/log-level", web::put().to(set_log_level_handler))
+        .route("/log-level", web::delete().to(reset_log_level_handler))
+        .route("/cache/purge", web::post().to(purge_cache_handler))
+        .route("/config", web::get().to(config_handler))
+        .route("/routes", web::get().to(routes_handler))
+        .route("/routes/{name}/targets", web::get().to(targets_handler))
+        .route("/routes/{name}/targets", web::post().to(add_target_handler))
+        .route(
+            "/routes/{name}/targets",
+            web::delete().to(remove_target_handler),
+        )
+        .route("/upstreams", web::get().to(upstreams_handler))
+        .route("/drain", web::get().to(drain_handler))
+        .route("/drain", web::post().to(start_drain_handler))
+        .route("/drain", web::delete().to(stop_drain_handler))
+        .route("/maintenance", web::get().to(maintenance_handler))
+        .route("/maintenance", web::put().to(enable_maintenance_handler))
+        .route(
+            "/maintenance",
+            web::delete().to(disable_maintenance_handler),
+        )
+        .route("/features", web::get().to(features_handler))
+        .route("/features", web::put().to(set_features_handler))
+        .route("/features", web::delete().to(reset_features_handler));
+}
+
+// HTML状态页
+async fn status_handler(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(
+            state
+                .dashboard
+                .render(&state.routes.load(), state.health.uptime()),
+        )
+}
+
+// Prometheus指标导出
+async fn metrics_handler() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::render_prometheus())
+}
+
+// 存活检查：进程能处理请求即返回200
+async fn healthz_handler(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(state.health.liveness())
+}
+
+// 就绪检查：未就绪时返回503及未通过的检查项
+async fn readyz_handler(state: web::Data<AppState>) -> HttpResponse {
+    let (ready, body) = state.health.readiness(&state.routes.load()).await;
+    if ready {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+// 最近的报文捕获，未启用捕获时为空数组
+async fn captures_handler(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(state.capture.recent())
+}
+
+// 以 Server-Sent Events 推送实时的请求摘要，查询参数为过滤条件
+async fn tap_handler(state: web::Data<AppState>, filter: web::Query<tap::Filter>) -> HttpResponse {
+    match state.tap.subscribe(filter.into_inner()) {
+        Some(stream) => HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .insert_header(("Cache-Control", "no-cache"))
+            .body(stream),
+        None => HttpResponse::ServiceUnavailable().body("订阅数已达上限"),
+    }
+}
+
+// 修改日志级别的请求体
+#[derive(Debug, Deserialize)]
+struct LogLevelRequest {
+    filter: String, // 过滤规则，语法与 RUST_LOG 相同，如 "info,rust_proxy::routes=debug"
+    duration_secs: Option<u64>, // 持续时间(秒)，到期后恢复为启动时的规则，不设置则一直生效
+}
+
+// 当前的日志过滤规则
+async fn log_level_handler() -> HttpResponse {
+    let (filter, default) = log_level::current();
+    HttpResponse::Ok().json(serde_json::json!({"filter": filter, "default": default}))
+}
+
+// 修改日志过滤规则，规则无效时返回400
+async fn set_log_level_handler(request: web::Json<LogLevelRequest>) -> HttpResponse {
+    let duration = request.duration_secs.map(std::time::Duration::from_secs);
+    match log_level::set(&request.filter, duration) {
+        Ok(()) => log_level_handler().await,
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
+
+// 恢复为启动时的日志过滤规则
+async fn reset_log_level_handler() -> HttpResponse {
+    match log_level::reset() {
+        Ok(()) => log_level_handler().await,
+        Err(e) => HttpResponse::InternalServerError().body(e),
+    }
+}
+
+// 清除缓存的请求体：url、prefix、tag、all 需要且只能指定一项
+#[derive(Debug, Deserialize)]
+struct PurgeRequest {
+    route: Option<String>,  // 只清除该路由的缓存，缺省为所有启用缓存的路由
+    url: Option<String>,    // 清除一个 URL（路径和查询参数）的所有变体，如 "/api/items?page=1"
+    prefix: Option<String>, // 清除路径以该前缀开头的条目，如 "/static/"
+    tag: Option<String>,    // 清除响应 Surrogate-Key 中带有该标签的条目
+    #[serde(default)]
+    all: bool, // 清空缓存
+}
+
+// 清除响应缓存，返回每个路由清除的条目数
+async fn purge_cache_handler(
+    state: web::Data<AppState>,
+    request: web::Json<PurgeRequest>,
+) -> HttpResponse {
+    let request = request.into_inner();
+    let purge = match (request.url, request.prefix, request.tag, request.all) {
+        (Some(url), None, None, false) => cache::Purge::Url(url),
+        (None, Some(prefix), None, false) => cache::Purge::Prefix(prefix),
+        (None, None, Some(tag), false) => cache::Purge::Tag(tag),
+        (None, None, None, true) => cache::Purge::All,
+        _ => return HttpResponse::BadRequest().body("url、prefix、tag、all 需要且只能指定一项"),
+    };
+    let routes: Vec<_> = state
+        .routes
+        .load()
+        .iter()
+        .filter(|route| route.cache.is_some())
+        .filter(|route| {
+            request
+                .route
+                .as_ref()
+                .is_none_or(|name| *name == route.name)
+        })
+        .cloned()
+        .collect();
+    if routes.is_empty() {
+        return HttpResponse::NotFound().body("没有匹配的启用缓存的路由");
+    }
+    let mut purged = serde_json::Map::new();
+    let mut total = 0;
+    for route in routes {
+        let Some(cache) = &route.cache else {
+            continue;
+        };
+        match cache.purge(&purge).await {
+            Ok(count) => {
+                total += count;
+                purged.insert(route.name.clone(), count.into());
+            }
+            Err(e) => {
+                log::error!("路由 {} 的缓存清除失败: {}", route.name, e);
+                return HttpResponse::InternalServerError()
+                    .body(format!("路由 {} 的缓存清除失败: {}", route.name, e));
+            }
+        }
+    }
+    log::info!("管理接口清除缓存 {:?}: 共 {} 个条目", purge, total);
+    HttpResponse::Ok().json(serde_json::json!({"purged": total, "routes": purged}))
+}
+
+// 当前配置：effective 为补全默认值后实际生效的配置，source 为合并 include、环境变量与命令行参数后、
+// 解析密钥引用之前的内容，两者的密码和令牌都已脱敏；environment 列出 APP_ 环境变量对应的配置键
+// 以及是否生效
+async fn config_handler(state: web::Data<AppState>) -> HttpResponse {
+    let config = state.config.load();
+    let mut source = config.source.clone();
+    redact::config(&mut source);
+    let effective = crate::effective_config(&config);
+    HttpResponse::Ok().json(serde_json::json!({
+        "path": config.config_path,
+        "profile": config.profile,
+        "files": config.config_files,
+        "environment": crate::app_env_vars(&effective),
+        "effective": effective,
+        "source": source,
+    }))
+}
+
+// 路由表，按匹配优先级排列
+async fn routes_handler(state: web::Data<AppState>) -> HttpResponse {
+    let routes: Vec<_> = state
+        .routes
+        .load()
+        .iter()
+        .map(|route| {
+            serde_json::json!({
+                "name": route.name,
+                "path_prefix": route.path_prefix,
+                "targets": route.targets().iter().map(|t| t.base_url()).collect::<Vec<_>>(),
+                "failover_target": route.failover.as_ref().map(|t| t.base_url()),
+                "retry": route.retry.is_some(),
+                "hedge": route.hedge.is_some(),
+                "circuit_breaker": route.breaker.status().0 != "disabled",
+                "fallback": route.fallback.is_some(),
+                "idempotency": route.idempotency.is_some(),
+                "cache": route.cache.is_some(),
+            })
+        })
+        .collect();
+    HttpResponse::Ok().json(routes)
+}
+
+// 注册或注销的目标服务器，如 {"url": "http://10.0.0.8:8080"}
+#[derive(Debug, Deserialize)]
+struct TargetRequest {
+    url: String, // 目标服务器的基础URL，端口缺省按协议取 80/443
+}
+
+impl TargetRequest {
+    fn target(&self) -> Result<TargetConfig, HttpResponse> {
+        match crate::cli::parse_target(&self.url) {
+            Ok((protocol, host, port)) => Ok(TargetConfig {
+                host,
+                port,
+                protocol,
+                proxy_url: None,
+            }),
+            Err(e) => Err(HttpResponse::BadRequest().body(e)),
+        }
+    }
+}
+
+// 路由生效的目标服务器，以及配置中的目标与运行时的增减
+fn targets_body(state: &AppState, route: &routes::Route) -> serde_json::Value {
+    let urls = |targets: &[TargetConfig]| targets.iter().map(|t| t.base_url()).collect::<Vec<_>>();
+    let changes = state.upstreams.changes(&route.name);
+    serde_json::json!({
+        "route": route.name,
+        "targets": urls(&route.targets()),
+        "configured": urls(&route.configured),
+        "added": urls(&changes.added),
+        "removed": urls(&changes.removed),
+    })
+}
+
+fn find_route(state: &AppState, name: &str) -> Result<Arc<routes::Route>, HttpResponse> {
+    state
+        .routes
+        .load()
+        .iter()
+        .find(|r| r.name == name)
+        .cloned()
+        .ok_or_else(|| HttpResponse::NotFound().body(format!("路由不存在: {}", name)))
+}
+
+// 路由的目标服务器
+async fn targets_handler(state: web::Data<AppState>, name: web::Path<String>) -> HttpResponse {
+    match find_route(&state, &name) {
+        Ok(route) => HttpResponse::Ok().json(targets_body(&state, &route)),
+        Err(resp) => resp,
+    }
+}
+
+// 为路由注册目标服务器，新注册时返回 201
+async fn add_target_handler(
+    state: web::Data<AppState>,
+    name: web::Path<String>,
+    request: web::Json<TargetRequest>,
+) -> HttpResponse {
+    let (route, target) = match (find_route(&state, &name), request.target()) {
+        (Ok(route), Ok(target)) => (route, target),
+        (Err(resp), _) | (_, Err(resp)) => return resp,
+    };
+    match state.upstreams.add(&route, target) {
+        Ok(true) => HttpResponse::Created().json(targets_body(&state, &route)),
+        Ok(false) => HttpResponse::Ok().json(targets_body(&state, &route)),
+        Err(e) => HttpResponse::InternalServerError().body(e),
+    }
+}
+
+// 注销路由的目标服务器，目标服务器以查询参数 url 指定
+async fn remove_target_handler(
+    state: web::Data<AppState>,
+    name: web::Path<String>,
+    request: web::Query<TargetRequest>,
+) -> HttpResponse {
+    let (route, target) = match (find_route(&state, &name), request.target()) {
+        (Ok(route), Ok(target)) => (route, target),
+        (Err(resp), _) | (_, Err(resp)) => return resp,
+    };
+    let targets = route.targets();
+    match state.upstreams.remove(&route, &target) {
+        Ok(true) => HttpResponse::Ok().json(targets_body(&state, &route)),
+        Ok(false) => HttpResponse::NotFound().body(format!(
+            "路由 {} 没有目标服务器 {}",
+            route.name,
+            target.base_url()
+        )),
+        Err(e) if targets.len() == 1 => HttpResponse::Conflict().body(e),
+        Err(e) => HttpResponse::InternalServerError().body(e),
+    }
+}
+
+// 每个路由的熔断器状态与各上游启动以来的调用结果
+async fn upstreams_handler(state: web::Data<AppState>) -> HttpResponse {
+    let mut outcomes: BTreeMap<(String, String), BTreeMap<String, u64>> = BTreeMap::new();
+    for (labels, value) in metrics::counter_values("proxy_upstream_requests_total") {
+        let label = |name: &str| {
+            labels
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.clone())
+                .unwrap_or_default()
+        };
+        *outcomes
+            .entry((label("route"), label("upstream")))
+            .or_default()
+            .entry(label("outcome"))
+            .or_default() += value;
+    }
+    let mut body = serde_json::Map::new();
+    for route in state.routes.load().iter() {
+        let (breaker, failures) = route.breaker.status();
+        let upstreams: serde_json::Map<_, _> = outcomes
+            .iter()
+            .filter(|((name, _), _)| *name == route.name)
+            .map(|((_, upstream), counts)| (upstream.clone(), serde_json::json!(counts)))
+            .collect();
+        body.insert(
+            route.name.clone(),
+            serde_json::json!({
+                "targets": route.targets().iter().map(|t| t.base_url()).collect::<Vec<_>>(),
+                "failover_target": route.failover.as_ref().map(|t| t.base_url()),
+                "circuit_breaker": {"state": breaker, "consecutive_failures": failures},
+                "outcomes": upstreams,
+            }),
+        );
+    }
+    HttpResponse::Ok().json(body)
+}
+
+// 排空状态
+async fn drain_handler(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({"draining": state.health.draining()}))
+}
+
+// 开始排空：就绪检查返回503，代理响应关闭客户端连接，已有请求照常完成
+async fn start_drain_handler(state: web::Data<AppState>) -> HttpResponse {
+    if !state.health.set_draining(true) {
+        log::warn!("管理接口开始排空流量");
+    }
+    drain_handler(state).await
+}
+
+// 停止排空，恢复接收流量
+async fn stop_drain_handler(state: web::Data<AppState>) -> HttpResponse {
+    if state.health.set_draining(false) {
+        log::warn!("管理接口停止排空流量");
+    }
+    drain_handler(state).await
+}
+
+// 维护模式设置，未开启时为 null
+async fn maintenance_handler(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({"maintenance": state.maintenance.get()}))
+}
+
+// 开启维护模式，请求体可以为空对象
+async fn enable_maintenance_handler(
+    state: web::Data<AppState>,
+    request: web::Json<maintenance::MaintenanceMode>,
+) -> HttpResponse {
+    let mode = request.into_inner();
+    let known: Vec<String> = state.routes.load().iter().map(|r| r.name.clone()).collect();
+    if let Some(unknown) = mode.routes.iter().find(|r| !known.contains(r)) {
+        return HttpResponse::NotFound().body(format!("路由不存在: {}", unknown));
+    }
+    let mode = state.maintenance.enable(mode);
+    match mode.routes.is_empty() {
+        true => log::warn!("管理接口开启维护模式: 所有路由"),
+        false => log::warn!("管理接口开启维护模式: {}", mode.routes.join(", ")),
+    }
+    HttpResponse::Ok().json(serde_json::json!({"maintenance": mode}))
+}
+
+// 关闭维护模式
+async fn disable_maintenance_handler(state: web::Data<AppState>) -> HttpResponse {
+    if state.maintenance.disable() {
+        log::warn!("管理接口关闭维护模式");
+    }
+    maintenance_handler(state).await
+}
+
+// 每个功能是否生效、配置的值与管理接口的覆盖
+async fn features_handler(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({"features": state.features.status()}))
+}
+
+// 覆盖功能开关，如 {"cache": false}，值为 null 时去掉覆盖
+async fn set_features_handler(
+    state: web::Data<AppState>,
+    request: web::Json<BTreeMap<String, Option<bool>>>,
+) -> HttpResponse {
+    if let Err(e) = state.features.set(&request) {
+        return HttpResponse::BadRequest().body(e);
+    }
+    features_handler(state).await
+}
+
+// 去掉所有覆盖，恢复为配置的开关
+async fn reset_features_handler(state: web::Data<AppState>) -> HttpResponse {
+    state.features.reset();
+    log::warn!("管理接口将所有功能开关恢复为配置的值");
+    features_handler(state).await
+}
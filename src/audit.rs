@@ -0,0 +1,86 @@
+// ==================== 安全审计日志 ====================
+//
+// 每个被鉴权、ACL、WAF或限流拒绝的请求都会以一行JSON写入独立的审计日志，便于SOC系统采集
+
+use actix_web::HttpRequest;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+
+// 审计日志配置
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditConfig {
+    #[serde(default)]
+    pub enabled: bool, // 是否启用审计日志
+    #[serde(default = "default_audit_path")]
+    pub path: String, // 审计日志文件路径（追加写入）
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            enabled: false,
+            path: default_audit_path(),
+        }
+    }
+}
+
+// 为审计日志路径提供默认值的函数
+fn default_audit_path() -> String {
+    "audit.log".to_string()
+}
+
+// 审计日志写入器，未启用时所有记录都会被丢弃
+pub struct AuditLog {
+    file: Option<Mutex<File>>,
+}
+
+impl AuditLog {
+    // 根据配置打开审计日志文件
+    pub fn open(config: &AuditConfig) -> std::io::Result<Self> {
+        let file = if config.enabled {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&config.path)?;
+            log::info!("审计日志: {}", config.path);
+            Some(Mutex::new(file))
+        } else {
+            None
+        };
+        Ok(AuditLog { file })
+    }
+
+    // 记录一次拒绝事件
+    pub fn record(
+        &self,
+        req: &HttpRequest, // 被拒绝的请求
+        source: &str,      // 拒绝来源，如 acl、auth、waf、rate_limit
+        rule_id: &str,     // 命中的规则ID
+        route: &str,       // 匹配到的路由
+        status: u16,       // 返回给客户端的状态码
+    ) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let entry = serde_json::json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "event": "request_denied",
+            "source": source,
+            "rule_id": rule_id,
+            "client_ip": crate::addr::peer_ip(req).map(|ip| ip.to_string()),
+            "method": req.method().as_str(),
+            "path": req.path(),
+            "route": route,
+            "status": status,
+            "user_agent": req.headers().get("user-agent").and_then(|v| v.to_str().ok()),
+            "request_id": crate::request_id::current(),
+        });
+        // 写入失败只记录告警，不影响请求处理
+        let mut file = file.lock().unwrap();
+        if let Err(e) = writeln!(file, "{}", entry) {
+            log::warn!("写入审计日志失败: {}", e);
+        }
+    }
+}
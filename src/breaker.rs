@@ -0,0 +1,122 @@
+// ==================== 熔断器 ====================
+//
+// 按路由统计连续的硬故障（连接失败、超时），达到阈值后熔断一段时间，期间直接跳过主目标；
+// 熔断时间结束后进入半开状态，放行一个试探请求，成功则恢复，失败则再次熔断
+
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// 熔断配置：对应路由中的 [routes.circuit_breaker]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BreakerConfig {
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32, // 触发熔断的连续失败次数
+    #[serde(default = "default_open_secs")]
+    pub open_secs: u64, // 熔断持续时间(秒)
+}
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_open_secs() -> u64 {
+    30
+}
+
+// 熔断器状态
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Closed,        // 正常放行
+    Open(Instant), // 熔断中，直到指定时间
+    HalfOpen,      // 已放行试探请求，等待结果
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: State,
+    failures: u32, // 当前连续失败次数
+}
+
+// 路由熔断器，未配置时始终放行
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    route: String, // 所属路由名称，用于日志
+    config: Option<BreakerConfig>,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(route: &str, config: Option<BreakerConfig>) -> Self {
+        CircuitBreaker {
+            route: route.to_string(),
+            config,
+            inner: Mutex::new(Inner {
+                state: State::Closed,
+                failures: 0,
+            }),
+        }
+    }
+
+    // 判断当前是否允许向主目标发送请求
+    pub fn allow(&self) -> bool {
+        if self.config.is_none() {
+            return true;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            State::Closed => true,
+            State::Open(until) if Instant::now() >= until => {
+                inner.state = State::HalfOpen; // 熔断时间已过，放行一个试探请求
+                true
+            }
+            State::Open(_) | State::HalfOpen => false,
+        }
+    }
+
+    // 当前状态名称与连续失败次数，用于状态页
+    pub fn status(&self) -> (&'static str, u32) {
+        if self.config.is_none() {
+            return ("disabled", 0);
+        }
+        let inner = self.inner.lock().unwrap();
+        let name = match inner.state {
+            State::Closed => "closed",
+            State::Open(until) if Instant::now() >= until => "half_open",
+            State::Open(_) => "open",
+            State::HalfOpen => "half_open",
+        };
+        (name, inner.failures)
+    }
+
+    // 记录一次成功，恢复为关闭状态
+    pub fn record_success(&self) {
+        if self.config.is_none() {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == State::HalfOpen {
+            log::info!("路由 {} 熔断器恢复", self.route);
+        }
+        inner.state = State::Closed;
+        inner.failures = 0;
+    }
+
+    // 记录一次硬故障，达到阈值或试探失败时进入熔断状态
+    pub fn record_failure(&self) {
+        let Some(config) = &self.config else {
+            return;
+        };
+        let mut inner = self.inner.lock().unwrap();
+        inner.failures += 1;
+        if inner.state == State::HalfOpen || inner.failures >= config.failure_threshold {
+            log::warn!(
+                "路由 {} 连续失败{}次，熔断{}秒",
+                self.route,
+                inner.failures,
+                config.open_secs
+            );
+            inner.state = State::Open(Instant::now() + Duration::from_secs(config.open_secs));
+        }
+    }
+}
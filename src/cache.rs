@@ -0,0 +1,1662 @@
+// ==================== 响应缓存 ====================
+//
+// 路由启用后，GET/HEAD 请求的响应按 RFC 7234 作为共享缓存保存在内存中：新鲜期取自响应的
+// Cache-Control（s-maxage、max-age）或 Expires，没有时使用 ttl_secs；遵守请求和响应中的
+// no-store、no-cache、private 等指令，按 Vary 区分变体；过期的条目带上 ETag/Last-Modified
+// 向上游发送条件请求，上游返回 304 时更新条目继续使用，客户端的条件请求由缓存直接应答 304。
+// 支持 RFC 5861：stale-while-revalidate 期间直接返回过期的响应并在后台更新，
+// stale-if-error 期间上游出错时返回过期的响应；可选地将 404/5xx 等错误响应保存较短的时间，
+// 避免大量相同的失败请求涌向出故障的后端；同一条目同时未命中的请求合并为一次上游请求
+// 缓存键为"方法 + URL + 选定请求头"（可按路由剔除追踪参数、加入 Cookie），条目数或总大小超出上限时淘汰最久未使用的条目。
+// 配置磁盘缓存后，较大的响应体保存在磁盘目录中而不占用内存，代理重启时从目录恢复；
+// 配置 Redis 后条目改为保存在 Redis 中，多个代理副本共享同一份缓存。
+// 管理接口可以按 URL、前缀、响应的 Surrogate-Key 标签清除条目，或清空整个缓存。
+// 缓存应答的响应带 X-Cache 头（HIT/MISS/STALE/REVALIDATED），命中率、条目数和淘汰数记入指标
+
+use crate::metrics;
+use crate::redis::{RedisClient, RedisConfig};
+use crate::snapshot::ResponseSnapshot;
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+// 缓存配置：对应路由中的 [routes.cache]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CacheConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool, // 是否启用，关闭后保留配置但不缓存
+    #[serde(default = "default_methods")]
+    pub methods: Vec<String>, // 缓存的请求方法，只能是 GET、HEAD
+    #[serde(default = "default_statuses")]
+    pub statuses: Vec<u16>, // 可以缓存的状态码
+    #[serde(default)]
+    pub max_object_kb: u64, // 单个响应体的大小上限(KB)，超出的响应不缓存，0表示只受总大小限制
+    #[serde(default)]
+    pub exclude_paths: Vec<String>, // 不缓存的请求路径，以 * 结尾时按前缀匹配，如 ["/api/me*"]
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64, // 响应没有 Cache-Control/Expires 时的保存时间(秒)
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize, // 最多保存的响应数
+    #[serde(default = "default_max_size_mb")]
+    pub max_size_mb: u64, // 所有响应的总大小上限(MB)
+    #[serde(default)]
+    pub key_headers: Vec<String>, // 计入缓存键的请求头，如 ["Accept-Encoding", "Accept-Language"]
+    #[serde(default)]
+    pub key: CacheKeyConfig, // 缓存键的组成，缺省为完整的 URL
+    #[serde(default = "default_tag_header")]
+    pub tag_header: String, // 列出响应标签的响应头，标签以空格或逗号分隔，用于按标签清除
+    #[serde(default = "default_status_header")]
+    pub status_header: String, // 标明缓存结果的响应头，为空时不添加
+    #[serde(default = "default_keep_stale_secs")]
+    pub keep_stale_secs: u64, // 过期后仍保留带验证器的条目的时间(秒)，用于向上游重新验证
+    #[serde(default)]
+    pub stale_while_revalidate_secs: u64, // 响应未声明 stale-while-revalidate 时的默认值(秒)，0表示不启用
+    #[serde(default)]
+    pub stale_if_error_secs: u64, // 响应未声明 stale-if-error 时的默认值(秒)，0表示不启用
+    #[serde(default)]
+    pub negative_ttl_secs: u64, // 错误响应的最长保存时间(秒)，0表示不缓存错误响应
+    #[serde(default = "default_negative_statuses")]
+    pub negative_statuses: Vec<u16>, // 启用错误响应缓存时可以保存的状态码
+    #[serde(default = "default_true")]
+    pub coalesce: bool, // 同一条目同时未命中的请求只向上游发送一次，其余请求等待其结果
+    #[serde(default = "default_coalesce_timeout_ms")]
+    pub coalesce_timeout_ms: u64, // 等待首个请求的最长时间(毫秒)，超时后自行请求上游
+    #[serde(default)]
+    pub disk: Option<DiskConfig>, // 磁盘缓存，缺省只使用内存
+    #[serde(default)]
+    pub redis: Option<RedisConfig>, // Redis 共享缓存，配置后条目只保存在 Redis 中
+}
+
+// 缓存键配置：对应路由中的 [routes.cache.key]；参数名和 Cookie 名以 * 结尾时按前缀匹配
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CacheKeyConfig {
+    #[serde(default)]
+    pub ignore_query: bool, // 查询参数不计入缓存键
+    #[serde(default)]
+    pub include_query: Vec<String>, // 只有这些查询参数计入缓存键，为空时不限制
+    #[serde(default)]
+    pub exclude_query: Vec<String>, // 不计入缓存键的查询参数，如 ["utm_*", "fbclid"]
+    #[serde(default)]
+    pub sort_query: bool, // 查询参数排序后计入缓存键，参数顺序不同的请求共用条目
+    #[serde(default)]
+    pub headers: Vec<String>, // 计入缓存键的请求头，与 key_headers 合并
+    #[serde(default)]
+    pub cookies: Vec<String>, // 计入缓存键的 Cookie，如 ["session"]，不同用户的响应分别保存
+}
+
+impl CacheKeyConfig {
+    // 是否需要改写 URL 中的查询参数
+    fn rewrites_query(&self) -> bool {
+        self.ignore_query
+            || self.sort_query
+            || !self.include_query.is_empty()
+            || !self.exclude_query.is_empty()
+    }
+}
+
+// 名称是否与配置的名称匹配，以 * 结尾的按前缀匹配
+fn name_matches(patterns: &[String], name: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == pattern,
+        })
+}
+
+// 磁盘缓存配置：对应路由中的 [routes.cache.disk]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DiskConfig {
+    pub path: String, // 缓存目录，每个路由使用单独的目录
+    #[serde(default = "default_disk_max_size_mb")]
+    pub max_size_mb: u64, // 磁盘上所有响应的总大小上限(MB)
+    #[serde(default = "default_min_object_kb")]
+    pub min_object_kb: u64, // 响应体达到该大小(KB)时保存到磁盘，较小的响应仍保存在内存中
+}
+
+// 以下函数为缓存配置提供默认值
+fn default_ttl_secs() -> u64 {
+    60
+}
+
+fn default_max_entries() -> usize {
+    10000
+}
+
+fn default_max_size_mb() -> u64 {
+    64
+}
+
+fn default_methods() -> Vec<String> {
+    vec!["GET".to_string(), "HEAD".to_string()]
+}
+
+fn default_statuses() -> Vec<u16> {
+    CACHEABLE_STATUS.to_vec()
+}
+
+fn default_status_header() -> String {
+    "X-Cache".to_string()
+}
+
+fn default_tag_header() -> String {
+    "Surrogate-Key".to_string()
+}
+
+fn default_keep_stale_secs() -> u64 {
+    300
+}
+
+fn default_negative_statuses() -> Vec<u16> {
+    vec![404, 410, 500, 502, 503, 504]
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_coalesce_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_disk_max_size_mb() -> u64 {
+    1024
+}
+
+fn default_min_object_kb() -> u64 {
+    64
+}
+
+// 缺省可以缓存的状态码（RFC 7231 中可由启发式规则缓存的成功与重定向状态）
+const CACHEABLE_STATUS: [u16; 6] = [200, 203, 204, 300, 301, 308];
+
+// 304 响应中保留的响应头（RFC 7232 4.1）
+const NOT_MODIFIED_HEADERS: [&str; 7] = [
+    "cache-control",
+    "content-location",
+    "date",
+    "etag",
+    "expires",
+    "last-modified",
+    "vary",
+];
+
+// Cache-Control 中的指令，名称为小写，值去掉了引号
+struct Directives(Vec<(String, Option<String>)>);
+
+impl Directives {
+    fn parse<'a>(values: impl Iterator<Item = &'a HeaderValue>) -> Self {
+        let mut directives = Vec::new();
+        for value in values.filter_map(|v| v.to_str().ok()) {
+            for directive in value.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+                let (name, value) = match directive.split_once('=') {
+                    Some((name, value)) => (name, Some(value.trim().trim_matches('"').to_string())),
+                    None => (directive, None),
+                };
+                directives.push((name.trim().to_ascii_lowercase(), value));
+            }
+        }
+        Directives(directives)
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.0.iter().any(|(n, _)| n == name)
+    }
+
+    // 以秒为单位的指令值，如 max-age=60
+    fn seconds(&self, name: &str) -> Option<Duration> {
+        self.0
+            .iter()
+            .find(|(n, _)| n == name)
+            .and_then(|(_, v)| v.as_deref()?.parse().ok())
+            .map(Duration::from_secs)
+    }
+}
+
+// 解析 HTTP 日期，如 "Sun, 06 Nov 1994 08:49:37 GMT"
+fn http_date(value: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    chrono::DateTime::parse_from_rfc2822(value)
+        .ok()
+        .map(|d| d.with_timezone(&chrono::Utc))
+}
+
+// 快照中的响应头
+fn header<'a>(response: &'a ResponseSnapshot, name: &str) -> Option<&'a str> {
+    response
+        .headers
+        .iter()
+        .find(|(k, _)| k == name)
+        .and_then(|(_, v)| v.to_str().ok())
+}
+
+// 快照中 Cache-Control 响应头的指令
+fn directives(response: &ResponseSnapshot) -> Directives {
+    Directives::parse(
+        response
+            .headers
+            .iter()
+            .filter(|(k, _)| k == "cache-control")
+            .map(|(_, v)| v),
+    )
+}
+
+// 发往上游的条件请求头：缓存处理的请求不转发客户端自己的条件请求头，
+// 改为携带缓存条目的验证器（没有可重新验证的条目时为空），由 build_proxy_request 读取
+#[derive(Debug, Clone, Default)]
+pub struct Conditional(pub Vec<(HeaderName, HeaderValue)>);
+
+// 是否为条件请求头
+pub fn is_conditional(name: &HeaderName) -> bool {
+    name == "if-none-match"
+        || name == "if-modified-since"
+        || name == "if-match"
+        || name == "if-unmodified-since"
+        || name == "if-range"
+}
+
+// 缓存的响应保存的位置
+#[derive(Debug, Clone)]
+enum Body {
+    Memory(ResponseSnapshot), // 保存在内存中
+    Disk(PathBuf),            // 保存在磁盘缓存目录的文件中
+}
+
+// 一个缓存的响应
+#[derive(Debug)]
+struct Entry {
+    body: Body,
+    primary: String,       // 主缓存键（不含 Vary 部分），用于维护 Vary 记录
+    uri: String,           // 路径和查询参数，用于失效
+    tags: Vec<String>,     // 响应的标签
+    stored: Instant,       // 保存或最近一次重新验证的时间
+    initial_age: Duration, // 保存时响应已有的年龄（Age 响应头）
+    freshness: Duration,   // 新鲜期
+    stale: Duration, // 过期后仍可使用的时间（stale-while-revalidate 与 stale-if-error 中较长的）
+    size: usize,     // 占用的字节数（响应头与响应体）
+    used: u64,       // 最近一次使用的序号，用于LRU淘汰
+}
+
+impl Entry {
+    fn age(&self) -> Duration {
+        self.initial_age + self.stored.elapsed()
+    }
+
+    fn on_disk(&self) -> bool {
+        matches!(self.body, Body::Disk(_))
+    }
+}
+
+// 内存或磁盘中的条目，各自按大小上限淘汰
+#[derive(Debug, Default)]
+struct Tier {
+    lru: BTreeMap<u64, String>, // 使用序号 -> 完整缓存键，最小的最久未使用
+    size: usize,                // 条目的总字节数
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,             // 完整缓存键 -> 条目
+    vary: HashMap<String, (Vec<String>, usize)>, // 主缓存键 -> (响应 Vary 的请求头, 变体数)
+    memory: Tier,                                // 保存在内存中的条目
+    disk: Tier,                                  // 保存在磁盘上的条目
+    tick: u64,                                   // 使用序号计数器
+    refreshing: HashSet<String>,                 // 正在后台更新的完整缓存键
+}
+
+impl Inner {
+    fn tier(&mut self, disk: bool) -> &mut Tier {
+        if disk {
+            &mut self.disk
+        } else {
+            &mut self.memory
+        }
+    }
+
+    // 删除条目，磁盘上的条目同时删除文件
+    fn remove(&mut self, key: &str) {
+        let Some(entry) = self.entries.remove(key) else {
+            return;
+        };
+        let tier = self.tier(entry.on_disk());
+        tier.lru.remove(&entry.used);
+        tier.size -= entry.size;
+        if let Some((_, count)) = self.vary.get_mut(&entry.primary) {
+            *count -= 1;
+            if *count == 0 {
+                self.vary.remove(&entry.primary);
+            }
+        }
+        if let Body::Disk(file) = &entry.body
+            && let Err(e) = std::fs::remove_file(file)
+        {
+            log::warn!("删除磁盘缓存文件 {} 失败: {}", file.display(), e);
+        }
+    }
+
+    // 加入条目，调用方负责删除同一键的旧条目并预留空间
+    fn add(&mut self, key: String, vary: Vec<String>, mut entry: Entry) {
+        self.tick += 1;
+        entry.used = self.tick;
+        let tier = self.tier(entry.on_disk());
+        tier.lru.insert(entry.used, key.clone());
+        tier.size += entry.size;
+        self.vary
+            .entry(entry.primary.clone())
+            .or_insert_with(|| (vary, 0))
+            .1 += 1;
+        self.entries.insert(key, entry);
+    }
+
+    // 标记条目刚被使用
+    fn touch(&mut self, key: &str) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(entry) = self.entries.get_mut(key) {
+            let old = std::mem::replace(&mut entry.used, tick);
+            let tier = if entry.on_disk() {
+                &mut self.disk
+            } else {
+                &mut self.memory
+            };
+            tier.lru.remove(&old);
+            tier.lru.insert(tick, key.to_string());
+        }
+    }
+}
+
+// 磁盘缓存文件和 Redis 中条目的元数据，保存在第一行
+#[derive(Debug, Serialize, Deserialize)]
+struct DiskMeta {
+    key: String,       // 完整缓存键
+    primary: String,   // 主缓存键
+    uri: String,       // 路径和查询参数
+    vary: Vec<String>, // 响应 Vary 的请求头
+    #[serde(default)]
+    tags: Vec<String>, // 响应的标签
+    status: u16,       // 状态码
+    stored_ms: u64,    // 保存时间(Unix毫秒)
+    age_ms: u64,       // 保存时响应已有的年龄(毫秒)
+    freshness_ms: u64, // 新鲜期(毫秒)
+    #[serde(default)]
+    stale_ms: u64, // 过期后仍可使用的时间(毫秒)
+}
+
+// 条目的存储格式：元数据JSON一行，随后每行一个"名称: 值"响应头，空行后为响应体
+fn encode(meta: &DiskMeta, response: &ResponseSnapshot) -> Vec<u8> {
+    let mut data = serde_json::to_vec(meta).unwrap_or_default();
+    data.push(b'\n');
+    for (name, value) in &response.headers {
+        data.extend_from_slice(name.as_str().as_bytes());
+        data.extend_from_slice(b": ");
+        data.extend_from_slice(value.as_bytes());
+        data.push(b'\n');
+    }
+    data.push(b'\n');
+    data.extend_from_slice(&response.body);
+    data
+}
+
+fn decode(data: web::Bytes) -> Option<(DiskMeta, ResponseSnapshot)> {
+    let (meta, mut rest) = split_line(&data)?;
+    let meta: DiskMeta = serde_json::from_slice(meta).ok()?;
+    let mut headers = Vec::new();
+    loop {
+        let (line, next) = split_line(rest)?;
+        rest = next;
+        if line.is_empty() {
+            break;
+        }
+        let colon = line.iter().position(|b| *b == b':')?;
+        let name = HeaderName::from_bytes(&line[..colon]).ok()?;
+        let value = HeaderValue::from_bytes(line[colon + 1..].trim_ascii_start()).ok()?;
+        headers.push((name, value));
+    }
+    let response = ResponseSnapshot {
+        status: StatusCode::from_u16(meta.status).ok()?,
+        headers,
+        body: data.slice(data.len() - rest.len()..),
+    };
+    Some((meta, response))
+}
+
+// 按换行符切分出第一行
+fn split_line(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let end = data.iter().position(|b| *b == b'\n')?;
+    Some((&data[..end], &data[end + 1..]))
+}
+
+// 只读取磁盘缓存文件的元数据行
+fn read_meta(file: &Path) -> Option<DiskMeta> {
+    let mut line = String::new();
+    std::io::BufReader::new(std::fs::File::open(file).ok()?)
+        .read_line(&mut line)
+        .ok()?;
+    serde_json::from_str(&line).ok()
+}
+
+fn unix_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// Redis 中主缓存键的 Vary 记录；条目的 Redis 键包含记录的 ID，删除记录即可使所有变体失效
+#[derive(Debug, Serialize, Deserialize)]
+struct VaryRecord {
+    vary: Vec<String>, // 响应 Vary 的请求头
+    id: String,        // 变体组 ID
+}
+
+// 磁盘缓存
+#[derive(Debug)]
+struct Disk {
+    dir: PathBuf,      // 缓存目录
+    max_size: usize,   // 总大小上限(字节)
+    min_object: usize, // 保存到磁盘的最小响应体(字节)
+}
+
+// 查找缓存的结果
+pub enum Lookup {
+    Bypass,            // 不经过缓存，直接转发
+    Hit(HttpResponse), // 由缓存应答（新鲜的响应、304，或 only-if-cached 未命中时的504）
+    Fetch(Pending),    // 需要请求上游，得到响应后调用 store
+    // 由过期的响应应答，需要在后台请求上游并调用 refreshed；同一条目已在更新时不再重复更新
+    Stale(HttpResponse, Option<Pending>),
+}
+
+// 等待上游响应的缓存请求
+pub struct Pending {
+    primary: String,                 // 主缓存键
+    key: String,                     // 查找时的完整缓存键，用于后台更新去重
+    stale: Option<ResponseSnapshot>, // 正在重新验证的过期响应
+    age: Duration,                   // 查找时过期响应的年龄
+    freshness: Duration,             // 过期响应的新鲜期
+    looked_up: Instant,              // 查找的时间
+    leader: Option<Leader>,          // 合并请求中负责请求上游的一方
+}
+
+// 合并请求的首个请求：完成或被取消而丢弃时移除登记，并唤醒等待的请求
+struct Leader {
+    fetching: Arc<Mutex<HashMap<String, watch::Receiver<()>>>>,
+    key: String,
+    _done: watch::Sender<()>, // 丢弃时等待者收到通知
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        self.fetching.lock().unwrap().remove(&self.key);
+    }
+}
+
+impl Pending {
+    // 过期响应当前的年龄
+    fn age(&self) -> Duration {
+        self.age + self.looked_up.elapsed()
+    }
+}
+
+// 路由的响应缓存
+#[derive(Debug)]
+pub struct ResponseCache {
+    name: String, // 所属路由的名称
+    config: CacheConfig,
+    max_size: usize,
+    disk: Option<Disk>,
+    redis: Option<RedisClient>,
+    inner: Mutex<Inner>,
+    fetching: Arc<Mutex<HashMap<String, watch::Receiver<()>>>>, // 正在请求上游的完整缓存键
+    hits: AtomicU64,    // 由缓存应答的请求数（含过期和重新验证的响应）
+    lookups: AtomicU64, // 经过缓存的请求数
+}
+
+impl ResponseCache {
+    // 创建缓存；配置了磁盘缓存时创建目录并恢复其中保存的条目
+    pub fn new(name: &str, config: &CacheConfig) -> Result<Self, String> {
+        if config.disk.is_some() && config.redis.is_some() {
+            return Err("磁盘缓存与 Redis 缓存不能同时配置".to_string());
+        }
+        if let Some(method) = config
+            .methods
+            .iter()
+            .find(|m| !m.eq_ignore_ascii_case("GET") && !m.eq_ignore_ascii_case("HEAD"))
+        {
+            return Err(format!(
+                "缓存不支持 {} 方法，methods 只能包含 GET、HEAD",
+                method
+            ));
+        }
+        let disk = config.disk.as_ref().map(|disk| Disk {
+            dir: PathBuf::from(&disk.path),
+            max_size: (disk.max_size_mb * 1024 * 1024) as usize,
+            min_object: (disk.min_object_kb * 1024) as usize,
+        });
+        let cache = ResponseCache {
+            name: name.to_string(),
+            config: config.clone(),
+            max_size: (config.max_size_mb * 1024 * 1024) as usize,
+            disk,
+            redis: config.redis.as_ref().map(RedisClient::new),
+            inner: Mutex::new(Inner::default()),
+            fetching: Arc::default(),
+            hits: AtomicU64::new(0),
+            lookups: AtomicU64::new(0),
+        };
+        if let Some(disk) = &cache.disk {
+            std::fs::create_dir_all(&disk.dir)
+                .map_err(|e| format!("无法创建磁盘缓存目录 {}: {}", disk.dir.display(), e))?;
+            cache.restore(disk)?;
+        }
+        cache.report();
+        Ok(cache)
+    }
+
+    // 从磁盘缓存目录恢复条目：未写完的临时文件和无法读取的文件直接删除，超出上限的按保存时间淘汰
+    fn restore(&self, disk: &Disk) -> Result<(), String> {
+        let files = std::fs::read_dir(&disk.dir)
+            .map_err(|e| format!("无法读取磁盘缓存目录 {}: {}", disk.dir.display(), e))?;
+        let mut found = Vec::new();
+        for file in files.flatten().map(|f| f.path()) {
+            match file.extension().and_then(|e| e.to_str()) {
+                Some("cache") => {}
+                Some("tmp") => {
+                    let _ = std::fs::remove_file(&file);
+                    continue;
+                }
+                _ => continue,
+            }
+            let size = std::fs::metadata(&file).map(|m| m.len() as usize);
+            match (read_meta(&file), size) {
+                (Some(meta), Ok(size)) => found.push((meta, file, size)),
+                _ => {
+                    log::warn!("磁盘缓存文件 {} 无法读取，已删除", file.display());
+                    let _ = std::fs::remove_file(&file);
+                }
+            }
+        }
+        found.sort_by_key(|(meta, _, _)| meta.stored_ms);
+
+        let now = unix_ms(SystemTime::now());
+        let mut inner = self.inner.lock().unwrap();
+        for (meta, file, size) in found {
+            // 同一主缓存键的变体必须使用相同的 Vary，保存时间较早的条目让位
+            if inner
+                .vary
+                .get(&meta.primary)
+                .is_some_and(|(names, _)| *names != meta.vary)
+            {
+                remove_variants(&mut inner, &meta.primary);
+            }
+            inner.remove(&meta.key);
+            self.evict(&mut inner, true, size);
+            // 停机期间同样计入年龄
+            let entry = Entry {
+                body: Body::Disk(file),
+                primary: meta.primary,
+                uri: meta.uri,
+                tags: meta.tags,
+                stored: Instant::now(),
+                initial_age: Duration::from_millis(
+                    meta.age_ms + now.saturating_sub(meta.stored_ms),
+                ),
+                freshness: Duration::from_millis(meta.freshness_ms),
+                stale: Duration::from_millis(meta.stale_ms),
+                size,
+                used: 0,
+            };
+            inner.add(meta.key, meta.vary, entry);
+        }
+        log::info!(
+            "从磁盘缓存目录 {} 恢复了 {} 个条目",
+            disk.dir.display(),
+            inner.disk.lru.len()
+        );
+        Ok(())
+    }
+
+    // 淘汰最久未使用的条目，直到条目数和该层的总大小都能容纳新条目
+    fn evict(&self, inner: &mut Inner, disk: bool, size: usize) {
+        let max_size = match (&self.disk, disk) {
+            (Some(d), true) => d.max_size,
+            _ => self.max_size,
+        };
+        loop {
+            let oldest = if inner.tier(disk).size + size > max_size {
+                inner.tier(disk).lru.values().next()
+            } else if inner.entries.len() >= self.config.max_entries {
+                // 条目数超限时淘汰两层中最久未使用的条目
+                inner
+                    .memory
+                    .lru
+                    .iter()
+                    .next()
+                    .into_iter()
+                    .chain(inner.disk.lru.iter().next())
+                    .min_by_key(|(used, _)| **used)
+                    .map(|(_, key)| key)
+            } else {
+                break;
+            };
+            let Some(oldest) = oldest.cloned() else {
+                break;
+            };
+            let tier = match inner.entries.get(&oldest).is_some_and(Entry::on_disk) {
+                true => "disk",
+                false => "memory",
+            };
+            metrics::counter_inc(
+                "proxy_cache_evictions_total",
+                &[("route", &self.name), ("tier", tier)],
+            );
+            inner.remove(&oldest);
+        }
+    }
+
+    // 主缓存键：方法、路径和查询参数、请求的主机、配置的请求头
+    fn primary_key(&self, req: &HttpRequest) -> String {
+        self.primary_key_for(req.method(), req)
+    }
+
+    fn primary_key_for(&self, method: &Method, req: &HttpRequest) -> String {
+        let mut key = format!("{} {}", method, self.key_uri(req));
+        // 主机另起一行，清除时仍按第一行的 URL 匹配；同一路由下不同主机的响应可能不同
+        // （host_header = "preserve" 时上游按 Host 区分虚拟主机，Location 等也按客户端的主机改写）
+        key.push_str("\nhost:");
+        key.push_str(&request_host(req));
+        for name in self
+            .config
+            .key_headers
+            .iter()
+            .chain(&self.config.key.headers)
+        {
+            key.push('\n');
+            key.push_str(&name.to_ascii_lowercase());
+            key.push(':');
+            key.push_str(header_str(req.headers(), name));
+        }
+        // Cookie 按名称排序，与请求中的顺序无关
+        let mut cookies: Vec<(String, String)> = req
+            .cookies()
+            .iter()
+            .flat_map(|cookies| cookies.iter())
+            .filter(|cookie| name_matches(&self.config.key.cookies, cookie.name()))
+            .map(|cookie| (cookie.name().to_string(), cookie.value().to_string()))
+            .collect();
+        cookies.sort();
+        for (name, value) in cookies {
+            key.push_str("\ncookie ");
+            key.push_str(&name);
+            key.push('=');
+            key.push_str(&value);
+        }
+        key
+    }
+
+    // 缓存键中的路径和查询参数：按配置剔除、筛选和排序查询参数
+    fn key_uri(&self, req: &HttpRequest) -> String {
+        if !self.config.key.rewrites_query() {
+            return request_uri(req);
+        }
+        self.normalize_uri(req.path(), req.query_string())
+    }
+
+    fn normalize_uri(&self, path: &str, query: &str) -> String {
+        let config = &self.config.key;
+        if !config.rewrites_query() {
+            return if query.is_empty() {
+                path.to_string()
+            } else {
+                format!("{}?{}", path, query)
+            };
+        }
+        let mut params: Vec<&str> = query
+            .split('&')
+            .filter(|param| !param.is_empty() && !config.ignore_query)
+            .filter(|param| {
+                let name = param.split('=').next().unwrap_or_default();
+                (config.include_query.is_empty() || name_matches(&config.include_query, name))
+                    && !name_matches(&config.exclude_query, name)
+            })
+            .collect();
+        if config.sort_query {
+            params.sort_unstable();
+        }
+        if params.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}?{}", path, params.join("&"))
+        }
+    }
+
+    // 完整缓存键：主缓存键加上响应 Vary 的请求头的值
+    fn variant_key(primary: &str, vary: &[String], req: &HttpRequest) -> String {
+        let mut key = primary.to_string();
+        for name in vary {
+            key.push_str("\nvary ");
+            key.push_str(name);
+            key.push(':');
+            key.push_str(header_str(req.headers(), name));
+        }
+        key
+    }
+
+    // 查找缓存：新鲜的条目直接应答；过期的条目带上验证器向上游重新验证
+    pub async fn lookup(&self, req: &HttpRequest) -> Lookup {
+        let lookup = self.find(req).await;
+        let Lookup::Fetch(mut pending) = lookup else {
+            return lookup;
+        };
+        if !self.config.coalesce {
+            return Lookup::Fetch(pending);
+        }
+        let claim = {
+            let mut fetching = self.fetching.lock().unwrap();
+            match fetching.get(&pending.key) {
+                Some(receiver) => Err(receiver.clone()),
+                None => {
+                    let (sender, receiver) = watch::channel(());
+                    fetching.insert(pending.key.clone(), receiver);
+                    Ok(sender)
+                }
+            }
+        };
+        match claim {
+            Ok(sender) => {
+                pending.leader = Some(Leader {
+                    fetching: self.fetching.clone(),
+                    key: pending.key.clone(),
+                    _done: sender,
+                });
+                Lookup::Fetch(pending)
+            }
+            Err(mut receiver) => {
+                log::debug!("等待相同请求的上游响应: {} {}", req.method(), req.uri());
+                let timeout = Duration::from_millis(self.config.coalesce_timeout_ms);
+                let _ = tokio::time::timeout(timeout, receiver.changed()).await;
+                // 首个请求完成后重新查找，响应不可缓存或与本请求的变体不同时自行请求上游
+                self.find(req).await
+            }
+        }
+    }
+
+    // 查找一次缓存条目
+    async fn find(&self, req: &HttpRequest) -> Lookup {
+        let method = req.method().as_str();
+        if !self
+            .config
+            .methods
+            .iter()
+            .any(|m| m.eq_ignore_ascii_case(method))
+            || name_matches(&self.config.exclude_paths, req.path())
+        {
+            return Lookup::Bypass;
+        }
+        let request = Directives::parse(req.headers().get_all("cache-control"));
+        if request.has("no-store") {
+            return Lookup::Bypass;
+        }
+        let no_cache = request.has("no-cache")
+            || header_str(req.headers(), "pragma").eq_ignore_ascii_case("no-cache");
+        let primary = self.primary_key(req);
+        let is_fresh = |age: Duration, freshness: Duration| {
+            age < freshness
+                && !no_cache
+                && request.seconds("max-age").is_none_or(|max| age <= max)
+                && request
+                    .seconds("min-fresh")
+                    .is_none_or(|min| freshness.saturating_sub(age) >= min)
+        };
+
+        let (key, found) = match &self.redis {
+            Some(redis) => self.redis_find(redis, &primary, req).await,
+            None => {
+                let mut inner = self.inner.lock().unwrap();
+                let vary = inner
+                    .vary
+                    .get(&primary)
+                    .map(|(names, _)| names.clone())
+                    .unwrap_or_default();
+                let key = Self::variant_key(&primary, &vary, req);
+                let found = inner
+                    .entries
+                    .get(&key)
+                    .map(|entry| (entry.body.clone(), entry.age(), entry.freshness));
+                let retain = inner
+                    .entries
+                    .get(&key)
+                    .map_or(Duration::ZERO, |entry| entry.stale)
+                    .max(self.keep_stale());
+                match found {
+                    Some((_, age, freshness)) if age >= freshness + retain => {
+                        inner.remove(&key);
+                        (key, None)
+                    }
+                    Some((_, age, freshness)) if is_fresh(age, freshness) => {
+                        inner.touch(&key);
+                        (key, found)
+                    }
+                    _ => (key, found),
+                }
+            }
+        };
+        let (stale, age, freshness) = match found {
+            Some((body, age, freshness)) => match self.load(&key, body).await {
+                Some(response) if is_fresh(age, freshness) => {
+                    return Lookup::Hit(self.mark(respond(&response, age, req), "HIT"));
+                }
+                response => (response, age, freshness),
+            },
+            None => (None, Duration::ZERO, Duration::ZERO),
+        };
+        // 客户端没有要求重新验证且没有限制年龄时，stale-while-revalidate 期间先返回过期的响应
+        let revalidate_later = stale.as_ref().is_some_and(|stale| {
+            !no_cache
+                && !request.has("max-age")
+                && !request.has("min-fresh")
+                && !must_revalidate(stale)
+                && age < freshness + self.stale_windows(stale).0
+        });
+        if !revalidate_later && request.has("only-if-cached") {
+            return Lookup::Hit(self.mark(HttpResponse::GatewayTimeout().finish(), "MISS"));
+        }
+        req.extensions_mut().insert(Conditional(
+            stale.as_ref().map(validators).unwrap_or_default(),
+        ));
+        let stale_resp = stale
+            .as_ref()
+            .filter(|_| revalidate_later)
+            .map(|stale| self.mark(respond(stale, age, req), "STALE"));
+        let pending = Pending {
+            primary,
+            key,
+            stale,
+            age,
+            freshness,
+            looked_up: Instant::now(),
+            leader: None,
+        };
+        match stale_resp {
+            Some(resp) => {
+                let refresh = self
+                    .inner
+                    .lock()
+                    .unwrap()
+                    .refreshing
+                    .insert(pending.key.clone());
+                Lookup::Stale(resp, refresh.then_some(pending))
+            }
+            None => Lookup::Fetch(pending),
+        }
+    }
+
+    // 后台更新的上游结果：保存新的响应，并允许该条目再次后台更新
+    pub async fn refreshed(
+        &self,
+        pending: Pending,
+        req: &HttpRequest,
+        result: Result<HttpResponse, crate::ProxyError>,
+    ) {
+        let key = pending.key.clone();
+        match result {
+            Ok(resp) => {
+                log::debug!("后台更新缓存: {} 上游返回 {}", req.uri(), resp.status());
+                self.save(pending, req, resp).await;
+            }
+            Err(e) => log::warn!("后台更新缓存失败: {} {}", req.uri(), e),
+        }
+        self.inner.lock().unwrap().refreshing.remove(&key);
+    }
+
+    // 上游出错时，stale-if-error 期间返回过期的响应
+    pub fn stale_if_error(&self, pending: &Pending, req: &HttpRequest) -> Option<HttpResponse> {
+        self.stale_response(pending, req)
+            .map(|resp| self.mark(resp, "STALE"))
+    }
+
+    fn stale_response(&self, pending: &Pending, req: &HttpRequest) -> Option<HttpResponse> {
+        let stale = pending.stale.as_ref()?;
+        let request = Directives::parse(req.headers().get_all("cache-control"));
+        let window = request
+            .seconds("stale-if-error")
+            .unwrap_or_default()
+            .max(self.stale_windows(stale).1);
+        let age = pending.age();
+        (age < pending.freshness + window).then(|| respond(stale, age, req))
+    }
+
+    // 响应的 stale-while-revalidate 与 stale-if-error 时长，未声明时使用路由配置的默认值
+    fn stale_windows(&self, response: &ResponseSnapshot) -> (Duration, Duration) {
+        let cache_control = directives(response);
+        (
+            cache_control
+                .seconds("stale-while-revalidate")
+                .unwrap_or(Duration::from_secs(self.config.stale_while_revalidate_secs)),
+            cache_control
+                .seconds("stale-if-error")
+                .unwrap_or(Duration::from_secs(self.config.stale_if_error_secs)),
+        )
+    }
+
+    fn keep_stale(&self) -> Duration {
+        Duration::from_secs(self.config.keep_stale_secs)
+    }
+
+    // Redis 中主缓存键的 Vary 记录的键，键中包含路由名称，各路由的条目互不影响
+    fn redis_vary_key(&self, redis: &RedisClient, primary: &str) -> Vec<u8> {
+        redis.key(&format!("cache:{}:vary:{}", self.name, primary))
+    }
+
+    // Redis 中条目的键：变体组 ID 加完整缓存键
+    fn redis_entry_key(&self, redis: &RedisClient, id: &str, key: &str) -> Vec<u8> {
+        redis.key(&format!("cache:{}:entry:{}:{}", self.name, id, key))
+    }
+
+    // Redis 中标签的键，值为带该标签的 Vary 记录的键的集合
+    fn redis_tag_key(&self, redis: &RedisClient, tag: &str) -> Vec<u8> {
+        redis.key(&format!("cache:{}:tag:{}", self.name, tag))
+    }
+
+    // 从 Redis 查找条目：先读取 Vary 记录得到完整缓存键，再读取条目；Redis 不可用时按未命中处理
+    async fn redis_find(
+        &self,
+        redis: &RedisClient,
+        primary: &str,
+        req: &HttpRequest,
+    ) -> (String, Option<(Body, Duration, Duration)>) {
+        let record = match redis.get(&self.redis_vary_key(redis, primary)).await {
+            Ok(record) => record.and_then(|r| serde_json::from_slice::<VaryRecord>(&r).ok()),
+            Err(e) => {
+                log::warn!("读取 Redis 缓存失败: {}", e);
+                None
+            }
+        };
+        let Some(record) = record else {
+            return (Self::variant_key(primary, &[], req), None);
+        };
+        let key = Self::variant_key(primary, &record.vary, req);
+        let entry_key = self.redis_entry_key(redis, &record.id, &key);
+        let data = match redis.get(&entry_key).await {
+            Ok(data) => data,
+            Err(e) => {
+                log::warn!("读取 Redis 缓存失败: {}", e);
+                None
+            }
+        };
+        let found = data
+            .and_then(|data| decode(data.into()))
+            .filter(|(meta, _)| meta.key == key)
+            .map(|(meta, response)| {
+                let now = unix_ms(SystemTime::now());
+                let age = meta.age_ms + now.saturating_sub(meta.stored_ms);
+                (
+                    Body::Memory(response),
+                    Duration::from_millis(age),
+                    Duration::from_millis(meta.freshness_ms),
+                )
+            });
+        (key, found)
+    }
+
+    // 读取条目保存的响应，磁盘文件无法读取时删除该条目
+    async fn load(&self, key: &str, body: Body) -> Option<ResponseSnapshot> {
+        let file = match body {
+            Body::Memory(response) => return Some(response),
+            Body::Disk(file) => file,
+        };
+        let data = tokio::fs::read(&file).await.map_err(|e| e.to_string());
+        match data.and_then(|data| decode(data.into()).ok_or_else(|| "格式错误".to_string())) {
+            Ok((meta, response)) if meta.key == key => Some(response),
+            result => {
+                let reason = result.err().unwrap_or_else(|| "缓存键不符".to_string());
+                log::warn!("磁盘缓存文件 {} 无法读取: {}", file.display(), reason);
+                let mut inner = self.inner.lock().unwrap();
+                if inner
+                    .entries
+                    .get(key)
+                    .is_some_and(|e| matches!(&e.body, Body::Disk(f) if *f == file))
+                {
+                    inner.remove(key);
+                }
+                None
+            }
+        }
+    }
+
+    // 处理上游响应：304 时更新过期的条目并由缓存应答，可缓存的响应保存后返回
+    pub async fn store(
+        &self,
+        pending: Pending,
+        req: &HttpRequest,
+        resp: HttpResponse,
+    ) -> HttpResponse {
+        let (resp, result) = self.save(pending, req, resp).await;
+        self.mark(resp, result)
+    }
+
+    // store 的实现，同时返回缓存结果；后台更新时不计入命中统计
+    async fn save(
+        &self,
+        pending: Pending,
+        req: &HttpRequest,
+        resp: HttpResponse,
+    ) -> (HttpResponse, &'static str) {
+        // 上游返回服务端错误或降级响应时，优先使用过期的响应
+        let failed = matches!(resp.status().as_u16(), 500 | 502 | 503 | 504)
+            || resp.headers().contains_key("x-proxy-fallback");
+        if failed && let Some(stale) = self.stale_response(&pending, req) {
+            log::warn!(
+                "上游返回 {}，使用过期的缓存响应: {}",
+                resp.status(),
+                req.uri()
+            );
+            return (stale, "STALE");
+        }
+        if resp.status() == StatusCode::NOT_MODIFIED
+            && let Some(mut stale) = pending.stale.clone()
+        {
+            // 用 304 中的响应头更新条目（RFC 7234 4.3.4），旧的 Age 不再适用
+            stale.headers.retain(|(k, _)| k != "age");
+            for (name, value) in resp.headers() {
+                if name == "content-length" || name == "transfer-encoding" {
+                    continue;
+                }
+                stale.headers.retain(|(k, _)| k != name);
+                stale.headers.push((name.clone(), value.clone()));
+            }
+            let age = age_header(&stale);
+            self.insert(&pending, req, stale.clone()).await;
+            return (respond(&stale, age, req), "REVALIDATED");
+        }
+        let status = resp.status();
+        let (snapshot, resp) = ResponseSnapshot::capture(resp).await;
+        let stored = self.insert(&pending, req, snapshot.clone()).await;
+        if !stored && pending.stale.is_some() && !status.is_server_error() {
+            // 资源已变化且新响应不可缓存，旧条目不再有效
+            self.remove_variants(&pending.primary).await;
+        }
+        if stored && not_modified(&snapshot, req) {
+            return (respond(&snapshot, Duration::ZERO, req), "MISS");
+        }
+        (resp, "MISS")
+    }
+
+    // 在响应上标明缓存结果并记入指标
+    fn mark(&self, mut resp: HttpResponse, result: &'static str) -> HttpResponse {
+        let lookups = self.lookups.fetch_add(1, Ordering::Relaxed) + 1;
+        let hits = match result {
+            "MISS" => self.hits.load(Ordering::Relaxed),
+            _ => self.hits.fetch_add(1, Ordering::Relaxed) + 1,
+        };
+        metrics::counter_inc(
+            "proxy_cache_requests_total",
+            &[
+                ("route", &self.name),
+                ("result", &result.to_ascii_lowercase()),
+            ],
+        );
+        metrics::gauge_set(
+            "proxy_cache_hit_ratio",
+            &[("route", &self.name)],
+            hits as f64 / lookups as f64,
+        );
+        self.report();
+        if let Ok(name) = HeaderName::try_from(self.config.status_header.as_str()) {
+            resp.headers_mut()
+                .insert(name, HeaderValue::from_static(result));
+        }
+        resp
+    }
+
+    // 更新条目数与占用大小的指标；Redis 中的条目不在本地统计
+    fn report(&self) {
+        if self.redis.is_some() {
+            return;
+        }
+        let inner = self.inner.lock().unwrap();
+        for (name, tier, disk) in [
+            ("memory", &inner.memory, false),
+            ("disk", &inner.disk, true),
+        ] {
+            if disk && self.disk.is_none() {
+                continue;
+            }
+            let labels = [("route", self.name.as_str()), ("tier", name)];
+            metrics::gauge_set("proxy_cache_entries", &labels, tier.lru.len() as f64);
+            metrics::gauge_set("proxy_cache_size_bytes", &labels, tier.size as f64);
+        }
+    }
+
+    // 按 RFC 7234 第3节判断并保存响应，返回是否已保存
+    async fn insert(
+        &self,
+        pending: &Pending,
+        req: &HttpRequest,
+        response: ResponseSnapshot,
+    ) -> bool {
+        let status = response.status.as_u16();
+        let negative =
+            self.config.negative_ttl_secs > 0 && self.config.negative_statuses.contains(&status);
+        if !(self.config.statuses.contains(&status) || negative)
+            || header(&response, "set-cookie").is_some()
+            || header(&response, "x-proxy-fallback").is_some()
+        {
+            return false;
+        }
+        let cache_control = directives(&response);
+        // 携带 Authorization 的请求，只有响应明确允许共享时才保存
+        if req.headers().contains_key("authorization")
+            && !cache_control.has("public")
+            && !cache_control.has("s-maxage")
+            && !cache_control.has("must-revalidate")
+        {
+            return false;
+        }
+        let Some(mut freshness) = self.freshness(&response) else {
+            return false;
+        };
+        // 错误响应最多保存 negative_ttl_secs，响应声明的新鲜期更短时以响应为准
+        if negative {
+            freshness = freshness.min(Duration::from_secs(self.config.negative_ttl_secs));
+        }
+        // 每次都需要重新验证却没有验证器的响应，保存也无法复用
+        if freshness.is_zero()
+            && header(&response, "etag").is_none()
+            && header(&response, "last-modified").is_none()
+        {
+            return false;
+        }
+        let vary: Vec<String> = response
+            .headers
+            .iter()
+            .filter(|(k, _)| k == "vary")
+            .filter_map(|(_, v)| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|name| name.trim().to_ascii_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect();
+        if vary.iter().any(|name| name == "*") {
+            return false;
+        }
+        let key = Self::variant_key(&pending.primary, &vary, req);
+        let size = key.len()
+            + response.body.len()
+            + response
+                .headers
+                .iter()
+                .map(|(k, v)| k.as_str().len() + v.len())
+                .sum::<usize>();
+        // 较大的响应体保存到磁盘，不占用内存
+        let disk = self
+            .disk
+            .as_ref()
+            .filter(|disk| response.body.len() >= disk.min_object);
+        let too_large = self.config.max_object_kb > 0
+            && response.body.len() as u64 > self.config.max_object_kb * 1024;
+        if too_large
+            || size > disk.map_or(self.max_size, |d| d.max_size)
+            || self.config.max_entries == 0
+        {
+            return false;
+        }
+        let initial_age = age_header(&response);
+        let meta = DiskMeta {
+            key: key.clone(),
+            primary: pending.primary.clone(),
+            uri: self.key_uri(req),
+            vary: vary.clone(),
+            tags: self.tags(&response),
+            status: response.status.as_u16(),
+            stored_ms: unix_ms(SystemTime::now()),
+            age_ms: initial_age.as_millis() as u64,
+            freshness_ms: freshness.as_millis() as u64,
+            stale_ms: {
+                let (swr, sie) = self.stale_windows(&response);
+                swr.max(sie).as_millis() as u64
+            },
+        };
+        if let Some(redis) = &self.redis {
+            return self.redis_insert(redis, &meta, &response).await;
+        }
+        let body = match disk {
+            Some(disk) => {
+                // 先写入临时文件再改名，进程中途退出不会留下不完整的缓存文件
+                let name = uuid::Uuid::new_v4().to_string();
+                let file = disk.dir.join(format!("{}.cache", name));
+                let tmp = disk.dir.join(format!("{}.tmp", name));
+                let written = match tokio::fs::write(&tmp, encode(&meta, &response)).await {
+                    Ok(()) => tokio::fs::rename(&tmp, &file).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = written {
+                    log::warn!("写入磁盘缓存文件 {} 失败: {}", file.display(), e);
+                    let _ = tokio::fs::remove_file(&tmp).await;
+                    return false;
+                }
+                Body::Disk(file)
+            }
+            None => Body::Memory(response),
+        };
+
+        let mut inner = self.inner.lock().unwrap();
+        // 响应的 Vary 变化后，按旧 Vary 保存的变体不会再被找到，一并删除
+        if inner
+            .vary
+            .get(&pending.primary)
+            .is_some_and(|(names, _)| *names != vary)
+        {
+            remove_variants(&mut inner, &pending.primary);
+        }
+        inner.remove(&key);
+        self.evict(&mut inner, disk.is_some(), size);
+        let entry = Entry {
+            body,
+            primary: pending.primary.clone(),
+            uri: meta.uri,
+            tags: meta.tags,
+            stored: Instant::now(),
+            initial_age,
+            freshness,
+            stale: Duration::from_millis(meta.stale_ms),
+            size,
+            used: 0,
+        };
+        inner.add(key, vary, entry);
+        true
+    }
+
+    // 条目写入 Redis：过期时间为剩余的新鲜期，再保留过期后仍可使用的时间，带验证器的条目至少保留 keep_stale_secs；
+    // Vary 变化时生成新的变体组 ID，旧的变体不再被找到，随后自然过期
+    async fn redis_insert(
+        &self,
+        redis: &RedisClient,
+        meta: &DiskMeta,
+        response: &ResponseSnapshot,
+    ) -> bool {
+        let mut ttl = Duration::from_millis(meta.freshness_ms.saturating_sub(meta.age_ms));
+        let mut retain = Duration::from_millis(meta.stale_ms);
+        if !validators(response).is_empty() {
+            retain = retain.max(self.keep_stale());
+        }
+        ttl += retain;
+        if ttl.is_zero() {
+            return false;
+        }
+        let vary_key = self.redis_vary_key(redis, &meta.primary);
+        let id = match redis.get(&vary_key).await {
+            Ok(record) => record
+                .and_then(|r| serde_json::from_slice::<VaryRecord>(&r).ok())
+                .filter(|r| r.vary == meta.vary)
+                .map(|r| r.id),
+            Err(e) => {
+                log::warn!("写入 Redis 缓存失败: {}", e);
+                return false;
+            }
+        };
+        let record = VaryRecord {
+            vary: meta.vary.clone(),
+            id: id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+        };
+        let entry_key = self.redis_entry_key(redis, &record.id, &meta.key);
+        let record = serde_json::to_vec(&record).unwrap_or_default();
+        let entry = encode(meta, response);
+        let ttl = ttl.as_millis().to_string();
+        let tag_keys: Vec<Vec<u8>> = meta
+            .tags
+            .iter()
+            .map(|tag| self.redis_tag_key(redis, tag))
+            .collect();
+        let mut commands: Vec<Vec<&[u8]>> = vec![
+            vec![b"SET", &entry_key, &entry, b"PX", ttl.as_bytes()],
+            vec![b"SET", &vary_key, &record, b"PX", ttl.as_bytes()],
+        ];
+        // 标签集合的过期时间随最近写入的条目延长
+        for tag_key in &tag_keys {
+            commands.push(vec![b"SADD", tag_key, &vary_key]);
+            commands.push(vec![b"PEXPIRE", tag_key, ttl.as_bytes()]);
+        }
+        let commands: Vec<&[&[u8]]> = commands.iter().map(Vec::as_slice).collect();
+        let result = redis.pipeline(&commands).await;
+        if let Err(e) = result {
+            log::warn!("写入 Redis 缓存失败: {}", e);
+            return false;
+        }
+        true
+    }
+
+    // 响应的新鲜期（RFC 7234 4.2.1），不可保存时返回None
+    fn freshness(&self, response: &ResponseSnapshot) -> Option<Duration> {
+        let cache_control = directives(response);
+        if cache_control.has("no-store") || cache_control.has("private") {
+            return None;
+        }
+        if cache_control.has("no-cache") {
+            return Some(Duration::ZERO);
+        }
+        if let Some(age) = cache_control
+            .seconds("s-maxage")
+            .or_else(|| cache_control.seconds("max-age"))
+        {
+            return Some(age);
+        }
+        if let Some(expires) = header(response, "expires") {
+            // 无效的 Expires 视为已过期
+            let Some(expires) = http_date(expires) else {
+                return Some(Duration::ZERO);
+            };
+            let date = header(response, "date")
+                .and_then(http_date)
+                .unwrap_or_else(chrono::Utc::now);
+            return Some((expires - date).to_std().unwrap_or(Duration::ZERO));
+        }
+        Some(Duration::from_secs(self.config.ttl_secs))
+    }
+
+    // 删除主缓存键下的所有变体，Redis 中删除 Vary 记录即可
+    async fn remove_variants(&self, primary: &str) {
+        match &self.redis {
+            Some(redis) => {
+                if let Err(e) = redis.del(&[&self.redis_vary_key(redis, primary)]).await {
+                    log::warn!("删除 Redis 缓存失败: {}", e);
+                }
+            }
+            None => remove_variants(&mut self.inner.lock().unwrap(), primary),
+        }
+    }
+
+    // 非安全方法的请求成功后，删除同一 URL 的缓存条目（RFC 7234 4.4）
+    pub async fn invalidate(&self, req: &HttpRequest, status: StatusCode) {
+        if req.method().is_safe() || !(status.is_success() || status.is_redirection()) {
+            return;
+        }
+        // Redis 中按该请求的 key_headers 删除 GET 和 HEAD 两个主缓存键
+        if let Some(redis) = &self.redis {
+            let keys: Vec<Vec<u8>> = [Method::GET, Method::HEAD]
+                .iter()
+                .map(|method| self.redis_vary_key(redis, &self.primary_key_for(method, req)))
+                .collect();
+            let keys: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+            if let Err(e) = redis.del(&keys).await {
+                log::warn!("删除 Redis 缓存失败: {}", e);
+            }
+            return;
+        }
+        let uri = self.key_uri(req);
+        {
+            let mut inner = self.inner.lock().unwrap();
+            let keys: Vec<String> = inner
+                .entries
+                .iter()
+                .filter(|(_, e)| e.uri == uri)
+                .map(|(k, _)| k.clone())
+                .collect();
+            for key in keys {
+                inner.remove(&key);
+            }
+        }
+        self.report();
+    }
+
+    // 响应的标签
+    fn tags(&self, response: &ResponseSnapshot) -> Vec<String> {
+        let name = self.config.tag_header.to_ascii_lowercase();
+        let mut tags: Vec<String> = response
+            .headers
+            .iter()
+            .filter(|(k, _)| k.as_str() == name)
+            .filter_map(|(_, v)| v.to_str().ok())
+            .flat_map(|v| v.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    // 清除匹配的条目，返回清除的条目数（Redis 中为主缓存键数）
+    pub async fn purge(&self, purge: &Purge) -> Result<usize, String> {
+        // URL 按缓存键的规则处理查询参数后比较
+        let url = match purge {
+            Purge::Url(url) => {
+                let (path, query) = url.split_once('?').unwrap_or((url, ""));
+                Some(self.normalize_uri(path, query))
+            }
+            _ => None,
+        };
+        if let Some(redis) = &self.redis {
+            return self.redis_purge(redis, purge, url.as_deref()).await;
+        }
+        let purged = {
+            let mut inner = self.inner.lock().unwrap();
+            let keys: Vec<String> = inner
+                .entries
+                .iter()
+                .filter(|(_, e)| match purge {
+                    Purge::Url(_) => Some(&e.uri) == url.as_ref(),
+                    Purge::Prefix(prefix) => e.uri.starts_with(prefix.as_str()),
+                    Purge::Tag(tag) => e.tags.contains(tag),
+                    Purge::All => true,
+                })
+                .map(|(k, _)| k.clone())
+                .collect();
+            for key in &keys {
+                inner.remove(key);
+            }
+            keys.len()
+        };
+        self.report();
+        Ok(purged)
+    }
+
+    // 清除 Redis 中的条目：删除 Vary 记录即可使其下的所有变体失效，条目随后自然过期。
+    // 按 URL、前缀清除或清空时用 SCAN 遍历该路由的 Vary 记录，按标签清除时读取标签集合
+    async fn redis_purge(
+        &self,
+        redis: &RedisClient,
+        purge: &Purge,
+        url: Option<&str>,
+    ) -> Result<usize, String> {
+        // 随后删除的标签集合，不计入清除的条目数
+        let mut tag_keys = Vec::new();
+        let mut keys = match purge {
+            Purge::Tag(tag) => {
+                let tag_key = self.redis_tag_key(redis, tag);
+                let keys = redis.smembers(&tag_key).await?;
+                tag_keys.push(tag_key);
+                keys
+            }
+            _ => {
+                // 主缓存键为"方法 路径?查询参数"，其后是各请求头；按模式初步筛选后再精确比较 URL
+                let prefix = self.redis_vary_key(redis, "");
+                let mut pattern = glob_escape(&String::from_utf8_lossy(&prefix));
+                let path = match purge {
+                    Purge::Prefix(prefix) => Some(prefix.as_str()),
+                    _ => url,
+                };
+                match path {
+                    Some(path) => pattern.push_str(&format!("* {}*", glob_escape(path))),
+                    None => pattern.push('*'),
+                }
+                let mut keys = redis.scan(pattern.as_bytes()).await?;
+                keys.retain(|key| {
+                    let primary =
+                        String::from_utf8_lossy(key.get(prefix.len()..).unwrap_or_default());
+                    let uri = primary
+                        .split_once(' ')
+                        .and_then(|(_, rest)| rest.split('\n').next())
+                        .unwrap_or_default();
+                    match (purge, url) {
+                        (Purge::Prefix(path), _) => uri.starts_with(path.as_str()),
+                        (_, Some(url)) => uri == url,
+                        _ => true,
+                    }
+                });
+                if matches!(purge, Purge::All) {
+                    let tags = redis.key(&format!("cache:{}:tag:*", glob_escape(&self.name)));
+                    tag_keys = redis.scan(&tags).await?;
+                }
+                keys
+            }
+        };
+        keys.sort();
+        keys.dedup();
+        let mut purged = 0;
+        for batch in keys.chunks(500) {
+            let batch: Vec<&[u8]> = batch.iter().map(Vec::as_slice).collect();
+            purged += redis.del(&batch).await?;
+        }
+        for batch in tag_keys.chunks(500) {
+            let batch: Vec<&[u8]> = batch.iter().map(Vec::as_slice).collect();
+            redis.del(&batch).await?;
+        }
+        Ok(purged as usize)
+    }
+}
+
+// 清除缓存的条件
+#[derive(Debug, Clone)]
+pub enum Purge {
+    Url(String),    // 一个 URL（路径和查询参数）的所有变体
+    Prefix(String), // 路径以该前缀开头的条目
+    Tag(String),    // 带有该标签的条目
+    All,            // 所有条目
+}
+
+// 转义 Redis 模式中的特殊字符
+fn glob_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn remove_variants(inner: &mut Inner, primary: &str) {
+    let keys: Vec<String> = inner
+        .entries
+        .iter()
+        .filter(|(_, e)| e.primary == primary)
+        .map(|(k, _)| k.clone())
+        .collect();
+    for key in keys {
+        inner.remove(&key);
+    }
+}
+
+// 缓存响应的验证器转换为条件请求头
+fn validators(response: &ResponseSnapshot) -> Vec<(HeaderName, HeaderValue)> {
+    let mut headers = Vec::new();
+    for (validator, condition) in [
+        ("etag", "if-none-match"),
+        ("last-modified", "if-modified-since"),
+    ] {
+        if let Some(value) = header(response, validator)
+            && let Ok(value) = HeaderValue::from_str(value)
+        {
+            headers.push((HeaderName::from_static(condition), value));
+        }
+    }
+    headers
+}
+
+// 由缓存的响应应答并附上 Age，客户端的条件请求匹配时返回 304
+fn respond(response: &ResponseSnapshot, age: Duration, req: &HttpRequest) -> HttpResponse {
+    let mut resp = if not_modified(response, req) {
+        let mut resp = HttpResponse::NotModified();
+        for (name, value) in &response.headers {
+            if NOT_MODIFIED_HEADERS.contains(&name.as_str()) {
+                resp.append_header((name.clone(), value.clone()));
+            }
+        }
+        resp.finish()
+    } else {
+        response.to_response(None)
+    };
+    if let Ok(age) = HeaderValue::from_str(&age.as_secs().to_string()) {
+        resp.headers_mut()
+            .insert(HeaderName::from_static("age"), age);
+    }
+    resp
+}
+
+// 响应是否要求过期后必须重新验证（共享缓存中 s-maxage 同样如此）
+fn must_revalidate(response: &ResponseSnapshot) -> bool {
+    let cache_control = directives(response);
+    cache_control.has("must-revalidate")
+        || cache_control.has("proxy-revalidate")
+        || cache_control.has("s-maxage")
+        || cache_control.has("no-cache")
+}
+
+// 客户端的条件请求是否与缓存的响应匹配（If-None-Match 优先于 If-Modified-Since）
+fn not_modified(response: &ResponseSnapshot, req: &HttpRequest) -> bool {
+    if response.status != StatusCode::OK {
+        return false;
+    }
+    if let Some(tags) = req
+        .headers()
+        .get("if-none-match")
+        .and_then(|v| v.to_str().ok())
+    {
+        let Some(etag) = header(response, "etag") else {
+            return false;
+        };
+        // GET/HEAD 使用弱比较
+        let weak = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+        return tags.trim() == "*" || tags.split(',').any(|tag| weak(tag) == weak(etag));
+    }
+    if let Some(since) = req
+        .headers()
+        .get("if-modified-since")
+        .and_then(|v| v.to_str().ok())
+        .and_then(http_date)
+        && let Some(modified) = header(response, "last-modified").and_then(http_date)
+    {
+        return modified <= since;
+    }
+    false
+}
+
+// 响应的 Age 响应头
+fn age_header(response: &ResponseSnapshot) -> Duration {
+    Duration::from_secs(
+        header(response, "age")
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(0),
+    )
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+}
+
+// 请求的主机（HTTP/2 为 :authority），转为小写
+fn request_host(req: &HttpRequest) -> String {
+    let host = req
+        .headers()
+        .get(actix_web::http::header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| req.uri().authority().map(|a| a.as_str()))
+        .unwrap_or_default();
+    host.to_ascii_lowercase()
+}
+
+fn request_uri(req: &HttpRequest) -> String {
+    req.uri()
+        .path_and_query()
+        .map(|pq| pq.as_str().to_string())
+        .unwrap_or_else(|| req.path().to_string())
+}
@@ -0,0 +1,242 @@
+// ==================== 调试报文捕获 ====================
+//
+// 显式启用后记录代理请求与响应的报文体，用于排查问题：JSON报文先按JSONPath规则脱敏字段，
+// 再按正则规则替换敏感内容，最后截断到配置的大小；捕获结果写入日志（目标为 capture），
+// 同时保留最近的若干条，通过管理接口 GET /captures 查看
+
+use crate::redact::REDACTED;
+use actix_web::HttpRequest;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+// 报文捕获配置：对应配置文件中的 [capture]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CaptureConfig {
+    #[serde(default)]
+    pub enabled: bool, // 是否启用报文捕获
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize, // 每个报文体最多记录的字节数
+    #[serde(default = "default_keep")]
+    pub keep: usize, // 在内存中保留的最近捕获数，0表示不保留
+    #[serde(default = "default_true")]
+    pub log: bool, // 是否写入日志
+    #[serde(default)]
+    pub redact_fields: Vec<String>, // JSON字段脱敏规则，如 $.password、$..token、$.items.*.card
+    #[serde(default)]
+    pub redact_patterns: Vec<String>, // 正则脱敏规则，匹配的内容替换为 [REDACTED]
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        CaptureConfig {
+            enabled: false,
+            max_body_bytes: default_max_body_bytes(),
+            keep: default_keep(),
+            log: true,
+            redact_fields: Vec::new(),
+            redact_patterns: Vec::new(),
+        }
+    }
+}
+
+// 以下函数为报文捕获配置提供默认值
+fn default_max_body_bytes() -> usize {
+    4096
+}
+
+fn default_keep() -> usize {
+    100
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// JSONPath中的一段
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String), // .name：对象中的字段
+    Any,         // .* 或 [*]：任意字段或数组元素
+    Descend,     // ..：任意深度
+}
+
+// 解析JSONPath规则，支持 $.a.b、$..name、$.items.*.name 与 $.items[*].name
+fn parse_path(path: &str) -> Result<Vec<Step>, String> {
+    let invalid = || format!("无效的脱敏字段规则: {}", path);
+    let rest = path.strip_prefix('$').ok_or_else(invalid)?;
+    let rest = rest.replace("[*]", ".*");
+    let mut steps = Vec::new();
+    let mut parts = rest.split('.').peekable();
+    // 以 . 开头，第一段是空串
+    if parts.next() != Some("") {
+        return Err(invalid());
+    }
+    while let Some(part) = parts.next() {
+        match part {
+            // 连续两个点表示任意深度，下一段是要匹配的字段
+            "" if parts.peek().is_some_and(|p| !p.is_empty()) => steps.push(Step::Descend),
+            "" => return Err(invalid()),
+            "*" => steps.push(Step::Any),
+            name => steps.push(Step::Key(name.to_string())),
+        }
+    }
+    if steps.is_empty() || steps.last() == Some(&Step::Descend) {
+        return Err(invalid());
+    }
+    Ok(steps)
+}
+
+// 把JSON中匹配路径的值替换为 [REDACTED]
+fn redact_path(value: &mut Value, steps: &[Step]) {
+    let Some((step, rest)) = steps.split_first() else {
+        *value = Value::String(REDACTED.to_string());
+        return;
+    };
+    match step {
+        Step::Key(name) => {
+            if let Some(child) = value.as_object_mut().and_then(|o| o.get_mut(name)) {
+                redact_path(child, rest);
+            }
+        }
+        Step::Any => for_each_child(value, |child| redact_path(child, rest)),
+        Step::Descend => {
+            // 在当前层匹配剩余路径，再对每个子节点继续向下查找
+            redact_path(value, rest);
+            for_each_child(value, |child| redact_path(child, steps));
+        }
+    }
+}
+
+fn for_each_child(value: &mut Value, mut f: impl FnMut(&mut Value)) {
+    match value {
+        Value::Object(map) => map.values_mut().for_each(&mut f),
+        Value::Array(items) => items.iter_mut().for_each(&mut f),
+        _ => {}
+    }
+}
+
+// 报文捕获器，未启用时不记录任何内容
+pub struct Capture {
+    config: CaptureConfig,
+    fields: Vec<Vec<Step>>,         // 解析后的字段脱敏规则
+    patterns: Vec<Regex>,           // 编译后的正则脱敏规则
+    recent: Mutex<VecDeque<Value>>, // 最近的捕获
+}
+
+impl Capture {
+    // 根据配置创建捕获器，脱敏规则无效时返回错误
+    pub fn new(config: &CaptureConfig) -> Result<Self, String> {
+        let fields = config
+            .redact_fields
+            .iter()
+            .map(|path| parse_path(path))
+            .collect::<Result<_, _>>()?;
+        let patterns = config
+            .redact_patterns
+            .iter()
+            .map(|p| Regex::new(p).map_err(|e| format!("无效的脱敏正则 {}: {}", p, e)))
+            .collect::<Result<_, _>>()?;
+        if config.enabled {
+            log::warn!(
+                "已启用报文捕获，每个报文体最多记录 {} 字节",
+                config.max_body_bytes
+            );
+        }
+        Ok(Capture {
+            config: config.clone(),
+            fields,
+            patterns,
+            recent: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    // 记录一次代理请求的请求体与响应体
+    pub fn record(
+        &self,
+        req: &HttpRequest,           // 客户端请求
+        route: &str,                 // 匹配的路由
+        request_body: &[u8],         // 请求体
+        status: u16,                 // 上游状态码
+        response_type: Option<&str>, // 上游响应的 Content-Type
+        response_body: &[u8],        // 响应体
+    ) {
+        if !self.config.enabled {
+            return;
+        }
+        let request_type = req
+            .headers()
+            .get("content-type")
+            .and_then(|v| v.to_str().ok());
+        let (request_body, request_truncated) = self.body(request_body, request_type);
+        let (response_body, response_truncated) = self.body(response_body, response_type);
+        let entry = serde_json::json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "request_id": crate::request_id::current(),
+            "route": route,
+            "method": req.method().as_str(),
+            "uri": req.uri().to_string(),
+            "status": status,
+            "request_body": request_body,
+            "request_truncated": request_truncated,
+            "response_body": response_body,
+            "response_truncated": response_truncated,
+        });
+        if self.config.log {
+            log::info!(target: "capture", "{}", entry);
+        }
+        if self.config.keep > 0 {
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() >= self.config.keep {
+                recent.pop_front();
+            }
+            recent.push_back(entry);
+        }
+    }
+
+    // 最近的捕获，按时间先后排列
+    pub fn recent(&self) -> Vec<Value> {
+        self.recent.lock().unwrap().iter().cloned().collect()
+    }
+
+    // 脱敏并截断报文体，返回文本与是否被截断；非UTF-8的报文只记录大小
+    fn body(&self, body: &[u8], content_type: Option<&str>) -> (String, bool) {
+        if body.is_empty() {
+            return (String::new(), false);
+        }
+        let json = content_type.is_some_and(|t| t.contains("json")) && !self.fields.is_empty();
+        let parsed = json
+            .then(|| serde_json::from_slice::<Value>(body).ok())
+            .flatten();
+        let mut text = match parsed {
+            Some(mut value) => {
+                for steps in &self.fields {
+                    redact_path(&mut value, steps);
+                }
+                value.to_string()
+            }
+            None => match std::str::from_utf8(body) {
+                Ok(text) => text.to_string(),
+                Err(_) => return (format!("<{}字节二进制数据>", body.len()), false),
+            },
+        };
+        for pattern in &self.patterns {
+            if let std::borrow::Cow::Owned(replaced) = pattern.replace_all(&text, REDACTED) {
+                text = replaced;
+            }
+        }
+        let limit = self.config.max_body_bytes;
+        if text.len() <= limit {
+            return (text, false);
+        }
+        // 在字符边界处截断
+        let mut end = limit;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        (text, true)
+    }
+}
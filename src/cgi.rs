@@ -0,0 +1,265 @@
+// ==================== CGI 参数 ====================
+//
+// FastCGI 与 uwsgi 目标都以 CGI 风格的参数描述请求。客户端连接与脚本的参数（REMOTE_ADDR、SERVER_NAME、
+// SCRIPT_NAME 等）在构建请求时确定，作为内部标记（见 marker.rs）带到发送处，客户端无法伪造；请求方法、查询参数、请求头（转换为 HTTP_*）
+// 与请求体在发送时从最终的请求得到，包括构建之后才加上的追踪与截止时间请求头。
+// 与 PROXY 协议相同，带有标记的请求不由 HTTP 客户端发送，而是按目标地址的协议单独建立连接，
+// 重试、对冲、熔断与超时照常生效；应用服务器可以监听 TCP 端口或 Unix 套接字
+
+use crate::ProxyError;
+use crate::forwarded::ForwardedPolicy;
+use actix_web::HttpRequest;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+// 附加的参数；写成列表而不是表，保留参数名的大小写
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Param {
+    pub name: String,  // 参数名，如 APP_ENV
+    pub value: String, // 参数值
+}
+
+// 检查并整理配置中的附加参数，参数名为空时启动失败
+pub fn extra_params(params: &[Param]) -> Result<Vec<(String, String)>, String> {
+    params
+        .iter()
+        .map(|p| match p.name.trim() {
+            "" => Err(format!("参数名不能为空: {:?}", p.value)),
+            name => Ok((name.to_string(), p.value.clone())),
+        })
+        .collect()
+}
+
+// 检查 Unix 套接字配置，当前平台不支持时启动失败
+pub fn check_socket(socket: &Option<String>) -> Result<(), String> {
+    match socket.is_some() && cfg!(not(unix)) {
+        true => Err("当前平台不支持unix套接字".to_string()),
+        false => Ok(()),
+    }
+}
+
+// 需要改用应用服务器协议发送的请求带有该内部请求头，值为标记的键，发送前去掉
+const MARK: &str = "x-rust-proxy-cgi";
+
+// 标记中携带的、发送时无法从请求本身得到的信息
+#[derive(Debug)]
+pub struct Marked {
+    socket: Option<String>,        // Unix 套接字
+    params: Vec<(String, String)>, // 客户端连接与脚本的参数
+    extra: Vec<(String, String)>,  // 配置中的参数，最后写入以覆盖同名参数
+}
+
+impl Marked {
+    pub fn new(
+        socket: Option<String>,
+        params: Vec<(&str, String)>,
+        extra: Vec<(String, String)>,
+    ) -> Self {
+        Marked {
+            socket,
+            params: params
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+            extra,
+        }
+    }
+
+    // 完整的参数：标记中的参数、请求方法与查询参数、请求头，最后是配置中的参数
+    pub fn params(&self, request: &reqwest::Request) -> Vec<(String, String)> {
+        let url = request.url();
+        let request_uri = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let mut params = self.params.clone();
+        params.extend([
+            ("REQUEST_METHOD".to_string(), request.method().to_string()),
+            ("REQUEST_URI".to_string(), request_uri),
+            (
+                "QUERY_STRING".to_string(),
+                url.query().unwrap_or_default().to_string(),
+            ),
+        ]);
+        let body = body(request);
+        if !body.is_empty() {
+            params.push(("CONTENT_LENGTH".to_string(), body.len().to_string()));
+        }
+        for name in request.headers().keys() {
+            let values: Vec<&str> = request
+                .headers()
+                .get_all(name)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .collect();
+            let value = match name == reqwest::header::COOKIE {
+                true => values.join("; "),
+                false => values.join(", "),
+            };
+            let param = match name.as_str() {
+                "content-type" => "CONTENT_TYPE".to_string(),
+                "content-length" => continue,
+                "proxy" => continue, // httpoxy：HTTP_PROXY 会被应用当作代理设置
+                name => format!("HTTP_{}", name.to_ascii_uppercase().replace('-', "_")),
+            };
+            params.retain(|(n, _)| *n != param);
+            params.push((param, value));
+        }
+        for (name, value) in &self.extra {
+            params.retain(|(n, _)| n != name);
+            params.push((name.clone(), value.clone()));
+        }
+        params
+    }
+}
+
+// 客户端连接的参数，与 nginx 的 fastcgi_params/uwsgi_params 相同；REMOTE_ADDR 为客户端信息中的客户端 IP
+pub fn server_params(
+    req: &HttpRequest,
+    forwarded: &ForwardedPolicy,
+) -> Vec<(&'static str, String)> {
+    let (scheme, host) = forwarded.origin(req);
+    let host = host.unwrap_or_default();
+    let server_name = match host.rsplit_once(':') {
+        Some((name, port)) if port.parse::<u16>().is_ok() => name,
+        _ => host.as_str(),
+    };
+    let local = crate::listeners::local(req).addr;
+    let peer = crate::addr::peer_addr(req);
+    let mut params = vec![
+        ("GATEWAY_INTERFACE", "CGI/1.1".to_string()),
+        (
+            "SERVER_SOFTWARE",
+            format!("rust_proxy/{}", env!("CARGO_PKG_VERSION")),
+        ),
+        ("SERVER_PROTOCOL", format!("{:?}", req.version())),
+        ("SERVER_NAME", server_name.to_string()),
+        (
+            "SERVER_ADDR",
+            crate::addr::canonical(local.ip()).to_string(),
+        ),
+        ("SERVER_PORT", local.port().to_string()),
+        (
+            "REMOTE_ADDR",
+            forwarded
+                .client_ip(req)
+                .map_or(String::new(), |ip| ip.to_string()),
+        ),
+        (
+            "REMOTE_PORT",
+            peer.map_or(String::new(), |p| p.port().to_string()),
+        ),
+        ("HTTP_HOST", host.clone()),
+    ];
+    if scheme == "https" {
+        params.push(("HTTPS", "on".to_string()));
+    }
+    params.push(("REQUEST_SCHEME", scheme));
+    params
+}
+
+// 为发往应用服务器的请求加上标记
+pub fn mark(
+    builder: reqwest::RequestBuilder,
+    req: &HttpRequest,
+    marked: Marked,
+) -> reqwest::RequestBuilder {
+    crate::marker::insert(req, builder, MARK, marked)
+}
+
+// 取出请求的标记，没有标记的请求照常发送
+pub fn take(request: &mut reqwest::Request) -> Option<Arc<Marked>> {
+    crate::marker::take(request, MARK)
+}
+
+// 解码路径中的 %XX，与 nginx 的 $document_uri 相同；SCRIPT_NAME 与 PATH_INFO 使用解码后的路径
+pub fn decode_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// 请求体，发往上游的请求体总是已经完整读取
+pub fn body(request: &reqwest::Request) -> &[u8] {
+    request
+        .body()
+        .and_then(|b| b.as_bytes())
+        .unwrap_or_default()
+}
+
+// 按目标地址的协议发送带有标记的请求；请求的总超时覆盖整个过程
+pub async fn send(
+    marked: Arc<Marked>,
+    request: reqwest::Request,
+) -> Result<reqwest::Response, ProxyError> {
+    let exchange = async {
+        match request.url().scheme() {
+            crate::fastcgi::PROTOCOL => crate::fastcgi::send(&marked, &request).await,
+            crate::uwsgi::PROTOCOL => crate::uwsgi::send(&marked, &request).await,
+            scheme => Err(ProxyError::RequestBuilderError(format!(
+                "不支持的应用服务器协议: {}",
+                scheme
+            ))),
+        }
+    };
+    let Some(limit) = request.timeout().copied() else {
+        return exchange.await;
+    };
+    tokio::time::timeout(limit, exchange).await.map_err(|_| {
+        ProxyError::UpstreamTimeout(format!("{}毫秒内未收到响应", limit.as_millis()))
+    })?
+}
+
+// 与应用服务器的连接
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+// 连接标记中的 Unix 套接字，没有时连接目标地址的主机与端口
+pub async fn connect(marked: &Marked, url: &reqwest::Url) -> Result<Box<dyn Stream>, ProxyError> {
+    match &marked.socket {
+        #[cfg(unix)]
+        Some(socket) => match tokio::net::UnixStream::connect(socket).await {
+            Ok(stream) => Ok(Box::new(stream)),
+            Err(e) => Err(ProxyError::UpstreamConnectFailed(format!(
+                "连接 {} 失败: {}",
+                socket, e
+            ))),
+        },
+        #[cfg(not(unix))]
+        Some(_) => Err(ProxyError::UpstreamConnectFailed(
+            "当前平台不支持unix套接字".to_string(),
+        )),
+        None => {
+            let host = url.host_str().unwrap_or_default();
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            let port = url.port().unwrap_or_default();
+            match tokio::net::TcpStream::connect((host, port)).await {
+                Ok(stream) => Ok(Box::new(stream)),
+                Err(e) => Err(ProxyError::UpstreamConnectFailed(format!(
+                    "连接 {} 失败: {}",
+                    crate::addr::join(host, port),
+                    e
+                ))),
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+// ==================== 命令行参数 ====================
+//
+// 子命令：run（默认，启动代理）、check（校验配置后退出）、print-config（输出生效的配置）、
+// init（生成配置模板）、version（输出版本），以及 Windows 上的 install-service、uninstall-service 与
+// run-service（由服务控制管理器调用）。
+// 选项覆盖配置文件中的对应项，加载和热加载配置时都会应用，例如不写配置文件直接运行
+// `rust_proxy --target http://localhost:3000`；参数无效时输出用法并以状态码 2 退出
+
+use std::sync::OnceLock;
+
+// 子命令
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Command {
+    #[default]
+    Run, // 启动代理
+    Check,            // 校验配置
+    PrintConfig,      // 输出生效的配置
+    Init,             // 生成配置模板
+    Version,          // 输出版本
+    Help,             // 输出用法
+    InstallService,   // 注册为 Windows 服务
+    UninstallService, // 删除 Windows 服务
+    RunService,       // 作为 Windows 服务运行
+}
+
+// 解析后的命令行参数
+#[derive(Debug, Clone, Default)]
+pub struct Cli {
+    pub command: Command,
+    pub config: Option<String>,    // --config：配置文件路径
+    pub port: Option<u16>,         // --port：监听端口
+    pub target: Option<String>,    // --target：目标服务器URL，如 http://localhost:3000
+    pub log_level: Option<String>, // --log-level：日志过滤规则，优先于 RUST_LOG
+    pub profile: Option<String>,   // --profile：配置环境，如 production，优先于 APP_ENV
+    pub force: bool,               // --force：init 时覆盖已存在的文件
+    pub daemon: bool,              // --daemon：转入后台运行（Unix）
+    pub pid_file: Option<String>,  // --pid-file：写入进程号的文件
+}
+
+static CLI: OnceLock<Cli> = OnceLock::new();
+
+// 解析进程的命令行参数，main 开始时调用一次
+pub fn init() -> Result<&'static Cli, String> {
+    let cli = parse(std::env::args().skip(1))?;
+    Ok(CLI.get_or_init(|| cli))
+}
+
+// 当前的命令行参数，未解析时视为没有参数
+pub fn args() -> &'static Cli {
+    CLI.get_or_init(Cli::default)
+}
+
+// 解析参数，选项的值可以写成 "--port 8080" 或 "--port=8080"
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Cli, String> {
+    let mut cli = Cli::default();
+    let mut command = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value)),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| match inline {
+            Some(value) => Ok(value.to_string()),
+            None => args.next().ok_or(format!("选项 {} 需要一个值", name)),
+        };
+        match name.as_str() {
+            "-c" | "--config" => cli.config = Some(value(&name)?),
+            "-p" | "--port" => {
+                let port = value(&name)?;
+                cli.port = Some(port.parse().map_err(|_| format!("无效的端口: {}", port))?);
+            }
+            "-t" | "--target" => {
+                let target = value(&name)?;
+                parse_target(&target)?;
+                cli.target = Some(target);
+            }
+            "-l" | "--log-level" => cli.log_level = Some(value(&name)?),
+            "--profile" => {
+                let profile = value(&name)?;
+                validate_profile(&profile)?;
+                cli.profile = Some(profile);
+            }
+            "-h" | "--help" => command = Some(Command::Help),
+            "-V" | "--version" => command = Some(Command::Version),
+            "--check" => command = Some(Command::Check),
+            "--force" => cli.force = true,
+            "-d" | "--daemon" => cli.daemon = true,
+            "--pid-file" => cli.pid_file = Some(value(&name)?),
+            _ if name.starts_with('-') => return Err(format!("未知的选项: {}", name)),
+            "run" | "check" | "print-config" | "init" | "version" | "help" | "install-service"
+            | "uninstall-service" | "run-service"
+                if command.is_none() =>
+            {
+                command = Some(match name.as_str() {
+                    "run" => Command::Run,
+                    "check" => Command::Check,
+                    "print-config" => Command::PrintConfig,
+                    "init" => Command::Init,
+                    "version" => Command::Version,
+                    "install-service" => Command::InstallService,
+                    "uninstall-service" => Command::UninstallService,
+                    "run-service" => Command::RunService,
+                    _ => Command::Help,
+                });
+            }
+            _ => return Err(format!("未知的子命令或多余的参数: {}", name)),
+        }
+    }
+    cli.command = command.unwrap_or(Command::Run);
+    if cli.daemon && cli.command != Command::Run {
+        return Err("--daemon 只能用于 run".to_string());
+    }
+    Ok(cli)
+}
+
+// 拆分目标服务器URL为协议、主机和端口，端口缺省按协议取 80/443
+pub fn parse_target(target: &str) -> Result<(String, String, u16), String> {
+    let url =
+        reqwest::Url::parse(target).map_err(|e| format!("无效的目标URL {}: {}", target, e))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(format!("目标URL只支持 http 或 https: {}", target));
+    }
+    if url.path() != "/" || url.query().is_some() {
+        return Err(format!("目标URL不能包含路径或查询参数: {}", target));
+    }
+    let host = url
+        .host_str()
+        .ok_or(format!("目标URL缺少主机: {}", target))?
+        .trim_matches(|c| c == '[' || c == ']')
+        .to_string();
+    let port = url
+        .port_or_known_default()
+        .ok_or(format!("目标URL缺少端口: {}", target))?;
+    Ok((url.scheme().to_string(), host, port))
+}
+
+// 配置环境名只能包含字母、数字、- 和 _，用于拼接覆盖文件名
+pub fn validate_profile(profile: &str) -> Result<(), String> {
+    let valid = !profile.is_empty()
+        && profile
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    match valid {
+        true => Ok(()),
+        false => Err(format!("无效的配置环境名: {:?}", profile)),
+    }
+}
+
+// 用法说明
+pub fn usage() -> String {
+    format!(
+        "rust_proxy {}\n\
+         \n\
+         用法: rust_proxy [子命令] [选项]\n\
+         \n\
+         子命令:\n\
+         \x20 run            启动代理（默认）\n\
+         \x20 check          校验配置，有效时以状态码 0 退出\n\
+         \x20 print-config   以 JSON 输出生效的配置（含默认值，密钥已脱敏）\n\
+         \x20 init           生成带注释的配置模板（写入 --config 指定的文件，缺省 config.toml）\n\
+         \x20 version        输出版本\n\
+         \x20 install-service   注册为 Windows 服务，以当前的 --config 等选项启动（需要管理员权限）\n\
+         \x20 uninstall-service 停止并删除 Windows 服务\n\
+         \n\
+         选项:\n\
+         \x20 -c, --config <PATH>      配置文件（.toml/.yaml/.yml/.json），缺省查找当前目录\n\
+         \x20 -p, --port <PORT>        监听端口，覆盖 [server] port\n\
+         \x20 -t, --target <URL>       目标服务器，如 http://localhost:3000，覆盖 [target]\n\
+         \x20 -l, --log-level <SPEC>   日志过滤规则，如 debug 或 info,rust_proxy::routes=debug\n\
+         \x20     --profile <NAME>     配置环境，叠加 config.<NAME>.toml 等覆盖文件，优先于 APP_ENV\n\
+         \x20     --check              同 check 子命令\n\
+         \x20     --force              init 时覆盖已存在的文件\n\
+         \x20 -d, --daemon             转入后台运行（Unix），监听就绪后前台进程退出\n\
+         \x20     --pid-file <PATH>    写入进程号，文件中的进程仍在运行时拒绝启动\n\
+         \x20 -h, --help               输出用法\n\
+         \x20 -V, --version            输出版本\n",
+        env!("CARGO_PKG_VERSION")
+    )
+}
@@ -0,0 +1,259 @@
+// ==================== 响应组合 ====================
+//
+// 路由配置 [routes.compose] 后成为一个虚拟的接口：每个请求并发发出配置中的多个子请求，
+// 各子请求返回的 JSON 按名称放在一起，再按 response 模板（见 json_template.rs）组织成一个响应返回，
+// 前端一次请求即可拿到原本需要调用多个后端接口的数据，相当于轻量的 BFF。
+// 子请求的路径模板中 $query.名称 取客户端的查询参数、$header.名称 取请求头、$path 取请求路径去掉
+// 路由前缀之后的部分，值按 URL 编码；子请求与普通转发一样带上客户端的请求头并经过请求头处理。
+// 必需的子请求失败（连接失败、超时或非 2xx）时返回 502，optional 的子请求失败时其值为 null
+
+use crate::json_template::{JsonTemplate, Segment, Template};
+use crate::{ProxyError, TargetConfig};
+use actix_web::{HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// 响应组合配置：对应路由中的 [routes.compose]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ComposeConfig {
+    pub requests: Vec<SubRequestConfig>, // 子请求，并发发出
+    #[serde(default)]
+    pub response: Option<Value>, // 响应模板，缺省时返回以子请求名称为键的对象
+}
+
+// 一个子请求
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubRequestConfig {
+    pub name: String, // 名称，响应模板中以 $名称 引用其结果
+    pub path: String, // 路径与查询参数的模板，如 "/users/$query.id?fields=name"
+    #[serde(default = "default_method")]
+    pub method: String, // 请求方法
+    #[serde(default)]
+    pub target: Option<TargetConfig>, // 目标服务器，缺省在路由的目标服务器中轮询选择
+    #[serde(default)]
+    pub body: bool, // 是否带上客户端的请求体
+    #[serde(default)]
+    pub optional: bool, // 失败时结果为 null，不影响整个响应
+}
+
+// 以下函数为响应组合提供默认值
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+// 编译后的子请求
+#[derive(Debug)]
+struct SubRequest {
+    name: String,
+    method: reqwest::Method,
+    path: Template,
+    target: Option<TargetConfig>,
+    body: bool,
+    optional: bool,
+}
+
+// 按客户端请求渲染好的子请求，由调用方构建并发送
+#[derive(Debug)]
+pub struct Planned<'a> {
+    pub path: String,                     // 渲染后的路径与查询参数
+    pub target: Option<&'a TargetConfig>, // 子请求指定的目标服务器
+    pub body: bool,                       // 是否带上客户端的请求体
+    method: &'a reqwest::Method,
+}
+
+impl Planned<'_> {
+    // 改为子请求的方法；不带请求体时去掉客户端的 Content-Type
+    pub fn prepare(
+        &self,
+        builder: reqwest::RequestBuilder,
+    ) -> Result<reqwest::RequestBuilder, ProxyError> {
+        let (client, request) = builder.build_split();
+        let mut request = request?;
+        *request.method_mut() = self.method.clone();
+        if !self.body {
+            request.headers_mut().remove(reqwest::header::CONTENT_TYPE);
+        }
+        Ok(reqwest::RequestBuilder::from_parts(client, request))
+    }
+}
+
+// 路由的响应组合设置
+#[derive(Debug)]
+pub struct Compose {
+    requests: Vec<SubRequest>,
+    response: Option<JsonTemplate>,
+}
+
+impl Compose {
+    // 从配置构建，没有子请求、名称为空或重复、路径模板或方法无效时启动失败
+    pub fn new(config: &ComposeConfig) -> Result<Self, String> {
+        if config.requests.is_empty() {
+            return Err("compose 至少需要一个子请求".to_string());
+        }
+        let mut requests: Vec<SubRequest> = Vec::new();
+        for sub in &config.requests {
+            let name = sub.name.trim();
+            if name.is_empty() || name.contains(['.', '|', '$']) {
+                return Err(format!("无效的子请求名称: {:?}", sub.name));
+            }
+            if requests.iter().any(|r| r.name == name) {
+                return Err(format!("子请求名称重复: {}", name));
+            }
+            if !sub.path.starts_with('/') {
+                return Err(format!("子请求 {} 的路径必须以/开头: {}", name, sub.path));
+            }
+            let path = Template::parse(&sub.path)?;
+            if let Some(field) = path.fields().find(|f| {
+                !matches!(
+                    (f[0].as_str(), f.len()),
+                    ("path", 1) | ("query", 2..) | ("header", 2..)
+                )
+            }) {
+                return Err(format!(
+                    "子请求 {} 的路径中的字段 ${{{}}} 应为 path、query.名称 或 header.名称",
+                    name,
+                    field.join(".")
+                ));
+            }
+            let method =
+                reqwest::Method::from_bytes(sub.method.trim().to_ascii_uppercase().as_bytes())
+                    .map_err(|_| format!("子请求 {} 的请求方法无效: {}", name, sub.method))?;
+            requests.push(SubRequest {
+                name: name.to_string(),
+                method,
+                path,
+                target: sub.target.clone(),
+                body: sub.body,
+                optional: sub.optional,
+            });
+        }
+        Ok(Compose {
+            requests,
+            response: config
+                .response
+                .as_ref()
+                .map(JsonTemplate::parse)
+                .transpose()?,
+        })
+    }
+
+    // 子请求指定的目标服务器，用于上游代理与配置校验
+    pub fn targets(config: &ComposeConfig) -> impl Iterator<Item = &TargetConfig> {
+        config.requests.iter().filter_map(|r| r.target.as_ref())
+    }
+
+    // 按客户端请求渲染每个子请求的路径；模板引用的查询参数或请求头不存在时返回 400
+    pub fn plan(&self, req: &HttpRequest, prefix: &str) -> Result<Vec<Planned<'_>>, ProxyError> {
+        let query = actix_web::web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+            .map(|q| q.into_inner())
+            .unwrap_or_default();
+        let rest = req.path().strip_prefix(prefix).unwrap_or_default();
+        let mut planned = Vec::new();
+        for sub in &self.requests {
+            let mut path = String::new();
+            for segment in &sub.path.segments {
+                let field = match segment {
+                    Segment::Literal(s) => {
+                        path.push_str(s);
+                        continue;
+                    }
+                    Segment::Field(field, _) => field,
+                };
+                let name = field[1..].join(".");
+                let value = match field[0].as_str() {
+                    "path" => Some(rest.trim_start_matches('/')),
+                    "query" => query
+                        .iter()
+                        .find(|(k, _)| *k == name)
+                        .map(|(_, v)| v.as_str()),
+                    _ => req.headers().get(&name).and_then(|v| v.to_str().ok()),
+                };
+                let Some(value) = value else {
+                    return Err(ProxyError::RequestBuilderError(format!(
+                        "子请求 {} 缺少 {}",
+                        sub.name,
+                        field.join(".")
+                    )));
+                };
+                match field[0].as_str() {
+                    "path" => path.push_str(value), // 已经是编码后的路径
+                    _ => path.push_str(&encode(value)),
+                }
+            }
+            planned.push(Planned {
+                path,
+                target: sub.target.as_ref(),
+                body: sub.body,
+                method: &sub.method,
+            });
+        }
+        Ok(planned)
+    }
+
+    // 读取各子请求的结果并按模板组合为响应；results 与子请求的顺序相同
+    pub async fn respond(
+        &self,
+        route: &str,
+        results: Vec<Result<reqwest::Response, ProxyError>>,
+    ) -> Result<HttpResponse, ProxyError> {
+        let mut values = Map::new();
+        let mut failures = Vec::new();
+        for (sub, result) in self.requests.iter().zip(results) {
+            let value = match result {
+                Ok(resp) if resp.status().is_success() => match resp.bytes().await {
+                    // 不是 JSON 的响应体作为字符串
+                    Ok(body) => Ok(serde_json::from_slice(&body).unwrap_or_else(|_| {
+                        Value::String(String::from_utf8_lossy(&body).into_owned())
+                    })),
+                    Err(e) => Err(format!("读取响应体失败: {}", e)),
+                },
+                Ok(resp) => Err(format!("状态码 {}", resp.status().as_u16())),
+                Err(e) => Err(e.to_string()),
+            };
+            let outcome = match (&value, sub.optional) {
+                (Ok(_), _) => "ok",
+                (Err(_), true) => "skipped",
+                (Err(_), false) => "failed",
+            };
+            crate::metrics::counter_inc(
+                "proxy_compose_requests_total",
+                &[
+                    ("route", route),
+                    ("request", &sub.name),
+                    ("result", outcome),
+                ],
+            );
+            let value = value.unwrap_or_else(|e| {
+                log::warn!("路由 {} 的子请求 {} 失败: {}", route, sub.name, e);
+                if !sub.optional {
+                    failures.push(format!("{}: {}", sub.name, e));
+                }
+                Value::Null
+            });
+            values.insert(sub.name.clone(), value);
+        }
+        if !failures.is_empty() {
+            return Err(ProxyError::CompositionFailed(failures.join("; ")));
+        }
+        let values = Value::Object(values);
+        let body = match &self.response {
+            Some(template) => template.render(&values),
+            None => values,
+        };
+        Ok(HttpResponse::Ok().json(body))
+    }
+}
+
+// 按 URL 编码模板中的值，只保留不需要编码的字符
+fn encode(value: &str) -> String {
+    let mut out = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(byte as char)
+            }
+            byte => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
@@ -0,0 +1,310 @@
+// ==================== 响应压缩 ====================
+//
+// 上游没有压缩响应时，[compression] 按客户端的 Accept-Encoding 用 br、zstd 或 gzip 压缩后再返回，
+// 后端不需要改动即可节省带宽。客户端同时接受多种编码时取 q 值最高的，q 值相同时按 encodings 的顺序；
+// 只压缩 types 中的内容类型、不小于 min_size 的响应，已有 Content-Encoding、带 Cache-Control: no-transform
+// 的响应以及 204、206、304 与 HEAD 请求的响应原样返回。可以压缩的响应都带上 Vary: Accept-Encoding，
+// 压缩后强 ETag 改为弱 ETag；压缩后没有变小时返回原始响应体
+
+use actix_web::HttpRequest;
+use actix_web::HttpResponse;
+use actix_web::body::MessageBody;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+// 响应压缩配置：对应配置文件中的 [compression]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct CompressionConfig {
+    #[serde(default)]
+    pub enabled: bool, // 是否压缩响应
+    #[serde(default = "default_encodings")]
+    pub encodings: Vec<String>, // 使用的编码，q 值相同时按此顺序优先
+    #[serde(default = "default_min_size")]
+    pub min_size: usize, // 小于该字节数的响应不压缩
+    #[serde(default = "default_types")]
+    pub types: Vec<String>, // 压缩的内容类型，不含参数，可以用 text/* 匹配一类
+    #[serde(default)]
+    pub levels: Levels, // 各编码的压缩级别
+}
+
+// 各编码的压缩级别
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Levels {
+    #[serde(default = "default_gzip_level")]
+    pub gzip: u32, // 0-9
+    #[serde(default = "default_br_level")]
+    pub br: u32, // 0-11
+    #[serde(default = "default_zstd_level")]
+    pub zstd: i32, // 1-22
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Levels {
+            gzip: default_gzip_level(),
+            br: default_br_level(),
+            zstd: default_zstd_level(),
+        }
+    }
+}
+
+// 以下函数为响应压缩提供默认值
+fn default_encodings() -> Vec<String> {
+    vec!["br".to_string(), "zstd".to_string(), "gzip".to_string()]
+}
+
+fn default_min_size() -> usize {
+    1024
+}
+
+fn default_types() -> Vec<String> {
+    [
+        "text/*",
+        "application/javascript",
+        "application/json",
+        "application/xml",
+        "application/wasm",
+        "image/svg+xml",
+    ]
+    .iter()
+    .map(|t| t.to_string())
+    .collect()
+}
+
+fn default_gzip_level() -> u32 {
+    6
+}
+
+fn default_br_level() -> u32 {
+    4
+}
+
+fn default_zstd_level() -> i32 {
+    3
+}
+
+// 支持的编码
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    fn parse(name: &str) -> Option<Encoding> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "br" => Some(Encoding::Brotli),
+            "zstd" => Some(Encoding::Zstd),
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+}
+
+// 响应压缩策略
+#[derive(Debug)]
+pub struct Compression {
+    enabled: bool,
+    encodings: Vec<Encoding>,
+    min_size: usize,
+    types: Vec<String>, // 小写
+    levels: Levels,
+}
+
+impl Compression {
+    // 从配置构建，编码未知或压缩级别超出范围时启动失败
+    pub fn new(config: &CompressionConfig) -> Result<Self, String> {
+        let encodings = config
+            .encodings
+            .iter()
+            .map(|name| {
+                Encoding::parse(name)
+                    .ok_or_else(|| format!("不支持的压缩编码: {}（可选: br、zstd、gzip）", name))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let levels = &config.levels;
+        if levels.gzip > 9 {
+            return Err(format!("gzip 压缩级别必须在 0-9 之间: {}", levels.gzip));
+        }
+        if levels.br > 11 {
+            return Err(format!("br 压缩级别必须在 0-11 之间: {}", levels.br));
+        }
+        if !(1..=22).contains(&levels.zstd) {
+            return Err(format!("zstd 压缩级别必须在 1-22 之间: {}", levels.zstd));
+        }
+        Ok(Compression {
+            enabled: config.enabled && !encodings.is_empty(),
+            encodings,
+            min_size: config.min_size,
+            types: config
+                .types
+                .iter()
+                .map(|t| t.trim().to_ascii_lowercase())
+                .collect(),
+            levels: levels.clone(),
+        })
+    }
+
+    // 按 Accept-Encoding 选择编码：q 值最高的，相同时按配置的顺序；客户端都不接受时返回 None
+    fn negotiate(&self, req: &HttpRequest) -> Option<Encoding> {
+        let mut accepted: Vec<(&str, f32)> = Vec::new();
+        for value in req.headers().get_all(header::ACCEPT_ENCODING) {
+            let Ok(value) = value.to_str() else { continue };
+            for item in value.split(',') {
+                let mut parts = item.split(';');
+                let name = parts.next().unwrap_or("").trim();
+                let q = parts
+                    .filter_map(|p| p.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                if !name.is_empty() {
+                    accepted.push((name, q));
+                }
+            }
+        }
+        let quality = |encoding: Encoding| {
+            let named = accepted
+                .iter()
+                .find(|(name, _)| Encoding::parse(name) == Some(encoding));
+            let any = accepted.iter().find(|(name, _)| *name == "*");
+            named.or(any).map(|(_, q)| *q).unwrap_or(0.0)
+        };
+        let mut best: Option<(Encoding, f32)> = None;
+        for encoding in &self.encodings {
+            let q = quality(*encoding);
+            if q > 0.0 && best.is_none_or(|(_, b)| q > b) {
+                best = Some((*encoding, q));
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+
+    // 内容类型是否需要压缩
+    fn compressible(&self, resp: &HttpResponse) -> bool {
+        let media_type = resp
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .map(|v| v.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        !media_type.is_empty()
+            && self.types.iter().any(|t| match t.strip_suffix('*') {
+                Some(prefix) => media_type.starts_with(prefix),
+                None => *t == media_type,
+            })
+    }
+
+    fn compress(&self, encoding: Encoding, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match encoding {
+            Encoding::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(
+                    Vec::new(),
+                    flate2::Compression::new(self.levels.gzip),
+                );
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            Encoding::Brotli => {
+                let mut encoder =
+                    brotli::CompressorWriter::new(Vec::new(), 4096, self.levels.br, 22);
+                encoder.write_all(body)?;
+                encoder.flush()?;
+                Ok(encoder.into_inner())
+            }
+            Encoding::Zstd => zstd::bulk::compress(body, self.levels.zstd),
+        }
+    }
+
+    // 按需压缩响应，不需要或无法压缩时原样返回
+    pub fn apply(&self, req: &HttpRequest, resp: HttpResponse) -> HttpResponse {
+        let status = resp.status();
+        if !self.enabled
+            || req.method() == Method::HEAD
+            || status.is_informational()
+            || matches!(
+                status,
+                StatusCode::NO_CONTENT | StatusCode::PARTIAL_CONTENT | StatusCode::NOT_MODIFIED
+            )
+            || resp.headers().contains_key(header::CONTENT_ENCODING)
+            || no_transform(&resp)
+            || !self.compressible(&resp)
+        {
+            return resp;
+        }
+        // 代理的响应体已完整读取，流式的响应体原样返回
+        let (mut resp, body) = resp.into_parts();
+        let bytes = match body.try_into_bytes() {
+            Ok(bytes) => bytes,
+            Err(body) => return resp.set_body(body),
+        };
+        if bytes.len() < self.min_size {
+            return resp.set_body(bytes).map_into_boxed_body();
+        }
+        add_vary(&mut resp);
+        let Some(encoding) = self.negotiate(req) else {
+            return resp.set_body(bytes).map_into_boxed_body();
+        };
+        let compressed = match self.compress(encoding, &bytes) {
+            Ok(compressed) if compressed.len() < bytes.len() => compressed,
+            Ok(_) => return resp.set_body(bytes).map_into_boxed_body(),
+            Err(e) => {
+                log::warn!("{} 压缩响应失败，返回原始响应体: {}", encoding.name(), e);
+                return resp.set_body(bytes).map_into_boxed_body();
+            }
+        };
+        crate::metrics::counter_inc(
+            "proxy_compressed_responses_total",
+            &[("encoding", encoding.name())],
+        );
+        crate::metrics::counter_add(
+            "proxy_compression_saved_bytes_total",
+            &[("encoding", encoding.name())],
+            (bytes.len() - compressed.len()) as u64,
+        );
+        let headers = resp.headers_mut();
+        headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.name()),
+        );
+        headers.remove(header::CONTENT_LENGTH);
+        if let Some(etag) = headers.get(header::ETAG).cloned() {
+            headers.insert(header::ETAG, crate::sub_filter::weaken_etag(&etag));
+        }
+        resp.set_body(compressed).map_into_boxed_body()
+    }
+}
+
+// 响应是否带有 Cache-Control: no-transform
+fn no_transform(resp: &HttpResponse) -> bool {
+    resp.headers()
+        .get_all(header::CACHE_CONTROL)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|d| d.trim().eq_ignore_ascii_case("no-transform"))
+}
+
+// 添加 Vary: Accept-Encoding，已有时不重复添加
+fn add_vary(resp: &mut HttpResponse<()>) {
+    let present = resp
+        .headers()
+        .get_all(header::VARY)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.trim() == "*" || v.trim().eq_ignore_ascii_case("accept-encoding"));
+    if !present {
+        resp.headers_mut()
+            .append(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+    }
+}
@@ -0,0 +1,98 @@
+// ==================== 连接层指标 ====================
+//
+// HTTP层以下的容量指标：代理监听接受的连接数与当前连接数、上游DNS解析耗时与失败数、
+// 每个路由正在进行的上游请求数（连接池占用），以及上游TLS握手失败的识别
+
+use crate::metrics;
+use actix_web::dev::Extensions;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use std::any::Any;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+// 连接存活期间计入当前连接数，连接关闭时随连接数据一起释放
+struct ConnectionGuard;
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        metrics::gauge_add("proxy_connections_active", &[], -1.0);
+    }
+}
+
+// 代理监听每接受一个连接调用一次，注册为 HttpServer::on_connect
+pub fn on_connect(_connection: &dyn Any, data: &mut Extensions) {
+    metrics::counter_inc("proxy_connections_accepted_total", &[]);
+    metrics::gauge_add("proxy_connections_active", &[], 1.0);
+    data.insert(ConnectionGuard);
+}
+
+// 持有期间计入路由正在进行的上游请求数
+pub struct UpstreamGuard {
+    route: String,
+}
+
+impl UpstreamGuard {
+    pub fn new(route: &str) -> Self {
+        metrics::gauge_add("proxy_upstream_inflight_requests", &[("route", route)], 1.0);
+        UpstreamGuard {
+            route: route.to_string(),
+        }
+    }
+}
+
+impl Drop for UpstreamGuard {
+    fn drop(&mut self) {
+        metrics::gauge_add(
+            "proxy_upstream_inflight_requests",
+            &[("route", &self.route)],
+            -1.0,
+        );
+    }
+}
+
+// 记录解析耗时的DNS解析器，使用系统解析（与HTTP客户端的缺省行为相同）；
+// 目标为IP地址时客户端不会调用解析器
+pub struct TimedResolver;
+
+impl Resolve for TimedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let started = Instant::now();
+            // 端口由客户端按URL替换，这里填0即可
+            let result = tokio::net::lookup_host((name.as_str(), 0)).await;
+            metrics::histogram_observe(
+                "proxy_dns_resolution_seconds",
+                &[],
+                started.elapsed().as_secs_f64(),
+            );
+            match result {
+                Ok(addrs) => {
+                    let addrs: Vec<SocketAddr> = addrs.collect();
+                    Ok(Box::new(addrs.into_iter()) as Addrs)
+                }
+                Err(e) => {
+                    metrics::counter_inc("proxy_dns_failures_total", &[]);
+                    log::warn!("解析 {} 失败: {}", name.as_str(), e);
+                    Err(e.into())
+                }
+            }
+        })
+    }
+}
+
+// 上游连接错误是否由TLS握手失败引起（证书无效、协议不匹配等）
+pub fn is_tls_error(error: &reqwest::Error) -> bool {
+    let mut source: Option<&dyn std::error::Error> = Some(error);
+    while let Some(e) = source {
+        let message = e.to_string().to_ascii_lowercase();
+        if ["tls", "ssl", "certificate", "handshake"]
+            .iter()
+            .any(|k| message.contains(k))
+        {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
@@ -0,0 +1,175 @@
+// ==================== 内容类型过滤 ====================
+//
+// 路由的 [routes.content_types] 按 Content-Type 拒绝请求与响应：request_allow 不为空时只接受其中类型的
+// 请求体（如只接受 JSON 的 API），request_deny 中的类型返回 415；上游响应的类型不在 response_allow 中
+// 或在 response_deny 中时返回 502，deny_extensions 按 Content-Disposition 的文件名与请求路径的扩展名
+// 拦截下载（如 exe、msi），上游返回 application/octet-stream 时同样生效。类型写作 application/json、
+// text/*（一类）或 *+json（结构化后缀），比较时忽略大小写与 charset 等参数。被拒绝的请求与响应写入审计日志
+
+use crate::ProxyError;
+use actix_web::HttpRequest;
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+// 内容类型过滤配置：对应配置文件中的 [routes.content_types]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ContentTypesConfig {
+    #[serde(default)]
+    pub request_allow: Vec<String>, // 允许的请求体类型，为空表示不限制
+    #[serde(default)]
+    pub request_deny: Vec<String>, // 拒绝的请求体类型
+    #[serde(default)]
+    pub response_allow: Vec<String>, // 允许的响应类型，为空表示不限制
+    #[serde(default)]
+    pub response_deny: Vec<String>, // 拒绝的响应类型
+    #[serde(default)]
+    pub deny_extensions: Vec<String>, // 拦截的下载文件扩展名，如 ["exe", "msi"]
+}
+
+// 类型匹配规则
+#[derive(Debug)]
+enum Pattern {
+    Exact(String),  // application/json
+    Prefix(String), // text/*，保存为 "text/"
+    Suffix(String), // *+json，保存为 "+json"
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Result<Self, String> {
+        let pattern = pattern.trim().to_ascii_lowercase();
+        if let Some(suffix) = pattern.strip_prefix('*')
+            && suffix.starts_with('+')
+        {
+            return Ok(Pattern::Suffix(suffix.to_string()));
+        }
+        if pattern == "*/*" {
+            return Ok(Pattern::Prefix(String::new()));
+        }
+        if let Some(prefix) = pattern.strip_suffix("/*") {
+            return Ok(Pattern::Prefix(format!("{}/", prefix)));
+        }
+        match pattern.split_once('/') {
+            Some((kind, sub)) if !kind.is_empty() && !sub.is_empty() && !pattern.contains('*') => {
+                Ok(Pattern::Exact(pattern))
+            }
+            _ => Err(format!(
+                "无效的内容类型规则 {:?}，应为 type/subtype、type/* 或 *+suffix",
+                pattern
+            )),
+        }
+    }
+
+    fn matches(&self, media_type: &str) -> bool {
+        match self {
+            Pattern::Exact(t) => media_type == t,
+            Pattern::Prefix(p) => media_type.starts_with(p.as_str()),
+            Pattern::Suffix(s) => media_type.ends_with(s.as_str()),
+        }
+    }
+}
+
+fn parse_all(patterns: &[String]) -> Result<Vec<Pattern>, String> {
+    patterns.iter().map(|p| Pattern::parse(p)).collect()
+}
+
+// 编译后的内容类型过滤规则
+#[derive(Debug)]
+pub struct ContentFilter {
+    request_allow: Vec<Pattern>,
+    request_deny: Vec<Pattern>,
+    response_allow: Vec<Pattern>,
+    response_deny: Vec<Pattern>,
+    deny_extensions: Vec<String>, // 小写，以 . 开头
+}
+
+impl ContentFilter {
+    // 从配置构建，类型规则无效时启动失败
+    pub fn new(config: &ContentTypesConfig) -> Result<Self, String> {
+        Ok(ContentFilter {
+            request_allow: parse_all(&config.request_allow)?,
+            request_deny: parse_all(&config.request_deny)?,
+            response_allow: parse_all(&config.response_allow)?,
+            response_deny: parse_all(&config.response_deny)?,
+            deny_extensions: config
+                .deny_extensions
+                .iter()
+                .map(|e| format!(".{}", e.trim().trim_start_matches('.').to_ascii_lowercase()))
+                .collect(),
+        })
+    }
+
+    // 检查请求体的类型；没有请求体的请求不检查，有请求体但没有 Content-Type 时按空类型处理
+    pub fn check_request(&self, req: &HttpRequest, body: &[u8]) -> Result<(), ProxyError> {
+        if body.is_empty() {
+            return Ok(());
+        }
+        let media_type = req
+            .headers()
+            .get(actix_web::http::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .map(|v| v.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        match allowed(&media_type, &self.request_allow, &self.request_deny) {
+            true => Ok(()),
+            false => Err(ProxyError::ContentTypeNotAllowed(shown(&media_type))),
+        }
+    }
+
+    // 检查上游响应的类型与下载文件的扩展名，在读取响应体之前进行
+    pub fn check_response(&self, req: &HttpRequest, headers: &HeaderMap) -> Result<(), ProxyError> {
+        let media_type = crate::sub_filter::media_type(headers);
+        if !allowed(&media_type, &self.response_allow, &self.response_deny) {
+            return Err(ProxyError::ResponseTypeBlocked(shown(&media_type)));
+        }
+        if self.deny_extensions.is_empty() {
+            return Ok(());
+        }
+        let filename = headers
+            .get(reqwest::header::CONTENT_DISPOSITION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(disposition_filename);
+        let names = [filename.as_deref(), req.path().rsplit('/').next()];
+        for name in names.into_iter().flatten() {
+            let name = name.to_ascii_lowercase();
+            if let Some(ext) = self.deny_extensions.iter().find(|e| name.ends_with(*e)) {
+                return Err(ProxyError::ResponseTypeBlocked(format!(
+                    "{} ({})",
+                    name, ext
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+// 类型是否允许：在 deny 中时拒绝，allow 不为空时必须在 allow 中
+fn allowed(media_type: &str, allow: &[Pattern], deny: &[Pattern]) -> bool {
+    !deny.iter().any(|p| p.matches(media_type))
+        && (allow.is_empty() || allow.iter().any(|p| p.matches(media_type)))
+}
+
+fn shown(media_type: &str) -> String {
+    match media_type.is_empty() {
+        true => "(无 Content-Type)".to_string(),
+        false => media_type.to_string(),
+    }
+}
+
+// Content-Disposition 中的文件名，filename* 优先
+fn disposition_filename(value: &str) -> Option<String> {
+    let mut plain = None;
+    for part in value.split(';').skip(1) {
+        let Some((name, v)) = part.split_once('=') else {
+            continue;
+        };
+        let v = v.trim().trim_matches('"');
+        match name.trim().to_ascii_lowercase().as_str() {
+            // RFC 5987：charset'language'编码后的值，扩展名只需要末尾的 ASCII 部分
+            "filename*" => return Some(v.rsplit('\'').next().unwrap_or(v).to_string()),
+            "filename" => plain = Some(v.to_string()),
+            _ => {}
+        }
+    }
+    plain
+}
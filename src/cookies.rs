@@ -0,0 +1,102 @@
+// ==================== Cookie 改写 ====================
+//
+// 上游所在的主机或路径与客户端看到的不同时，上游 Set-Cookie 中的 Domain 与 Path 指向内部名称，
+// 浏览器不会把 Cookie 发回代理，会话因此失效。[routes.cookie_rewrite] 声明改写规则：
+// domains 按域名整体匹配（忽略开头的点与大小写），to 为空时去掉 Domain，Cookie 只属于代理的主机；
+// paths 按前缀匹配，如上游的 Path=/ 改写为 Path=/app/。两者都按顺序使用第一条匹配的规则，
+// Cookie 的名称、值与其他属性原样保留
+
+use reqwest::header::HeaderValue;
+use serde::{Deserialize, Serialize};
+
+// Cookie 改写配置：对应配置文件中的 [routes.cookie_rewrite]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CookieRewriteConfig {
+    #[serde(default)]
+    pub domains: Vec<CookieRule>, // Domain 改写规则
+    #[serde(default)]
+    pub paths: Vec<CookieRule>, // Path 前缀改写规则
+}
+
+// 改写规则
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CookieRule {
+    pub from: String, // 上游使用的域名或路径前缀
+    pub to: String,   // 替换为的域名或路径前缀，域名为空时去掉 Domain
+}
+
+// 编译后的 Cookie 改写规则
+#[derive(Debug)]
+pub struct CookieRewrite {
+    domains: Vec<(String, String)>, // 小写、去掉开头的点
+    paths: Vec<CookieRule>,
+}
+
+impl CookieRewrite {
+    // 从配置构建，路径不以/开头时启动失败
+    pub fn new(config: &CookieRewriteConfig) -> Result<Self, String> {
+        for rule in &config.paths {
+            if !rule.from.starts_with('/') || !rule.to.starts_with('/') {
+                return Err(format!(
+                    "Cookie 路径改写规则必须以/开头: {} -> {}",
+                    rule.from, rule.to
+                ));
+            }
+        }
+        let domain = |d: &str| d.trim().trim_start_matches('.').to_ascii_lowercase();
+        let domains = config
+            .domains
+            .iter()
+            .map(|rule| (domain(&rule.from), domain(&rule.to)))
+            .collect();
+        Ok(CookieRewrite {
+            domains,
+            paths: config.paths.clone(),
+        })
+    }
+
+    // 改写一个 Set-Cookie；没有匹配的规则或结果不是合法的响应头值时原样返回
+    pub fn rewrite(&self, value: &HeaderValue) -> HeaderValue {
+        let Ok(cookie) = value.to_str() else {
+            return value.clone();
+        };
+        let mut changed = false;
+        let mut parts: Vec<String> = Vec::new();
+        for (i, part) in cookie.split(';').enumerate() {
+            let part = part.trim();
+            let (name, attr) = part.split_once('=').unwrap_or((part, ""));
+            // 第一段为 Cookie 的名称与值
+            if i > 0 && name.trim().eq_ignore_ascii_case("domain") {
+                let attr = attr.trim().trim_start_matches('.');
+                if let Some((_, to)) = self
+                    .domains
+                    .iter()
+                    .find(|(from, _)| from.eq_ignore_ascii_case(attr))
+                {
+                    changed = true;
+                    if !to.is_empty() {
+                        parts.push(format!("Domain={}", to));
+                    }
+                    continue;
+                }
+            }
+            if i > 0
+                && name.trim().eq_ignore_ascii_case("path")
+                && let Some(rule) = self.paths.iter().find(|r| attr.trim().starts_with(&r.from))
+            {
+                changed = true;
+                parts.push(format!(
+                    "Path={}{}",
+                    rule.to,
+                    &attr.trim()[rule.from.len()..]
+                ));
+                continue;
+            }
+            parts.push(part.to_string());
+        }
+        if !changed {
+            return value.clone();
+        }
+        HeaderValue::from_str(&parts.join("; ")).unwrap_or_else(|_| value.clone())
+    }
+}
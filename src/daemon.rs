@@ -0,0 +1,140 @@
+// ==================== 后台运行 ====================
+//
+// --daemon 在启动异步运行时之前 fork 出子进程转入后台（setsid，标准输入输出重定向到 /dev/null），
+// 前台进程等到子进程的监听全部就绪后以状态码 0 退出，子进程启动失败时以 1 退出，启动阶段的错误仍输出到终端；
+// 当前目录不变，相对路径的配置文件照常读取。--pid-file 写入进程号：启动时文件中的进程仍在运行则拒绝启动，
+// 退出时删除；平滑升级启动的新进程不再转入后台，接管监听后把自己的进程号写入同一个文件。
+// 守护进程只支持 Unix，Windows 上使用 install-service 安装为服务
+
+use std::sync::OnceLock;
+
+// 通知前台进程启动完成的管道写端，-1 表示不是由 --daemon 启动
+#[cfg(unix)]
+static READY_FD: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(-1);
+
+// --pid-file 指定的文件
+static PID_FILE: OnceLock<String> = OnceLock::new();
+
+// 检查 PID 文件：文件中的进程仍在运行时返回错误；平滑升级启动的新进程跳过检查
+pub fn check_pid_file(path: &str) -> Result<(), String> {
+    PID_FILE.get_or_init(|| path.to_string());
+    #[cfg(unix)]
+    {
+        if crate::upgrade::parent().is_some() {
+            return Ok(());
+        }
+        let Ok(text) = std::fs::read_to_string(path) else {
+            return Ok(());
+        };
+        let Ok(pid) = text.trim().parse::<libc::pid_t>() else {
+            return Ok(()); // 内容无效，视为残留文件
+        };
+        // kill(pid, 0) 只检查进程是否存在；EPERM 表示进程存在但属于其他用户
+        let alive = unsafe { libc::kill(pid, 0) } == 0
+            || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+        if alive && pid as u32 != std::process::id() {
+            return Err(format!("PID 文件 {} 中的进程 {} 仍在运行", path, pid));
+        }
+    }
+    Ok(())
+}
+
+// 转入后台：fork 子进程，前台进程等待子进程启动完成后退出，本函数只在子进程中返回
+pub fn daemonize() -> Result<(), String> {
+    #[cfg(unix)]
+    {
+        // 平滑升级启动的新进程由已在后台的旧进程启动，不再 fork
+        if crate::upgrade::parent().is_some() {
+            return Ok(());
+        }
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(format!("无法创建管道: {}", std::io::Error::last_os_error()));
+        }
+        let (read, write) = (fds[0], fds[1]);
+        match unsafe { libc::fork() } {
+            -1 => Err(format!("fork 失败: {}", std::io::Error::last_os_error())),
+            0 => {
+                // 子进程：脱离终端，成为新会话的首进程
+                unsafe {
+                    libc::close(read);
+                    libc::setsid();
+                }
+                crate::upgrade::set_cloexec(write, true).map_err(|e| e.to_string())?;
+                READY_FD.store(write, std::sync::atomic::Ordering::SeqCst);
+                // 标准错误保留到启动完成，启动失败的原因仍能输出到终端
+                redirect(&[libc::STDIN_FILENO, libc::STDOUT_FILENO])
+            }
+            child => {
+                // 前台进程：读到子进程的就绪通知时成功退出；子进程退出导致管道关闭时失败退出
+                unsafe { libc::close(write) };
+                let mut byte = 0u8;
+                let n = unsafe { libc::read(read, (&mut byte as *mut u8).cast(), 1) };
+                if n == 1 {
+                    println!("已转入后台运行(pid {})", child);
+                    std::process::exit(0);
+                }
+                std::process::exit(1);
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    Err("--daemon 只支持 Unix，Windows 上请使用 install-service 安装为服务".to_string())
+}
+
+// 把描述符重定向到 /dev/null
+#[cfg(unix)]
+fn redirect(fds: &[i32]) -> Result<(), String> {
+    let null = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")
+        .map_err(|e| format!("无法打开 /dev/null: {}", e))?;
+    for &fd in fds {
+        if unsafe { libc::dup2(std::os::fd::AsRawFd::as_raw_fd(&null), fd) } == -1 {
+            return Err(format!(
+                "无法重定向描述符 {}: {}",
+                fd,
+                std::io::Error::last_os_error()
+            ));
+        }
+    }
+    Ok(())
+}
+
+// 所有服务都已监听：写入 PID 文件；由 --daemon 启动时通知前台进程退出，之后标准错误也重定向到 /dev/null
+pub fn ready() {
+    if let Some(path) = PID_FILE.get() {
+        match std::fs::write(path, format!("{}\n", std::process::id())) {
+            Ok(()) => log::info!("已写入 PID 文件: {}", path),
+            Err(e) => log::warn!("无法写入 PID 文件 {}: {}", path, e),
+        }
+    }
+    #[cfg(unix)]
+    {
+        let fd = READY_FD.swap(-1, std::sync::atomic::Ordering::SeqCst);
+        if fd >= 0 {
+            unsafe {
+                libc::write(fd, b"1".as_ptr().cast(), 1);
+                libc::close(fd);
+            }
+            if let Err(e) = redirect(&[libc::STDERR_FILENO]) {
+                log::warn!("{}", e);
+            }
+        }
+    }
+    #[cfg(windows)]
+    crate::winservice::running();
+}
+
+// 退出前删除 PID 文件；文件已被平滑升级启动的新进程改写时保留
+pub fn exit() {
+    let Some(path) = PID_FILE.get() else {
+        return;
+    };
+    let ours = std::fs::read_to_string(path)
+        .is_ok_and(|text| text.trim() == std::process::id().to_string());
+    if ours && let Err(e) = std::fs::remove_file(path) {
+        log::warn!("无法删除 PID 文件 {}: {}", path, e);
+    }
+}
@@ -0,0 +1,260 @@
+// ==================== 状态页 ====================
+//
+// 管理接口 GET /status 提供一个简单的HTML状态页：运行时长、每个路由的请求数与请求速率、
+// 熔断器状态、各上游的调用结果统计、启用缓存的路由的命中率以及最近的出错请求，页面每5秒自动刷新
+
+use crate::metrics;
+use crate::routes::RouteTable;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// 保留的最近出错请求数
+const RECENT_ERRORS: usize = 50;
+
+// 一次出错的请求
+struct ErrorEntry {
+    time: chrono::DateTime<chrono::Local>,
+    request_id: Option<String>,
+    method: String,
+    path: String,
+    route: Option<String>,
+    status: u16,
+    duration: Duration,
+}
+
+// 状态页数据
+#[derive(Default)]
+pub struct Dashboard {
+    errors: Mutex<VecDeque<ErrorEntry>>,
+    last: Mutex<Option<(Instant, BTreeMap<String, u64>)>>, // 上次渲染时各路由的请求数，用于计算速率
+}
+
+impl Dashboard {
+    // 记录一次出错的请求（状态码不低于500）
+    pub fn record_error(
+        &self,
+        method: &str,
+        path: &str,
+        route: Option<&str>,
+        status: u16,
+        duration: Duration,
+    ) {
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() >= RECENT_ERRORS {
+            errors.pop_back();
+        }
+        errors.push_front(ErrorEntry {
+            time: chrono::Local::now(),
+            request_id: crate::request_id::current(),
+            method: method.to_string(),
+            path: path.to_string(),
+            route: route.map(|r| r.to_string()),
+            status,
+            duration,
+        });
+    }
+
+    // 渲染状态页
+    pub fn render(&self, routes: &RouteTable, uptime: Duration) -> String {
+        // 按路由汇总请求数与5xx数
+        let mut requests: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+        for (labels, value) in metrics::counter_values("proxy_requests_total") {
+            let route = label(&labels, "route");
+            let entry = requests.entry(route.to_string()).or_default();
+            entry.0 += value;
+            if label(&labels, "status").starts_with('5') {
+                entry.1 += value;
+            }
+        }
+        // 与上次渲染比较得到请求速率，首次渲染时取启动以来的平均值
+        let now = Instant::now();
+        let totals: BTreeMap<String, u64> = requests
+            .iter()
+            .map(|(k, (total, _))| (k.clone(), *total))
+            .collect();
+        let previous = self.last.lock().unwrap().replace((now, totals.clone()));
+        let rate = |route: &str| {
+            let total = totals.get(route).copied().unwrap_or(0);
+            let (elapsed, before) = match &previous {
+                Some((at, before)) if now.duration_since(*at) >= Duration::from_secs(1) => (
+                    now.duration_since(*at),
+                    before.get(route).copied().unwrap_or(0),
+                ),
+                _ => (uptime, 0),
+            };
+            total.saturating_sub(before) as f64 / elapsed.as_secs_f64().max(1.0)
+        };
+        // 按路由与上游汇总调用结果
+        let mut upstreams: BTreeMap<(String, String), (u64, u64)> = BTreeMap::new();
+        for (labels, value) in metrics::counter_values("proxy_upstream_requests_total") {
+            let key = (
+                label(&labels, "route").to_string(),
+                label(&labels, "upstream").to_string(),
+            );
+            let entry = upstreams.entry(key).or_default();
+            let outcome = label(&labels, "outcome");
+            if outcome.starts_with(['2', '3', '4']) {
+                entry.0 += value;
+            } else {
+                entry.1 += value;
+            }
+        }
+
+        // 按路由汇总缓存结果：命中、过期、重新验证、未命中
+        let mut caches: BTreeMap<String, [u64; 4]> = BTreeMap::new();
+        for (labels, value) in metrics::counter_values("proxy_cache_requests_total") {
+            let entry = caches
+                .entry(label(&labels, "route").to_string())
+                .or_default();
+            match label(&labels, "result") {
+                "hit" => entry[0] += value,
+                "stale" => entry[1] += value,
+                "revalidated" => entry[2] += value,
+                _ => entry[3] += value,
+            }
+        }
+
+        let mut html = String::new();
+        let _ = write!(
+            html,
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta http-equiv=\"refresh\" content=\"5\">\
+             <title>rust_proxy 状态</title><style>\
+             body{{font-family:sans-serif;margin:2em;color:#222}}table{{border-collapse:collapse;margin-bottom:2em}}\
+             th,td{{border:1px solid #ccc;padding:4px 10px;text-align:left}}th{{background:#f0f0f0}}\
+             .bad{{color:#c00;font-weight:bold}}.ok{{color:#080}}</style></head><body>\
+             <h1>rust_proxy 状态</h1><p>版本 {} · 运行时长 {}</p>",
+            env!("CARGO_PKG_VERSION"),
+            format_uptime(uptime)
+        );
+
+        html.push_str(
+            "<h2>路由</h2><table><tr><th>路由</th><th>路径前缀</th><th>目标</th>\
+             <th>请求数</th><th>请求/秒</th><th>5xx</th><th>熔断器</th></tr>",
+        );
+        for route in routes.iter() {
+            let (total, errors) = requests.get(&route.name).copied().unwrap_or_default();
+            let (breaker, failures) = route.breaker.status();
+            let breaker_class = if breaker == "open" { "bad" } else { "ok" };
+            let _ = write!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.2}</td><td{}>{}</td>\
+                 <td class=\"{}\">{}{}</td></tr>",
+                escape(&route.name),
+                if route.path_prefix.is_empty() {
+                    "/".to_string()
+                } else {
+                    escape(&route.path_prefix)
+                },
+                escape(&route.targets_display()),
+                total,
+                rate(&route.name),
+                if errors > 0 { " class=\"bad\"" } else { "" },
+                errors,
+                breaker_class,
+                breaker,
+                if failures > 0 {
+                    format!("（连续失败{}次）", failures)
+                } else {
+                    String::new()
+                }
+            );
+        }
+        html.push_str("</table>");
+
+        if !caches.is_empty() {
+            html.push_str(
+                "<h2>缓存</h2><table><tr><th>路由</th><th>命中</th><th>过期</th><th>重新验证</th>\
+                 <th>未命中</th><th>命中率</th></tr>",
+            );
+            for (route, [hit, stale, revalidated, miss]) in &caches {
+                let served = hit + stale + revalidated;
+                let _ = write!(
+                    html,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}%</td></tr>",
+                    escape(route),
+                    hit,
+                    stale,
+                    revalidated,
+                    miss,
+                    served as f64 / (served + miss).max(1) as f64 * 100.0
+                );
+            }
+            html.push_str("</table>");
+        }
+
+        html.push_str(
+            "<h2>上游</h2><table><tr><th>路由</th><th>上游</th><th>成功</th><th>失败</th><th>失败率</th></tr>",
+        );
+        for ((route, upstream), (ok, failed)) in &upstreams {
+            let ratio = *failed as f64 / (*ok + *failed).max(1) as f64;
+            let _ = write!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"{}\">{:.1}%</td></tr>",
+                escape(route),
+                escape(upstream),
+                ok,
+                failed,
+                if ratio > 0.05 { "bad" } else { "ok" },
+                ratio * 100.0
+            );
+        }
+        if upstreams.is_empty() {
+            html.push_str("<tr><td colspan=\"5\">暂无上游调用</td></tr>");
+        }
+        html.push_str("</table>");
+
+        html.push_str(
+            "<h2>最近的出错请求</h2><table><tr><th>时间</th><th>请求ID</th><th>方法</th>\
+             <th>路径</th><th>路由</th><th>状态码</th><th>耗时</th></tr>",
+        );
+        let errors = self.errors.lock().unwrap();
+        for e in errors.iter() {
+            let _ = write!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"bad\">{}</td><td>{}ms</td></tr>",
+                e.time.format("%Y-%m-%d %H:%M:%S"),
+                escape(e.request_id.as_deref().unwrap_or("-")),
+                escape(&e.method),
+                escape(&e.path),
+                escape(e.route.as_deref().unwrap_or("-")),
+                e.status,
+                e.duration.as_millis()
+            );
+        }
+        if errors.is_empty() {
+            html.push_str("<tr><td colspan=\"7\">暂无</td></tr>");
+        }
+        html.push_str("</table></body></html>");
+        html
+    }
+}
+
+fn label<'a>(labels: &'a metrics::Labels, name: &str) -> &'a str {
+    labels
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+        .unwrap_or("")
+}
+
+fn format_uptime(uptime: Duration) -> String {
+    let secs = uptime.as_secs();
+    format!(
+        "{}天{}小时{}分{}秒",
+        secs / 86400,
+        secs % 86400 / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+// 转义HTML特殊字符
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,208 @@
+// ==================== 截止时间传递 ====================
+//
+// 每个请求进入时根据路由的总超时计算截止时间，可信调用方传入的更早截止时间或更短超时会被采用；
+// 发往上游时以剩余时间作为请求超时，并可通过 X-Request-Deadline / grpc-timeout 传给上游，
+// 让上游在客户端已经放弃等待时及时停止处理
+
+use crate::ProxyError;
+use crate::acl::IpNet;
+use actix_web::HttpRequest;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// 截止时间配置：对应配置文件中的 [deadline]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeadlineConfig {
+    #[serde(default)]
+    pub propagate: bool, // 是否向上游传递截止时间
+    #[serde(default = "default_header")]
+    pub header: String, // 传递截止时间的请求头，值为Unix时间戳(毫秒)
+    #[serde(default = "default_true")]
+    pub grpc_timeout: bool, // 是否为gRPC请求设置grpc-timeout
+    #[serde(default = "default_timeout_header")]
+    pub timeout_header: String, // 可信调用方指定本次请求超时(毫秒)的请求头，不超过路由总超时
+    #[serde(default)]
+    pub trusted: Vec<String>, // 信任其传入截止时间的调用方网段
+}
+
+impl Default for DeadlineConfig {
+    fn default() -> Self {
+        DeadlineConfig {
+            propagate: false,
+            header: default_header(),
+            grpc_timeout: true,
+            timeout_header: default_timeout_header(),
+            trusted: Vec::new(),
+        }
+    }
+}
+
+// 以下函数为截止时间配置提供默认值
+fn default_header() -> String {
+    "X-Request-Deadline".to_string()
+}
+
+fn default_timeout_header() -> String {
+    "X-Request-Timeout".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// 截止时间策略
+#[derive(Debug)]
+pub struct DeadlinePolicy {
+    config: DeadlineConfig,
+    header: HeaderName,         // 解析后的截止时间请求头
+    timeout_header: HeaderName, // 解析后的超时请求头
+    trusted: Vec<IpNet>,        // 可信调用方
+}
+
+impl DeadlinePolicy {
+    // 从配置构建策略，无效的请求头名称或网段会导致启动失败
+    pub fn new(config: &DeadlineConfig) -> Result<Self, String> {
+        let header = HeaderName::from_bytes(config.header.as_bytes())
+            .map_err(|_| format!("无效的截止时间请求头: {}", config.header))?;
+        let timeout_header = HeaderName::from_bytes(config.timeout_header.as_bytes())
+            .map_err(|_| format!("无效的超时请求头: {}", config.timeout_header))?;
+        let trusted = config
+            .trusted
+            .iter()
+            .map(|s| IpNet::parse(s).ok_or_else(|| format!("无效的可信网段: {}", s)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(DeadlinePolicy {
+            config: config.clone(),
+            header,
+            timeout_header,
+            trusted,
+        })
+    }
+
+    // 计算请求的截止时间：路由总超时与可信调用方传入的截止时间、超时取较早者
+    pub fn resolve(&self, req: &HttpRequest, total: Duration) -> Result<Deadline, ProxyError> {
+        let mut budget = total;
+        if self.is_trusted(req)
+            && let Some(incoming) = self.incoming(req)
+        {
+            if incoming.is_zero() {
+                return Err(ProxyError::UpstreamTimeout(
+                    "调用方的截止时间已过".to_string(),
+                ));
+            }
+            budget = budget.min(incoming);
+        }
+        Ok(Deadline {
+            at: Instant::now() + budget,
+            grpc: self.config.grpc_timeout
+                && req
+                    .headers()
+                    .get("content-type")
+                    .and_then(|v| v.to_str().ok())
+                    .is_some_and(|v| v.starts_with("application/grpc")),
+        })
+    }
+
+    // 请求是否来自可信调用方
+    fn is_trusted(&self, req: &HttpRequest) -> bool {
+        crate::addr::peer_ip(req).is_some_and(|ip| self.trusted.iter().any(|net| net.contains(ip)))
+    }
+
+    // 读取调用方传入的剩余时间，多个请求头都有时取最短者
+    fn incoming(&self, req: &HttpRequest) -> Option<Duration> {
+        let headers = req.headers();
+        let absolute = headers
+            .get(&self.header)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(|ms| {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_millis() as u64)
+                    .unwrap_or(0);
+                Duration::from_millis(ms.saturating_sub(now))
+            });
+        let grpc = headers
+            .get("grpc-timeout")
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_grpc_timeout);
+        let timeout = headers
+            .get(&self.timeout_header)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_millis);
+        [absolute, grpc, timeout].into_iter().flatten().min()
+    }
+
+    // 为代理请求设置剩余时间：作为请求超时，启用传递时同时写入请求头（覆盖客户端传入的值）
+    pub fn apply(
+        &self,
+        deadline: &Deadline,
+        request: reqwest::RequestBuilder,
+    ) -> reqwest::RequestBuilder {
+        let remaining = deadline.remaining();
+        let request = request.timeout(remaining);
+        if !self.config.propagate {
+            return request;
+        }
+        let mut headers = HeaderMap::new();
+        let unix_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0)
+            + remaining.as_millis() as u64;
+        headers.insert(self.header.clone(), HeaderValue::from(unix_ms));
+        if deadline.grpc
+            && let Ok(value) = HeaderValue::from_str(&format_grpc_timeout(remaining))
+        {
+            headers.insert("grpc-timeout", value);
+        }
+        request.headers(headers)
+    }
+}
+
+// 单个请求的截止时间
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    at: Instant, // 截止时刻
+    grpc: bool,  // 是否为gRPC请求
+}
+
+impl Deadline {
+    // 距截止时间的剩余时间，已过期时返回1毫秒，使上游请求立即超时
+    pub fn remaining(&self) -> Duration {
+        self.at
+            .saturating_duration_since(Instant::now())
+            .max(Duration::from_millis(1))
+    }
+}
+
+// 解析gRPC超时格式：最多8位数字加单位(H/M/S/m/u/n)
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (digits, unit) = value.split_at(value.len() - 1);
+    let n: u64 = digits.parse().ok()?;
+    Some(match unit {
+        "H" => Duration::from_secs(n * 3600),
+        "M" => Duration::from_secs(n * 60),
+        "S" => Duration::from_secs(n),
+        "m" => Duration::from_millis(n),
+        "u" => Duration::from_micros(n),
+        "n" => Duration::from_nanos(n),
+        _ => return None,
+    })
+}
+
+// 生成gRPC超时格式，优先使用毫秒，超出8位数字时改用秒
+fn format_grpc_timeout(remaining: Duration) -> String {
+    let ms = remaining.as_millis();
+    if ms < 100_000_000 {
+        format!("{}m", ms)
+    } else {
+        format!("{}S", remaining.as_secs().min(99_999_999))
+    }
+}
@@ -0,0 +1,102 @@
+// ==================== 请求体解压 ====================
+//
+// 有的上游不支持压缩的上传。路由配置 [routes.request_decompression] 后，带 Content-Encoding 的请求体
+// （gzip、deflate、br、zstd，可以是逗号分隔的多层编码）在转发之前解压，发往上游的请求去掉 Content-Encoding，
+// Content-Length 按解压后的请求体重新生成。解压在请求体校验之前进行，Schema 与 OpenAPI 校验的是解压后的内容。
+// 解压后超过 max_kb 的请求返回 413，避免压缩炸弹；编码不支持返回 415，数据损坏返回 400
+
+use crate::ProxyError;
+use actix_web::HttpRequest;
+use actix_web::web::Bytes;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+
+// 请求体解压配置：对应配置文件中的 [routes.request_decompression]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DecompressionConfig {
+    #[serde(default = "default_max_kb")]
+    pub max_kb: u64, // 解压后请求体的大小上限
+}
+
+// 以下函数为请求体解压提供默认值
+fn default_max_kb() -> u64 {
+    10240
+}
+
+// 请求体解压策略
+#[derive(Debug)]
+pub struct Decompression {
+    max_size: u64,
+}
+
+impl Decompression {
+    pub fn new(config: &DecompressionConfig) -> Self {
+        Decompression {
+            max_size: config.max_kb * 1024,
+        }
+    }
+
+    // 按 Content-Encoding 解压请求体；没有编码或为 identity 时原样返回
+    pub fn decode(
+        &self,
+        req: &HttpRequest,
+        body: &Bytes,
+        route: &str,
+    ) -> Result<Bytes, ProxyError> {
+        let encodings: Vec<String> = req
+            .headers()
+            .get_all(actix_web::http::header::CONTENT_ENCODING)
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|e| e.trim().to_ascii_lowercase())
+            .filter(|e| !e.is_empty() && e != "identity")
+            .collect();
+        if encodings.is_empty() || body.is_empty() {
+            return Ok(body.clone());
+        }
+        // 多层编码按应用的相反顺序解压
+        let mut data = body.to_vec();
+        for encoding in encodings.iter().rev() {
+            data = self.decode_one(encoding, &data)?;
+        }
+        log::debug!(
+            "路由 {} 的请求体已解压({}): {} -> {} 字节",
+            route,
+            encodings.join(", "),
+            body.len(),
+            data.len()
+        );
+        crate::metrics::counter_inc(
+            "proxy_decompressed_requests_total",
+            &[("route", route), ("encoding", &encodings.join(","))],
+        );
+        Ok(Bytes::from(data))
+    }
+
+    fn decode_one(&self, encoding: &str, data: &[u8]) -> Result<Vec<u8>, ProxyError> {
+        let reader: Box<dyn Read + '_> = match encoding {
+            "gzip" | "x-gzip" => Box::new(flate2::read::MultiGzDecoder::new(data)),
+            // HTTP 的 deflate 为 zlib 格式，也有客户端发送不带 zlib 头的原始 deflate
+            "deflate" if data.first().is_some_and(|b| b & 0x0f == 8) => {
+                Box::new(flate2::read::ZlibDecoder::new(data))
+            }
+            "deflate" => Box::new(flate2::read::DeflateDecoder::new(data)),
+            "br" => Box::new(brotli::Decompressor::new(data, 4096)),
+            "zstd" => Box::new(
+                zstd::stream::read::Decoder::new(data)
+                    .map_err(|e| ProxyError::DecompressionFailed(format!("{}: {}", encoding, e)))?,
+            ),
+            other => return Err(ProxyError::UnsupportedEncoding(other.to_string())),
+        };
+        // 多读一个字节以判断是否超过上限
+        let mut out = Vec::new();
+        reader
+            .take(self.max_size + 1)
+            .read_to_end(&mut out)
+            .map_err(|e| ProxyError::DecompressionFailed(format!("{}: {}", encoding, e)))?;
+        if out.len() as u64 > self.max_size {
+            return Err(ProxyError::DecompressedTooLarge(self.max_size / 1024));
+        }
+        Ok(out)
+    }
+}
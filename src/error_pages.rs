@@ -0,0 +1,426 @@
+// ==================== 错误页 ====================
+//
+// 代理自身产生的错误默认返回 {"error","details","request_id"} 形式的 JSON。[[error_pages]] 按状态码
+// 替换这些响应体：status 可以是精确的状态码（如 "503"）、一类状态码（如 "5xx"）或 "default"，
+// 匹配时精确值优先，其次是同类，最后是 default。响应体可以是内联模板 body、模板文件 file，
+// 或 json 表（其中的字符串按模板渲染），可用的变量见 Variable。intercept 为 true 时上游返回的
+// 同类错误响应同样被替换，$upstream_status 为上游的状态码。替换只影响响应体与 Content-Type，
+// 状态码以及 Retry-After、Allow 等响应头保留；模板文件在启动与热加载时读取
+
+use actix_web::body::MessageBody;
+use actix_web::http::StatusCode;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// 错误页配置：对应配置文件中的 [[error_pages]]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ErrorPageConfig {
+    pub status: String, // 状态码，如 "503"、"5xx" 或 "default"
+    #[serde(default)]
+    pub body: Option<String>, // 内联模板
+    #[serde(default)]
+    pub file: Option<String>, // 模板文件
+    #[serde(default)]
+    pub json: Option<Value>, // JSON 响应体，其中的字符串按模板渲染
+    #[serde(default)]
+    pub content_type: Option<String>, // 缺省时 body 与 file 为 text/html，json 为 application/json
+    #[serde(default)]
+    pub intercept: bool, // 同时替换上游返回的该状态码的响应
+}
+
+// 模板中可以使用的变量
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Variable {
+    Status,         // 返回给客户端的状态码
+    Reason,         // 状态码的原因短语，如 Service Unavailable
+    Error,          // 错误的简短说明，如 上游响应超时
+    Details,        // 错误详情
+    RequestId,      // 请求ID
+    Route,          // 匹配的路由
+    RequestMethod,  // 请求方法
+    Uri,            // 路径
+    Host,           // 客户端请求的 Host
+    UpstreamStatus, // 上游的状态码，代理自身产生的错误为空
+}
+
+impl Variable {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "status" => Variable::Status,
+            "reason" => Variable::Reason,
+            "error" => Variable::Error,
+            "details" => Variable::Details,
+            "request_id" => Variable::RequestId,
+            "route" => Variable::Route,
+            "request_method" => Variable::RequestMethod,
+            "uri" => Variable::Uri,
+            "host" => Variable::Host,
+            "upstream_status" => Variable::UpstreamStatus,
+            _ => return None,
+        })
+    }
+}
+
+// 模板片段
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Variable(Variable),
+}
+
+// 解析后的模板，变量写作 $name 或 ${name}；$$ 表示字面的 $
+#[derive(Debug, Clone)]
+struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    fn parse(format: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '$' {
+                literal.push(c);
+                continue;
+            }
+            let name: String = match chars.peek() {
+                Some('$') => {
+                    chars.next();
+                    literal.push('$');
+                    continue;
+                }
+                Some('{') => {
+                    chars.next();
+                    chars.by_ref().take_while(|c| *c != '}').collect()
+                }
+                _ => {
+                    let mut name = String::new();
+                    while let Some(c) = chars
+                        .peek()
+                        .filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+                    {
+                        name.push(*c);
+                        chars.next();
+                    }
+                    name
+                }
+            };
+            let variable = Variable::parse(&name)
+                .ok_or_else(|| format!("错误页模板中有未知变量: ${}", name))?;
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+            }
+            segments.push(Segment::Variable(variable));
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Template { segments })
+    }
+
+    // 渲染模板，escape 对变量的值做转义
+    fn render(&self, context: &Context, escape: fn(&str) -> String) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => out.push_str(s),
+                Segment::Variable(v) => out.push_str(&escape(&context.value(*v))),
+            }
+        }
+        out
+    }
+
+    // 模板是否只有一个变量，json 中的 "$status" 据此渲染为数字
+    fn single(&self) -> Option<Variable> {
+        match self.segments.as_slice() {
+            [Segment::Variable(v)] => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+// 渲染错误页时的变量取值
+struct Context<'a> {
+    req: &'a HttpRequest,
+    route: &'a str,
+    status: StatusCode,
+    error: String,
+    details: String,
+    upstream_status: Option<StatusCode>,
+}
+
+impl Context<'_> {
+    fn value(&self, variable: Variable) -> String {
+        match variable {
+            Variable::Status => self.status.as_u16().to_string(),
+            Variable::Reason => self
+                .status
+                .canonical_reason()
+                .unwrap_or_default()
+                .to_string(),
+            Variable::Error => self.error.clone(),
+            Variable::Details => self.details.clone(),
+            Variable::RequestId => crate::request_id::current().unwrap_or_default(),
+            Variable::Route => self.route.to_string(),
+            Variable::RequestMethod => self.req.method().to_string(),
+            Variable::Uri => self.req.path().to_string(),
+            Variable::Host => self.req.connection_info().host().to_string(),
+            Variable::UpstreamStatus => self
+                .upstream_status
+                .map(|s| s.as_u16().to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+// 编译后的响应体
+#[derive(Debug)]
+enum Body {
+    Text(Template, Escape), // 文本模板，以及变量的转义方式
+    Json(JsonTemplate),
+}
+
+// 文本模板中变量的转义方式，按 Content-Type 确定
+#[derive(Debug, Clone, Copy)]
+enum Escape {
+    Html, // HTML 与 XML
+    Json, // 变量位于 JSON 字符串中
+    None,
+}
+
+// json 表中的模板
+#[derive(Debug)]
+enum JsonTemplate {
+    Value(Value), // 不含模板的值
+    String(Template),
+    Array(Vec<JsonTemplate>),
+    Object(Vec<(String, JsonTemplate)>),
+}
+
+impl JsonTemplate {
+    fn parse(value: &Value) -> Result<Self, String> {
+        Ok(match value {
+            Value::String(s) => JsonTemplate::String(Template::parse(s)?),
+            Value::Array(items) => JsonTemplate::Array(
+                items
+                    .iter()
+                    .map(JsonTemplate::parse)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Object(map) => JsonTemplate::Object(
+                map.iter()
+                    .map(|(k, v)| Ok((k.clone(), JsonTemplate::parse(v)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            other => JsonTemplate::Value(other.clone()),
+        })
+    }
+
+    fn render(&self, context: &Context) -> Value {
+        match self {
+            JsonTemplate::Value(value) => value.clone(),
+            // 只有状态码变量的字符串渲染为数字，没有上游状态码时为 null
+            JsonTemplate::String(template) => match template.single() {
+                Some(Variable::Status) => Value::from(context.status.as_u16()),
+                Some(Variable::UpstreamStatus) => context
+                    .upstream_status
+                    .map(|s| Value::from(s.as_u16()))
+                    .unwrap_or(Value::Null),
+                _ => Value::String(template.render(context, |s| s.to_string())),
+            },
+            JsonTemplate::Array(items) => {
+                Value::Array(items.iter().map(|item| item.render(context)).collect())
+            }
+            JsonTemplate::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.render(context)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+// 编译后的错误页
+#[derive(Debug)]
+struct Page {
+    status: String, // 小写，如 "503"、"5xx"、"default"
+    body: Body,
+    content_type: HeaderValue,
+    intercept: bool,
+}
+
+// 错误页集合
+#[derive(Debug, Default)]
+pub struct ErrorPages {
+    pages: Vec<Page>,
+}
+
+impl ErrorPages {
+    // 从配置构建，状态码无效、没有或有多个响应体、模板文件无法读取或模板中有未知变量时启动失败
+    pub fn new(configs: &[ErrorPageConfig]) -> Result<Self, String> {
+        let mut pages: Vec<Page> = Vec::with_capacity(configs.len());
+        for config in configs {
+            let status = config.status.trim().to_ascii_lowercase();
+            let valid = status == "default"
+                || (status.len() == 3
+                    && matches!(status.as_bytes()[0], b'4' | b'5')
+                    && (&status[1..] == "xx" || status[1..].bytes().all(|b| b.is_ascii_digit())));
+            if !valid {
+                return Err(format!(
+                    "错误页的 status 必须是 4xx/5xx 状态码、\"4xx\"、\"5xx\" 或 \"default\": {}",
+                    config.status
+                ));
+            }
+            if pages.iter().any(|p| p.status == status) {
+                return Err(format!("错误页的 status 重复: {}", config.status));
+            }
+            let (body, default_type) = match (&config.body, &config.file, &config.json) {
+                (Some(body), None, None) => (body.clone(), "text/html; charset=utf-8"),
+                (None, Some(file), None) => (
+                    std::fs::read_to_string(file)
+                        .map_err(|e| format!("无法读取错误页文件 {}: {}", file, e))?,
+                    "text/html; charset=utf-8",
+                ),
+                (None, None, Some(_)) => (String::new(), "application/json"),
+                _ => {
+                    return Err(format!(
+                        "错误页 {} 必须且只能设置 body、file、json 之一",
+                        config.status
+                    ));
+                }
+            };
+            let content_type = config.content_type.as_deref().unwrap_or(default_type);
+            let body = match &config.json {
+                Some(json) => Body::Json(JsonTemplate::parse(json)?),
+                None => {
+                    let media_type = content_type.to_ascii_lowercase();
+                    let escape = match media_type {
+                        m if m.contains("html") || m.contains("xml") => Escape::Html,
+                        m if m.contains("json") => Escape::Json,
+                        _ => Escape::None,
+                    };
+                    Body::Text(Template::parse(&body)?, escape)
+                }
+            };
+            pages.push(Page {
+                status,
+                body,
+                content_type: HeaderValue::from_str(content_type)
+                    .map_err(|_| format!("无效的错误页 content_type: {}", content_type))?,
+                intercept: config.intercept,
+            });
+        }
+        Ok(ErrorPages { pages })
+    }
+
+    // 状态码对应的错误页：精确值优先，其次是同类，最后是 default
+    fn find(&self, status: StatusCode) -> Option<&Page> {
+        let code = status.as_str();
+        let class = format!("{}xx", &code[..1]);
+        [code, class.as_str(), "default"]
+            .iter()
+            .find_map(|key| self.pages.iter().find(|p| p.status == *key))
+    }
+
+    // 代理自身产生的错误按错误页替换响应体，没有匹配的错误页时返回默认的 JSON 错误响应
+    pub fn error(&self, req: &HttpRequest, route: &str, error: crate::ProxyError) -> HttpResponse {
+        let resp = HttpResponse::from_error(error);
+        let status = resp.status();
+        let Some(page) = self.find(status) else {
+            return resp;
+        };
+        // 错误说明与详情取自默认 JSON 错误响应的 error 与 details 字段
+        let (head, body) = resp.into_parts();
+        let default: Value = body
+            .try_into_bytes()
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        let details = match &default["details"] {
+            Value::String(s) => s.clone(),
+            Value::Array(items) => items
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join("; "),
+            _ => String::new(),
+        };
+        let context = Context {
+            req,
+            route,
+            status,
+            error: default["error"].as_str().unwrap_or_default().to_string(),
+            details,
+            upstream_status: None,
+        };
+        render(page, head, &context)
+    }
+
+    // intercept 为 true 的错误页替换上游返回的错误响应，其他响应原样返回
+    pub fn intercept(&self, req: &HttpRequest, route: &str, resp: HttpResponse) -> HttpResponse {
+        let status = resp.status();
+        if !(status.is_client_error() || status.is_server_error()) {
+            return resp;
+        }
+        let Some(page) = self.find(status).filter(|p| p.intercept) else {
+            return resp;
+        };
+        let context = Context {
+            req,
+            route,
+            status,
+            error: status.canonical_reason().unwrap_or_default().to_string(),
+            details: String::new(),
+            upstream_status: Some(status),
+        };
+        let (head, _) = resp.into_parts();
+        render(page, head, &context)
+    }
+}
+
+// 渲染错误页，保留状态码与其他响应头，去掉与原响应体相关的响应头
+fn render(page: &Page, mut head: HttpResponse<()>, context: &Context) -> HttpResponse {
+    let body = match &page.body {
+        Body::Text(template, Escape::Html) => template.render(context, escape_html),
+        Body::Text(template, Escape::Json) => template.render(context, escape_json),
+        Body::Text(template, Escape::None) => template.render(context, |s| s.to_string()),
+        Body::Json(template) => template.render(context).to_string(),
+    };
+    let headers = head.headers_mut();
+    for name in [
+        header::CONTENT_LENGTH,
+        header::CONTENT_ENCODING,
+        header::ETAG,
+        header::LAST_MODIFIED,
+    ] {
+        headers.remove(name);
+    }
+    headers.insert(header::CONTENT_TYPE, page.content_type.clone());
+    head.set_body(body).map_into_boxed_body()
+}
+
+// HTML 转义，变量的值可能来自客户端（如请求路径）；XML 网关用于 XML 文本
+pub fn escape_html(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// JSON 字符串转义，不含两端的引号
+fn escape_json(value: &str) -> String {
+    let quoted = Value::String(value.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
@@ -0,0 +1,539 @@
+// ==================== ESI 片段组装 ====================
+//
+// 路由配置 [routes.esi] 后，上游返回的 HTML 中的 Edge Side Includes 标签由代理处理：
+// <esi:include src="..." alt="..." onerror="continue"/> 替换为片段的内容，<esi:remove> 的内容与
+// <esi:comment/> 被去掉，<!--esi ... --> 只去掉注释标记。相对地址按路由表找到对应路由的上游获取，
+// 绝对地址只允许 hosts 中的主机；同一层的片段并发获取，片段中的 include 继续展开，最多 max_depth 层。
+// 片段按自身的 Cache-Control（s-maxage 优先于 max-age）单独缓存，没有 Set-Cookie 且不是 private、
+// no-store、no-cache 的片段才缓存，页面本身可以不缓存而只缓存其中不常变化的部分。
+// 片段获取失败时先尝试 alt，仍然失败且没有 onerror="continue" 时返回 502。组装后的页面不再
+// 对应上游的 ETag 与 Last-Modified，这两个响应头与 Surrogate-Control 不返回给客户端
+
+use crate::{AppState, ProxyError, metrics};
+use actix_web::HttpRequest;
+use actix_web::web::Bytes;
+use reqwest::header::{CACHE_CONTROL, HeaderMap, HeaderName, SET_COOKIE, VARY};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// ESI 配置：对应配置文件中的 [routes.esi]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EsiConfig {
+    #[serde(default = "default_types")]
+    pub types: Vec<String>, // 处理 ESI 标签的响应类型
+    #[serde(default = "default_max_depth")]
+    pub max_depth: usize, // 片段嵌套的最大层数
+    #[serde(default = "default_max_includes")]
+    pub max_includes: usize, // 每个页面最多获取的片段数
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64, // 获取单个片段的超时时间（毫秒）
+    #[serde(default)]
+    pub hosts: Vec<String>, // 允许的绝对地址主机，为空时只允许相对地址
+    #[serde(default = "default_forward_headers")]
+    pub forward_headers: Vec<String>, // 随片段请求转发的客户端请求头
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize, // 片段缓存最多保存的条目数，0 表示不缓存
+}
+
+// 以下函数为 ESI 配置提供默认值
+fn default_types() -> Vec<String> {
+    vec!["text/html".to_string()]
+}
+
+fn default_max_depth() -> usize {
+    3
+}
+
+fn default_max_includes() -> usize {
+    32
+}
+
+fn default_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_forward_headers() -> Vec<String> {
+    vec!["Cookie".to_string(), "Accept-Language".to_string()]
+}
+
+fn default_max_entries() -> usize {
+    1000
+}
+
+// 缓存的片段
+#[derive(Debug)]
+struct Fragment {
+    body: Bytes,
+    expires: Instant,
+}
+
+type FragmentCache = Arc<Mutex<HashMap<String, Fragment>>>;
+
+// 路由的 ESI 处理
+#[derive(Debug)]
+pub struct Esi {
+    types: Vec<String>,
+    max_depth: usize,
+    max_includes: usize,
+    timeout: Duration,
+    hosts: Vec<String>,               // 小写
+    forward_headers: Vec<HeaderName>, // 随片段请求转发的请求头
+    max_entries: usize,
+    cache: FragmentCache, // 片段地址 -> 片段
+}
+
+impl Esi {
+    // 从配置构建，层数或片段数为0、请求头名称无效时启动失败
+    pub fn new(config: &EsiConfig) -> Result<Self, String> {
+        if config.max_depth == 0 || config.max_includes == 0 {
+            return Err("ESI 的 max_depth 与 max_includes 必须大于0".to_string());
+        }
+        let forward_headers = config
+            .forward_headers
+            .iter()
+            .map(|h| {
+                HeaderName::from_bytes(h.trim().as_bytes())
+                    .map_err(|_| format!("无效的请求头名称: {}", h))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Esi {
+            types: config
+                .types
+                .iter()
+                .map(|t| t.trim().to_ascii_lowercase())
+                .collect(),
+            max_depth: config.max_depth,
+            max_includes: config.max_includes,
+            timeout: Duration::from_millis(config.timeout_ms),
+            hosts: config
+                .hosts
+                .iter()
+                .map(|h| h.trim().to_ascii_lowercase())
+                .collect(),
+            forward_headers,
+            max_entries: config.max_entries,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    // 上游响应是否需要处理：类型匹配且未经压缩
+    pub fn applies(&self, headers: &HeaderMap) -> bool {
+        !crate::sub_filter::encoded(headers)
+            && self.types.contains(&crate::sub_filter::media_type(headers))
+    }
+
+    // 展开页面中的 ESI 标签；页面不含 ESI 标签或不是 UTF-8 时返回 None
+    pub async fn assemble(
+        &self,
+        req: &HttpRequest,
+        state: &AppState,
+        route: &str,
+        body: &[u8],
+    ) -> Result<Option<Vec<u8>>, ProxyError> {
+        let Ok(html) = std::str::from_utf8(body) else {
+            log::warn!("路由 {} 的响应不是 UTF-8 文本，跳过 ESI 处理", route);
+            return Ok(None);
+        };
+        if !has_markup(html) {
+            return Ok(None);
+        }
+        let mut headers = HeaderMap::new();
+        for name in &self.forward_headers {
+            for value in req.headers().get_all(name) {
+                headers.append(name.clone(), value.clone());
+            }
+        }
+        let mut page = Page {
+            req,
+            state,
+            route,
+            headers,
+            includes: 0,
+        };
+        let html = self.expand(&mut page, html.to_string(), 0).await?;
+        Ok(Some(html.into_bytes()))
+    }
+
+    // 展开一层 ESI 标签：并发获取这一层的片段，再逐个展开片段中的标签
+    fn expand<'a>(
+        &'a self,
+        page: &'a mut Page<'_>,
+        html: String,
+        depth: usize,
+    ) -> Pin<Box<dyn Future<Output = Result<String, ProxyError>> + 'a>> {
+        Box::pin(async move {
+            let pieces = parse(&html);
+            let mut fetches = tokio::task::JoinSet::new();
+            let mut failures: HashMap<usize, String> = HashMap::new();
+            for (index, piece) in pieces.iter().enumerate() {
+                let Piece::Include(include) = piece else {
+                    continue;
+                };
+                page.includes += 1;
+                if depth >= self.max_depth {
+                    failures.insert(index, format!("超过最大嵌套深度 {}", self.max_depth));
+                    continue;
+                }
+                if page.includes > self.max_includes {
+                    failures.insert(index, format!("超过片段数量上限 {}", self.max_includes));
+                    continue;
+                }
+                let candidates: Vec<_> = [Some(&include.src), include.alt.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .map(|src| self.request(page, src))
+                    .collect();
+                let (cache, max_entries) = (self.cache.clone(), self.max_entries);
+                let route = page.route.to_string();
+                fetches.spawn(async move {
+                    let result = fetch(candidates, &cache, max_entries).await;
+                    let outcome = match &result {
+                        Ok((_, true)) => "hit",
+                        Ok((_, false)) => "miss",
+                        Err(_) => "error",
+                    };
+                    metrics::counter_inc(
+                        "proxy_esi_fragments_total",
+                        &[("route", &route), ("result", outcome)],
+                    );
+                    (index, result.map(|(body, _)| body))
+                });
+            }
+            let mut fragments: HashMap<usize, Bytes> = HashMap::new();
+            for (index, result) in fetches.join_all().await {
+                match result {
+                    Ok(body) => {
+                        fragments.insert(index, body);
+                    }
+                    Err(e) => {
+                        failures.insert(index, e);
+                    }
+                }
+            }
+
+            let mut out = String::with_capacity(html.len());
+            for (index, piece) in pieces.into_iter().enumerate() {
+                let include = match piece {
+                    Piece::Text(text) => {
+                        out.push_str(text);
+                        continue;
+                    }
+                    Piece::Include(include) => include,
+                };
+                if let Some(body) = fragments.remove(&index) {
+                    let fragment = String::from_utf8_lossy(&body).into_owned();
+                    match has_markup(&fragment) {
+                        true => out.push_str(&self.expand(page, fragment, depth + 1).await?),
+                        false => out.push_str(&fragment),
+                    }
+                    continue;
+                }
+                let reason = failures.remove(&index).unwrap_or_default();
+                if !include.continue_on_error {
+                    log::warn!(
+                        "路由 {} 的 ESI 片段 {} 获取失败: {}",
+                        page.route,
+                        include.src,
+                        reason
+                    );
+                    return Err(ProxyError::EsiIncludeFailed(format!(
+                        "{}: {}",
+                        include.src, reason
+                    )));
+                }
+                log::warn!(
+                    "路由 {} 的 ESI 片段 {} 获取失败，按 onerror=\"continue\" 忽略: {}",
+                    page.route,
+                    include.src,
+                    reason
+                );
+            }
+            Ok(out)
+        })
+    }
+
+    // 解析片段地址并构建请求；相对地址按页面路径解析后交给匹配的路由的上游
+    fn request(
+        &self,
+        page: &Page<'_>,
+        src: &str,
+    ) -> Result<(String, reqwest::RequestBuilder), String> {
+        const BASE: &str = "esi.invalid";
+        let base = reqwest::Url::parse(&format!("http://{}{}", BASE, page.req.path()))
+            .map_err(|e| e.to_string())?;
+        let url = base
+            .join(src)
+            .map_err(|e| format!("无效的片段地址: {}", e))?;
+        let global = page.state.client.load();
+        let (url, client) = match url.host_str() {
+            Some(BASE) => {
+                let path_and_query = match url.query() {
+                    Some(query) => format!("{}?{}", url.path(), query),
+                    None => url.path().to_string(),
+                };
+                let routes = page.state.routes.load();
+                let route = routes
+                    .find(url.path())
+                    .ok_or_else(|| format!("没有匹配 {} 的路由", url.path()))?;
+                let targets = route.targets();
+                let target = &targets[route.pick_target(&targets)];
+                let client = route.client.as_ref().unwrap_or(&global).clone();
+                (format!("{}{}", target.base_url(), path_and_query), client)
+            }
+            host => {
+                let host = host.unwrap_or_default().to_ascii_lowercase();
+                if !matches!(url.scheme(), "http" | "https") || !self.hosts.contains(&host) {
+                    return Err(format!("不允许的片段主机: {}", host));
+                }
+                (url.to_string(), global.as_ref().clone())
+            }
+        };
+        let builder = client
+            .get(&url)
+            .headers(page.headers.clone())
+            .timeout(self.timeout);
+        Ok((url, builder))
+    }
+}
+
+// 一个页面的展开过程
+struct Page<'a> {
+    req: &'a HttpRequest,
+    state: &'a AppState,
+    route: &'a str,
+    headers: HeaderMap, // 随片段请求转发的请求头
+    includes: usize,    // 已遇到的 include 数
+}
+
+// 依次尝试 src 与 alt，先查缓存；成功时返回片段内容与是否命中缓存
+async fn fetch(
+    candidates: Vec<Result<(String, reqwest::RequestBuilder), String>>,
+    cache: &FragmentCache,
+    max_entries: usize,
+) -> Result<(Bytes, bool), String> {
+    let mut last_error = String::new();
+    for candidate in candidates {
+        let (url, builder) = match candidate {
+            Ok(candidate) => candidate,
+            Err(e) => {
+                last_error = e;
+                continue;
+            }
+        };
+        if let Some(fragment) = cache.lock().unwrap().get(&url)
+            && fragment.expires > Instant::now()
+        {
+            return Ok((fragment.body.clone(), true));
+        }
+        let response = match builder.send().await {
+            Ok(response) if response.status().is_success() => response,
+            Ok(response) => {
+                last_error = format!("上游返回 {}", response.status());
+                continue;
+            }
+            Err(e) => {
+                last_error = e.to_string();
+                continue;
+            }
+        };
+        let ttl = cacheable_for(response.headers());
+        let body = match response.bytes().await {
+            Ok(body) => body,
+            Err(e) => {
+                last_error = e.to_string();
+                continue;
+            }
+        };
+        if let Some(ttl) = ttl {
+            let mut cache = cache.lock().unwrap();
+            let now = Instant::now();
+            cache.retain(|_, f| f.expires > now);
+            if cache.len() < max_entries || cache.contains_key(&url) {
+                let expires = now + ttl;
+                let fragment = Fragment {
+                    body: body.clone(),
+                    expires,
+                };
+                cache.insert(url, fragment);
+            }
+        }
+        return Ok((body, false));
+    }
+    Err(last_error)
+}
+
+// 片段可以缓存的时间：s-maxage 优先于 max-age，减去 Age；带 Set-Cookie、按请求头区分（Vary）
+// 或声明 private、no-store、no-cache 的片段不缓存
+fn cacheable_for(headers: &HeaderMap) -> Option<Duration> {
+    if headers.contains_key(SET_COOKIE) {
+        return None;
+    }
+    let varies = headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| !v.trim().is_empty() && !v.trim().eq_ignore_ascii_case("accept-encoding"));
+    if varies {
+        return None;
+    }
+    let (mut max_age, mut s_maxage) = (None, None);
+    for directive in headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+    {
+        let (name, value) = directive.split_once('=').unwrap_or((directive, ""));
+        let value = value.trim().trim_matches('"').parse::<u64>().ok();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "private" | "no-store" | "no-cache" => return None,
+            "max-age" => max_age = value,
+            "s-maxage" => s_maxage = value,
+            _ => {}
+        }
+    }
+    let age = headers
+        .get(reqwest::header::AGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(0);
+    match s_maxage.or(max_age)?.saturating_sub(age) {
+        0 => None,
+        ttl => Some(Duration::from_secs(ttl)),
+    }
+}
+
+// 页面中的一段：原样输出的文本或需要获取的片段
+#[derive(Debug)]
+enum Piece<'a> {
+    Text(&'a str),
+    Include(Include),
+}
+
+#[derive(Debug)]
+struct Include {
+    src: String,
+    alt: Option<String>,
+    continue_on_error: bool, // onerror="continue"
+}
+
+fn has_markup(html: &str) -> bool {
+    html.contains("<esi:") || html.contains("<!--esi")
+}
+
+// 把页面切分为文本与 include；不支持的 ESI 标签（如 esi:choose）原样保留
+fn parse(html: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = html;
+    loop {
+        let next = [rest.find("<esi:"), rest.find("<!--esi")]
+            .into_iter()
+            .flatten()
+            .min();
+        let Some(start) = next else {
+            pieces.push(Piece::Text(rest));
+            return pieces;
+        };
+        pieces.push(Piece::Text(&rest[..start]));
+        rest = &rest[start..];
+
+        // <!--esi ... -->：去掉注释标记，其中的标签照常处理
+        if let Some(inner) = rest.strip_prefix("<!--esi") {
+            let Some(end) = inner.find("-->") else {
+                pieces.push(Piece::Text(rest));
+                return pieces;
+            };
+            pieces.extend(parse(&inner[..end]));
+            rest = &inner[end + 3..];
+            continue;
+        }
+
+        let Some(tag_end) = rest.find('>') else {
+            pieces.push(Piece::Text(rest));
+            return pieces;
+        };
+        let tag = &rest[..=tag_end];
+        let after = &rest[tag_end + 1..];
+        let self_closing = tag.ends_with("/>");
+        let name: String = tag["<esi:".len()..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect();
+        match name.as_str() {
+            "include" => {
+                rest = match self_closing {
+                    true => after,
+                    false => after
+                        .find("</esi:include>")
+                        .map_or(after, |end| &after[end + "</esi:include>".len()..]),
+                };
+                match attribute(tag, "src") {
+                    Some(src) => pieces.push(Piece::Include(Include {
+                        src,
+                        alt: attribute(tag, "alt"),
+                        continue_on_error: attribute(tag, "onerror").as_deref() == Some("continue"),
+                    })),
+                    None => log::warn!("忽略没有 src 的 ESI 标签: {}", tag),
+                }
+            }
+            "remove" => {
+                rest = match after.find("</esi:remove>") {
+                    Some(end) => &after[end + "</esi:remove>".len()..],
+                    None => "",
+                };
+            }
+            "comment" => rest = after,
+            _ => {
+                pieces.push(Piece::Text(tag));
+                rest = after;
+            }
+        }
+    }
+}
+
+// 读取标签的属性值，支持单引号与双引号，值中的 &amp; 还原为 &
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .trim_end_matches('/');
+    // 跳过标签名
+    rest = rest.trim_start_matches(|c: char| !c.is_whitespace());
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        rest = rest[key_end..].trim_start();
+        let Some(value_part) = rest.strip_prefix('=') else {
+            continue; // 没有值的属性
+        };
+        let value_part = value_part.trim_start();
+        let (value, remaining) = match value_part.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let inner = &value_part[1..];
+                let end = inner.find(quote).unwrap_or(inner.len());
+                (&inner[..end], inner.get(end + 1..).unwrap_or(""))
+            }
+            _ => {
+                let end = value_part
+                    .find(char::is_whitespace)
+                    .unwrap_or(value_part.len());
+                (&value_part[..end], &value_part[end..])
+            }
+        };
+        if key.eq_ignore_ascii_case(name) {
+            return Some(value.replace("&amp;", "&"));
+        }
+        rest = remaining;
+    }
+}
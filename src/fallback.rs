@@ -0,0 +1,116 @@
+// ==================== 降级响应 ====================
+//
+// 上游出错（不可达、超时或返回指定的5xx状态码）时，返回路由预先配置的降级响应，
+// 而不是直接把错误暴露给用户。降级内容可以是内联响应体、文件，或该路径最近一次成功的响应
+
+use crate::snapshot::ResponseSnapshot;
+use actix_web::HttpResponse;
+use actix_web::http::StatusCode;
+use actix_web::web;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// 降级配置：对应路由中的 [routes.fallback]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FallbackConfig {
+    #[serde(default = "default_status")]
+    pub status: u16, // 静态降级响应的状态码
+    #[serde(default)]
+    pub content_type: Option<String>, // 静态降级响应的Content-Type
+    #[serde(default)]
+    pub body: Option<String>, // 内联响应体
+    #[serde(default)]
+    pub file: Option<String>, // 从文件读取响应体，优先于body
+    #[serde(default)]
+    pub last_good: bool, // 是否优先返回该路径最近一次成功的响应
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize, // 最多保存多少个路径的成功响应
+    #[serde(default = "default_on_status")]
+    pub on_status: Vec<u16>, // 触发降级的上游状态码
+}
+
+// 以下函数为降级配置提供默认值
+fn default_status() -> u16 {
+    200
+}
+
+fn default_max_entries() -> usize {
+    1000
+}
+
+fn default_on_status() -> Vec<u16> {
+    vec![500, 502, 503, 504]
+}
+
+// 路由的降级响应
+#[derive(Debug)]
+pub struct Fallback {
+    config: FallbackConfig,
+    static_body: Option<web::Bytes>, // 静态响应体（启动时读取）
+    last_good: Mutex<HashMap<String, ResponseSnapshot>>, // 路径 -> 最近一次成功的响应
+}
+
+impl Fallback {
+    // 根据配置构建降级响应，配置了文件时在启动阶段读取
+    pub fn new(config: &FallbackConfig) -> Result<Self, String> {
+        let static_body = match (&config.file, &config.body) {
+            (Some(file), _) => {
+                Some(web::Bytes::from(std::fs::read(file).map_err(|e| {
+                    format!("读取降级响应文件 {} 失败: {}", file, e)
+                })?))
+            }
+            (None, Some(body)) => Some(web::Bytes::from(body.clone())),
+            (None, None) => None,
+        };
+        Ok(Fallback {
+            config: config.clone(),
+            static_body,
+            last_good: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // 上游返回的状态码是否应触发降级
+    pub fn applies_to_status(&self, status: StatusCode) -> bool {
+        self.config.on_status.contains(&status.as_u16())
+    }
+
+    // 保存一次成功的响应，供之后降级时使用
+    pub fn remember(
+        &self,
+        key: &str,
+        status: StatusCode,
+        headers: &reqwest::header::HeaderMap, // 上游响应头
+        body: &web::Bytes,
+    ) {
+        if !self.config.last_good || !status.is_success() {
+            return;
+        }
+        let mut last_good = self.last_good.lock().unwrap();
+        if last_good.len() >= self.config.max_entries && !last_good.contains_key(key) {
+            return; // 已达上限时只更新已有路径
+        }
+        last_good.insert(
+            key.to_string(),
+            ResponseSnapshot::new(status, headers, body),
+        );
+    }
+
+    // 生成降级响应：优先使用最近一次成功的响应，其次使用静态内容，都没有时返回None
+    pub fn respond(&self, key: &str) -> Option<HttpResponse> {
+        if self.config.last_good
+            && let Some(stored) = self.last_good.lock().unwrap().get(key)
+        {
+            return Some(stored.to_response(Some(("X-Proxy-Fallback", "last-good"))));
+        }
+
+        let body = self.static_body.clone()?;
+        let status = StatusCode::from_u16(self.config.status).unwrap_or(StatusCode::OK);
+        let mut resp = HttpResponse::build(status);
+        if let Some(content_type) = &self.config.content_type {
+            resp.content_type(content_type.as_str());
+        }
+        resp.insert_header(("X-Proxy-Fallback", "static"));
+        Some(resp.body(body))
+    }
+}
@@ -0,0 +1,147 @@
+// ==================== 请求广播 ====================
+//
+// 路由配置 [routes.fanout] 后，请求不再按轮询选择一个目标服务器，而是复制给路由的全部目标服务器并发发送，
+// 代理成为简单的 webhook 广播中继：一个 webhook 来源只需配置一个地址，事件由代理分发给多个订阅方。
+// 返回给客户端的是汇总结果：每个目标的状态码或错误与耗时，以及按 require 判断的总体状态——
+// 全部成功为 200，满足 require 但有目标失败为 207，未满足为 502，webhook 来源据此决定是否重发。
+// 目标返回 2xx 视为成功；各目标的响应体读取后丢弃
+
+use crate::ProxyError;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+// 广播配置：对应路由中的 [routes.fanout]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FanoutConfig {
+    #[serde(default)]
+    pub require: Require, // 总体成功所需的成功目标数
+}
+
+// 总体成功所需的成功目标数
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Require {
+    #[default]
+    All, // 全部目标成功
+    Any,      // 至少一个目标成功
+    Majority, // 超过半数的目标成功
+}
+
+impl Require {
+    fn met(self, succeeded: usize, total: usize) -> bool {
+        match self {
+            Require::All => succeeded == total,
+            Require::Any => succeeded > 0,
+            Require::Majority => succeeded * 2 > total,
+        }
+    }
+}
+
+// 向一个目标发送的结果
+#[derive(Debug)]
+pub struct Delivery {
+    target: String,              // 目标服务器的基础URL
+    result: Result<u16, String>, // 状态码或错误
+    time: Duration,              // 收到响应头或失败的耗时
+}
+
+impl Delivery {
+    // 读取并丢弃响应体，连接可以复用；读取失败不影响已收到的状态码
+    pub async fn new(
+        target: String,
+        result: Result<reqwest::Response, ProxyError>,
+        time: Duration,
+    ) -> Self {
+        let result = match result {
+            Ok(resp) => {
+                let status = resp.status().as_u16();
+                let _ = resp.bytes().await;
+                Ok(status)
+            }
+            Err(e) => Err(e.to_string()),
+        };
+        Delivery {
+            target,
+            result,
+            time,
+        }
+    }
+
+    fn succeeded(&self) -> bool {
+        matches!(self.result, Ok(status) if (200..300).contains(&status))
+    }
+}
+
+// 并发发送已构建好的请求，按请求的顺序返回各自的结果与耗时；超时由请求自身携带
+pub async fn send(
+    requests: Vec<reqwest::RequestBuilder>,
+    header_timeout: Option<Duration>,
+) -> Vec<(Result<reqwest::Response, ProxyError>, Duration)> {
+    let mut sends = tokio::task::JoinSet::new();
+    for (index, request) in requests.into_iter().enumerate() {
+        sends.spawn(async move {
+            let started = Instant::now();
+            let result = crate::timeouts::send(request, header_timeout).await;
+            (index, result, started.elapsed())
+        });
+    }
+    let mut results = sends.join_all().await;
+    results.sort_by_key(|(index, _, _)| *index);
+    results
+        .into_iter()
+        .map(|(_, result, time)| (result, time))
+        .collect()
+}
+
+// 汇总各目标的结果，记录指标并生成返回客户端的响应
+pub fn respond(config: &FanoutConfig, route: &str, deliveries: &[Delivery]) -> HttpResponse {
+    let succeeded = deliveries.iter().filter(|d| d.succeeded()).count();
+    let (mut builder, outcome) = match (succeeded, config.require.met(succeeded, deliveries.len()))
+    {
+        (n, true) if n == deliveries.len() => (HttpResponse::Ok(), "ok"),
+        (_, true) => (
+            HttpResponse::build(actix_web::http::StatusCode::MULTI_STATUS),
+            "partial",
+        ),
+        (_, false) => (HttpResponse::BadGateway(), "failed"),
+    };
+    crate::metrics::counter_inc(
+        "proxy_fanout_requests_total",
+        &[("route", route), ("result", outcome)],
+    );
+    if outcome != "ok" {
+        let failed: Vec<&str> = deliveries
+            .iter()
+            .filter(|d| !d.succeeded())
+            .map(|d| d.target.as_str())
+            .collect();
+        log::warn!(
+            "路由 {} 的广播有 {} 个目标失败: {}",
+            route,
+            failed.len(),
+            failed.join(", ")
+        );
+    }
+    let results: Vec<serde_json::Value> = deliveries
+        .iter()
+        .map(|d| {
+            let mut result = serde_json::json!({
+                "target": d.target,
+                "ok": d.succeeded(),
+                "duration_ms": d.time.as_millis() as u64,
+            });
+            match &d.result {
+                Ok(status) => result["status"] = (*status).into(),
+                Err(e) => result["error"] = e.as_str().into(),
+            }
+            result
+        })
+        .collect();
+    builder.json(serde_json::json!({
+        "succeeded": succeeded,
+        "failed": deliveries.len() - succeeded,
+        "results": results,
+        "request_id": crate::request_id::current()
+    }))
+}
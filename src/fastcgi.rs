@@ -0,0 +1,282 @@
+// ==================== FastCGI ====================
+//
+// 目标服务器的 protocol 为 "fastcgi" 时，请求按 FastCGI 协议发给 php-fpm 等应用服务器，
+// PHP 应用不必再在前面部署 nginx。请求转换为 CGI 参数（见 cgi.rs）、请求体作为 STDIN；
+// 应用输出的 Status 与其他 CGI 响应头转换为 HTTP 响应，STDERR 写入代理日志。
+// 脚本路径为路由 [routes.fastcgi] 的 root 加上请求路径中第一个 .php 为止的部分，之后为 PATH_INFO，
+// 以 / 结尾的路径使用 index；配置 script 后所有请求都交给这个前端控制器
+
+use crate::ProxyError;
+use crate::cgi::{self, Marked, Param};
+use actix_web::HttpRequest;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// FastCGI 配置：对应配置文件中的 [routes.fastcgi]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FastCgiConfig {
+    #[serde(default = "default_root")]
+    pub root: String, // 应用服务器上的文档根目录
+    #[serde(default = "default_index")]
+    pub index: String, // 以 / 结尾的路径使用的脚本
+    #[serde(default)]
+    pub script: Option<String>, // 所有请求都交给的前端控制器，相对于 root，如 "/index.php"
+    #[serde(default)]
+    pub socket: Option<String>, // 应用服务器的 Unix 套接字，配置后代替目标的主机与端口
+    #[serde(default)]
+    pub params: Vec<Param>, // 附加或覆盖的 FastCGI 参数
+}
+
+impl Default for FastCgiConfig {
+    fn default() -> Self {
+        FastCgiConfig {
+            root: default_root(),
+            index: default_index(),
+            script: None,
+            socket: None,
+            params: Vec::new(),
+        }
+    }
+}
+
+// 以下函数为 FastCGI 提供默认值
+fn default_root() -> String {
+    "/var/www/html".to_string()
+}
+
+fn default_index() -> String {
+    "index.php".to_string()
+}
+
+// 目标服务器配置中表示 FastCGI 的协议
+pub const PROTOCOL: &str = "fastcgi";
+
+// 路由的 FastCGI 设置
+#[derive(Debug)]
+pub struct FastCgi {
+    root: String, // 不含末尾的 /
+    index: String,
+    script: Option<String>,
+    socket: Option<String>,
+    params: Vec<(String, String)>,
+}
+
+impl FastCgi {
+    // 从配置构建，参数名为空、script 不以 / 开头或当前平台不支持 Unix 套接字时启动失败
+    pub fn new(config: &FastCgiConfig) -> Result<Self, String> {
+        if let Some(script) = config.script.as_ref().filter(|s| !s.starts_with('/')) {
+            return Err(format!("FastCGI 的 script 必须以/开头: {}", script));
+        }
+        cgi::check_socket(&config.socket)?;
+        Ok(FastCgi {
+            root: config.root.trim_end_matches('/').to_string(),
+            index: config.index.trim_start_matches('/').to_string(),
+            script: config.script.clone(),
+            socket: config.socket.clone(),
+            params: cgi::extra_params(&config.params).map_err(|e| format!("FastCGI {}", e))?,
+        })
+    }
+
+    // 请求路径对应的脚本路径与 PATH_INFO
+    fn locate(&self, path: &str) -> (String, String) {
+        if let Some(script) = &self.script {
+            return (script.clone(), String::new());
+        }
+        let path = match path.ends_with('/') {
+            true => format!("{}{}", path, self.index),
+            false => path.to_string(),
+        };
+        let split = path
+            .match_indices(".php")
+            .map(|(i, _)| i + 4)
+            .find(|&end| end == path.len() || path[end..].starts_with('/'));
+        match split {
+            Some(end) => (path[..end].to_string(), path[end..].to_string()),
+            None => (path, String::new()),
+        }
+    }
+
+    // 为发往 FastCGI 目标的请求加上标记，url 为发往上游的地址
+    pub fn mark(
+        &self,
+        builder: reqwest::RequestBuilder,
+        req: &HttpRequest,
+        url: &reqwest::Url,
+        forwarded: &crate::forwarded::ForwardedPolicy,
+    ) -> reqwest::RequestBuilder {
+        let path = cgi::decode_path(url.path());
+        let (script_name, path_info) = self.locate(&path);
+        let mut params = cgi::server_params(req, forwarded);
+        params.extend([
+            ("DOCUMENT_ROOT", self.root.clone()),
+            ("DOCUMENT_URI", path),
+            ("SCRIPT_FILENAME", format!("{}{}", self.root, script_name)),
+            ("SCRIPT_NAME", script_name),
+            ("REDIRECT_STATUS", "200".to_string()), // php-cgi 的 force-cgi-redirect 检查
+        ]);
+        if !path_info.is_empty() {
+            params.push(("PATH_TRANSLATED", format!("{}{}", self.root, path_info)));
+            params.push(("PATH_INFO", path_info));
+        }
+        let marked = Marked::new(self.socket.clone(), params, self.params.clone());
+        cgi::mark(builder, req, marked)
+    }
+}
+
+// 建立连接并完成一次请求，返回转换后的 HTTP 响应
+pub async fn send(
+    marked: &Marked,
+    request: &reqwest::Request,
+) -> Result<reqwest::Response, ProxyError> {
+    let stream = cgi::connect(marked, request.url()).await?;
+    let (stdout, stderr) = exchange(stream, &records(marked, request))
+        .await
+        .map_err(ProxyError::InvalidAppResponse)?;
+    if !stderr.is_empty() {
+        log::warn!(
+            "FastCGI 应用 {} 输出错误: {}",
+            request.url().path(),
+            String::from_utf8_lossy(&stderr).trim_end()
+        );
+    }
+    response(&stdout).map_err(ProxyError::InvalidAppResponse)
+}
+
+// 一个请求的全部记录：BEGIN_REQUEST、PARAMS 与 STDIN
+fn records(marked: &Marked, request: &reqwest::Request) -> Vec<u8> {
+    let mut out = Vec::new();
+    // 角色为 RESPONDER，标志为 0：应用在响应后关闭连接
+    record(BEGIN_REQUEST, &[0, 1, 0, 0, 0, 0, 0, 0], &mut out);
+    let mut encoded = Vec::new();
+    for (name, value) in marked.params(request) {
+        pair(name.as_bytes(), value.as_bytes(), &mut encoded);
+    }
+    stream(PARAMS, &encoded, &mut out);
+    stream(STDIN, cgi::body(request), &mut out);
+    out
+}
+
+// 记录类型
+const BEGIN_REQUEST: u8 = 1;
+const END_REQUEST: u8 = 3;
+const PARAMS: u8 = 4;
+const STDIN: u8 = 5;
+const STDOUT: u8 = 6;
+const STDERR: u8 = 7;
+
+// 每个连接只有一个请求，请求ID固定为 1
+const REQUEST_ID: u16 = 1;
+
+// 一条记录：8 字节头、内容与补齐到 8 字节的填充
+fn record(kind: u8, content: &[u8], out: &mut Vec<u8>) {
+    let padding = (8 - content.len() % 8) % 8;
+    let [id_high, id_low] = REQUEST_ID.to_be_bytes();
+    let [len_high, len_low] = (content.len() as u16).to_be_bytes();
+    out.extend_from_slice(&[
+        1,
+        kind,
+        id_high,
+        id_low,
+        len_high,
+        len_low,
+        padding as u8,
+        0,
+    ]);
+    out.extend_from_slice(content);
+    out.resize(out.len() + padding, 0);
+}
+
+// 数据流：按记录的最大长度分段，以空记录结束
+fn stream(kind: u8, data: &[u8], out: &mut Vec<u8>) {
+    for chunk in data.chunks(u16::MAX as usize) {
+        record(kind, chunk, out);
+    }
+    record(kind, &[], out);
+}
+
+// 名称-值对：长度小于 128 时为 1 字节，否则为最高位置 1 的 4 字节
+fn pair(name: &[u8], value: &[u8], out: &mut Vec<u8>) {
+    for len in [name.len(), value.len()] {
+        match len < 128 {
+            true => out.push(len as u8),
+            false => out.extend_from_slice(&(len as u32 | 0x8000_0000).to_be_bytes()),
+        }
+    }
+    out.extend_from_slice(name);
+    out.extend_from_slice(value);
+}
+
+// 发送请求的记录并读取到 END_REQUEST 为止，返回 STDOUT 与 STDERR 的内容
+async fn exchange<S>(mut io: S, records: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    io.write_all(records)
+        .await
+        .map_err(|e| format!("发送请求失败: {}", e))?;
+    let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+    loop {
+        let mut header = [0u8; 8];
+        io.read_exact(&mut header)
+            .await
+            .map_err(|e| format!("应用在结束请求前关闭了连接: {}", e))?;
+        let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+        let mut content = vec![0u8; len + header[6] as usize];
+        io.read_exact(&mut content)
+            .await
+            .map_err(|e| format!("读取记录失败: {}", e))?;
+        content.truncate(len);
+        match header[1] {
+            STDOUT => stdout.extend_from_slice(&content),
+            STDERR => stderr.extend_from_slice(&content),
+            END_REQUEST => {
+                return match content.get(4) {
+                    Some(0) => Ok((stdout, stderr)),
+                    Some(2) => Err("应用服务器过载".to_string()),
+                    status => Err(format!("应用拒绝了请求: {:?}", status)),
+                };
+            }
+            _ => {} // 管理记录等与该请求无关的记录
+        }
+    }
+}
+
+// 把应用输出的 CGI 响应转换为 HTTP 响应：Status 为状态码，只有 Location 时为 302
+fn response(stdout: &[u8]) -> Result<reqwest::Response, String> {
+    let (head, body) = match stdout.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(i) => (&stdout[..i], &stdout[i + 4..]),
+        None => match stdout.windows(2).position(|w| w == b"\n\n") {
+            Some(i) => (&stdout[..i], &stdout[i + 2..]),
+            None => return Err("应用的输出中没有响应头".to_string()),
+        },
+    };
+    let head = std::str::from_utf8(head).map_err(|_| "应用的响应头不是文本".to_string())?;
+    let mut builder = hyper::Response::builder();
+    let mut status = None;
+    let mut location = false;
+    for line in head.lines() {
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(format!("无效的响应头: {:?}", line));
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "status" => {
+                let code = value.split(' ').next().unwrap_or_default();
+                status = Some(
+                    code.parse::<u16>()
+                        .map_err(|_| format!("无效的状态: {:?}", value))?,
+                );
+            }
+            name => {
+                location |= name == "location";
+                builder = builder.header(name, value);
+            }
+        }
+    }
+    let status = status.unwrap_or(if location { 302 } else { 200 });
+    let response = builder
+        .status(status)
+        .body(body.to_vec())
+        .map_err(|e| e.to_string())?;
+    Ok(reqwest::Response::from(response))
+}
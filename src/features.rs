@@ -0,0 +1,232 @@
+// ==================== 功能开关 ====================
+//
+// [features] 在运行时关闭或打开中间件与子系统，不需要删除它们的配置：关闭后请求跳过该功能，配置保持原样，
+// 重新打开即恢复。管理接口 PUT /features 可以临时覆盖配置中的开关，覆盖只保存在内存中，
+// 热加载后仍然生效，DELETE /features 或重启后恢复为配置的值。
+// 开关只决定已配置的功能是否生效，打开没有配置（如没有 [tracing] endpoint）的功能没有效果
+
+use crate::metrics;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+// 功能开关配置：对应配置文件中的 [features]，缺省都打开
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FeaturesConfig {
+    #[serde(default = "default_enabled")]
+    pub acl: bool, // 访问控制
+    #[serde(default = "default_enabled")]
+    pub access_log: bool, // 访问日志
+    #[serde(default = "default_enabled")]
+    pub cache: bool, // 响应缓存
+    #[serde(default = "default_enabled")]
+    pub capture: bool, // 调试报文捕获
+    #[serde(default = "default_enabled")]
+    pub compression: bool, // 响应压缩
+    #[serde(default = "default_enabled")]
+    pub concurrency: bool, // 并发限制与排队
+    #[serde(default = "default_enabled")]
+    pub hedge: bool, // 对冲请求
+    #[serde(default = "default_enabled")]
+    pub load_shedding: bool, // 自适应降载
+    #[serde(default = "default_enabled")]
+    pub tracing: bool, // 分布式追踪
+}
+
+// 以下函数为功能开关提供默认值
+fn default_enabled() -> bool {
+    true
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        FeaturesConfig {
+            acl: true,
+            access_log: true,
+            cache: true,
+            capture: true,
+            compression: true,
+            concurrency: true,
+            hedge: true,
+            load_shedding: true,
+            tracing: true,
+        }
+    }
+}
+
+// 可以开关的功能
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Feature {
+    Acl,
+    AccessLog,
+    Cache,
+    Capture,
+    Compression,
+    Concurrency,
+    Hedge,
+    LoadShedding,
+    Tracing,
+}
+
+// 所有功能，顺序与 Features 中的下标一致
+const ALL: [Feature; 9] = [
+    Feature::Acl,
+    Feature::AccessLog,
+    Feature::Cache,
+    Feature::Capture,
+    Feature::Compression,
+    Feature::Concurrency,
+    Feature::Hedge,
+    Feature::LoadShedding,
+    Feature::Tracing,
+];
+
+impl Feature {
+    // 功能名称，与 [features] 中的键相同
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Acl => "acl",
+            Feature::AccessLog => "access_log",
+            Feature::Cache => "cache",
+            Feature::Capture => "capture",
+            Feature::Compression => "compression",
+            Feature::Concurrency => "concurrency",
+            Feature::Hedge => "hedge",
+            Feature::LoadShedding => "load_shedding",
+            Feature::Tracing => "tracing",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Feature> {
+        ALL.into_iter().find(|f| f.name() == name)
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn configured(self, config: &FeaturesConfig) -> bool {
+        match self {
+            Feature::Acl => config.acl,
+            Feature::AccessLog => config.access_log,
+            Feature::Cache => config.cache,
+            Feature::Capture => config.capture,
+            Feature::Compression => config.compression,
+            Feature::Concurrency => config.concurrency,
+            Feature::Hedge => config.hedge,
+            Feature::LoadShedding => config.load_shedding,
+            Feature::Tracing => config.tracing,
+        }
+    }
+}
+
+// 管理接口的覆盖：没有覆盖、关闭、打开
+const NO_OVERRIDE: u8 = 0;
+const OFF: u8 = 1;
+const ON: u8 = 2;
+
+// 运行时的功能开关，每个请求都会读取，用原子变量保存
+pub struct Features {
+    configured: [AtomicBool; ALL.len()], // 配置中的开关
+    overrides: [AtomicU8; ALL.len()],    // 管理接口的覆盖
+}
+
+impl Features {
+    pub fn new(config: &FeaturesConfig) -> Self {
+        let features = Features {
+            configured: std::array::from_fn(|_| AtomicBool::new(true)),
+            overrides: std::array::from_fn(|_| AtomicU8::new(NO_OVERRIDE)),
+        };
+        features.configure(config);
+        features
+    }
+
+    // 应用新的配置，启动与热加载时调用；管理接口的覆盖保留
+    pub fn configure(&self, config: &FeaturesConfig) {
+        for feature in ALL {
+            let enabled = feature.configured(config);
+            let old = self.configured[feature.index()].swap(enabled, Ordering::Relaxed);
+            if old != enabled && !enabled {
+                log::info!("功能 {} 已在配置中关闭", feature.name());
+            }
+        }
+        self.export();
+    }
+
+    // 功能当前是否生效
+    pub fn enabled(&self, feature: Feature) -> bool {
+        match self.overrides[feature.index()].load(Ordering::Relaxed) {
+            OFF => false,
+            ON => true,
+            _ => self.configured[feature.index()].load(Ordering::Relaxed),
+        }
+    }
+
+    // 管理接口覆盖开关，值为 None 时去掉覆盖；名称未知时不做任何修改
+    pub fn set(&self, changes: &BTreeMap<String, Option<bool>>) -> Result<(), String> {
+        let mut parsed = Vec::with_capacity(changes.len());
+        for (name, enabled) in changes {
+            let feature = Feature::parse(name).ok_or(format!(
+                "未知的功能: {}（可选: {}）",
+                name,
+                ALL.map(Feature::name).join(", ")
+            ))?;
+            parsed.push((feature, *enabled));
+        }
+        for (feature, enabled) in parsed {
+            let value = match enabled {
+                None => NO_OVERRIDE,
+                Some(false) => OFF,
+                Some(true) => ON,
+            };
+            self.overrides[feature.index()].store(value, Ordering::Relaxed);
+            match enabled {
+                None => log::warn!("管理接口去掉功能 {} 的覆盖，恢复为配置的值", feature.name()),
+                Some(false) => log::warn!("管理接口关闭功能 {}", feature.name()),
+                Some(true) => log::warn!("管理接口打开功能 {}", feature.name()),
+            }
+        }
+        self.export();
+        Ok(())
+    }
+
+    // 去掉所有覆盖，恢复为配置的值
+    pub fn reset(&self) {
+        for value in &self.overrides {
+            value.store(NO_OVERRIDE, Ordering::Relaxed);
+        }
+        self.export();
+    }
+
+    // 每个功能是否生效、配置的值与管理接口的覆盖
+    pub fn status(&self) -> serde_json::Value {
+        let features: serde_json::Map<String, serde_json::Value> = ALL
+            .into_iter()
+            .map(|feature| {
+                let value = serde_json::json!({
+                    "enabled": self.enabled(feature),
+                    "configured": self.configured[feature.index()].load(Ordering::Relaxed),
+                    "override": match self.overrides[feature.index()].load(Ordering::Relaxed) {
+                        OFF => Some(false),
+                        ON => Some(true),
+                        _ => None,
+                    },
+                });
+                (feature.name().to_string(), value)
+            })
+            .collect();
+        serde_json::Value::Object(features)
+    }
+
+    // 更新 proxy_feature_enabled 指标
+    fn export(&self) {
+        for feature in ALL {
+            let enabled = if self.enabled(feature) { 1.0 } else { 0.0 };
+            metrics::gauge_set(
+                "proxy_feature_enabled",
+                &[("feature", feature.name())],
+                enabled,
+            );
+        }
+    }
+}
@@ -0,0 +1,694 @@
+// ==================== 正向代理与 HTTPS 解密 ====================
+//
+// [forward_proxy] 在独立端口上提供正向代理，应用把它设为 HTTP 代理（http_proxy/https_proxy）后：
+// 绝对地址的 HTTP 请求（GET http://host/path）由代理转发，CONNECT 建立到目标的 TCP 隧道。正向代理
+// 不经过路由表，只服务本机开发调试。需要查看或修改应用的 HTTPS 流量时可以显式启用 [forward_proxy.mitm]：
+// CONNECT 之后代理用本地 CA 为目标主机签发证书并完成 TLS 握手，解密后的请求与转发的 HTTP 请求一样
+// 进入访问日志、/tap 与报文捕获，按请求头规则修改后再经 HTTPS 发往目标。解密默认关闭，客户端必须
+// 信任该 CA；启用时只能监听回环地址（除非显式允许），并在日志与标准错误输出醒目的警告
+
+use crate::header_rules::{Context as RuleContext, HeaderRules, HeaderRulesConfig};
+use crate::{AppState, ProxyError};
+use actix_codec::Framed;
+use actix_http::{HttpService, Protocol, Request, Response, body::MessageBody, h1};
+use actix_server::{Server, ServerBuilder};
+use actix_service::{
+    IntoServiceFactory, Service, ServiceFactory, ServiceFactoryExt, fn_service, map_config,
+};
+use actix_web::dev::{AppConfig, Extensions};
+use actix_web::{HttpRequest, HttpResponse, http::Method, web};
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::x509::extension::{
+    BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
+};
+use openssl::x509::{X509, X509NameBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::TcpStream;
+
+// 正向代理配置：对应配置文件中的 [forward_proxy]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ForwardProxyConfig {
+    #[serde(default)]
+    pub enabled: bool, // 是否启用正向代理
+    #[serde(default = "default_host")]
+    pub host: String, // 监听地址，可以是逗号分隔的多个
+    #[serde(default = "default_port")]
+    pub port: u16, // 监听端口
+    #[serde(default = "default_connect_ports")]
+    pub connect_ports: Vec<u16>, // CONNECT 允许的目标端口
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64, // 转发请求的总超时与建立隧道的超时(毫秒)
+    #[serde(default)]
+    pub mitm: MitmConfig, // HTTPS 解密（只用于开发调试）
+}
+
+// HTTPS 解密配置：对应配置文件中的 [forward_proxy.mitm]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MitmConfig {
+    #[serde(default)]
+    pub enabled: bool, // 是否解密 CONNECT 隧道中的 HTTPS 流量，默认关闭
+    #[serde(default)]
+    pub ca_cert: String, // 签发主机证书的 CA 证书（PEM），客户端需要信任它
+    #[serde(default)]
+    pub ca_key: String, // CA 私钥（PEM）
+    #[serde(default)]
+    pub hosts: Vec<String>, // 只解密这些主机，"*.example.com" 匹配子域名，为空表示全部
+    #[serde(default)]
+    pub allow_remote_clients: bool, // 是否允许监听非回环地址
+    #[serde(default)]
+    pub accept_invalid_certs: bool, // 解密后访问目标时是否接受无效证书
+    #[serde(default)]
+    pub request_headers: Option<HeaderRulesConfig>, // 解密后的请求发往目标之前的请求头规则
+    #[serde(default)]
+    pub response_headers: Option<HeaderRulesConfig>, // 解密后的响应返回客户端之前的响应头规则
+}
+
+impl Default for ForwardProxyConfig {
+    fn default() -> Self {
+        ForwardProxyConfig {
+            enabled: false,
+            host: default_host(),
+            port: default_port(),
+            connect_ports: default_connect_ports(),
+            timeout_ms: default_timeout_ms(),
+            mitm: MitmConfig::default(),
+        }
+    }
+}
+
+// 以下函数为正向代理配置提供默认值
+fn default_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_port() -> u16 {
+    3128
+}
+
+fn default_connect_ports() -> Vec<u16> {
+    vec![443]
+}
+
+fn default_timeout_ms() -> u64 {
+    30000
+}
+
+// 监听的名称，也是 systemd 套接字的 FileDescriptorName
+const NAME: &str = "forward_proxy";
+
+// 请求头规则、报文捕获与日志中使用的路由名
+const ROUTE: &str = "forward_proxy";
+
+// 缓存的主机证书数量上限，超出后清空重新签发
+const MAX_ISSUED: usize = 1024;
+
+// 运行时的正向代理
+pub struct ForwardProxy {
+    host: String,
+    port: u16,
+    connect_ports: Vec<u16>,
+    timeout: Duration,
+    client: reqwest::Client, // 转发的 HTTP 请求使用
+    mitm: Option<Mitm>,
+}
+
+// HTTPS 解密
+struct Mitm {
+    authority: CertificateAuthority,
+    hosts: Vec<String>,
+    client: reqwest::Client, // 解密后的请求使用，按配置决定是否校验目标的证书
+    request_headers: Option<HeaderRules>,
+    response_headers: Option<HeaderRules>,
+}
+
+impl ForwardProxy {
+    // 从配置构建，未启用时返回 None；CA 证书或私钥无效、解密时监听非回环地址会导致启动失败
+    pub fn new(config: &ForwardProxyConfig) -> Result<Option<Self>, String> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let timeout = Duration::from_millis(config.timeout_ms);
+        let client = |accept_invalid_certs: bool| {
+            reqwest::Client::builder()
+                .danger_accept_invalid_certs(accept_invalid_certs)
+                .timeout(timeout)
+                .redirect(reqwest::redirect::Policy::none())
+                .no_proxy() // 不读取代理环境变量，避免请求又发回本代理
+                .build()
+                .map_err(|e| format!("正向代理: 无法创建 HTTP 客户端: {}", e))
+        };
+        let loopback = config.host.split(',').map(str::trim).all(|host| {
+            host == "localhost" || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+        });
+        let mitm = match &config.mitm {
+            mitm if !mitm.enabled => None,
+            mitm => {
+                if !loopback && !mitm.allow_remote_clients {
+                    return Err(format!(
+                        "正向代理启用了 HTTPS 解密，只能监听回环地址（当前为 {}）；确需其他设备使用时设置 forward_proxy.mitm.allow_remote_clients = true",
+                        config.host
+                    ));
+                }
+                let rules = |rules: &Option<HeaderRulesConfig>| {
+                    rules
+                        .as_ref()
+                        .map(HeaderRules::new)
+                        .transpose()
+                        .map_err(|e| format!("forward_proxy.mitm: {}", e))
+                };
+                Some(Mitm {
+                    authority: CertificateAuthority::load(&mitm.ca_cert, &mitm.ca_key)?,
+                    hosts: mitm.hosts.iter().map(|h| h.to_ascii_lowercase()).collect(),
+                    client: client(mitm.accept_invalid_certs)?,
+                    request_headers: rules(&mitm.request_headers)?,
+                    response_headers: rules(&mitm.response_headers)?,
+                })
+            }
+        };
+        if !loopback {
+            log::warn!(
+                "正向代理监听在非回环地址 {}，任何能访问该地址的客户端都可以通过它访问其他网站",
+                config.host
+            );
+        }
+        if mitm.is_some() {
+            let listen = crate::addr::join(&config.host, config.port);
+            for line in [
+                "!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!"
+                    .to_string(),
+                format!("!!! 警告：正向代理 {} 已启用 HTTPS 解密（MITM）", listen),
+                "!!! 经过它的 HTTPS 流量（含密码、Cookie 与令牌）会被解密、记录并可能被修改"
+                    .to_string(),
+                format!(
+                    "!!! 只用于开发调试；CA 私钥 {} 泄露后任何人都能冒充任意网站",
+                    config.mitm.ca_key
+                ),
+                "!!! 不要在生产环境或他人的设备上启用，调试结束后删除对 CA 证书的信任".to_string(),
+                "!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!"
+                    .to_string(),
+            ] {
+                log::warn!("{}", line);
+                eprintln!("{}", line); // 日志级别或输出位置不影响该警告
+            }
+        }
+        Ok(Some(ForwardProxy {
+            host: config.host.clone(),
+            port: config.port,
+            connect_ports: config.connect_ports.clone(),
+            timeout,
+            client: client(false)?,
+            mitm,
+        }))
+    }
+
+    // 需要解密的主机对应的解密设置
+    fn intercepts(&self, host: &str) -> Option<&Mitm> {
+        let mitm = self.mitm.as_ref()?;
+        let host = host.to_ascii_lowercase();
+        let matched = mitm.hosts.is_empty()
+            || mitm
+                .hosts
+                .iter()
+                .any(|pattern| match pattern.strip_prefix("*.") {
+                    Some(domain) => host.ends_with(&format!(".{}", domain)),
+                    None => *pattern == host,
+                });
+        matched.then_some(mitm)
+    }
+}
+
+// 签发主机证书的本地 CA；所有主机证书共用一个启动时生成的密钥
+struct CertificateAuthority {
+    cert: X509,
+    key: PKey<Private>,
+    host_key: PKey<Private>,
+    issued: Mutex<HashMap<String, tokio_native_tls::TlsAcceptor>>, // 已签发的主机证书
+}
+
+impl CertificateAuthority {
+    fn load(cert: &str, key: &str) -> Result<Self, String> {
+        let cert_pem =
+            std::fs::read(cert).map_err(|e| format!("无法读取 CA 证书 {}: {}", cert, e))?;
+        let key_pem = std::fs::read(key).map_err(|e| format!("无法读取 CA 私钥 {}: {}", key, e))?;
+        let cert = X509::from_pem(&cert_pem).map_err(|e| format!("CA 证书无效: {}", e))?;
+        let key =
+            PKey::private_key_from_pem(&key_pem).map_err(|e| format!("CA 私钥无效: {}", e))?;
+        let matches = cert.public_key().is_ok_and(|public| public.public_eq(&key));
+        if !matches {
+            return Err("CA 证书与私钥不匹配".to_string());
+        }
+        let host_key = Rsa::generate(2048)
+            .and_then(PKey::from_rsa)
+            .map_err(|e| format!("无法生成主机证书的密钥: {}", e))?;
+        Ok(CertificateAuthority {
+            cert,
+            key,
+            host_key,
+            issued: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // 主机证书的 TLS 接受器，第一次访问该主机时签发
+    fn acceptor(&self, host: &str) -> Result<tokio_native_tls::TlsAcceptor, String> {
+        let host = host.to_ascii_lowercase();
+        if let Some(acceptor) = self.issued.lock().unwrap().get(&host) {
+            return Ok(acceptor.clone());
+        }
+        let cert = self
+            .issue(&host)
+            .map_err(|e| format!("无法为 {} 签发证书: {}", host, e))?;
+        let mut chain = cert.to_pem().map_err(|e| e.to_string())?;
+        chain.extend(self.cert.to_pem().map_err(|e| e.to_string())?);
+        let key = self
+            .host_key
+            .private_key_to_pem_pkcs8()
+            .map_err(|e| e.to_string())?;
+        let identity = native_tls::Identity::from_pkcs8(&chain, &key).map_err(|e| e.to_string())?;
+        let acceptor: tokio_native_tls::TlsAcceptor = native_tls::TlsAcceptor::new(identity)
+            .map_err(|e| format!("无法创建 TLS 接受器: {}", e))?
+            .into();
+        log::warn!("正向代理为 {} 签发了解密用的证书", host);
+        let mut issued = self.issued.lock().unwrap();
+        if issued.len() >= MAX_ISSUED {
+            issued.clear();
+        }
+        issued.insert(host, acceptor.clone());
+        Ok(acceptor)
+    }
+
+    // 签发主机证书：主题与 subjectAltName 为该主机，有效期一年，从一天前开始以容忍时钟偏差
+    fn issue(&self, host: &str) -> Result<X509, openssl::error::ErrorStack> {
+        let mut name = X509NameBuilder::new()?;
+        name.append_entry_by_text("CN", host)?;
+        let name = name.build();
+        let mut serial = BigNum::new()?;
+        serial.rand(127, MsbOption::MAYBE_ZERO, false)?;
+        let now = chrono::Utc::now().timestamp();
+        let mut builder = X509::builder()?;
+        builder.set_version(2)?;
+        let serial = serial.to_asn1_integer()?;
+        builder.set_serial_number(&serial)?;
+        builder.set_subject_name(&name)?;
+        builder.set_issuer_name(self.cert.subject_name())?;
+        builder.set_pubkey(&self.host_key)?;
+        let (not_before, not_after) = (
+            Asn1Time::from_unix(now - 86400)?,
+            Asn1Time::from_unix(now + 365 * 86400)?,
+        );
+        builder.set_not_before(&not_before)?;
+        builder.set_not_after(&not_after)?;
+        builder.append_extension(BasicConstraints::new().build()?)?;
+        builder.append_extension(
+            KeyUsage::new()
+                .critical()
+                .digital_signature()
+                .key_encipherment()
+                .build()?,
+        )?;
+        builder.append_extension(ExtendedKeyUsage::new().server_auth().build()?)?;
+        let mut alt_name = SubjectAlternativeName::new();
+        match host.parse::<IpAddr>() {
+            Ok(_) => alt_name.ip(host),
+            Err(_) => alt_name.dns(host),
+        };
+        let alt_name = alt_name.build(&builder.x509v3_context(Some(&self.cert), None))?;
+        builder.append_extension(alt_name)?;
+        builder.sign(&self.key, MessageDigest::sha256())?;
+        Ok(builder.build())
+    }
+}
+
+// 解密后的连接所属的 CONNECT 目标，保存在连接数据中
+#[derive(Clone)]
+struct Intercepted {
+    authority: String, // CONNECT 请求中的主机与端口
+}
+
+// 正向代理的请求处理函数：转发绝对地址的 HTTP 请求，以及解密后的 HTTPS 请求
+pub async fn handler(
+    req: HttpRequest,
+    body: web::Bytes,
+    state: web::Data<AppState>,
+    forward: web::Data<ForwardProxy>,
+) -> Result<HttpResponse, ProxyError> {
+    // 1. 确定目标地址：解密的连接发往 CONNECT 的目标，否则请求本身必须是绝对地址
+    let intercepted = req.conn_data::<Intercepted>().cloned();
+    let (url, mitm, mode) = match &intercepted {
+        Some(intercepted) => {
+            let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+            let url = format!("https://{}{}", intercepted.authority, path);
+            (url, forward.mitm.as_ref(), "mitm")
+        }
+        None => match req.uri().scheme_str() {
+            Some("http" | "https") if req.uri().authority().is_some() => {
+                (req.uri().to_string(), None, "http")
+            }
+            _ => {
+                return Err(ProxyError::RequestBuilderError(
+                    "正向代理只接受绝对地址的请求，如 GET http://example.com/".to_string(),
+                ));
+            }
+        },
+    };
+    let context = |status| RuleContext {
+        req: &req,
+        route: ROUTE,
+        client_ip: crate::addr::peer_ip(&req),
+        status,
+    };
+
+    // 2. 复制请求头，去掉逐跳请求头与 Host（按目标地址重新生成）
+    let hop_by_hop = state.hop_by_hop.load();
+    let stripped = hop_by_hop.strip(
+        req.headers().get_all(actix_web::http::header::CONNECTION),
+        req.headers().get(actix_web::http::header::TE),
+    );
+    let mut headers = reqwest::header::HeaderMap::new();
+    for (key, value) in req.headers() {
+        if key != actix_web::http::header::HOST && !stripped.contains(key) {
+            headers.append(key.clone(), value.clone());
+        }
+    }
+    if let Some(rules) = mitm.and_then(|m| m.request_headers.as_ref()) {
+        rules.apply(&mut headers, &context(None));
+    }
+
+    // 3. 发送请求
+    let client = mitm.map_or(&forward.client, |m| &m.client);
+    let method = reqwest::Method::from_bytes(req.method().as_str().as_bytes())
+        .map_err(|e| ProxyError::RequestBuilderError(e.to_string()))?;
+    let response = client
+        .request(method, &url)
+        .headers(headers)
+        .body(body.to_vec())
+        .send()
+        .await
+        .map_err(|e| match e.is_timeout() {
+            true => ProxyError::UpstreamTimeout(url.clone()),
+            false => ProxyError::UpstreamConnectFailed(format!("{}: {}", url, e)),
+        })?;
+
+    // 4. 复制响应头与响应体，按解密设置修改响应头
+    let status = response.status();
+    let mut client_resp = HttpResponse::build(status);
+    let stripped = hop_by_hop.strip(
+        response
+            .headers()
+            .get_all(actix_web::http::header::CONNECTION),
+        response.headers().get(actix_web::http::header::TE),
+    );
+    for (key, value) in response.headers() {
+        if key != "content-length" && !stripped.contains(key) {
+            client_resp.append_header((key.clone(), value.clone()));
+        }
+    }
+    let content_type = response
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let bytes = response.bytes().await?;
+    log::info!("正向代理 {} {} -> {}", req.method(), url, status);
+    crate::metrics::counter_inc("proxy_forward_requests_total", &[("mode", mode)]);
+    if state.features.enabled(crate::Feature::Capture) {
+        state.capture.record(
+            &req,
+            ROUTE,
+            &body,
+            status.as_u16(),
+            content_type.as_deref(),
+            &bytes,
+        );
+    }
+    let mut client_resp = client_resp.body(bytes);
+    if let Some(rules) = mitm.and_then(|m| m.response_headers.as_ref()) {
+        rules.apply_response(&mut client_resp, &context(Some(status)));
+    }
+    Ok(client_resp)
+}
+
+// 处理 CONNECT：检查目标端口后，解密需要解密的主机，其他主机建立 TCP 隧道
+async fn connect<F>(
+    req: Request,
+    framed: Framed<TcpStream, h1::Codec>,
+    forward: web::Data<ForwardProxy>,
+    decrypted: Rc<F>,
+) -> Result<(), actix_web::Error>
+where
+    F: ServiceFactory<(Decrypted, Option<SocketAddr>), Config = ()>,
+    F::Error: std::fmt::Display,
+    F::InitError: std::fmt::Debug,
+{
+    let parts = framed.into_parts();
+    let mut io = parts.io;
+    let buffered = parts.read_buf.to_vec(); // 客户端紧跟在 CONNECT 之后发送的数据
+    let peer = io.peer_addr().ok();
+    let Some(authority) = req
+        .uri()
+        .authority()
+        .filter(|_| *req.method() == Method::CONNECT)
+    else {
+        return Ok(reply(&mut io, "400 Bad Request").await?);
+    };
+    let host = authority
+        .host()
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = authority.port_u16().unwrap_or(443);
+    if !forward.connect_ports.contains(&port) {
+        log::warn!(
+            "正向代理拒绝 CONNECT {}：端口不在 connect_ports 中",
+            authority
+        );
+        return Ok(reply(&mut io, "403 Forbidden").await?);
+    }
+
+    // 1. 解密：先应答 CONNECT，再以签发的主机证书完成握手，之后按普通的 HTTP/1.1 连接处理
+    if let Some(mitm) = forward.intercepts(host) {
+        let acceptor = match mitm.authority.acceptor(host) {
+            Ok(acceptor) => acceptor,
+            Err(e) => {
+                log::error!("正向代理: {}", e);
+                return Ok(reply(&mut io, "502 Bad Gateway").await?);
+            }
+        };
+        reply(&mut io, "200 Connection Established").await?;
+        let io = Prefixed {
+            buffered,
+            read: 0,
+            io,
+        };
+        let stream = match tokio::time::timeout(forward.timeout, acceptor.accept(io)).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(e)) => {
+                // 客户端不信任 CA 时在这里失败
+                log::warn!("正向代理解密 {} 时 TLS 握手失败: {}", authority, e);
+                return Ok(());
+            }
+            Err(_) => return Ok(()),
+        };
+        log::info!("正向代理解密 CONNECT {}", authority);
+        crate::metrics::counter_inc("proxy_forward_requests_total", &[("mode", "connect")]);
+        let service = decrypted
+            .new_service(())
+            .await
+            .map_err(|e| std::io::Error::other(format!("{:?}", e)))?;
+        let stream = Decrypted {
+            stream: Box::new(stream),
+            authority: authority.to_string(),
+        };
+        service
+            .call((stream, peer))
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        return Ok(());
+    }
+
+    // 2. 隧道：连接目标后原样转发两个方向的数据
+    let mut upstream =
+        match tokio::time::timeout(forward.timeout, TcpStream::connect((host, port))).await {
+            Ok(Ok(upstream)) => upstream,
+            Ok(Err(e)) => {
+                log::warn!("正向代理无法连接 {}: {}", authority, e);
+                return Ok(reply(&mut io, "502 Bad Gateway").await?);
+            }
+            Err(_) => return Ok(reply(&mut io, "504 Gateway Timeout").await?),
+        };
+    reply(&mut io, "200 Connection Established").await?;
+    log::info!("正向代理建立隧道 CONNECT {}", authority);
+    crate::metrics::counter_inc("proxy_forward_requests_total", &[("mode", "tunnel")]);
+    upstream.write_all(&buffered).await?;
+    tokio::io::copy_bidirectional(&mut io, &mut upstream).await?;
+    Ok(())
+}
+
+// 应答 CONNECT；非 2xx 的应答之后关闭连接
+async fn reply(io: &mut TcpStream, status: &str) -> std::io::Result<()> {
+    let response = match status.starts_with('2') {
+        true => format!("HTTP/1.1 {}\r\n\r\n", status),
+        false => format!(
+            "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            status
+        ),
+    };
+    io.write_all(response.as_bytes()).await
+}
+
+// 先读出 CONNECT 之后已读入缓冲区的数据，再读连接
+struct Prefixed {
+    buffered: Vec<u8>,
+    read: usize,
+    io: TcpStream,
+}
+
+impl AsyncRead for Prefixed {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if this.read < this.buffered.len() {
+            let rest = &this.buffered[this.read..];
+            let n = rest.len().min(buf.remaining());
+            buf.put_slice(&rest[..n]);
+            this.read += n;
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.io).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Prefixed {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.get_mut().io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+}
+
+// 解密后的连接
+struct Decrypted {
+    stream: Box<tokio_native_tls::TlsStream<Prefixed>>,
+    authority: String,
+}
+
+impl AsyncRead for Decrypted {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Decrypted {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+// 在 [forward_proxy] 的地址上运行正向代理。factory 为每个工作线程创建处理转发请求的 App，
+// 需要注册 ForwardProxy 供 handler 使用；CONNECT 交给 connect 处理，解密后的连接使用同样的 App
+pub fn serve<F, I, S, B>(
+    forward: web::Data<ForwardProxy>,
+    tuning: &crate::ServerConfig,
+    factory: F,
+) -> std::io::Result<Server>
+where
+    F: Fn() -> I + Send + Clone + 'static,
+    I: IntoServiceFactory<S, Request>,
+    S: ServiceFactory<Request, Config = AppConfig> + 'static,
+    S::Error: Into<actix_web::Error> + 'static,
+    S::InitError: std::fmt::Debug,
+    S::Response: Into<Response<B>> + 'static,
+    <S::Service as Service<Request>>::Future: 'static,
+    S::Service: 'static,
+    B: MessageBody + 'static,
+{
+    let mut builder = ServerBuilder::default()
+        .max_concurrent_connections(tuning.max_connections)
+        .shutdown_timeout(tuning.shutdown_timeout_secs);
+    if let Some(workers) = tuning.workers {
+        builder = builder.workers(workers);
+    }
+    let request_timeout = Duration::from_millis(tuning.client_request_timeout_ms);
+    let sockets = crate::upgrade::listen(NAME, &forward.host, forward.port, tuning.backlog)?;
+    for socket in sockets {
+        let addr = socket.local_addr()?;
+        log::info!("正向代理: http://{}", addr);
+        let (factory, forward) = (factory.clone(), forward.clone());
+        builder = builder.listen(format!("{}-{}", NAME, addr), socket, move || {
+            // 正向代理不区分监听地址与协议，App 使用默认的连接配置
+            let app = || {
+                map_config(
+                    factory()
+                        .into_factory()
+                        .map_err(|err| err.into().error_response()),
+                    |_| AppConfig::default(),
+                )
+            };
+            let decrypted = Rc::new(
+                HttpService::build()
+                    .client_request_timeout(request_timeout)
+                    .on_connect_ext(|io: &Decrypted, data: &mut Extensions| {
+                        data.insert(Intercepted {
+                            authority: io.authority.clone(),
+                        });
+                    })
+                    .h1(app()),
+            );
+            let forward = forward.clone();
+            let http = HttpService::build()
+                .client_request_timeout(request_timeout)
+                .local_addr(addr)
+                .upgrade(fn_service(
+                    move |(req, framed): (Request, Framed<TcpStream, h1::Codec>)| {
+                        connect(req, framed, forward.clone(), decrypted.clone())
+                    },
+                ))
+                .finish(app());
+            fn_service(|io: TcpStream| async move {
+                let peer = io.peer_addr().ok();
+                Ok::<_, actix_http::error::DispatchError>((io, Protocol::Http1, peer))
+            })
+            .and_then(http)
+        })?;
+    }
+    Ok(builder.run())
+}
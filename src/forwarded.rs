@@ -0,0 +1,386 @@
+// ==================== 客户端信息传递 ====================
+//
+// 向上游传递真实的客户端信息，可以使用 X-Forwarded-* 系列请求头、RFC 7239 的 Forwarded 请求头或两者同时使用：
+// X-Forwarded-For 追加客户端地址，X-Forwarded-Proto、X-Forwarded-Host 为客户端请求的协议与主机，
+// X-Real-IP 为客户端 IP；Forwarded 追加一个 for=;proto=;host= 元素。只有来自可信网段（前级负载均衡器
+// 或代理）的请求保留其传入的这些请求头并在末尾追加，传入的 Forwarded 优先于 X-Forwarded-For，
+// 两种写法之间自动转换；其他客户端传入的值一律丢弃后重写，避免伪造来源地址。
+// 经过多级可信代理时，X-Real-IP 为地址链中从右往左第一个不可信的地址。
+// 启用 obfuscate 后 Forwarded 中本跳的 for= 为混淆标识（RFC 7239 第 6.3 节），同一客户端在进程运行期间标识不变
+
+use crate::acl::IpNet;
+use actix_web::HttpRequest;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
+use std::sync::LazyLock;
+
+// 客户端信息传递配置：对应配置文件中的 [forwarded]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ForwardedConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool, // 是否设置客户端信息请求头，关闭时客户端传入的值原样转发
+    #[serde(default)]
+    pub mode: Mode, // 使用的请求头
+    #[serde(default)]
+    pub trusted: Vec<String>, // 可信的前级代理网段，保留其传入的客户端信息
+    #[serde(default)]
+    pub obfuscate: bool, // Forwarded 中本跳的 for= 是否使用混淆标识代替客户端地址
+}
+
+// 向上游发送的客户端信息请求头
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    #[default]
+    XForwarded, // X-Forwarded-For/Proto/Host 与 X-Real-IP
+    Forwarded, // RFC 7239 Forwarded
+    Both,      // 两者都发送
+}
+
+impl Default for ForwardedConfig {
+    fn default() -> Self {
+        ForwardedConfig {
+            enabled: true,
+            mode: Mode::XForwarded,
+            trusted: Vec::new(),
+            obfuscate: false,
+        }
+    }
+}
+
+// 以下函数为客户端信息传递提供默认值
+fn default_true() -> bool {
+    true
+}
+
+// 由本模块重写的请求头
+const FORWARDED: HeaderName = HeaderName::from_static("forwarded");
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+const X_REAL_IP: HeaderName = HeaderName::from_static("x-real-ip");
+
+// 混淆标识的密钥，进程内不变，热加载后同一客户端的标识保持一致
+static OBFUSCATION_KEY: LazyLock<u64> = LazyLock::new(rand::random);
+
+// Forwarded 中的一个元素，对应经过的一跳
+#[derive(Debug, Default, Clone)]
+struct Element {
+    node: Option<String>,  // for=，客户端地址、unknown 或混淆标识
+    by: Option<String>,    // by=，原样保留
+    proto: Option<String>, // proto=
+    host: Option<String>,  // host=
+}
+
+// 客户端信息传递策略
+#[derive(Debug)]
+pub struct ForwardedPolicy {
+    enabled: bool,
+    mode: Mode,
+    trusted: Vec<IpNet>, // 可信的前级代理
+    obfuscate: bool,
+}
+
+impl ForwardedPolicy {
+    // 从配置构建策略，无效的网段会导致启动失败
+    pub fn new(config: &ForwardedConfig) -> Result<Self, String> {
+        let trusted = config
+            .trusted
+            .iter()
+            .map(|s| IpNet::parse(s).ok_or_else(|| format!("无效的可信代理网段: {}", s)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ForwardedPolicy {
+            enabled: config.enabled,
+            mode: config.mode,
+            trusted,
+            obfuscate: config.obfuscate,
+        })
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted.iter().any(|net| net.contains(ip))
+    }
+
+    // 客户端 IP：对端可信时为传入的各跳中从右往左第一个不可信的地址，与 X-Real-IP 相同
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let peer = crate::addr::peer_ip(req)?;
+        if !self.is_trusted(peer) {
+            return Some(peer);
+        }
+        let nodes: Vec<IpAddr> = incoming(req)
+            .iter()
+            .filter_map(|e| e.node.as_deref())
+            .filter_map(node_ip)
+            .chain([peer])
+            .collect();
+        self.real_ip(&nodes)
+    }
+
+    // 从右往左跳过可信代理；全部可信时取最前一跳
+    fn real_ip(&self, nodes: &[IpAddr]) -> Option<IpAddr> {
+        nodes
+            .iter()
+            .rev()
+            .find(|ip| !self.is_trusted(**ip))
+            .or(nodes.first())
+            .copied()
+    }
+
+    // 客户端访问的协议与主机：对端可信时取传入的最前一跳记录的值，与 X-Forwarded-Proto/Host 相同
+    pub fn origin(&self, req: &HttpRequest) -> (String, Option<String>) {
+        let (scheme, host) = own_origin(req);
+        let trusted = crate::addr::peer_ip(req).is_some_and(|ip| self.is_trusted(ip));
+        let first = match trusted {
+            true => incoming(req).into_iter().next().unwrap_or_default(),
+            false => Element::default(),
+        };
+        (
+            first.proto.unwrap_or_else(|| scheme.to_string()),
+            first.host.or(host),
+        )
+    }
+
+    // 请求头是否由本策略重写，复制客户端请求头时跳过
+    pub fn rewrites(&self, name: &HeaderName) -> bool {
+        self.enabled
+            && [
+                FORWARDED,
+                X_FORWARDED_FOR,
+                X_FORWARDED_PROTO,
+                X_FORWARDED_HOST,
+                X_REAL_IP,
+            ]
+            .contains(name)
+    }
+
+    // 发往上游的客户端信息请求头；未启用时为空
+    pub fn headers(&self, req: &HttpRequest) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if !self.enabled {
+            return headers;
+        }
+        let peer = crate::addr::peer_ip(req);
+        let trusted = peer.is_some_and(|ip| self.is_trusted(ip));
+
+        // 1. 可信对端传入的各跳，再追加本跳
+        let mut chain = match trusted {
+            true => incoming(req),
+            false => Vec::new(),
+        };
+        let (scheme, host) = own_origin(req);
+        let first = chain.first().cloned().unwrap_or_default();
+        chain.push(Element {
+            node: peer.map(|ip| ip.to_string()),
+            by: None,
+            proto: Some(scheme.to_string()),
+            host: host.clone(),
+        });
+        let mut insert = |name: HeaderName, value: &str| {
+            if let Ok(value) = HeaderValue::from_str(value) {
+                headers.insert(name, value);
+            }
+        };
+
+        // 2. X-Forwarded-*：协议与主机取最前一跳记录的值
+        if self.mode != Mode::Forwarded {
+            // 由 Forwarded 转换时去掉端口与方括号，只保留地址
+            let nodes: Vec<String> = chain
+                .iter()
+                .filter_map(|e| e.node.as_deref())
+                .map(|node| node_ip(node).map_or(node.to_string(), |ip| ip.to_string()))
+                .collect();
+            if !nodes.is_empty() {
+                insert(X_FORWARDED_FOR, &nodes.join(", "));
+            }
+            // X-Real-IP：从右往左跳过可信代理
+            let ips: Vec<IpAddr> = nodes.iter().filter_map(|node| node_ip(node)).collect();
+            if let Some(ip) = self.real_ip(&ips) {
+                insert(X_REAL_IP, &ip.to_string());
+            }
+            insert(X_FORWARDED_PROTO, first.proto.as_deref().unwrap_or(scheme));
+            if let Some(host) = first.host.or(host) {
+                insert(X_FORWARDED_HOST, &host);
+            }
+        }
+
+        // 3. Forwarded：每跳一个元素
+        if self.mode != Mode::XForwarded {
+            if self.obfuscate
+                && let Some(last) = chain.last_mut()
+            {
+                last.node = last.node.as_deref().map(obfuscate);
+            }
+            let elements: Vec<String> = chain.iter().map(format_element).collect();
+            insert(FORWARDED, &elements.join(", "));
+        }
+        headers
+    }
+}
+
+// 本跳的协议与客户端请求的 Host（HTTP/2 为 :authority）
+fn own_origin(req: &HttpRequest) -> (&'static str, Option<String>) {
+    let scheme = crate::listeners::local(req).scheme();
+    let host = req
+        .headers()
+        .get(actix_web::http::header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| req.uri().authority().map(|a| a.to_string()));
+    (scheme, host)
+}
+
+// 读取可信对端传入的各跳：有 Forwarded 时使用 Forwarded，否则由 X-Forwarded-* 转换
+fn incoming(req: &HttpRequest) -> Vec<Element> {
+    let values = |name: &HeaderName| -> Option<String> {
+        let values: Vec<&str> = req
+            .headers()
+            .get_all(name)
+            .filter_map(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .collect();
+        (!values.is_empty()).then(|| values.join(", "))
+    };
+    if let Some(forwarded) = values(&FORWARDED) {
+        return parse(&forwarded);
+    }
+    let mut chain: Vec<Element> = values(&X_FORWARDED_FOR)
+        .map(|v| {
+            v.split(',')
+                .map(|node| Element {
+                    node: Some(node.trim().to_string()),
+                    ..Element::default()
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let proto = values(&X_FORWARDED_PROTO);
+    let host = values(&X_FORWARDED_HOST);
+    if proto.is_some() || host.is_some() {
+        if chain.is_empty() {
+            chain.push(Element::default());
+        }
+        chain[0].proto = proto;
+        chain[0].host = host;
+    }
+    chain
+}
+
+// 解析 Forwarded 请求头：元素以逗号分隔，参数以分号分隔，值可以是带引号的字符串；无法识别的参数忽略
+fn parse(value: &str) -> Vec<Element> {
+    split_unquoted(value, ',')
+        .into_iter()
+        .filter(|element| !element.trim().is_empty())
+        .map(|element| {
+            let mut parsed = Element::default();
+            for pair in split_unquoted(element, ';') {
+                let Some((key, value)) = pair.split_once('=') else {
+                    continue;
+                };
+                let value = unquote(value.trim());
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "for" => parsed.node = Some(value),
+                    "by" => parsed.by = Some(value),
+                    "proto" => parsed.proto = Some(value),
+                    "host" => parsed.host = Some(value),
+                    _ => {}
+                }
+            }
+            parsed
+        })
+        .collect()
+}
+
+// 按分隔符切分，引号内的分隔符不切分
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                parts.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+// 去掉引号与转义
+fn unquote(value: &str) -> String {
+    match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(inner) => {
+            let mut out = String::with_capacity(inner.len());
+            let mut chars = inner.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => out.extend(chars.next()),
+                    c => out.push(c),
+                }
+            }
+            out
+        }
+        None => value.to_string(),
+    }
+}
+
+// 节点中的 IP，去掉端口与 IPv6 的方括号；unknown 与混淆标识返回 None
+fn node_ip(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    let host = match node.strip_prefix('[') {
+        Some(rest) => rest.split(']').next()?,
+        None => node.rsplit_once(':').map(|(host, _)| host).unwrap_or(node),
+    };
+    host.parse().ok()
+}
+
+// 客户端地址的混淆标识，如 _3f9a1c0b7e2d
+fn obfuscate(node: &str) -> String {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    OBFUSCATION_KEY.hash(&mut hasher);
+    node.hash(&mut hasher);
+    format!("_{:012x}", hasher.finish() & 0xffff_ffff_ffff)
+}
+
+// 格式化一个元素；IPv6 地址加方括号，含有 token 以外字符的值加引号
+fn format_element(element: &Element) -> String {
+    let node = |node: &str| match node.parse::<IpAddr>() {
+        Ok(IpAddr::V6(v6)) => format!("[{}]", v6),
+        _ => node.to_string(),
+    };
+    let params = [
+        ("by", element.by.as_deref().map(node)),
+        (
+            "for",
+            Some(node(element.node.as_deref().unwrap_or("unknown"))),
+        ),
+        ("host", element.host.clone()),
+        ("proto", element.proto.clone()),
+    ];
+    params
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|v| format!("{}={}", key, quote(&v))))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+// 值不是 token 时加引号
+fn quote(value: &str) -> String {
+    let token = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c));
+    match token {
+        true => value.to_string(),
+        false => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+    }
+}
@@ -0,0 +1,462 @@
+// ==================== GraphQL ====================
+//
+// 路由的 [routes.graphql] 让代理理解经过它的 GraphQL 请求：从 POST 的 JSON 请求体（包括批量请求的数组）、
+// application/graphql 请求体或 GET 的 query 参数中取出查询并解析，按 operationName 选出要执行的操作。
+// operations 限制允许的操作名，fields 限制允许的根字段（如 "Query.user"、"Mutation.*"），
+// 不在其中的请求返回 403；查询的嵌套深度、字段数（展开片段之后）与批量请求的操作数超过上限、
+// 查询无法解析时返回 400，后端不必处理恶意构造的深层或巨大查询。
+// 每个操作的请求数、检查结果与处理时间按操作名记录在指标中，匿名操作的操作名为空
+
+use crate::ProxyError;
+use actix_web::http::Method;
+use actix_web::{HttpMessage, HttpRequest};
+use graphql_parser::query::{
+    Definition, Document, FragmentDefinition, OperationDefinition, Selection, SelectionSet,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+// GraphQL 配置：对应配置文件中的 [routes.graphql]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GraphqlConfig {
+    #[serde(default)]
+    pub operations: Vec<String>, // 允许的操作名，为空时不限制；限制后匿名操作也被拒绝
+    #[serde(default)]
+    pub fields: Vec<String>, // 允许的根字段，写成“根类型.字段”，字段为 * 表示该根类型的全部字段；为空时不限制
+    #[serde(default = "default_introspection")]
+    pub introspection: bool, // 是否允许 __schema 与 __type 内省查询
+    #[serde(default = "default_max_depth")]
+    pub max_depth: usize, // 字段的最大嵌套深度，0 表示不限制
+    #[serde(default = "default_max_complexity")]
+    pub max_complexity: usize, // 展开片段后的最大字段数，0 表示不限制
+    #[serde(default = "default_max_batch")]
+    pub max_batch: usize, // 批量请求中的最大操作数，0 表示不限制
+}
+
+// 以下函数为 GraphQL 提供默认值
+fn default_introspection() -> bool {
+    true
+}
+
+fn default_max_depth() -> usize {
+    10
+}
+
+fn default_max_complexity() -> usize {
+    1000
+}
+
+fn default_max_batch() -> usize {
+    10
+}
+
+// 根类型的名称，与常见 Schema 中根类型的命名相同
+const ROOT_TYPES: [&str; 3] = ["Query", "Mutation", "Subscription"];
+
+// 检查通过的操作名与开始检查的时间，存入请求的扩展数据，记录处理时间时使用
+#[derive(Debug, Clone)]
+struct Checked {
+    operations: Vec<String>,
+    started: std::time::Instant,
+}
+
+// 一次请求中的一个 GraphQL 操作
+#[derive(Debug)]
+struct Request {
+    query: String,
+    operation_name: Option<String>,
+}
+
+// 选出的操作的信息
+#[derive(Debug)]
+struct Operation {
+    name: String,        // 操作名，匿名操作为空
+    kind: &'static str,  // 根类型
+    fields: Vec<String>, // 根字段，包括片段中的
+    depth: usize,        // 最大嵌套深度
+    complexity: usize,   // 展开片段后的字段数
+}
+
+// 检查未通过的原因
+#[derive(Debug)]
+enum Rejection {
+    Invalid(String),    // 无法解析或超过上限，返回 400
+    NotAllowed(String), // 不在允许范围内，返回 403
+}
+
+// 路由的 GraphQL 设置
+#[derive(Debug)]
+pub struct Graphql {
+    operations: Vec<String>,
+    fields: Vec<(String, String)>, // 根类型与字段，字段为 * 表示全部
+    introspection: bool,
+    max_depth: usize,
+    max_complexity: usize,
+    max_batch: usize,
+}
+
+impl Graphql {
+    // 从配置构建，根字段的写法或根类型无效时启动失败
+    pub fn new(config: &GraphqlConfig) -> Result<Self, String> {
+        let fields = config
+            .fields
+            .iter()
+            .map(|field| match field.trim().split_once('.') {
+                Some((kind, name)) if ROOT_TYPES.contains(&kind) && !name.is_empty() => {
+                    Ok((kind.to_string(), name.to_string()))
+                }
+                _ => Err(format!(
+                    "GraphQL 的根字段应写成 Query.字段、Mutation.字段 或 Subscription.字段: {:?}",
+                    field
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Graphql {
+            operations: config.operations.clone(),
+            fields,
+            introspection: config.introspection,
+            max_depth: config.max_depth,
+            max_complexity: config.max_complexity,
+            max_batch: config.max_batch,
+        })
+    }
+
+    // 检查请求中的每个 GraphQL 操作，通过的操作名存入请求的扩展数据；不含查询的 GET 等请求原样转发
+    pub fn check(&self, req: &HttpRequest, body: &[u8], route: &str) -> Result<(), ProxyError> {
+        let requests = match requests(req, body) {
+            Ok(Some(requests)) => requests,
+            Ok(None) => return Ok(()),
+            Err(reason) => return self.reject(route, "", Rejection::Invalid(reason)),
+        };
+        if self.max_batch > 0 && requests.len() > self.max_batch {
+            let reason = format!(
+                "批量请求包含 {} 个操作，超过 {} 个",
+                requests.len(),
+                self.max_batch
+            );
+            return self.reject(route, "", Rejection::Invalid(reason));
+        }
+        let mut names = Vec::new();
+        for request in &requests {
+            let operation = match operation(request) {
+                Ok(operation) => operation,
+                Err(reason) => {
+                    let name = request.operation_name.as_deref().unwrap_or_default();
+                    return self.reject(route, name, Rejection::Invalid(reason));
+                }
+            };
+            if operation.kind == "Mutation" && req.method() == Method::GET {
+                let reason = "GET 请求不能执行 mutation".to_string();
+                return self.reject(route, &operation.name, Rejection::Invalid(reason));
+            }
+            if let Err(rejection) = self.allows(&operation) {
+                return self.reject(route, &operation.name, rejection);
+            }
+            names.push(operation.name);
+        }
+        for name in &names {
+            crate::metrics::counter_inc(
+                "proxy_graphql_requests_total",
+                &[("route", route), ("operation", name), ("result", "allowed")],
+            );
+        }
+        req.extensions_mut().insert(Checked {
+            operations: names,
+            started: std::time::Instant::now(),
+        });
+        Ok(())
+    }
+
+    // 按配置检查选出的操作
+    fn allows(&self, operation: &Operation) -> Result<(), Rejection> {
+        if !self.operations.is_empty() && !self.operations.contains(&operation.name) {
+            return Err(Rejection::NotAllowed(match operation.name.as_str() {
+                "" => "不允许匿名操作".to_string(),
+                name => format!("操作 {} 不在允许的范围内", name),
+            }));
+        }
+        for field in &operation.fields {
+            let allowed = match field.as_str() {
+                "__typename" => true,
+                "__schema" | "__type" => self.introspection,
+                _ => {
+                    self.fields.is_empty()
+                        || self.fields.iter().any(|(kind, name)| {
+                            kind == operation.kind && (name == "*" || name == field)
+                        })
+                }
+            };
+            if !allowed {
+                return Err(Rejection::NotAllowed(format!(
+                    "字段 {}.{} 不在允许的范围内",
+                    operation.kind, field
+                )));
+            }
+        }
+        if self.max_depth > 0 && operation.depth > self.max_depth {
+            return Err(Rejection::Invalid(format!(
+                "查询深度为 {}，超过 {}",
+                operation.depth, self.max_depth
+            )));
+        }
+        if self.max_complexity > 0 && operation.complexity > self.max_complexity {
+            return Err(Rejection::Invalid(format!(
+                "查询包含 {} 个字段，超过 {} 个",
+                operation.complexity, self.max_complexity
+            )));
+        }
+        Ok(())
+    }
+
+    // 记录被拒绝的请求并返回对应的错误
+    fn reject(&self, route: &str, operation: &str, rejection: Rejection) -> Result<(), ProxyError> {
+        let (result, reason) = match &rejection {
+            Rejection::Invalid(reason) => ("invalid", reason),
+            Rejection::NotAllowed(reason) => ("rejected", reason),
+        };
+        crate::metrics::counter_inc(
+            "proxy_graphql_requests_total",
+            &[
+                ("route", route),
+                ("operation", operation),
+                ("result", result),
+            ],
+        );
+        log::info!("路由 {} 的 GraphQL 请求被拒绝: {}", route, reason);
+        Err(match rejection {
+            Rejection::Invalid(reason) => ProxyError::InvalidGraphql(reason),
+            Rejection::NotAllowed(reason) => ProxyError::GraphqlNotAllowed(reason),
+        })
+    }
+
+    // 按操作名记录检查通过的请求的处理时间与响应状态；批量请求中的每个操作都记录整个请求的耗时
+    pub fn record(&self, req: &HttpRequest, route: &str, status: StatusCode) {
+        let Some(checked) = req.extensions().get::<Checked>().cloned() else {
+            return;
+        };
+        let elapsed = checked.started.elapsed();
+        for name in &checked.operations {
+            crate::metrics::counter_inc(
+                "proxy_graphql_responses_total",
+                &[
+                    ("route", route),
+                    ("operation", name),
+                    ("status", status.as_str()),
+                ],
+            );
+            crate::metrics::histogram_observe(
+                "proxy_graphql_duration_seconds",
+                &[("route", route), ("operation", name)],
+                elapsed.as_secs_f64(),
+            );
+        }
+    }
+}
+
+// 取出请求中的 GraphQL 操作：POST 的 JSON 请求体可以是单个对象或批量请求的数组，
+// application/graphql 请求体是查询本身，GET 使用 query 与 operationName 参数；
+// 没有查询的 GET 与其他方法返回 None
+fn requests(req: &HttpRequest, body: &[u8]) -> Result<Option<Vec<Request>>, String> {
+    let params = actix_web::web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(|q| q.into_inner())
+        .unwrap_or_default();
+    let operation_name = params
+        .get("operationName")
+        .filter(|n| !n.is_empty())
+        .cloned();
+    if req.method() == Method::GET {
+        return Ok(params.get("query").map(|query| {
+            vec![Request {
+                query: query.clone(),
+                operation_name,
+            }]
+        }));
+    }
+    if req.method() != Method::POST {
+        return Ok(None);
+    }
+    let media_type = req
+        .headers()
+        .get(actix_web::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+    if media_type == "application/graphql" {
+        let query = std::str::from_utf8(body).map_err(|_| "查询不是 UTF-8 文本".to_string())?;
+        return Ok(Some(vec![Request {
+            query: query.to_string(),
+            operation_name,
+        }]));
+    }
+    let value: Value =
+        serde_json::from_slice(body).map_err(|e| format!("请求体不是合法的 JSON: {}", e))?;
+    let items = match value {
+        Value::Array(items) if items.is_empty() => return Err("批量请求为空".to_string()),
+        Value::Array(items) => items,
+        value => vec![value],
+    };
+    items
+        .iter()
+        .map(|item| {
+            let query = item
+                .get("query")
+                .and_then(Value::as_str)
+                .ok_or("请求中没有 GraphQL 查询")?;
+            Ok(Request {
+                query: query.to_string(),
+                operation_name: item
+                    .get("operationName")
+                    .and_then(Value::as_str)
+                    .filter(|n| !n.is_empty())
+                    .map(str::to_string),
+            })
+        })
+        .collect::<Result<_, &str>>()
+        .map(Some)
+        .map_err(str::to_string)
+}
+
+// 解析查询并选出要执行的操作：有 operationName 时按名称选择，否则文档中只能有一个操作
+fn operation(request: &Request) -> Result<Operation, String> {
+    let document: Document<&str> = graphql_parser::parse_query(&request.query).map_err(|e| {
+        format!(
+            "无法解析 GraphQL 查询: {}",
+            e.to_string().trim_end().replace('\n', " ")
+        )
+    })?;
+    let mut operations = Vec::new();
+    let mut fragments = HashMap::new();
+    for definition in &document.definitions {
+        match definition {
+            Definition::Operation(operation) => operations.push(operation),
+            Definition::Fragment(fragment) => {
+                fragments.insert(fragment.name, fragment);
+            }
+        }
+    }
+    let selected = match &request.operation_name {
+        Some(wanted) => operations
+            .into_iter()
+            .find(|op| name(op) == Some(wanted.as_str()))
+            .ok_or_else(|| format!("查询中没有操作 {}", wanted))?,
+        None if operations.len() == 1 => operations[0],
+        None if operations.is_empty() => return Err("查询中没有操作".to_string()),
+        None => return Err("查询包含多个操作，需要指定 operationName".to_string()),
+    };
+    let (kind, selection_set) = match selected {
+        OperationDefinition::SelectionSet(set) => ("Query", set),
+        OperationDefinition::Query(q) => ("Query", &q.selection_set),
+        OperationDefinition::Mutation(m) => ("Mutation", &m.selection_set),
+        OperationDefinition::Subscription(s) => ("Subscription", &s.selection_set),
+    };
+    let mut walker = Walker {
+        fragments,
+        measured: HashMap::new(),
+    };
+    let mut fields = Vec::new();
+    walker.root_fields(selection_set, &mut fields, &mut Vec::new())?;
+    let (depth, complexity) = walker.measure(selection_set, &mut Vec::new())?;
+    Ok(Operation {
+        name: name(selected).unwrap_or_default().to_string(),
+        kind,
+        fields,
+        depth,
+        complexity,
+    })
+}
+
+fn name<'a>(operation: &OperationDefinition<'a, &'a str>) -> Option<&'a str> {
+    match operation {
+        OperationDefinition::SelectionSet(_) => None,
+        OperationDefinition::Query(q) => q.name,
+        OperationDefinition::Mutation(m) => m.name,
+        OperationDefinition::Subscription(s) => s.name,
+    }
+}
+
+// 遍历选择集；片段的深度与字段数只计算一次，重复使用的片段不会让计算量成倍增长
+struct Walker<'a> {
+    fragments: HashMap<&'a str, &'a FragmentDefinition<'a, &'a str>>,
+    measured: HashMap<&'a str, (usize, usize)>,
+}
+
+impl<'a> Walker<'a> {
+    fn fragment(
+        &self,
+        name: &str,
+        stack: &[&str],
+    ) -> Result<&'a FragmentDefinition<'a, &'a str>, String> {
+        if stack.contains(&name) {
+            return Err(format!("片段 {} 循环引用自身", name));
+        }
+        self.fragments
+            .get(name)
+            .copied()
+            .ok_or_else(|| format!("查询中没有片段 {}", name))
+    }
+
+    // 收集根字段，根选择集中的片段与内联片段一并展开
+    fn root_fields(
+        &self,
+        set: &'a SelectionSet<'a, &'a str>,
+        fields: &mut Vec<String>,
+        stack: &mut Vec<&'a str>,
+    ) -> Result<(), String> {
+        for selection in &set.items {
+            match selection {
+                Selection::Field(field) => {
+                    if !fields.iter().any(|f| f == field.name) {
+                        fields.push(field.name.to_string());
+                    }
+                }
+                Selection::FragmentSpread(spread) => {
+                    let fragment = self.fragment(spread.fragment_name, stack)?;
+                    stack.push(spread.fragment_name);
+                    self.root_fields(&fragment.selection_set, fields, stack)?;
+                    stack.pop();
+                }
+                Selection::InlineFragment(inline) => {
+                    self.root_fields(&inline.selection_set, fields, stack)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 选择集的最大嵌套深度与字段数
+    fn measure(
+        &mut self,
+        set: &'a SelectionSet<'a, &'a str>,
+        stack: &mut Vec<&'a str>,
+    ) -> Result<(usize, usize), String> {
+        let (mut depth, mut complexity) = (0, 0usize);
+        for selection in &set.items {
+            let (d, c) = match selection {
+                Selection::Field(field) => {
+                    let (d, c) = self.measure(&field.selection_set, stack)?;
+                    (d + 1, c.saturating_add(1))
+                }
+                Selection::FragmentSpread(spread) => {
+                    match self.measured.get(spread.fragment_name) {
+                        Some(measured) => *measured,
+                        None => {
+                            let fragment = self.fragment(spread.fragment_name, stack)?;
+                            stack.push(spread.fragment_name);
+                            let measured = self.measure(&fragment.selection_set, stack)?;
+                            stack.pop();
+                            self.measured.insert(spread.fragment_name, measured);
+                            measured
+                        }
+                    }
+                }
+                Selection::InlineFragment(inline) => self.measure(&inline.selection_set, stack)?,
+            };
+            depth = depth.max(d);
+            complexity = complexity.saturating_add(c);
+        }
+        Ok((depth, complexity))
+    }
+}
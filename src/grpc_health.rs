@@ -0,0 +1,222 @@
+// ==================== gRPC 健康检查 ====================
+//
+// 就绪检查与启动探测缺省只检查能否建立 TCP 连接；只提供 gRPC 接口的服务端口总是可以连接，
+// 进程卡住或依赖不可用时也一样。路由配置 [routes.grpc_health_check] 后，它的目标服务器改为调用
+// 标准的 grpc.health.v1.Health/Check（HTTP/2，https 目标经 TLS 以 ALPN 协商 h2，http 目标直接使用 h2c），
+// 返回 SERVING 才算可达；NOT_SERVING、未注册的服务或 gRPC 错误都算作不可达并给出原因。
+// 消息格式简单，请求与响应在这里直接编码与解码，不依赖 protobuf 代码生成
+
+use crate::TargetConfig;
+use hyper::body::HttpBody;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+
+// gRPC 健康检查配置：对应路由中的 [routes.grpc_health_check]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GrpcHealthConfig {
+    #[serde(default)]
+    pub service: String, // 检查的服务名，如 "helloworld.Greeter"；为空表示整个服务器
+}
+
+// 路由的 gRPC 健康检查
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrpcHealth {
+    service: String,
+    accept_invalid_certs: bool, // 与 [request] 相同，是否接受 https 目标的无效证书
+}
+
+// 健康检查的路径
+const PATH: &str = "/grpc.health.v1.Health/Check";
+
+impl GrpcHealth {
+    // 从配置构建，目标服务器不是 http 或 https 时启动失败
+    pub fn new<'a>(
+        config: &GrpcHealthConfig,
+        targets: impl IntoIterator<Item = &'a TargetConfig>,
+        accept_invalid_certs: bool,
+    ) -> Result<Self, String> {
+        if let Some(target) = targets
+            .into_iter()
+            .find(|t| t.protocol != "http" && t.protocol != "https")
+        {
+            return Err(format!(
+                "gRPC 健康检查只支持 http 与 https 目标: {}",
+                target.base_url()
+            ));
+        }
+        Ok(GrpcHealth {
+            service: config.service.trim().to_string(),
+            accept_invalid_certs,
+        })
+    }
+
+    // 检查一个目标服务器，超时由调用方控制
+    pub async fn check(&self, target: &TargetConfig) -> Result<(), String> {
+        let host = target.host.trim_start_matches('[').trim_end_matches(']');
+        let stream = TcpStream::connect((host, target.port))
+            .await
+            .map_err(|e| e.to_string())?;
+        let authority = crate::addr::join(host, target.port);
+        if target.protocol != "https" {
+            return self.call(stream, "http", &authority).await;
+        }
+        let connector = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(self.accept_invalid_certs)
+            .request_alpns(&["h2"])
+            .build()
+            .map_err(|e| e.to_string())?;
+        let stream = tokio_native_tls::TlsConnector::from(connector)
+            .connect(host, stream)
+            .await
+            .map_err(|e| format!("TLS 握手失败: {}", e))?;
+        self.call(stream, "https", &authority).await
+    }
+
+    // 在已建立的连接上发送 Check 请求；连接只用于这一次检查
+    async fn call<S>(&self, stream: S, scheme: &str, authority: &str) -> Result<(), String>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (mut sender, connection) = hyper::client::conn::Builder::new()
+            .http2_only(true)
+            .handshake(stream)
+            .await
+            .map_err(|e| format!("HTTP/2 握手失败: {}", e))?;
+        // 探测在 JoinSet 中并发执行，不在 actix 的 LocalSet 上
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                log::debug!("gRPC 健康检查的连接出错: {}", e);
+            }
+        });
+        let request = hyper::Request::post(format!("{}://{}{}", scheme, authority, PATH))
+            .header("content-type", "application/grpc")
+            .header("te", "trailers")
+            .body(hyper::Body::from(request_message(&self.service)))
+            .map_err(|e| e.to_string())?;
+        let response = sender
+            .send_request(request)
+            .await
+            .map_err(|e| e.to_string())?;
+        if response.status() != hyper::StatusCode::OK {
+            return Err(format!("HTTP 状态码 {}", response.status().as_u16()));
+        }
+        // 出错时服务端可以只返回响应头（Trailers-Only），grpc-status 在响应头中
+        let (parts, mut body) = response.into_parts();
+        grpc_status(&parts.headers)?;
+        let mut message = Vec::new();
+        while let Some(chunk) = body.data().await {
+            message.extend_from_slice(&chunk.map_err(|e| e.to_string())?);
+        }
+        match body.trailers().await.map_err(|e| e.to_string())? {
+            Some(trailers) => grpc_status(&trailers)?,
+            None if !parts.headers.contains_key("grpc-status") => {
+                return Err("响应缺少 grpc-status".to_string());
+            }
+            None => {}
+        }
+        match serving_status(&message)? {
+            1 => Ok(()),
+            status => Err(format!("服务状态为 {}", status_name(status))),
+        }
+    }
+}
+
+// HealthCheckRequest { string service = 1; } 加上 gRPC 的 5 字节消息头（不压缩、长度）
+fn request_message(service: &str) -> Vec<u8> {
+    let mut payload = Vec::new();
+    if !service.is_empty() {
+        payload.push(0x0a); // 字段 1，长度分隔
+        put_varint(&mut payload, service.len() as u64);
+        payload.extend_from_slice(service.as_bytes());
+    }
+    let mut message = vec![0];
+    message.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    message.extend_from_slice(&payload);
+    message
+}
+
+// grpc-status 不为 0 时返回错误，带上 grpc-message
+fn grpc_status(headers: &hyper::HeaderMap) -> Result<(), String> {
+    let Some(status) = headers.get("grpc-status").and_then(|v| v.to_str().ok()) else {
+        return Ok(());
+    };
+    if status.trim() == "0" {
+        return Ok(());
+    }
+    let message = headers
+        .get("grpc-message")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let name = match status.trim() {
+        "5" => " NOT_FOUND（服务未注册）",
+        "12" => " UNIMPLEMENTED（没有提供健康检查服务）",
+        "14" => " UNAVAILABLE",
+        _ => "",
+    };
+    Err(format!("gRPC 状态 {}{} {}", status, name, message)
+        .trim_end()
+        .to_string())
+}
+
+// 解码 HealthCheckResponse { ServingStatus status = 1; }，缺少该字段时为 UNKNOWN(0)
+fn serving_status(message: &[u8]) -> Result<u64, String> {
+    let invalid = || "无效的健康检查响应".to_string();
+    let (header, payload) = message.split_at_checked(5).ok_or_else(invalid)?;
+    if header[0] != 0 {
+        return Err("不支持压缩的健康检查响应".to_string());
+    }
+    let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    let mut payload = payload.get(..length).ok_or_else(invalid)?;
+    let mut status = 0;
+    while !payload.is_empty() {
+        let key = take_varint(&mut payload).ok_or_else(invalid)?;
+        match key & 7 {
+            0 => {
+                let value = take_varint(&mut payload).ok_or_else(invalid)?;
+                if key >> 3 == 1 {
+                    status = value;
+                }
+            }
+            2 => {
+                let length = take_varint(&mut payload).ok_or_else(invalid)? as usize;
+                payload = payload.get(length..).ok_or_else(invalid)?;
+            }
+            1 => payload = payload.get(8..).ok_or_else(invalid)?,
+            5 => payload = payload.get(4..).ok_or_else(invalid)?,
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(status)
+}
+
+// ServingStatus 的名称
+fn status_name(status: u64) -> String {
+    match status {
+        0 => "UNKNOWN".to_string(),
+        2 => "NOT_SERVING".to_string(),
+        3 => "SERVICE_UNKNOWN".to_string(),
+        status => status.to_string(),
+    }
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn take_varint(input: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input.split_first()?;
+        *input = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
@@ -0,0 +1,312 @@
+// ==================== 请求头与响应头规则 ====================
+//
+// 路由的 [routes.request_headers] 声明发往上游的请求头如何修改，简单的改动不需要再改代码：
+// remove 去掉请求头（以 * 结尾时按前缀匹配），rename 改名，set 设置并替换已有的值，add 追加一个值，
+// set_if_absent 只在没有该请求头时设置。规则按 remove、rename、set、add、set_if_absent 的顺序执行，
+// 作用于复制客户端请求头并加上客户端信息与 Via 之后的结果。值中可以使用 $client_ip、$route、
+// $request_id 等变量，写法与访问日志模板相同。
+// [routes.response_headers] 使用同样的规则修改返回客户端的响应头，如去掉 Server、X-Powered-By，
+// 或加上 Cache-Control；在路由的响应（含缓存命中与降级响应）交给客户端之前执行，可以使用 $status
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+// 请求头规则配置：对应配置文件中的 [routes.request_headers] 与 [routes.response_headers]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HeaderRulesConfig {
+    #[serde(default)]
+    pub remove: Vec<String>, // 去掉的请求头，以 * 结尾时按前缀匹配，如 "x-internal-*"
+    #[serde(default)]
+    pub rename: BTreeMap<String, String>, // 改名，旧名称 = 新名称
+    #[serde(default)]
+    pub set: BTreeMap<String, String>, // 设置，替换已有的值
+    #[serde(default)]
+    pub add: BTreeMap<String, String>, // 追加一个值，保留已有的值
+    #[serde(default)]
+    pub set_if_absent: BTreeMap<String, String>, // 没有该请求头时设置
+}
+
+// 值模板中支持的变量
+#[derive(Debug, Clone)]
+enum Variable {
+    ClientIp,           // 客户端 IP，经过可信代理时为 X-Real-IP 的取值
+    RemoteAddr,         // 直接连接代理的对端 IP
+    Route,              // 匹配的路由
+    RequestId,          // 请求ID
+    Host,               // 客户端请求的 Host（HTTP/2 为 :authority）
+    Scheme,             // 客户端请求的协议，http 或 https
+    RequestMethod,      // 请求方法
+    Uri,                // 路径
+    RequestUri,         // 路径和查询参数
+    Status,             // 响应状态码，只用于响应头规则
+    Header(HeaderName), // $http_<名称>：客户端请求头
+}
+
+impl Variable {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "client_ip" => Variable::ClientIp,
+            "remote_addr" => Variable::RemoteAddr,
+            "route" => Variable::Route,
+            "request_id" => Variable::RequestId,
+            "host" => Variable::Host,
+            "scheme" => Variable::Scheme,
+            "request_method" => Variable::RequestMethod,
+            "uri" => Variable::Uri,
+            "request_uri" => Variable::RequestUri,
+            "status" => Variable::Status,
+            _ => {
+                // 变量名中的下划线对应请求头中的连字符
+                let rest = name.strip_prefix("http_")?;
+                Variable::Header(HeaderName::from_bytes(rest.replace('_', "-").as_bytes()).ok()?)
+            }
+        })
+    }
+}
+
+// 模板片段
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Variable(Variable),
+}
+
+// 解析后的值模板
+#[derive(Debug, Clone)]
+struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    // 解析模板，变量写作 $name 或 ${name}；$$ 表示字面的 $
+    fn parse(format: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '$' {
+                literal.push(c);
+                continue;
+            }
+            let name: String = match chars.peek() {
+                Some('$') => {
+                    chars.next();
+                    literal.push('$');
+                    continue;
+                }
+                Some('{') => {
+                    chars.next();
+                    chars.by_ref().take_while(|c| *c != '}').collect()
+                }
+                _ => {
+                    let mut name = String::new();
+                    while let Some(c) = chars
+                        .peek()
+                        .filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+                    {
+                        name.push(*c);
+                        chars.next();
+                    }
+                    name
+                }
+            };
+            let variable = Variable::parse(&name)
+                .ok_or_else(|| format!("请求头规则 {:?} 中有未知变量: ${}", format, name))?;
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+            }
+            segments.push(Segment::Variable(variable));
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Template { segments })
+    }
+
+    // 渲染模板，没有值的变量为空字符串
+    fn render(&self, context: &Context) -> String {
+        let mut value = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => value.push_str(s),
+                Segment::Variable(v) => value.push_str(&context.value(v)),
+            }
+        }
+        value
+    }
+}
+
+// 渲染变量所需的请求信息
+pub struct Context<'a> {
+    pub req: &'a HttpRequest,       // 客户端请求
+    pub route: &'a str,             // 匹配的路由
+    pub client_ip: Option<IpAddr>,  // 客户端 IP
+    pub status: Option<StatusCode>, // 响应状态码，请求头规则中为 None
+}
+
+impl Context<'_> {
+    fn value(&self, variable: &Variable) -> String {
+        let req = self.req;
+        let header = |name: &HeaderName| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+        match variable {
+            Variable::ClientIp => self.client_ip.map(|ip| ip.to_string()).unwrap_or_default(),
+            Variable::RemoteAddr => crate::addr::peer_ip(req)
+                .map(|ip| ip.to_string())
+                .unwrap_or_default(),
+            Variable::Route => self.route.to_string(),
+            Variable::RequestId => crate::request_id::current().unwrap_or_default(),
+            Variable::Host => req
+                .headers()
+                .get(reqwest::header::HOST)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+                .or_else(|| req.uri().authority().map(|a| a.to_string()))
+                .unwrap_or_default(),
+            Variable::Scheme => crate::listeners::local(req).scheme().to_string(),
+            Variable::RequestMethod => req.method().to_string(),
+            Variable::Uri => req.path().to_string(),
+            Variable::RequestUri => req
+                .uri()
+                .path_and_query()
+                .map(|pq| pq.as_str().to_string())
+                .unwrap_or_else(|| req.path().to_string()),
+            Variable::Status => self
+                .status
+                .map(|s| s.as_u16().to_string())
+                .unwrap_or_default(),
+            Variable::Header(name) => header(name),
+        }
+    }
+}
+
+// 要去掉的请求头
+#[derive(Debug)]
+enum Pattern {
+    Exact(HeaderName),
+    Prefix(String), // 小写的名称前缀
+}
+
+impl Pattern {
+    fn matches(&self, name: &HeaderName) -> bool {
+        match self {
+            Pattern::Exact(exact) => name == exact,
+            Pattern::Prefix(prefix) => name.as_str().starts_with(prefix.as_str()),
+        }
+    }
+}
+
+// 编译后的请求头规则
+#[derive(Debug)]
+pub struct HeaderRules {
+    remove: Vec<Pattern>,
+    rename: Vec<(HeaderName, HeaderName)>,
+    set: Vec<(HeaderName, Template)>,
+    add: Vec<(HeaderName, Template)>,
+    set_if_absent: Vec<(HeaderName, Template)>,
+}
+
+impl HeaderRules {
+    // 从配置构建规则，无效的请求头名称或未知的变量会导致启动失败
+    pub fn new(config: &HeaderRulesConfig) -> Result<Self, String> {
+        let remove = config
+            .remove
+            .iter()
+            .map(|name| match name.trim().strip_suffix('*') {
+                Some(prefix) => Ok(Pattern::Prefix(prefix.to_ascii_lowercase())),
+                None => header_name(name).map(Pattern::Exact),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let rename = config
+            .rename
+            .iter()
+            .map(|(from, to)| Ok((header_name(from)?, header_name(to)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        let templates = |values: &BTreeMap<String, String>| {
+            values
+                .iter()
+                .map(|(name, value)| Ok((header_name(name)?, Template::parse(value)?)))
+                .collect::<Result<Vec<_>, String>>()
+        };
+        Ok(HeaderRules {
+            remove,
+            rename,
+            set: templates(&config.set)?,
+            add: templates(&config.add)?,
+            set_if_absent: templates(&config.set_if_absent)?,
+        })
+    }
+
+    // 按规则修改请求头；渲染结果为空或不是合法的请求头值时跳过该条规则
+    pub fn apply(&self, headers: &mut HeaderMap, context: &Context) {
+        let removed: Vec<HeaderName> = headers
+            .keys()
+            .filter(|name| self.remove.iter().any(|p| p.matches(name)))
+            .cloned()
+            .collect();
+        for name in removed {
+            headers.remove(&name);
+        }
+        // 改名时新名称已有的值被替换
+        for (from, to) in &self.rename {
+            let values: Vec<HeaderValue> = headers.get_all(from).iter().cloned().collect();
+            if values.is_empty() {
+                continue;
+            }
+            headers.remove(from);
+            headers.remove(to);
+            for value in values {
+                headers.append(to.clone(), value);
+            }
+        }
+        let render = |template: &Template| {
+            let value = template.render(context);
+            match HeaderValue::from_str(&value) {
+                Ok(value) if !value.is_empty() => Some(value),
+                _ => None,
+            }
+        };
+        for (name, template) in &self.set {
+            if let Some(value) = render(template) {
+                headers.insert(name.clone(), value);
+            }
+        }
+        for (name, template) in &self.add {
+            if let Some(value) = render(template) {
+                headers.append(name.clone(), value);
+            }
+        }
+        for (name, template) in &self.set_if_absent {
+            if !headers.contains_key(name)
+                && let Some(value) = render(template)
+            {
+                headers.insert(name.clone(), value);
+            }
+        }
+    }
+
+    // 按规则修改返回客户端的响应头
+    pub fn apply_response(&self, resp: &mut HttpResponse, context: &Context) {
+        let mut headers: HeaderMap = resp
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        self.apply(&mut headers, context);
+        *resp.headers_mut() = headers.into();
+    }
+}
+
+fn header_name(name: &str) -> Result<HeaderName, String> {
+    HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|_| format!("无效的请求头名称: {}", name))
+}
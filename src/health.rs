@@ -0,0 +1,211 @@
+// ==================== 健康检查与就绪检查 ====================
+//
+// 管理接口上的 /healthz 表示进程存活；/readyz 在配置已加载、代理监听已绑定，
+// 且每个路由至少有一个上游（含故障转移目标）可以建立TCP连接时返回200，否则返回503，
+// 供 Kubernetes 探针和负载均衡器决定是否转发流量；通过管理接口开始排空后 /readyz 始终返回503
+
+use crate::TargetConfig;
+use crate::grpc_health::GrpcHealth;
+use crate::routes::RouteTable;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+// 就绪检查配置：对应配置文件中的 [admin.readiness]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReadinessConfig {
+    #[serde(default = "default_true")]
+    pub check_upstreams: bool, // 是否检查上游可达
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64, // 每个上游的连接超时(毫秒)
+    #[serde(default)]
+    pub require_all_targets: bool, // 是否要求路由的所有目标都可达，缺省只要求一个
+}
+
+impl Default for ReadinessConfig {
+    fn default() -> Self {
+        ReadinessConfig {
+            check_upstreams: true,
+            timeout_ms: default_timeout_ms(),
+            require_all_targets: false,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_timeout_ms() -> u64 {
+    1000
+}
+
+// 进程的健康状态
+pub struct Health {
+    config: ReadinessConfig,
+    started: Instant,
+    listening: AtomicBool, // 代理监听是否已绑定
+    draining: AtomicBool,  // 是否正在排空流量
+}
+
+impl Health {
+    pub fn new(config: &ReadinessConfig) -> Self {
+        Health {
+            config: config.clone(),
+            started: Instant::now(),
+            listening: AtomicBool::new(false),
+            draining: AtomicBool::new(false),
+        }
+    }
+
+    // 标记代理监听已绑定
+    pub fn set_listening(&self) {
+        self.listening.store(true, Ordering::Relaxed);
+    }
+
+    // 开始或停止排空：排空期间就绪检查失败，负载均衡器不再转发新的流量，
+    // 代理响应带上 Connection: close 使客户端转向其他实例；返回之前的状态
+    pub fn set_draining(&self, draining: bool) -> bool {
+        self.draining.swap(draining, Ordering::Relaxed)
+    }
+
+    pub fn draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    // 进程运行时长
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    // 存活检查结果
+    pub fn liveness(&self) -> serde_json::Value {
+        serde_json::json!({
+            "status": "ok",
+            "uptime_secs": self.uptime().as_secs(),
+        })
+    }
+
+    // 就绪检查：返回是否就绪与各项检查的详情
+    pub async fn readiness(&self, routes: &RouteTable) -> (bool, serde_json::Value) {
+        let listening = self.listening.load(Ordering::Relaxed);
+        let draining = self.draining();
+        let mut ready = listening && !draining;
+        let mut upstreams = BTreeMap::new();
+        if self.config.check_upstreams {
+            let timeout = Duration::from_millis(self.config.timeout_ms);
+            let results = probe(routes, timeout).await;
+            for route in routes.iter() {
+                let targets: Vec<String> = route
+                    .targets()
+                    .iter()
+                    .map(|t| crate::addr::join(&t.host, t.port))
+                    .collect();
+                let reachable = |addr: &String| results.get(addr).is_some_and(|r| r.is_ok());
+                let failover = route
+                    .failover
+                    .as_ref()
+                    .map(|t| crate::addr::join(&t.host, t.port));
+                let route_ready = if self.config.require_all_targets {
+                    targets.iter().all(reachable)
+                } else {
+                    targets.iter().chain(failover.as_ref()).any(reachable)
+                };
+                ready &= route_ready;
+                let details: BTreeMap<&String, String> = targets
+                    .iter()
+                    .chain(failover.as_ref())
+                    .map(|addr| {
+                        let status = match results.get(addr) {
+                            Some(Ok(())) => "ok".to_string(),
+                            Some(Err(e)) => e.clone(),
+                            None => "未探测".to_string(),
+                        };
+                        (addr, status)
+                    })
+                    .collect();
+                upstreams.insert(
+                    route.name.clone(),
+                    serde_json::json!({"ready": route_ready, "targets": details}),
+                );
+            }
+        }
+        let body = serde_json::json!({
+            "status": if ready { "ready" } else { "not_ready" },
+            "checks": {
+                "config": "ok",
+                "listeners": if listening { "ok" } else { "未绑定" },
+                "drain": if draining { "排空中" } else { "ok" },
+                "upstreams": upstreams,
+            },
+        });
+        (ready, body)
+    }
+}
+
+// 对一个上游的检查
+#[derive(PartialEq)]
+enum Check {
+    Tcp(String, u16),               // 能否建立TCP连接
+    Grpc(TargetConfig, GrpcHealth), // 调用 gRPC 健康检查
+}
+
+// 并发探测所有路由的上游（含故障转移目标），每个地址按同一种方式只探测一次，按地址返回结果；
+// 路由配置了 gRPC 健康检查时调用 Health/Check，否则检查能否建立TCP连接。
+// 同一地址被多个路由以不同方式检查时，全部通过才算可达
+pub async fn probe(routes: &RouteTable, timeout: Duration) -> BTreeMap<String, Result<(), String>> {
+    let mut checks: Vec<Check> = Vec::new();
+    for route in routes.iter() {
+        let mut targets: Vec<_> = route.targets().iter().cloned().collect();
+        targets.extend(route.failover.clone());
+        for target in targets {
+            // 运行时注册的目标可能不是 http 或 https，只检查 TCP 连接
+            let check = match &route.grpc_health {
+                Some(grpc) if matches!(target.protocol.as_str(), "http" | "https") => {
+                    Check::Grpc(target, grpc.clone())
+                }
+                _ => Check::Tcp(target.host, target.port),
+            };
+            if !checks.contains(&check) {
+                checks.push(check);
+            }
+        }
+    }
+    let mut probes = tokio::task::JoinSet::new();
+    for check in checks {
+        probes.spawn(async move {
+            match check {
+                Check::Tcp(host, port) => {
+                    let result = tokio::time::timeout(
+                        timeout,
+                        tokio::net::TcpStream::connect((host.as_str(), port)),
+                    )
+                    .await;
+                    let status = match result {
+                        Ok(Ok(_)) => Ok(()),
+                        Ok(Err(e)) => Err(e.to_string()),
+                        Err(_) => Err(format!("{}毫秒内未能连接", timeout.as_millis())),
+                    };
+                    (crate::addr::join(&host, port), status)
+                }
+                Check::Grpc(target, grpc) => {
+                    let status = tokio::time::timeout(timeout, grpc.check(&target))
+                        .await
+                        .unwrap_or_else(|_| {
+                            Err(format!("{}毫秒内未完成健康检查", timeout.as_millis()))
+                        });
+                    (crate::addr::join(&target.host, target.port), status)
+                }
+            }
+        });
+    }
+    let mut results = BTreeMap::new();
+    for (addr, status) in probes.join_all().await {
+        let result = results.entry(addr).or_insert(Ok(()));
+        if result.is_ok() {
+            *result = status;
+        }
+    }
+    results
+}
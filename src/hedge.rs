@@ -0,0 +1,145 @@
+// ==================== 对冲请求 ====================
+//
+// 对幂等的GET/HEAD请求，如果主请求在一段时间内没有响应，就向另一个后端再发一份相同的请求，
+// 采用先返回的结果并取消另一个。等待时间取该路由近期延迟的指定百分位，从而只对长尾请求对冲
+
+use crate::ProxyError;
+use crate::deadline::{Deadline, DeadlinePolicy};
+use crate::retry::{self, RetryBudget};
+use crate::routes::Route;
+use crate::timeouts;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// 对冲配置：对应路由中的 [routes.hedge]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HedgeConfig {
+    #[serde(default = "default_percentile")]
+    pub percentile: f64, // 触发对冲的延迟百分位，如95表示P95
+    #[serde(default = "default_min_delay_ms")]
+    pub min_delay_ms: u64, // 对冲等待时间下限(毫秒)
+    #[serde(default = "default_max_delay_ms")]
+    pub max_delay_ms: u64, // 对冲等待时间上限(毫秒)，样本不足时也使用该值
+    #[serde(default = "default_min_samples")]
+    pub min_samples: usize, // 计算百分位所需的最少样本数
+}
+
+// 以下函数为对冲配置提供默认值
+fn default_percentile() -> f64 {
+    95.0
+}
+
+fn default_min_delay_ms() -> u64 {
+    10
+}
+
+fn default_max_delay_ms() -> u64 {
+    1000
+}
+
+fn default_min_samples() -> usize {
+    20
+}
+
+// 最近请求的延迟样本窗口
+#[derive(Debug, Default)]
+pub struct LatencyWindow {
+    samples: Mutex<VecDeque<u64>>, // 延迟样本(毫秒)，最多保留 MAX_SAMPLES 个
+}
+
+const MAX_SAMPLES: usize = 1000;
+
+impl LatencyWindow {
+    // 记录一次请求的延迟
+    pub fn record(&self, latency: Duration) {
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() == MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(latency.as_millis() as u64);
+    }
+
+    // 计算指定百分位的延迟，样本数不足时返回None
+    pub fn percentile(&self, percentile: f64, min_samples: usize) -> Option<u64> {
+        let mut sorted: Vec<u64> = self.samples.lock().unwrap().iter().copied().collect();
+        if sorted.is_empty() || sorted.len() < min_samples {
+            return None;
+        }
+        sorted.sort_unstable();
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * (sorted.len() - 1) as f64).round();
+        Some(sorted[rank as usize])
+    }
+}
+
+// 根据近期延迟计算对冲等待时间
+fn hedge_delay(config: &HedgeConfig, latency: &LatencyWindow) -> Duration {
+    let delay = latency
+        .percentile(config.percentile, config.min_samples)
+        .unwrap_or(config.max_delay_ms)
+        .clamp(
+            config.min_delay_ms,
+            config.max_delay_ms.max(config.min_delay_ms),
+        );
+    Duration::from_millis(delay)
+}
+
+// 只对GET/HEAD请求对冲
+pub fn is_hedgeable(method: &Method) -> bool {
+    *method == Method::GET || *method == Method::HEAD
+}
+
+// 发送主请求，超过等待时间仍未响应时向备用后端发送对冲请求，返回先成功的结果
+#[allow(clippy::too_many_arguments)]
+pub async fn send(
+    config: &HedgeConfig,               // 对冲配置
+    route: &Route,                      // 匹配的路由，提供延迟样本、重试策略与超时设置
+    budget: &RetryBudget,               // 全局重试预算，对冲请求同样占用预算
+    method: &Method,                    // 请求方法
+    primary: reqwest::RequestBuilder,   // 发往主后端的请求
+    secondary: reqwest::RequestBuilder, // 发往备用后端的请求
+    deadlines: &DeadlinePolicy,         // 截止时间策略，主请求的每次重试重新设置剩余时间
+    deadline: &Deadline,                // 请求截止时间
+) -> Result<reqwest::Response, ProxyError> {
+    let delay = hedge_delay(config, &route.latency);
+    let first = retry::send(route, budget, method, primary, deadlines, deadline);
+    tokio::pin!(first);
+
+    // 1. 在等待时间内主请求完成则直接返回
+    tokio::select! {
+        result = &mut first => return result,
+        _ = tokio::time::sleep(delay) => {}
+    }
+
+    // 2. 预算不足时不再对冲，继续等待主请求
+    if !budget.try_acquire() {
+        return first.await;
+    }
+    log::info!("主请求{}毫秒内未响应，发送对冲请求", delay.as_millis());
+    let second = timeouts::send(secondary, route.timeouts.header);
+    tokio::pin!(second);
+
+    // 3. 两个请求竞争，先成功的胜出，另一个随future一起被丢弃（即取消）；
+    //    先完成的请求失败时，继续等待另一个
+    tokio::select! {
+        result = &mut first => match result {
+            Ok(resp) => Ok(resp),
+            Err(e) => {
+                log::warn!("主请求失败({})，等待对冲请求", e);
+                second.await
+            }
+        },
+        result = &mut second => match result {
+            Ok(resp) => {
+                log::info!("对冲请求先于主请求返回");
+                Ok(resp)
+            }
+            Err(e) => {
+                log::warn!("对冲请求失败({})，等待主请求", e);
+                first.await
+            }
+        },
+    }
+}
@@ -0,0 +1,93 @@
+// ==================== 逐跳请求头 ====================
+//
+// 逐跳（hop-by-hop）请求头只对一个连接有意义，代理转发时两个方向都要去掉（RFC 9110 第 7.6.1 节）：
+// Connection 及其列出的所有请求头，以及 Keep-Alive、Proxy-Connection、TE、Trailer、Transfer-Encoding、
+// Upgrade、Proxy-Authorization、Proxy-Authenticate。值恰好为 trailers 的 TE 保留，gRPC 依赖它；
+// [hop_by_hop] preserve 中列出的请求头照常转发，用于上游确实需要的情况（如由上游校验 Proxy-Authorization）。
+// Transfer-Encoding 与 Content-Length 由 HTTP 客户端与服务器按实际报文体重新生成，不受 preserve 影响
+
+use actix_web::http::header::{HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+
+// 逐跳请求头配置：对应配置文件中的 [hop_by_hop]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HopByHopConfig {
+    #[serde(default)]
+    pub preserve: Vec<String>, // 照常转发的逐跳请求头
+}
+
+// 固定的逐跳请求头
+const HOP_BY_HOP: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "proxy-authorization",
+    "proxy-authenticate",
+];
+
+// 逐跳请求头策略
+#[derive(Debug)]
+pub struct HopByHop {
+    preserve: Vec<HeaderName>,
+}
+
+// 一个报文中需要去掉的请求头
+pub struct Stripped<'a> {
+    policy: &'a HopByHop,
+    connection: Vec<HeaderName>, // Connection 列出的请求头
+    te_trailers: bool,           // TE 的值是否恰好为 trailers
+}
+
+impl HopByHop {
+    // 从配置构建策略，无效的请求头名称会导致启动失败
+    pub fn new(config: &HopByHopConfig) -> Result<Self, String> {
+        let preserve = config
+            .preserve
+            .iter()
+            .map(|name| {
+                HeaderName::from_bytes(name.trim().as_bytes())
+                    .map_err(|_| format!("无效的请求头名称: {}", name))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(HopByHop { preserve })
+    }
+
+    // 根据报文的 Connection 与 TE 确定需要去掉的请求头
+    pub fn strip<'a, 'v>(
+        &'a self,
+        connection: impl IntoIterator<Item = &'v HeaderValue>,
+        te: Option<&HeaderValue>,
+    ) -> Stripped<'a> {
+        let connection = connection
+            .into_iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .filter_map(|token| HeaderName::from_bytes(token.trim().as_bytes()).ok())
+            .collect();
+        let te_trailers = te
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.trim().eq_ignore_ascii_case("trailers"));
+        Stripped {
+            policy: self,
+            connection,
+            te_trailers,
+        }
+    }
+}
+
+impl Stripped<'_> {
+    // 请求头是否需要去掉
+    pub fn contains(&self, name: &HeaderName) -> bool {
+        if !(HOP_BY_HOP.contains(&name.as_str()) || self.connection.contains(name)) {
+            return false;
+        }
+        if *name == "te" && self.te_trailers && !self.connection.contains(name) {
+            return false; // TE: trailers 保留
+        }
+        *name == "transfer-encoding" || !self.policy.preserve.contains(name)
+    }
+}
@@ -0,0 +1,149 @@
+// ==================== HTTP/1.0 客户端 ====================
+//
+// HTTP/1.0 没有分块传输编码，响应体只能由 Content-Length 或关闭连接来界定；只支持 1.0 的客户端（一些嵌入式设备）
+// 收到 Transfer-Encoding: chunked 的响应会把分块的长度行当作内容。代理转发的响应总是完整读取后返回，
+// 本身带有 Content-Length；流式的响应体（如管理接口的实时流量 SSE）对 1.0 客户端改为先读取到内存再带上
+// Content-Length 返回，超过 buffer_size 或是 text/event-stream 时不再缓冲，直接输出并在结束后关闭连接。
+// 1.0 客户端没有发送 Connection: keep-alive 时响应后关闭连接，发送了时按 keep_alive 决定是否保持
+
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::http::{ConnectionType, Version};
+use actix_web::middleware::Next;
+use actix_web::web::{self, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// HTTP/1.0 客户端配置：对应配置文件中的 [http10]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Http10Config {
+    #[serde(default = "default_buffer_size")]
+    pub buffer_size: usize, // 为生成 Content-Length 最多缓冲的流式响应体字节数，0 表示不缓冲
+    #[serde(default = "default_keep_alive")]
+    pub keep_alive: bool, // 是否保持请求了 keep-alive 的 1.0 客户端的连接
+}
+
+impl Default for Http10Config {
+    fn default() -> Self {
+        Http10Config {
+            buffer_size: default_buffer_size(),
+            keep_alive: default_keep_alive(),
+        }
+    }
+}
+
+// 以下函数为 HTTP/1.0 客户端提供默认值
+fn default_buffer_size() -> usize {
+    1024 * 1024
+}
+
+fn default_keep_alive() -> bool {
+    true
+}
+
+// HTTP/1.0 中间件：需要注册在最外层，处理的是其他中间件都加工过的最终响应
+pub async fn middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    if req.version() != Version::HTTP_10 {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_boxed_body);
+    }
+    crate::metrics::counter_inc("proxy_http10_requests_total", &[]);
+    let config = req
+        .app_data::<web::Data<crate::AppState>>()
+        .map(|state| state.http10.load().as_ref().clone())
+        .unwrap_or_default();
+    // 不能保留请求的克隆，内层中间件需要独占请求才能修改
+    let mut res = next.call(req).await?.map_into_boxed_body();
+    res.headers_mut().remove(header::TRANSFER_ENCODING);
+    if !config.keep_alive {
+        res.response_mut()
+            .head_mut()
+            .set_connection_type(ConnectionType::Close);
+    }
+    if res.response().body().size() != BodySize::Stream {
+        return Ok(res);
+    }
+    // 事件流不会结束，缓冲只会推迟第一个事件
+    let event_stream = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    let limit = match event_stream {
+        true => 0,
+        false => config.buffer_size,
+    };
+    let (http_req, response) = res.into_parts();
+    let (response, body) = response.into_parts();
+    let (buffered, rest) = buffer(body, limit).await.map_err(|e| {
+        log::warn!("读取返回给 HTTP/1.0 客户端的响应体失败: {}", e);
+        actix_web::error::ErrorBadGateway(e)
+    })?;
+    let Some(body) = rest else {
+        let response = response.set_body(buffered).map_into_boxed_body();
+        return Ok(ServiceResponse::new(http_req, response));
+    };
+    // 无法预先确定长度，以关闭连接结束响应体
+    let mut response = response
+        .set_body(Rest {
+            buffered: Some(buffered),
+            body,
+        })
+        .map_into_boxed_body();
+    response.headers_mut().remove(header::CONTENT_LENGTH);
+    let head = response.head_mut();
+    head.no_chunking(true);
+    head.set_connection_type(ConnectionType::Close);
+    Ok(ServiceResponse::new(http_req, response))
+}
+
+// 读取响应体直到结束或超过 limit 字节；超过时同时返回已读取的部分与剩余的响应体
+async fn buffer(
+    mut body: BoxBody,
+    limit: usize,
+) -> Result<(Bytes, Option<BoxBody>), Box<dyn std::error::Error>> {
+    if limit == 0 {
+        return Ok((Bytes::new(), Some(body)));
+    }
+    let mut buffered = BytesMut::new();
+    while let Some(chunk) = std::future::poll_fn(|cx| Pin::new(&mut body).poll_next(cx)).await {
+        buffered.extend_from_slice(&chunk?);
+        if buffered.len() > limit {
+            return Ok((buffered.freeze(), Some(body)));
+        }
+    }
+    Ok((buffered.freeze(), None))
+}
+
+// 超过缓冲上限的响应体：先输出已读取的部分，再继续输出剩余的部分
+struct Rest {
+    buffered: Option<Bytes>,
+    body: BoxBody,
+}
+
+impl MessageBody for Rest {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        if let Some(buffered) = self.buffered.take()
+            && !buffered.is_empty()
+        {
+            return Poll::Ready(Some(Ok(buffered)));
+        }
+        Pin::new(&mut self.body).poll_next(cx)
+    }
+}
@@ -0,0 +1,252 @@
+// ==================== 幂等键去重 ====================
+//
+// 路由启用后，携带相同 Idempotency-Key 的请求只会向上游发送一次：
+// 并发到达的重复请求等待首个请求完成并共享其响应，之后到达的重复请求直接重放保存的响应，
+// 防止支付等接口因客户端重试或重复点击而被多次提交
+
+use crate::ProxyError;
+use crate::snapshot::ResponseSnapshot;
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+// 幂等配置：对应路由中的 [routes.idempotency]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IdempotencyConfig {
+    #[serde(default = "default_header")]
+    pub header: String, // 携带幂等键的请求头
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64, // 响应保存时间(秒)
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize, // 最多保存多少个幂等键
+}
+
+// 以下函数为幂等配置提供默认值
+fn default_header() -> String {
+    "Idempotency-Key".to_string()
+}
+
+fn default_ttl_secs() -> u64 {
+    86400
+}
+
+fn default_max_entries() -> usize {
+    10000
+}
+
+// 共享给等待者的结果：None表示首个请求尚未完成
+type Shared = Option<Arc<ResponseSnapshot>>;
+
+// 幂等键对应的记录
+#[derive(Debug)]
+enum Entry {
+    InFlight {
+        fingerprint: u64,                  // 请求指纹
+        receiver: watch::Receiver<Shared>, // 等待首个请求的结果
+    },
+    Done {
+        fingerprint: u64,                // 请求指纹
+        response: Arc<ResponseSnapshot>, // 保存的响应
+        expires: Instant,                // 过期时间
+    },
+}
+
+// 查找幂等键的结果
+enum Claim {
+    Replay(Arc<ResponseSnapshot>), // 已有保存的响应
+    Wait(watch::Receiver<Shared>), // 相同请求正在处理，等待其结果
+    Lead(watch::Sender<Shared>),   // 首个请求，负责调用上游
+    Bypass,                        // 记录已满，不做去重
+}
+
+// 路由的幂等记录
+#[derive(Debug)]
+pub struct IdempotencyStore {
+    config: IdempotencyConfig,
+    entries: Mutex<HashMap<String, Entry>>, // 幂等键 -> 记录
+}
+
+impl IdempotencyStore {
+    pub fn new(config: &IdempotencyConfig) -> Self {
+        IdempotencyStore {
+            config: config.clone(),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // 读取请求中的幂等键，未携带时返回None
+    pub fn key<'a>(&self, req: &'a HttpRequest) -> Option<&'a str> {
+        req.headers()
+            .get(self.config.header.as_str())
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty())
+    }
+
+    // 计算请求指纹：方法、URI、认证信息和请求体，相同幂等键但指纹不同的请求会被拒绝
+    pub fn fingerprint(req: &HttpRequest, body: &web::Bytes) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        req.method().as_str().hash(&mut hasher);
+        req.uri().to_string().hash(&mut hasher);
+        req.headers()
+            .get("authorization")
+            .map(|v| v.as_bytes())
+            .hash(&mut hasher);
+        body.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // 按幂等键执行上游调用：重复请求合并到同一次调用上，或直接重放已保存的响应
+    pub async fn execute<F>(
+        &self,
+        key: &str,        // 幂等键
+        fingerprint: u64, // 请求指纹
+        upstream: F,      // 实际的上游调用
+    ) -> Result<HttpResponse, ProxyError>
+    where
+        F: Future<Output = Result<HttpResponse, ProxyError>>,
+    {
+        match self.claim(key, fingerprint)? {
+            Claim::Replay(response) => {
+                log::info!("幂等键 {} 命中已保存的响应，直接重放", key);
+                Ok(response.to_response(Some(("Idempotent-Replayed", "true"))))
+            }
+            Claim::Wait(mut receiver) => {
+                log::info!("幂等键 {} 的请求正在处理，等待其结果", key);
+                match receiver.wait_for(|shared| shared.is_some()).await {
+                    Ok(shared) => {
+                        let response = shared.clone().expect("已完成的结果");
+                        Ok(response.to_response(Some(("Idempotent-Replayed", "true"))))
+                    }
+                    Err(_) => Err(ProxyError::IdempotencyConflict(format!(
+                        "使用幂等键 {} 的首个请求未能完成，请重试",
+                        key
+                    ))),
+                }
+            }
+            Claim::Lead(sender) => {
+                // 调用中途被取消（如客户端断开）时由守卫清除记录，避免等待者永远挂起
+                let mut guard = InFlightGuard {
+                    store: self,
+                    key,
+                    armed: true,
+                };
+                let result = upstream.await;
+                guard.armed = false;
+                self.complete(key, fingerprint, sender, result).await
+            }
+            Claim::Bypass => upstream.await,
+        }
+    }
+
+    // 查找或登记幂等键
+    fn claim(&self, key: &str, fingerprint: u64) -> Result<Claim, ProxyError> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        if let Some(Entry::Done { expires, .. }) = entries.get(key)
+            && *expires <= now
+        {
+            entries.remove(key);
+        }
+
+        let existing = match entries.get(key) {
+            Some(Entry::Done {
+                fingerprint: f,
+                response,
+                ..
+            }) => Some((*f, Claim::Replay(response.clone()))),
+            Some(Entry::InFlight {
+                fingerprint: f,
+                receiver,
+            }) => Some((*f, Claim::Wait(receiver.clone()))),
+            None => None,
+        };
+        if let Some((f, claim)) = existing {
+            if f != fingerprint {
+                return Err(ProxyError::IdempotencyConflict(format!(
+                    "幂等键 {} 已被另一个不同的请求使用",
+                    key
+                )));
+            }
+            return Ok(claim);
+        }
+
+        // 记录已满时先清理过期项，仍然满则不做去重
+        if entries.len() >= self.config.max_entries {
+            entries.retain(|_, e| !matches!(e, Entry::Done { expires, .. } if *expires <= now));
+            if entries.len() >= self.config.max_entries {
+                log::warn!("幂等记录已满({}条)，幂等键 {} 不做去重", entries.len(), key);
+                return Ok(Claim::Bypass);
+            }
+        }
+        let (sender, receiver) = watch::channel(None);
+        entries.insert(
+            key.to_string(),
+            Entry::InFlight {
+                fingerprint,
+                receiver,
+            },
+        );
+        Ok(Claim::Lead(sender))
+    }
+
+    // 首个请求完成：把响应共享给等待者，非5xx响应保存下来供之后重放
+    async fn complete(
+        &self,
+        key: &str,
+        fingerprint: u64,
+        sender: watch::Sender<Shared>,
+        result: Result<HttpResponse, ProxyError>,
+    ) -> Result<HttpResponse, ProxyError> {
+        let resp = match result {
+            Ok(resp) => resp,
+            Err(e) => {
+                // 出错时清除记录，丢弃sender使等待者收到失败通知，客户端可以用同一个键重试
+                self.entries.lock().unwrap().remove(key);
+                return Err(e);
+            }
+        };
+        let (snapshot, resp) = ResponseSnapshot::capture(resp).await;
+        let snapshot = Arc::new(snapshot);
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if snapshot.status.is_server_error() {
+                entries.remove(key);
+            } else {
+                entries.insert(
+                    key.to_string(),
+                    Entry::Done {
+                        fingerprint,
+                        response: snapshot.clone(),
+                        expires: Instant::now() + Duration::from_secs(self.config.ttl_secs),
+                    },
+                );
+            }
+        }
+        let _ = sender.send(Some(snapshot));
+        Ok(resp)
+    }
+}
+
+// 首个请求的守卫：上游调用未完成就被丢弃时清除进行中的记录
+struct InFlightGuard<'a> {
+    store: &'a IdempotencyStore,
+    key: &'a str,
+    armed: bool,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        if self.armed {
+            let mut entries = self.store.entries.lock().unwrap();
+            if matches!(entries.get(self.key), Some(Entry::InFlight { .. })) {
+                entries.remove(self.key);
+            }
+        }
+    }
+}
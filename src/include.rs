@@ -0,0 +1,161 @@
+// ==================== 配置文件包含 ====================
+//
+// 配置文件顶层的 include = ["routes.toml", "conf.d"] 先按顺序读取列出的文件，再读取当前文件，
+// 后读取的覆盖先读取的：表按键逐层合并，元素都是表的数组（如 [[routes]]、[[log.sinks]]）按读取顺序拼接，
+// 其他值（包括普通数组）整体替换。路径相对于声明 include 的文件，目录表示其中所有配置文件（按文件名排序），
+// 被包含的文件可以继续 include，但不能循环包含；各文件的格式分别按扩展名判断
+
+use crate::ProxyError;
+use config::{Config, ConfigError, File, Map, Source, Value, ValueKind};
+use std::path::{Path, PathBuf};
+
+// 合并后的配置树，作为配置加载器的一个源
+#[derive(Debug, Clone)]
+pub struct Merged(Map<String, Value>);
+
+impl Source for Merged {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        Ok(self.0.clone())
+    }
+}
+
+// 读取配置文件及其包含的文件，返回合并后的配置和读到的所有文件（用于热加载监视）；
+// 主配置文件不存在且不要求存在时返回空配置
+pub fn load(path: &str, required: bool) -> Result<(Merged, Vec<String>), ProxyError> {
+    let mut files = vec![path.to_string()];
+    if !required && !Path::new(path).exists() {
+        return Ok((Merged(Map::new()), files));
+    }
+    let table = load_file(Path::new(path), &mut Vec::new(), &mut files)?;
+    Ok((Merged(table), files))
+}
+
+impl Merged {
+    pub fn new(table: Map<String, Value>) -> Self {
+        Merged(table)
+    }
+
+    // 合并后的配置树，用于迁移旧版本的配置
+    pub fn table_mut(&mut self) -> &mut Map<String, Value> {
+        &mut self.0
+    }
+
+    // 用另一份配置覆盖，规则与 include 相同
+    pub fn overlay(&mut self, other: Merged) {
+        merge(&mut self.0, other.0);
+    }
+}
+
+// 读取一个文件：先合并它包含的文件，再用它自己的内容覆盖
+fn load_file(
+    path: &Path,
+    stack: &mut Vec<PathBuf>, // 正在读取的文件链，用于发现循环包含
+    files: &mut Vec<String>,  // 读到的所有文件
+) -> Result<Map<String, Value>, ProxyError> {
+    let name = path.display().to_string();
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| error(format!("无法读取配置文件 {}: {}", name, e)))?;
+    if stack.contains(&canonical) {
+        return Err(error(format!("配置文件循环包含: {}", name)));
+    }
+    if !files.contains(&name) {
+        files.push(name.clone());
+    }
+    let format = crate::config_format(&name)?;
+    let mut table = Config::builder()
+        .add_source(File::new(&name, format))
+        .build()?
+        .collect()?;
+
+    let includes = match table.remove("include").map(|v| v.kind) {
+        None => Vec::new(),
+        Some(ValueKind::String(include)) => vec![include],
+        Some(ValueKind::Array(items)) => items
+            .into_iter()
+            .map(|item| item.into_string())
+            .collect::<Result<_, _>>()
+            .map_err(|_| error(format!("{}: include 只能包含文件或目录路径", name)))?,
+        Some(_) => return Err(error(format!("{}: include 应为路径的数组", name))),
+    };
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut merged = Map::new();
+    stack.push(canonical);
+    for include in includes {
+        let target = dir.join(&include);
+        let entries = if target.is_dir() {
+            directory_files(&target)?
+        } else if target.is_file() {
+            vec![target]
+        } else {
+            return Err(error(format!(
+                "{}: include 的文件不存在: {}",
+                name,
+                target.display()
+            )));
+        };
+        for entry in entries {
+            let included = load_file(&entry, stack, files)?;
+            merge(&mut merged, included);
+        }
+    }
+    stack.pop();
+    merge(&mut merged, table);
+    Ok(merged)
+}
+
+// 目录中扩展名为 .toml/.yaml/.yml/.json 的文件，按文件名排序
+fn directory_files(dir: &Path) -> Result<Vec<PathBuf>, ProxyError> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| error(format!("无法读取目录 {}: {}", dir.display(), e)))?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| ["toml", "yaml", "yml", "json"].contains(&e))
+        })
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+// 把 overlay 合并到 base 中：表逐层合并，元素都是表的数组拼接，其他值替换
+pub fn merge(base: &mut Map<String, Value>, overlay: Map<String, Value>) {
+    for (key, value) in overlay {
+        let Some(existing) = base.remove(&key) else {
+            base.insert(key, value);
+            continue;
+        };
+        let merged = match (existing.kind, value.kind) {
+            (ValueKind::Table(mut table), ValueKind::Table(other)) => {
+                merge(&mut table, other);
+                Value::new(None, ValueKind::Table(table))
+            }
+            (ValueKind::Array(mut items), ValueKind::Array(other))
+                if !other.is_empty() && all_tables(&items) && all_tables(&other) =>
+            {
+                items.extend(other);
+                Value::new(None, ValueKind::Array(items))
+            }
+            (_, kind) => Value::new(None, kind),
+        };
+        base.insert(key, merged);
+    }
+}
+
+fn all_tables(items: &[Value]) -> bool {
+    items
+        .iter()
+        .all(|item| matches!(item.kind, ValueKind::Table(_)))
+}
+
+fn error(message: String) -> ProxyError {
+    ProxyError::ConfigError(ConfigError::Message(message))
+}
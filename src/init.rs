@@ -0,0 +1,37 @@
+// ==================== 配置模板 ====================
+//
+// init 子命令使用：写出一份带注释的 config.toml，包含运行所需的基本配置和常用功能的示例（已注释），
+// 新用户不必从反序列化错误中反推字段名。--port、--target 参数的值会填入模板；
+// 目标文件已存在时不覆盖，除非指定 --force
+
+use crate::cli::{self, Cli};
+use std::path::Path;
+
+// 模板内容，__XXX__ 占位符在写出时替换
+const TEMPLATE: &str = include_str!("init.toml");
+
+// 生成配置文件，返回写出的路径
+pub fn write(args: &Cli) -> Result<String, String> {
+    let path = args.config.clone().unwrap_or("config.toml".to_string());
+    if !path.to_ascii_lowercase().ends_with(".toml") {
+        return Err(format!("模板为 TOML 格式，文件扩展名应为 .toml: {}", path));
+    }
+    if Path::new(&path).exists() && !args.force {
+        return Err(format!("文件已存在: {}（使用 --force 覆盖）", path));
+    }
+    std::fs::write(&path, render(args)?).map_err(|e| format!("无法写入 {}: {}", path, e))?;
+    Ok(path)
+}
+
+// 填入命令行参数后的模板
+fn render(args: &Cli) -> Result<String, String> {
+    let (protocol, host, port) = match &args.target {
+        Some(target) => cli::parse_target(target)?,
+        None => ("http".to_string(), "127.0.0.1".to_string(), 8080),
+    };
+    Ok(TEMPLATE
+        .replace("__SERVER_PORT__", &args.port.unwrap_or(3000).to_string())
+        .replace("__TARGET_PROTOCOL__", &protocol)
+        .replace("__TARGET_HOST__", &host)
+        .replace("__TARGET_PORT__", &port.to_string()))
+}
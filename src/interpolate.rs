@@ -0,0 +1,103 @@
+// ==================== 环境变量插值 ====================
+//
+// 配置中任意字符串值里的 `${VAR}` 在加载时替换为环境变量的值，可以只替换一部分，
+// 如 host = "${REGION}.api.example.com"；`${VAR:-默认值}` 在变量未设置或为空时使用默认值，
+// `$${` 表示字面的 `${`。需要数字的配置项写成字符串即可，如 port = "${UPSTREAM_PORT}"。
+// 在解析密钥引用之前进行，因此也可以写成 token = "file:${SECRETS_DIR}/token"；
+// 所有未设置的变量一次列出
+
+use crate::ProxyError;
+use config::{ConfigError, Map, Value, ValueKind};
+
+// 替换配置树中所有字符串值里的占位符
+pub fn expand(table: Map<String, Value>) -> Result<Map<String, Value>, ProxyError> {
+    let mut missing = Vec::new();
+    let table = table
+        .into_iter()
+        .map(|(key, value)| {
+            let value = expand_value(&key, value, &mut missing);
+            (key, value)
+        })
+        .collect();
+    missing.sort(); // 配置树的键无序，排序使输出稳定
+    match missing.is_empty() {
+        true => Ok(table),
+        false => Err(ProxyError::ConfigError(ConfigError::Message(
+            missing.join("; "),
+        ))),
+    }
+}
+
+// 替换单个配置值；表和数组会继续向下递归
+fn expand_value(path: &str, value: Value, missing: &mut Vec<String>) -> Value {
+    let kind = match value.kind {
+        ValueKind::String(s) if s.contains('$') => ValueKind::String(expand_str(path, &s, missing)),
+        ValueKind::Table(table) => ValueKind::Table(
+            table
+                .into_iter()
+                .map(|(key, child)| {
+                    let child = expand_value(&format!("{}.{}", path, key), child, missing);
+                    (key, child)
+                })
+                .collect(),
+        ),
+        ValueKind::Array(array) => ValueKind::Array(
+            array
+                .into_iter()
+                .enumerate()
+                .map(|(index, child)| expand_value(&format!("{}[{}]", path, index), child, missing))
+                .collect(),
+        ),
+        other => other,
+    };
+    Value::new(None, kind)
+}
+
+// 替换字符串中的占位符，未设置的变量和格式错误记入 missing
+fn expand_str(path: &str, value: &str, missing: &mut Vec<String>) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        if let Some(after) = tail.strip_prefix("$${") {
+            out.push_str("${");
+            rest = after;
+            continue;
+        }
+        let Some(body) = tail.strip_prefix("${") else {
+            out.push('$');
+            rest = &tail[1..];
+            continue;
+        };
+        let Some(end) = body.find('}') else {
+            missing.push(format!("{}: 占位符缺少右花括号: {}", path, tail));
+            out.push_str(tail);
+            return out;
+        };
+        let (name, default) = match body[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&body[..end], None),
+        };
+        if !valid_name(name) {
+            missing.push(format!("{}: 无效的环境变量名: ${{{}}}", path, name));
+        } else {
+            match (std::env::var(name), default) {
+                (Ok(v), Some(default)) if v.is_empty() => out.push_str(default),
+                (Ok(v), _) => out.push_str(&v),
+                (Err(_), Some(default)) => out.push_str(default),
+                (Err(_), None) => missing.push(format!("{}: 环境变量 {} 未设置", path, name)),
+            }
+        }
+        rest = &body[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// 环境变量名：字母、数字和下划线，不以数字开头
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,203 @@
+// ==================== JSON 模板 ====================
+//
+// XML 网关与响应组合共用的模板：字符串中 $路径 或 ${路径} 引用 JSON 中以 . 分隔的字段，
+// 数组中的元素用下标选择，$$ 表示字面的 $；路径之后的 |number、|bool、|array 转换类型。
+// JSON 模板中的字符串按模板渲染，只有一个字段的字符串保留字段的值，其余按文本拼接
+
+use serde_json::Value;
+
+// 类型转换，写在字段路径之后，如 ${Order.Total|number}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Conversion {
+    None,
+    Number, // 转换为数字，无法转换时为 null
+    Bool,   // true/1 与 false/0，其他为 null
+    Array,  // 单个值包装为数组，缺少时为空数组
+}
+
+// 模板片段
+#[derive(Debug, Clone)]
+pub enum Segment {
+    Literal(String),
+    Field(Vec<String>, Conversion), // 以 . 分隔的路径
+}
+
+// 解析后的模板，字段写作 $路径 或 ${路径}；$$ 表示字面的 $
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut rest = format;
+        while let Some(start) = rest.find('$') {
+            literal.push_str(&rest[..start]);
+            rest = &rest[start + 1..];
+            if let Some(after) = rest.strip_prefix('$') {
+                literal.push('$');
+                rest = after;
+                continue;
+            }
+            // 不带花括号时字段到第一个不属于路径的字符为止，末尾的 . 不计入
+            let (field, after) = match rest.strip_prefix('{') {
+                Some(inner) => {
+                    let end = inner
+                        .find('}')
+                        .ok_or_else(|| format!("模板中的 ${{ 没有闭合: {:?}", format))?;
+                    (&inner[..end], &inner[end + 1..])
+                }
+                None => {
+                    let end = rest
+                        .find(|c: char| !(c.is_alphanumeric() || "_-.@|".contains(c)))
+                        .unwrap_or(rest.len());
+                    let field = rest[..end].trim_end_matches('.');
+                    (field, &rest[field.len()..])
+                }
+            };
+            if field.is_empty() {
+                literal.push('$');
+                continue;
+            }
+            let (path, conversion) = match field.split_once('|') {
+                Some((path, "number")) => (path, Conversion::Number),
+                Some((path, "bool")) => (path, Conversion::Bool),
+                Some((path, "array")) => (path, Conversion::Array),
+                Some((_, other)) => {
+                    return Err(format!(
+                        "未知的类型转换 |{}（可选: number、bool、array）",
+                        other
+                    ));
+                }
+                None => (field, Conversion::None),
+            };
+            let path: Vec<String> = path.trim().split('.').map(|s| s.to_string()).collect();
+            if path.iter().any(|s| s.is_empty()) {
+                return Err(format!("模板中有无效的字段路径: ${}", field));
+            }
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+            }
+            segments.push(Segment::Field(path, conversion));
+            rest = after;
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Template { segments })
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = &Vec<String>> {
+        self.segments.iter().filter_map(|s| match s {
+            Segment::Field(path, _) => Some(path),
+            Segment::Literal(_) => None,
+        })
+    }
+}
+
+// 响应模板
+#[derive(Debug)]
+pub enum JsonTemplate {
+    Value(Value), // 不含模板的值
+    String(Template),
+    Array(Vec<JsonTemplate>),
+    Object(Vec<(String, JsonTemplate)>),
+}
+
+impl JsonTemplate {
+    pub fn parse(value: &Value) -> Result<Self, String> {
+        Ok(match value {
+            Value::String(s) => JsonTemplate::String(Template::parse(s)?),
+            Value::Array(items) => JsonTemplate::Array(
+                items
+                    .iter()
+                    .map(JsonTemplate::parse)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Object(map) => JsonTemplate::Object(
+                map.iter()
+                    .map(|(k, v)| Ok((k.clone(), JsonTemplate::parse(v)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            other => JsonTemplate::Value(other.clone()),
+        })
+    }
+
+    pub fn render(&self, root: &Value) -> Value {
+        match self {
+            JsonTemplate::Value(value) => value.clone(),
+            // 只有一个字段的字符串保留字段的类型，其余按文本拼接
+            JsonTemplate::String(template) => match template.segments.as_slice() {
+                [Segment::Field(path, conversion)] => convert(lookup(root, path), *conversion),
+                segments => Value::String(
+                    segments
+                        .iter()
+                        .map(|segment| match segment {
+                            Segment::Literal(s) => s.clone(),
+                            Segment::Field(path, conversion) => {
+                                match convert(lookup(root, path), *conversion) {
+                                    Value::String(s) => s,
+                                    Value::Null => String::new(),
+                                    other => other.to_string(),
+                                }
+                            }
+                        })
+                        .collect(),
+                ),
+            },
+            JsonTemplate::Array(items) => {
+                Value::Array(items.iter().map(|item| item.render(root)).collect())
+            }
+            JsonTemplate::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.render(root)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+// 按路径取值，数组中的元素用下标选择
+pub fn lookup<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| match value {
+        Value::Object(map) => map.get(key),
+        Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => None,
+    })
+}
+
+pub fn convert(value: Option<&Value>, conversion: Conversion) -> Value {
+    let text = |value: &Value| match value {
+        Value::String(s) => s.trim().to_string(),
+        other => other.to_string(),
+    };
+    match (value, conversion) {
+        (None, Conversion::Array) => Value::Array(Vec::new()),
+        (None, _) => Value::Null,
+        (Some(value), Conversion::None) => value.clone(),
+        (Some(Value::Array(items)), Conversion::Array) => Value::Array(items.clone()),
+        (Some(value), Conversion::Array) => Value::Array(vec![value.clone()]),
+        (Some(value), Conversion::Number) => {
+            let text = text(value);
+            text.parse::<i64>()
+                .map(Value::from)
+                .ok()
+                .or_else(|| {
+                    text.parse::<f64>()
+                        .ok()
+                        .and_then(serde_json::Number::from_f64)
+                        .map(Value::Number)
+                })
+                .unwrap_or(Value::Null)
+        }
+        (Some(value), Conversion::Bool) => match text(value).as_str() {
+            "true" | "1" => Value::Bool(true),
+            "false" | "0" => Value::Bool(false),
+            _ => Value::Null,
+        },
+    }
+}
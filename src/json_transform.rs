@@ -0,0 +1,268 @@
+// ==================== JSON 响应改写 ====================
+//
+// [routes.json_transform] 按 JSONPath 修改上游返回的 JSON 响应体，不改后端即可去掉敏感字段、
+// 为旧客户端改名字段或补上默认值。依次执行 remove、rename、set：remove 删除匹配的字段或数组元素，
+// rename 把匹配的字段改名（位置不变），set 设置匹配的字段，字段不存在时在父对象中新增。
+// 支持的 JSONPath：$ 根、.name 与 ['name'] 字段、[N] 数组下标（负数从末尾计）、.* 与 [*] 所有子元素、
+// ..name 任意深度的字段。响应体不是合法的 JSON 时原样转发
+
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// JSON 响应改写配置：对应配置文件中的 [routes.json_transform]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct JsonTransformConfig {
+    #[serde(default)]
+    pub remove: Vec<String>, // 删除的字段，如 "$.user.password"、"$..secret"
+    #[serde(default)]
+    pub rename: Vec<RenameRule>, // 字段改名
+    #[serde(default)]
+    pub set: Vec<SetRule>, // 设置字段
+}
+
+// 字段改名规则
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RenameRule {
+    pub path: String, // 字段路径，最后一段必须是字段名，如 "$.items[*].userName"
+    pub to: String,   // 新的字段名，如 "user_name"
+}
+
+// 设置字段规则
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SetRule {
+    pub path: String, // 字段路径，如 "$.apiVersion"
+    pub value: Value, // 设置的值，可以是任意 TOML 值
+}
+
+// 选择子元素的方式
+#[derive(Debug, Clone)]
+enum Selector {
+    Key(String), // 字段
+    Index(i64),  // 数组下标，负数从末尾计
+    Wildcard,    // 所有子元素
+}
+
+// 路径中的一步
+#[derive(Debug, Clone)]
+enum Step {
+    Child(Selector),  // 子元素
+    DescendantOrSelf, // 自身与所有后代，由 .. 产生
+}
+
+// 解析后的 JSONPath，最后一步总是子元素，编辑在其父元素上进行
+#[derive(Debug, Clone)]
+struct Path {
+    parents: Vec<Step>,
+    last: Selector,
+}
+
+impl Path {
+    fn parse(path: &str) -> Result<Self, String> {
+        let invalid = |reason: &str| format!("无效的 JSONPath {:?}: {}", path, reason);
+        let mut rest = path
+            .trim()
+            .strip_prefix('$')
+            .ok_or_else(|| invalid("必须以 $ 开头"))?;
+        let mut steps = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix("..") {
+                steps.push(Step::DescendantOrSelf);
+                rest = after;
+                if rest.starts_with('[') {
+                    continue;
+                }
+            } else if let Some(after) = rest.strip_prefix('.') {
+                rest = after;
+            } else if !rest.starts_with('[') {
+                return Err(invalid("字段之间需要 . 或 []"));
+            }
+            if let Some(after) = rest.strip_prefix('[') {
+                let end = after.find(']').ok_or_else(|| invalid("缺少 ]"))?;
+                let inner = after[..end].trim();
+                rest = &after[end + 1..];
+                let quoted = inner
+                    .strip_prefix('\'')
+                    .and_then(|s| s.strip_suffix('\''))
+                    .or_else(|| inner.strip_prefix('"').and_then(|s| s.strip_suffix('"')));
+                let selector = match (inner, quoted) {
+                    ("*", _) => Selector::Wildcard,
+                    (_, Some(key)) => Selector::Key(key.to_string()),
+                    _ => Selector::Index(
+                        inner
+                            .parse()
+                            .map_err(|_| invalid("[] 中必须是下标、* 或带引号的字段名"))?,
+                    ),
+                };
+                steps.push(Step::Child(selector));
+            } else {
+                let end = rest.find(['.', '[']).unwrap_or(rest.len());
+                let name = &rest[..end];
+                rest = &rest[end..];
+                steps.push(Step::Child(match name {
+                    "" => return Err(invalid("字段名为空")),
+                    "*" => Selector::Wildcard,
+                    name => Selector::Key(name.to_string()),
+                }));
+            }
+        }
+        match steps.pop() {
+            Some(Step::Child(last)) => Ok(Path {
+                parents: steps,
+                last,
+            }),
+            _ => Err(invalid("不能只有 $ 或以 .. 结尾")),
+        }
+    }
+
+    // 对所有匹配的父元素调用 f
+    fn for_each_parent(&self, value: &mut Value, f: &mut dyn FnMut(&mut Value, &Selector)) {
+        walk(value, &self.parents, &self.last, f);
+    }
+}
+
+fn walk(
+    value: &mut Value,
+    steps: &[Step],
+    last: &Selector,
+    f: &mut dyn FnMut(&mut Value, &Selector),
+) {
+    match steps.split_first() {
+        None => f(value, last),
+        Some((Step::Child(selector), rest)) => {
+            for child in children(value, selector) {
+                walk(child, rest, last, f);
+            }
+        }
+        Some((Step::DescendantOrSelf, rest)) => {
+            walk(value, rest, last, f);
+            for child in children(value, &Selector::Wildcard) {
+                walk(child, steps, last, f);
+            }
+        }
+    }
+}
+
+// 按选择方式取得子元素
+fn children<'a>(value: &'a mut Value, selector: &Selector) -> Vec<&'a mut Value> {
+    match (value, selector) {
+        (Value::Object(map), Selector::Key(key)) => map.get_mut(key).into_iter().collect(),
+        (Value::Object(map), Selector::Wildcard) => map.values_mut().collect(),
+        (Value::Array(items), Selector::Index(index)) => {
+            let index = resolve(*index, items.len());
+            index.and_then(|i| items.get_mut(i)).into_iter().collect()
+        }
+        (Value::Array(items), Selector::Wildcard) => items.iter_mut().collect(),
+        _ => Vec::new(),
+    }
+}
+
+// 负数下标从末尾计
+fn resolve(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+// 编译后的 JSON 响应改写规则
+#[derive(Debug)]
+pub struct JsonTransform {
+    remove: Vec<Path>,
+    rename: Vec<(Path, String)>,
+    set: Vec<(Path, Value)>,
+}
+
+impl JsonTransform {
+    // 从配置构建，JSONPath 无效或改名的目标不是字段时启动失败
+    pub fn new(config: &JsonTransformConfig) -> Result<Self, String> {
+        let remove = config
+            .remove
+            .iter()
+            .map(|p| Path::parse(p))
+            .collect::<Result<Vec<_>, _>>()?;
+        let rename = config
+            .rename
+            .iter()
+            .map(|rule| {
+                let path = Path::parse(&rule.path)?;
+                match path.last {
+                    Selector::Key(_) => Ok((path, rule.to.clone())),
+                    _ => Err(format!("改名的路径必须以字段名结尾: {}", rule.path)),
+                }
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let set = config
+            .set
+            .iter()
+            .map(|rule| Ok((Path::parse(&rule.path)?, rule.value.clone())))
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(JsonTransform {
+            remove,
+            rename,
+            set,
+        })
+    }
+
+    // 响应是否需要改写：内容类型为 JSON 且没有压缩
+    pub fn applies(&self, headers: &HeaderMap) -> bool {
+        let media_type = crate::sub_filter::media_type(headers);
+        !crate::sub_filter::encoded(headers)
+            && (media_type == "application/json" || media_type.ends_with("+json"))
+    }
+
+    // 改写响应体；不是合法的 JSON 时返回 None
+    pub fn apply(&self, body: &[u8]) -> Option<Vec<u8>> {
+        let mut value: Value = match serde_json::from_slice(body) {
+            Ok(value) => value,
+            Err(e) => {
+                log::debug!("响应体不是合法的 JSON，跳过改写: {}", e);
+                return None;
+            }
+        };
+        for path in &self.remove {
+            path.for_each_parent(&mut value, &mut |parent, last| match (parent, last) {
+                (Value::Object(map), Selector::Key(key)) => {
+                    map.shift_remove(key);
+                }
+                (Value::Object(map), Selector::Wildcard) => map.clear(),
+                (Value::Array(items), Selector::Index(index)) => {
+                    if let Some(i) = resolve(*index, items.len()) {
+                        items.remove(i);
+                    }
+                }
+                (Value::Array(items), Selector::Wildcard) => items.clear(),
+                _ => {}
+            });
+        }
+        for (path, to) in &self.rename {
+            path.for_each_parent(&mut value, &mut |parent, last| {
+                if let (Value::Object(map), Selector::Key(key)) = (parent, last)
+                    && map.contains_key(key)
+                    && key != to
+                {
+                    // 重建对象以保持字段的位置
+                    *map = std::mem::take(map)
+                        .into_iter()
+                        .filter(|(k, _)| k != to)
+                        .map(|(k, v)| match k == *key {
+                            true => (to.clone(), v),
+                            false => (k, v),
+                        })
+                        .collect();
+                }
+            });
+        }
+        for (path, new) in &self.set {
+            path.for_each_parent(&mut value, &mut |parent, last| match (parent, last) {
+                (Value::Object(map), Selector::Key(key)) => {
+                    map.insert(key.clone(), new.clone());
+                }
+                (parent, selector) => {
+                    for child in children(parent, selector) {
+                        *child = new.clone();
+                    }
+                }
+            });
+        }
+        serde_json::to_vec(&value).ok()
+    }
+}
@@ -0,0 +1,108 @@
+// ==================== 并发限制与排队 ====================
+//
+// 限制同时处理的代理请求数。达到上限时，可以让请求在有界队列中等待许可（带超时），
+// 队列已满或等待超时才拒绝，从而平滑短时的流量突增
+
+use crate::ProxyError;
+use crate::metrics;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// 并发配置：对应配置文件中的 [concurrency]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ConcurrencyConfig {
+    #[serde(default)]
+    pub max_requests: Option<usize>, // 最大并发请求数，缺省不限制
+    #[serde(default)]
+    pub queue_depth: usize, // 达到上限时允许排队的请求数，0表示立即拒绝
+    #[serde(default = "default_queue_timeout_ms")]
+    pub queue_timeout_ms: u64, // 排队等待的最长时间(毫秒)
+}
+
+fn default_queue_timeout_ms() -> u64 {
+    1000
+}
+
+// 并发限制器
+pub struct ConcurrencyLimiter {
+    semaphore: Option<Arc<Semaphore>>, // 并发许可，未配置上限时为None
+    queue_depth: usize,
+    queue_timeout: Duration,
+    waiting: AtomicUsize, // 当前排队的请求数
+}
+
+// 持有期间占用一个并发许可，并计入正在处理的请求数
+pub struct Permit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        metrics::gauge_add("proxy_inflight_requests", &[], -1.0);
+    }
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(config: &ConcurrencyConfig) -> Self {
+        ConcurrencyLimiter {
+            semaphore: config.max_requests.map(|n| Arc::new(Semaphore::new(n))),
+            queue_depth: config.queue_depth,
+            queue_timeout: Duration::from_millis(config.queue_timeout_ms),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    // 获取并发许可；队列已满或等待超时时返回 ProxyError::Overloaded
+    pub async fn acquire(&self) -> Result<Permit, ProxyError> {
+        let permit = match &self.semaphore {
+            None => None,
+            Some(semaphore) => Some(self.acquire_from(semaphore).await?),
+        };
+        metrics::gauge_add("proxy_inflight_requests", &[], 1.0);
+        Ok(Permit { _permit: permit })
+    }
+
+    async fn acquire_from(
+        &self,
+        semaphore: &Arc<Semaphore>,
+    ) -> Result<OwnedSemaphorePermit, ProxyError> {
+        // 1. 有空闲许可时直接获取
+        if let Ok(permit) = semaphore.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        // 2. 队列已满则立即拒绝
+        let position = self.waiting.fetch_add(1, Ordering::SeqCst);
+        if position >= self.queue_depth {
+            self.waiting.fetch_sub(1, Ordering::SeqCst);
+            metrics::counter_inc("proxy_queue_rejected_total", &[("reason", "queue_full")]);
+            return Err(ProxyError::Overloaded("并发请求数已达上限".to_string()));
+        }
+        metrics::gauge_set("proxy_queue_depth", &[], (position + 1) as f64);
+
+        // 3. 在队列中等待许可，超时则拒绝
+        let started = Instant::now();
+        let result =
+            tokio::time::timeout(self.queue_timeout, semaphore.clone().acquire_owned()).await;
+        let depth = self.waiting.fetch_sub(1, Ordering::SeqCst) - 1;
+        metrics::gauge_set("proxy_queue_depth", &[], depth as f64);
+        metrics::histogram_observe(
+            "proxy_queue_wait_seconds",
+            &[],
+            started.elapsed().as_secs_f64(),
+        );
+        match result {
+            Ok(Ok(permit)) => Ok(permit),
+            _ => {
+                metrics::counter_inc("proxy_queue_rejected_total", &[("reason", "timeout")]);
+                Err(ProxyError::Overloaded(format!(
+                    "排队等待超过{}毫秒",
+                    self.queue_timeout.as_millis()
+                )))
+            }
+        }
+    }
+}
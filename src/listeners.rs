@@ -0,0 +1,360 @@
+// ==================== 多个监听地址 ====================
+//
+// [[listeners]] 让同一个进程监听多个地址：每个监听可以是 HTTP 或 HTTPS（配置证书与私钥），可以只服务部分路由，
+// 也可以把所有请求重定向到 HTTPS。没有配置 [[listeners]] 时按 [server] 的地址监听，服务所有路由。
+// 所有监听共用一组工作线程和同一份共享状态；监听在启动时固定，修改后重启或平滑升级生效。
+// HTTPS 监听通过 ALPN 与客户端协商 HTTP/2 或 HTTP/1.1，明文监听只使用 HTTP/1.1
+
+use crate::proxy_protocol::{ProxyProtocol, ProxyProtocolConfig};
+use crate::routes::{Route, RouteTable};
+use actix_http::{HttpService, KeepAlive, Protocol, Request, Response, body::MessageBody};
+use actix_server::{Server, ServerBuilder};
+use actix_service::{
+    IntoServiceFactory, Service, ServiceFactory, ServiceFactoryExt, fn_service, map_config,
+};
+use actix_web::dev::{AppConfig, Extensions};
+use actix_web::{HttpRequest, HttpResponse, http::Method, web};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+
+// 监听配置：对应配置文件中的 [[listeners]]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ListenerConfig {
+    pub name: String, // 名称，用于日志，也是 systemd 套接字的 FileDescriptorName
+    #[serde(default = "default_host")]
+    pub host: String, // 监听地址，可以是逗号分隔的多个，:: 为双栈
+    pub port: u16,    // 监听端口
+    #[serde(default)]
+    pub tls: Option<TlsConfig>, // 证书与私钥，配置后为 HTTPS
+    #[serde(default)]
+    pub routes: Vec<String>, // 只服务这些路由（按名称），为空表示所有路由
+    #[serde(default)]
+    pub redirect_https: Option<u16>, // 把所有请求重定向到该端口上的 HTTPS，不再代理
+    #[serde(default)]
+    pub proxy_protocol: Option<ProxyProtocolConfig>, // 接受负载均衡器发送的 PROXY 协议头
+}
+
+// TLS 证书配置
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TlsConfig {
+    pub cert: String, // PEM 格式的证书链文件
+    pub key: String,  // PEM 格式的 PKCS#8 私钥文件
+    #[serde(default = "default_handshake_timeout_ms")]
+    pub handshake_timeout_ms: u64, // TLS 握手超时(毫秒)
+}
+
+// 以下函数为监听配置提供默认值
+fn default_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_handshake_timeout_ms() -> u64 {
+    10000
+}
+
+// 没有配置 [[listeners]] 时按 [server] 监听的名称，也是 systemd 套接字的默认归属
+pub const DEFAULT_NAME: &str = "proxy";
+
+// 运行时的监听，请求处理时通过 app_data 取得
+pub struct Listener {
+    pub name: String,                              // 名称
+    pub host: String,                              // 监听的主机，可以是逗号分隔的多个
+    pub port: u16,                                 // 监听端口
+    pub routes: Vec<String>,                       // 服务的路由，为空表示所有路由
+    pub redirect_https: Option<u16>,               // 重定向到的 HTTPS 端口
+    tls: Option<(crate::tls::Acceptor, Duration)>, // TLS 接受器与握手超时
+    proxy_protocol: Option<Arc<ProxyProtocol>>,    // PROXY 协议
+}
+
+impl Listener {
+    pub fn new(config: &ListenerConfig) -> Result<Self, String> {
+        let tls = match &config.tls {
+            Some(tls) => Some((
+                crate::tls::Acceptor::new(&tls.cert, &tls.key)
+                    .map_err(|e| format!("监听 {}: {}", config.name, e))?,
+                Duration::from_millis(tls.handshake_timeout_ms),
+            )),
+            None => None,
+        };
+        Ok(Listener {
+            name: config.name.clone(),
+            host: config.host.clone(),
+            port: config.port,
+            routes: config.routes.clone(),
+            redirect_https: config.redirect_https,
+            tls,
+            proxy_protocol: proxy_protocol(config.proxy_protocol.as_ref())
+                .map_err(|e| format!("监听 {}: {}", config.name, e))?,
+        })
+    }
+
+    // 配置中的所有监听；没有配置 [[listeners]] 时为 [server] 的地址
+    pub fn all(config: &crate::AppConfig) -> Result<Vec<Listener>, String> {
+        if config.listeners.is_empty() {
+            return Ok(vec![Listener {
+                name: DEFAULT_NAME.to_string(),
+                host: config.server.host.clone(),
+                port: config.server.port,
+                routes: Vec::new(),
+                redirect_https: None,
+                tls: None,
+                proxy_protocol: proxy_protocol(config.server.proxy_protocol.as_ref())?,
+            }]);
+        }
+        config.listeners.iter().map(Listener::new).collect()
+    }
+
+    pub fn scheme(&self) -> &'static str {
+        match self.tls {
+            Some(_) => "https",
+            None => "http",
+        }
+    }
+
+    // 配置了 redirect_https 时的重定向响应：GET/HEAD 用 301，其他方法用 308 保留方法与请求体
+    pub fn redirect(&self, req: &HttpRequest) -> Option<HttpResponse> {
+        let port = self.redirect_https?;
+        let info = req.connection_info();
+        let host = info.host();
+        // 去掉 Host 中的端口，IPv6 地址保留方括号
+        let host = match host.rfind(':') {
+            Some(i) if !host[i..].contains(']') => &host[..i],
+            _ => host,
+        };
+        let location = match port {
+            443 => format!("https://{}{}", host, path_and_query(req)),
+            port => format!("https://{}:{}{}", host, port, path_and_query(req)),
+        };
+        let mut resp = match *req.method() == Method::GET || *req.method() == Method::HEAD {
+            true => HttpResponse::MovedPermanently(),
+            false => HttpResponse::PermanentRedirect(),
+        };
+        Some(resp.insert_header(("Location", location)).finish())
+    }
+}
+
+fn proxy_protocol(
+    config: Option<&ProxyProtocolConfig>,
+) -> Result<Option<Arc<ProxyProtocol>>, String> {
+    config
+        .map(|c| ProxyProtocol::new(c).map(Arc::new))
+        .transpose()
+}
+
+fn path_and_query(req: &HttpRequest) -> &str {
+    req.uri()
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/")
+}
+
+// 查找请求所在监听服务的路由中与路径匹配的路由
+pub fn find_route(req: &HttpRequest, table: &RouteTable) -> Option<Arc<Route>> {
+    match req.app_data::<web::Data<Listener>>() {
+        Some(listener) => table.find_in(req.path(), &listener.routes),
+        None => table.find(req.path()),
+    }
+}
+
+// 连接所在的监听套接字，连接建立时保存在连接数据中
+#[derive(Debug, Clone, Copy)]
+pub struct Local {
+    pub addr: SocketAddr, // 本地地址
+    pub secure: bool,     // 是否为 HTTPS
+}
+
+impl Local {
+    pub fn scheme(&self) -> &'static str {
+        match self.secure {
+            true => "https",
+            false => "http",
+        }
+    }
+}
+
+// 请求所在连接的本地地址与协议；不经过监听的请求（如测试）取自 AppConfig
+pub fn local(req: &HttpRequest) -> Local {
+    match req.conn_data::<Local>() {
+        Some(local) => *local,
+        None => Local {
+            addr: req.app_config().local_addr(),
+            secure: req.app_config().secure(),
+        },
+    }
+}
+
+// 明文或 TLS 连接
+enum Stream {
+    Plain(TcpStream),
+    Tls(Box<crate::tls::TlsStream<TcpStream>>),
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(s) => Pin::new(s).poll_read(cx, buf),
+            Stream::Tls(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Plain(s) => Pin::new(s).poll_write(cx, buf),
+            Stream::Tls(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(s) => Pin::new(s).poll_flush(cx),
+            Stream::Tls(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(s) => Pin::new(s).poll_shutdown(cx),
+            Stream::Tls(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}
+
+// 在所有监听上运行代理服务。factory 为每个工作线程、每个监听创建 App，参数为该监听，
+// 需要注册为 app_data 供 find_route 使用；工作线程与连接参数取自 [server]
+pub fn serve<F, I, S, B>(
+    listeners: Vec<Listener>,
+    tuning: &crate::ServerConfig,
+    factory: F,
+) -> std::io::Result<Server>
+where
+    F: Fn(web::Data<Listener>) -> I + Send + Clone + 'static,
+    I: IntoServiceFactory<S, Request>,
+    S: ServiceFactory<Request, Config = AppConfig> + 'static,
+    S::Error: Into<actix_web::Error> + 'static,
+    S::InitError: std::fmt::Debug,
+    S::Response: Into<Response<B>> + 'static,
+    <S::Service as Service<Request>>::Future: 'static,
+    S::Service: 'static,
+    B: MessageBody + 'static,
+{
+    let mut builder = ServerBuilder::default()
+        .max_concurrent_connections(tuning.max_connections)
+        .shutdown_timeout(tuning.shutdown_timeout_secs);
+    if let Some(workers) = tuning.workers {
+        builder = builder.workers(workers);
+    }
+    let keep_alive = match tuning.keep_alive_secs {
+        0 => KeepAlive::Disabled,
+        secs => KeepAlive::Timeout(Duration::from_secs(secs)),
+    };
+    let request_timeout = Duration::from_millis(tuning.client_request_timeout_ms);
+    for listener in listeners {
+        let listener = web::Data::new(listener);
+        let sockets = crate::upgrade::listen(
+            &listener.name,
+            &listener.host,
+            listener.port,
+            tuning.backlog,
+        )?;
+        for socket in sockets {
+            let addr = socket.local_addr()?;
+            log::info!("监听 {}: {}://{}", listener.name, listener.scheme(), addr);
+            let (factory, listener) = (factory.clone(), listener.clone());
+            builder = builder.listen(format!("{}-{}", listener.name, addr), socket, move || {
+                let app = factory(listener.clone())
+                    .into_factory()
+                    .map_err(|err| err.into().error_response());
+                let local = Local {
+                    addr,
+                    secure: listener.tls.is_some(),
+                };
+                let http = HttpService::build()
+                    .keep_alive(keep_alive)
+                    .client_request_timeout(request_timeout)
+                    .client_disconnect_timeout(Duration::from_secs(1))
+                    .local_addr(addr)
+                    .on_connect_ext(move |io: &Stream, data: &mut Extensions| {
+                        data.insert(local);
+                        crate::connections::on_connect(io, data)
+                    })
+                    // 本地地址与协议通过连接数据提供，见 local()
+                    .finish(map_config(app, |_| AppConfig::default()));
+                let tls = listener.tls.clone();
+                let proxy_protocol = listener.proxy_protocol.clone();
+                fn_service(move |mut io: TcpStream| {
+                    let tls = tls.clone();
+                    let proxy_protocol = proxy_protocol.clone();
+                    async move {
+                        let mut peer = io.peer_addr().ok();
+                        // 启用 PROXY 协议时先读取协议头，其中的源地址作为客户端地址
+                        if let Some(proxy_protocol) = proxy_protocol {
+                            match proxy_protocol.accept(&mut io, peer).await {
+                                Ok(Some(source)) => peer = Some(source),
+                                Ok(None) => {}
+                                Err(e) => return Err(proxy_protocol_error(peer, e)),
+                            }
+                        }
+                        let (stream, protocol) = match tls {
+                            None => (Stream::Plain(io), Protocol::Http1),
+                            Some((acceptor, timeout)) => {
+                                match tokio::time::timeout(timeout, acceptor.accept(io)).await {
+                                    Ok(Ok(stream)) => {
+                                        // 按 ALPN 的协商结果选择协议
+                                        let protocol = match stream.is_h2() {
+                                            true => Protocol::Http2,
+                                            false => Protocol::Http1,
+                                        };
+                                        (Stream::Tls(Box::new(stream)), protocol)
+                                    }
+                                    Ok(Err(e)) => return Err(handshake_error(peer, e)),
+                                    Err(_) => {
+                                        return Err(handshake_error(peer, "握手超时".to_string()));
+                                    }
+                                }
+                            }
+                        };
+                        Ok((stream, protocol, peer))
+                    }
+                })
+                .and_then(http)
+            })?;
+        }
+    }
+    Ok(builder.run())
+}
+
+fn proxy_protocol_error(
+    peer: Option<std::net::SocketAddr>,
+    message: String,
+) -> actix_http::error::DispatchError {
+    let peer = peer.map(|p| p.to_string()).unwrap_or("-".to_string());
+    log::debug!("拒绝来自 {} 的连接，PROXY 协议头无效: {}", peer, message);
+    crate::metrics::counter_inc("proxy_protocol_rejected_total", &[]);
+    actix_http::error::DispatchError::Io(std::io::Error::other(message))
+}
+
+fn handshake_error(
+    peer: Option<std::net::SocketAddr>,
+    message: String,
+) -> actix_http::error::DispatchError {
+    let peer = peer.map(|p| p.to_string()).unwrap_or("-".to_string());
+    log::debug!("客户端 {} TLS 握手失败: {}", peer, message);
+    crate::metrics::counter_inc("proxy_tls_handshake_failures_total", &[]);
+    actix_http::error::DispatchError::Io(std::io::Error::other(message))
+}
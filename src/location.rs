@@ -0,0 +1,135 @@
+// ==================== Location 改写 ====================
+//
+// 上游按自己的地址生成重定向时（如 Location: http://10.0.0.8:8080/login），浏览器会绕过代理直接访问内部地址。
+// 响应的 Location 与 Content-Location 是指向路由目标服务器（含故障转移目标）的绝对 URL 时，
+// 把协议与主机改写为客户端访问代理时使用的协议与 Host，路径不变（代理转发时不去掉路由前缀）。
+// 上游使用的主机名与目标地址不同、或路径需要映射时，用 rules 声明前缀替换，先于按目标地址的改写。
+// 协议与 Host 经过 [forwarded] trusted 中的代理时取 X-Forwarded-Proto/Host，与传给上游的值一致
+
+use crate::routes::Route;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+
+// Location 改写配置：对应配置文件中的 [routes.location_rewrite]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LocationRewriteConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool, // 是否改写指向目标服务器的 Location 与 Content-Location
+    #[serde(default)]
+    pub rules: Vec<RewriteRule>, // 前缀替换规则，按顺序匹配第一条
+}
+
+// 前缀替换规则
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RewriteRule {
+    pub from: String, // 上游响应中的绝对 URL 前缀，如 "http://app.internal:8080/"
+    pub to: String,   // 替换为的前缀，绝对 URL 或以 / 开头的路径，如 "/app/"
+}
+
+impl Default for LocationRewriteConfig {
+    fn default() -> Self {
+        LocationRewriteConfig {
+            enabled: default_enabled(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+// 以下函数为 Location 改写提供默认值
+fn default_enabled() -> bool {
+    true
+}
+
+// 改写的响应头
+const HEADERS: [HeaderName; 2] = [reqwest::header::LOCATION, reqwest::header::CONTENT_LOCATION];
+
+// 编译后的 Location 改写规则
+#[derive(Debug)]
+pub struct LocationRewrite {
+    enabled: bool,
+    rules: Vec<RewriteRule>,
+}
+
+impl LocationRewrite {
+    // 从配置构建，from 不是绝对 URL 或 to 既不是绝对 URL 也不是路径时启动失败
+    pub fn new(config: &LocationRewriteConfig) -> Result<Self, String> {
+        for rule in &config.rules {
+            if reqwest::Url::parse(&rule.from).is_err() {
+                return Err(format!(
+                    "Location 改写规则的 from 必须是绝对 URL: {}",
+                    rule.from
+                ));
+            }
+            if !rule.to.starts_with('/') && reqwest::Url::parse(&rule.to).is_err() {
+                return Err(format!(
+                    "Location 改写规则的 to 必须是绝对 URL 或以/开头的路径: {}",
+                    rule.to
+                ));
+            }
+        }
+        Ok(LocationRewrite {
+            enabled: config.enabled,
+            rules: config.rules.clone(),
+        })
+    }
+
+    // 上游响应中需要改写的 Location 与 Content-Location；scheme 与 host 为客户端访问代理时使用的值
+    pub fn rewrite(
+        &self,
+        headers: &HeaderMap,
+        route: &Route,
+        scheme: &str,
+        host: Option<&str>,
+    ) -> Vec<(HeaderName, HeaderValue)> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        HEADERS
+            .iter()
+            .filter_map(|name| {
+                let value = headers.get(name)?.to_str().ok()?;
+                let rewritten = self.rewrite_value(value, route, scheme, host)?;
+                log::debug!("改写 {}: {} -> {}", name, value, rewritten);
+                Some((name.clone(), HeaderValue::from_str(&rewritten).ok()?))
+            })
+            .collect()
+    }
+
+    fn rewrite_value(
+        &self,
+        value: &str,
+        route: &Route,
+        scheme: &str,
+        host: Option<&str>,
+    ) -> Option<String> {
+        if let Some(rule) = self.rules.iter().find(|r| value.starts_with(&r.from)) {
+            return Some(format!("{}{}", rule.to, &value[rule.from.len()..]));
+        }
+        // 相对地址不需要改写
+        let url = reqwest::Url::parse(value).ok()?;
+        let upstream_host = url
+            .host_str()?
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let targets = route.targets();
+        let upstream = targets
+            .iter()
+            .chain(&route.configured)
+            .chain(route.failover.as_ref())
+            .any(|t| {
+                url.scheme().eq_ignore_ascii_case(&t.protocol)
+                    && upstream_host
+                        .eq_ignore_ascii_case(t.host.trim_start_matches('[').trim_end_matches(']'))
+                    && url.port_or_known_default() == Some(t.port)
+            });
+        if !upstream {
+            return None;
+        }
+        // 保留原值中主机之后的部分（路径、查询参数与片段）
+        let after_scheme = &value[value.find("://")? + 3..];
+        let rest = after_scheme
+            .find(['/', '?', '#'])
+            .map_or("", |i| &after_scheme[i..]);
+        Some(format!("{}://{}{}", scheme, host?, rest))
+    }
+}
@@ -0,0 +1,132 @@
+// ==================== 运行时日志级别 ====================
+//
+// 日志过滤规则保存在这里而不是固定在 env_logger 中，管理接口 /log-level 可以在运行时修改全局级别
+// 和单个模块的级别（语法与 RUST_LOG 相同，如 "info,rust_proxy::routes=debug"），排查故障时无需重启；
+// 修改只在内存中生效，可以指定持续时间，到期后自动恢复为启动时的规则；
+// 配置热加载时启动时的规则随配置文件更新
+
+use env_logger::filter::{Builder, Filter};
+use log::{LevelFilter, Metadata, Record};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+
+// 当前生效的过滤规则
+struct Current {
+    spec: String,
+    filter: Filter,
+}
+
+struct State {
+    default: RwLock<String>,  // 启动时的规则，热加载时更新
+    current: RwLock<Current>, // 当前规则
+    generation: AtomicU64,    // 每次修改加一，到期恢复时用于判断规则是否已被再次修改
+}
+
+static STATE: OnceLock<State> = OnceLock::new();
+
+// 用启动时的规则初始化，规则无效时返回错误
+pub fn init(spec: &str) -> Result<(), String> {
+    let filter = parse(spec)?;
+    log::set_max_level(filter.filter());
+    let _ = STATE.set(State {
+        default: RwLock::new(spec.to_string()),
+        current: RwLock::new(Current {
+            spec: spec.to_string(),
+            filter,
+        }),
+        generation: AtomicU64::new(0),
+    });
+    Ok(())
+}
+
+// 当前规则与启动时的规则
+pub fn current() -> (String, String) {
+    match STATE.get() {
+        Some(state) => (
+            state.current.read().unwrap().spec.clone(),
+            state.default.read().unwrap().clone(),
+        ),
+        None => (String::new(), String::new()),
+    }
+}
+
+// 替换过滤规则，指定持续时间时到期后恢复为启动时的规则
+pub fn set(spec: &str, duration: Option<Duration>) -> Result<(), String> {
+    let state = STATE.get().ok_or("日志系统未初始化")?;
+    let filter = parse(spec)?;
+    log::set_max_level(filter.filter());
+    *state.current.write().unwrap() = Current {
+        spec: spec.to_string(),
+        filter,
+    };
+    let generation = state.generation.fetch_add(1, Ordering::Relaxed) + 1;
+    log::warn!("日志级别已修改为: {}", spec);
+    if let Some(duration) = duration {
+        tokio::spawn(async move {
+            tokio::time::sleep(duration).await;
+            // 期间规则被再次修改时，由最后一次修改决定
+            if state.generation.load(Ordering::Relaxed) == generation {
+                let _ = reset();
+            }
+        });
+    }
+    Ok(())
+}
+
+// 恢复为启动时的规则
+pub fn reset() -> Result<(), String> {
+    let state = STATE.get().ok_or("日志系统未初始化")?;
+    let default = state.default.read().unwrap().clone();
+    set(&default, None)
+}
+
+// 校验过滤规则
+pub fn validate(spec: &str) -> Result<(), String> {
+    parse(spec).map(|_| ())
+}
+
+// 热加载时替换启动时的规则并立即生效
+pub fn set_default(spec: &str) -> Result<(), String> {
+    let state = STATE.get().ok_or("日志系统未初始化")?;
+    parse(spec)?;
+    *state.default.write().unwrap() = spec.to_string();
+    set(spec, None)
+}
+
+pub fn enabled(metadata: &Metadata) -> bool {
+    STATE
+        .get()
+        .is_some_and(|state| state.current.read().unwrap().filter.enabled(metadata))
+}
+
+pub fn matches(record: &Record) -> bool {
+    STATE
+        .get()
+        .is_some_and(|state| state.current.read().unwrap().filter.matches(record))
+}
+
+// 解析过滤规则；env_logger 遇到无效规则只打印警告并忽略，这里先逐项校验
+fn parse(spec: &str) -> Result<Filter, String> {
+    let (directives, regex) = match spec.split_once('/') {
+        Some((directives, regex)) => (directives, Some(regex)),
+        None => (spec, None),
+    };
+    if let Some(regex) = regex {
+        regex::Regex::new(regex).map_err(|e| format!("无效的日志过滤正则: {}", e))?;
+    }
+    for directive in directives.split(',').map(str::trim) {
+        if directive.is_empty() {
+            continue;
+        }
+        let level = match directive.split_once('=') {
+            Some((module, level)) if !module.trim().is_empty() => level.trim(),
+            Some(_) => return Err(format!("无效的日志过滤规则: {}", directive)),
+            // 单独一项可以是级别（全局）或模块名（该模块输出所有级别）
+            None => continue,
+        };
+        LevelFilter::from_str(level).map_err(|_| format!("无效的日志级别: {}", level))?;
+    }
+    Ok(Builder::new().parse(spec).build())
+}
@@ -0,0 +1,419 @@
+// ==================== 远程日志输出 ====================
+//
+// 在标准错误输出之外，把应用日志（未配置文件时也包括访问日志）转发到远程：
+// syslog（RFC 5424，UDP/TCP/unix套接字）、按行发送JSON的TCP，以及批量POST JSON的HTTP，
+// 容器和设备无需额外的日志采集进程即可上报日志。日志先进入内存队列再由后台任务发送，队列满时丢弃
+
+use crate::{log_level, request_id};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+// 输出类型
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkKind {
+    Syslog, // RFC 5424 syslog
+    Tcp,    // 每行一个JSON对象
+    Http,   // 批量POST JSON数组
+}
+
+// syslog传输协议
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogProtocol {
+    #[default]
+    Udp,
+    Tcp,  // 使用RFC 6587的长度前缀分帧
+    Unix, // unix数据报套接字，如 /dev/log
+}
+
+// 日志输出配置：对应配置文件中的 [[log.sinks]]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SinkConfig {
+    pub kind: SinkKind, // 输出类型: syslog、tcp、http
+    #[serde(default)]
+    pub address: Option<String>, // syslog/tcp的地址(host:port)，unix协议时为套接字路径
+    #[serde(default)]
+    pub protocol: SyslogProtocol, // syslog传输协议: udp、tcp、unix
+    #[serde(default)]
+    pub url: Option<String>, // http输出的地址
+    #[serde(default)]
+    pub headers: HashMap<String, String>, // http输出附加的请求头
+    #[serde(default = "default_level")]
+    pub level: String, // 最低输出级别
+    #[serde(default = "default_facility")]
+    pub facility: String, // syslog设施，如 local0、daemon
+    #[serde(default = "default_app_name")]
+    pub app_name: String, // syslog中的应用名
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize, // http每批最多发送的日志数
+    #[serde(default = "default_flush_interval_ms")]
+    pub flush_interval_ms: u64, // http发送间隔(毫秒)
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize, // 等待发送的最大日志数，超出时丢弃
+}
+
+// 以下函数为日志输出配置提供默认值
+fn default_level() -> String {
+    "info".to_string()
+}
+
+fn default_facility() -> String {
+    "local0".to_string()
+}
+
+fn default_app_name() -> String {
+    "rust_proxy".to_string()
+}
+
+fn default_batch_size() -> usize {
+    100
+}
+
+fn default_flush_interval_ms() -> u64 {
+    1000
+}
+
+fn default_queue_size() -> usize {
+    10000
+}
+
+// syslog设施名称与编号
+const FACILITIES: &[(&str, u8)] = &[
+    ("kern", 0),
+    ("user", 1),
+    ("mail", 2),
+    ("daemon", 3),
+    ("auth", 4),
+    ("syslog", 5),
+    ("lpr", 6),
+    ("news", 7),
+    ("uucp", 8),
+    ("cron", 9),
+    ("authpriv", 10),
+    ("ftp", 11),
+    ("local0", 16),
+    ("local1", 17),
+    ("local2", 18),
+    ("local3", 19),
+    ("local4", 20),
+    ("local5", 21),
+    ("local6", 22),
+    ("local7", 23),
+];
+
+// HTTP客户端等自身的日志不转发，避免发送日志时产生新的日志形成循环
+const EXCLUDED_TARGETS: &[&str] = &["hyper", "reqwest", "h2", "mio", "tokio_util"];
+
+// 进入发送队列的一条日志
+#[derive(Debug, Clone)]
+struct Entry {
+    time: chrono::DateTime<chrono::Utc>,
+    level: Level,
+    target: String,
+    message: String,
+    request_id: Option<String>,
+}
+
+impl Entry {
+    // 转换为JSON对象，用于tcp与http输出
+    fn to_json(&self, app_name: &str) -> serde_json::Value {
+        serde_json::json!({
+            "timestamp": self.time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            "level": self.level.as_str(),
+            "target": self.target,
+            "message": self.message,
+            "request_id": self.request_id,
+            "app": app_name,
+            "host": hostname(),
+        })
+    }
+
+    // 格式化为RFC 5424消息：<PRI>1 时间 主机 应用 进程ID 消息ID - 内容
+    fn to_syslog(&self, facility: u8, app_name: &str) -> String {
+        let severity = match self.level {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        };
+        // 消息ID最长32个可见字符
+        let msgid: String = self
+            .target
+            .chars()
+            .filter(|c| c.is_ascii_graphic())
+            .take(32)
+            .collect();
+        let message = match &self.request_id {
+            Some(id) => format!("{} {}", id, self.message),
+            None => self.message.clone(),
+        };
+        format!(
+            "<{}>1 {} {} {} {} {} - {}",
+            facility as u16 * 8 + severity,
+            self.time
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            hostname(),
+            app_name,
+            std::process::id(),
+            if msgid.is_empty() {
+                "-".to_string()
+            } else {
+                msgid
+            },
+            message
+        )
+    }
+}
+
+// 主机名，读取失败时为 -
+pub fn hostname() -> &'static str {
+    static HOSTNAME: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+    HOSTNAME.get_or_init(|| {
+        std::env::var("HOSTNAME")
+            .ok()
+            .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+            .map(|h| h.trim().to_string())
+            .filter(|h| !h.is_empty())
+            .unwrap_or_else(|| "-".to_string())
+    })
+}
+
+// 一个已启动的输出
+struct Sink {
+    level: LevelFilter,
+    sender: mpsc::Sender<Entry>,
+}
+
+// 组合日志器：按当前过滤规则交给 env_logger 输出，再转发到各个远程输出
+pub struct Logger {
+    inner: env_logger::Logger,
+    sinks: Vec<Sink>,
+}
+
+impl Logger {
+    // 创建日志器并启动各个输出的后台任务，配置无效时返回错误
+    pub fn new(inner: env_logger::Logger, configs: &[SinkConfig]) -> Result<Self, String> {
+        let mut sinks = Vec::with_capacity(configs.len());
+        for config in configs {
+            let level = LevelFilter::from_str(&config.level)
+                .map_err(|_| format!("无效的日志输出级别: {}", config.level))?;
+            let facility = FACILITIES
+                .iter()
+                .find(|(name, _)| *name == config.facility)
+                .map(|(_, code)| *code)
+                .ok_or_else(|| format!("无效的syslog设施: {}", config.facility))?;
+            let (sender, receiver) = mpsc::channel(config.queue_size.max(1));
+            match config.kind {
+                SinkKind::Syslog | SinkKind::Tcp => {
+                    let address = config
+                        .address
+                        .clone()
+                        .ok_or_else(|| format!("{:?}日志输出缺少address", config.kind))?;
+                    tokio::spawn(run_stream(config.clone(), address, facility, receiver));
+                }
+                SinkKind::Http => {
+                    let url = config
+                        .url
+                        .clone()
+                        .ok_or_else(|| "http日志输出缺少url".to_string())?;
+                    let mut headers = reqwest::header::HeaderMap::new();
+                    for (name, value) in &config.headers {
+                        let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                            .map_err(|_| format!("无效的日志输出请求头: {}", name))?;
+                        let value = reqwest::header::HeaderValue::from_str(value)
+                            .map_err(|_| format!("日志输出请求头 {} 的值无效", name))?;
+                        headers.insert(name, value);
+                    }
+                    let client = reqwest::Client::builder()
+                        .timeout(Duration::from_secs(10))
+                        .default_headers(headers)
+                        .build()
+                        .map_err(|e| format!("日志输出客户端构建失败: {}", e))?;
+                    tokio::spawn(run_http(config.clone(), url, client, receiver));
+                }
+            }
+            sinks.push(Sink { level, sender });
+        }
+        Ok(Logger { inner, sinks })
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        log_level::enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        // 过滤规则可在运行时修改，env_logger 本身不再过滤；输出级别只能在此基础上进一步收窄
+        if !log_level::matches(record) {
+            return;
+        }
+        self.inner.log(record);
+        let target = record.target();
+        if EXCLUDED_TARGETS.iter().any(|t| target.starts_with(t)) {
+            return;
+        }
+        let mut entry = None;
+        for sink in &self.sinks {
+            if record.level() > sink.level {
+                continue;
+            }
+            let entry = entry.get_or_insert_with(|| Entry {
+                time: chrono::Utc::now(),
+                level: record.level(),
+                target: target.to_string(),
+                message: record.args().to_string(),
+                request_id: request_id::current(),
+            });
+            // 队列已满时丢弃，不阻塞请求处理
+            let _ = sink.sender.try_send(entry.clone());
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+// syslog与tcp输出：维持一个连接（或数据报套接字），发送失败时丢弃该条日志并在下一条时重连
+async fn run_stream(
+    config: SinkConfig,
+    address: String,
+    facility: u8,
+    mut receiver: mpsc::Receiver<Entry>,
+) {
+    let mut connection: Option<Connection> = None;
+    while let Some(entry) = receiver.recv().await {
+        let payload = match config.kind {
+            SinkKind::Syslog => {
+                let message = entry.to_syslog(facility, &config.app_name);
+                if config.protocol == SyslogProtocol::Tcp {
+                    format!("{} {}", message.len(), message) // RFC 6587 长度前缀分帧
+                } else {
+                    message
+                }
+            }
+            _ => format!("{}\n", entry.to_json(&config.app_name)),
+        };
+        if connection.is_none() {
+            match Connection::open(&config, &address).await {
+                Ok(c) => connection = Some(c),
+                Err(e) => {
+                    // 不能用log宏，否则会再次进入本输出
+                    eprintln!("连接日志输出 {} 失败: {}", address, e);
+                    continue;
+                }
+            }
+        }
+        if let Some(c) = &mut connection
+            && let Err(e) = c.send(payload.as_bytes()).await
+        {
+            eprintln!("发送日志到 {} 失败: {}", address, e);
+            connection = None;
+        }
+    }
+}
+
+// 流式输出使用的连接
+enum Connection {
+    Udp(tokio::net::UdpSocket),
+    Tcp(tokio::net::TcpStream),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixDatagram),
+}
+
+impl Connection {
+    async fn open(config: &SinkConfig, address: &str) -> std::io::Result<Self> {
+        let protocol = match config.kind {
+            SinkKind::Syslog => config.protocol,
+            _ => SyslogProtocol::Tcp,
+        };
+        match protocol {
+            SyslogProtocol::Udp => {
+                // 本地地址与目标的地址族一致，IPv6 目标（如 [::1]:514）需要绑定 [::]
+                let target = tokio::net::lookup_host(address)
+                    .await?
+                    .next()
+                    .ok_or_else(|| std::io::Error::other(format!("无法解析地址 {}", address)))?;
+                let local = match target {
+                    std::net::SocketAddr::V4(_) => "0.0.0.0:0",
+                    std::net::SocketAddr::V6(_) => "[::]:0",
+                };
+                let socket = tokio::net::UdpSocket::bind(local).await?;
+                socket.connect(target).await?;
+                Ok(Connection::Udp(socket))
+            }
+            SyslogProtocol::Tcp => Ok(Connection::Tcp(
+                tokio::net::TcpStream::connect(address).await?,
+            )),
+            #[cfg(unix)]
+            SyslogProtocol::Unix => {
+                let socket = tokio::net::UnixDatagram::unbound()?;
+                socket.connect(address)?;
+                Ok(Connection::Unix(socket))
+            }
+            #[cfg(not(unix))]
+            SyslogProtocol::Unix => Err(std::io::Error::other("当前平台不支持unix套接字")),
+        }
+    }
+
+    async fn send(&mut self, payload: &[u8]) -> std::io::Result<()> {
+        match self {
+            Connection::Udp(socket) => socket.send(payload).await.map(|_| ()),
+            Connection::Tcp(stream) => stream.write_all(payload).await,
+            #[cfg(unix)]
+            Connection::Unix(socket) => socket.send(payload).await.map(|_| ()),
+        }
+    }
+}
+
+// http输出：攒够一批或到达发送间隔时POST一个JSON数组
+async fn run_http(
+    config: SinkConfig,
+    url: String,
+    client: reqwest::Client,
+    mut receiver: mpsc::Receiver<Entry>,
+) {
+    let mut batch = Vec::new();
+    let mut ticker = tokio::time::interval(Duration::from_millis(config.flush_interval_ms.max(1)));
+    loop {
+        tokio::select! {
+            entry = receiver.recv() => match entry {
+                Some(entry) => {
+                    batch.push(entry.to_json(&config.app_name));
+                    if batch.len() >= config.batch_size {
+                        post(&client, &url, &mut batch).await;
+                    }
+                }
+                None => {
+                    post(&client, &url, &mut batch).await;
+                    return;
+                }
+            },
+            _ = ticker.tick() => post(&client, &url, &mut batch).await,
+        }
+    }
+}
+
+async fn post(client: &reqwest::Client, url: &str, batch: &mut Vec<serde_json::Value>) {
+    if batch.is_empty() {
+        return;
+    }
+    let entries = std::mem::take(batch);
+    match client.post(url).json(&entries).send().await {
+        Ok(resp) if resp.status().is_success() => {}
+        Ok(resp) => eprintln!(
+            "发送 {} 条日志到 {} 失败: 返回 {}",
+            entries.len(),
+            url,
+            resp.status()
+        ),
+        Err(e) => eprintln!("发送 {} 条日志到 {} 失败: {}", entries.len(), url, e),
+    }
+}
@@ -0,0 +1,66 @@
+// ==================== 维护模式 ====================
+//
+// 通过管理接口开启后，代理请求不再转发到上游，直接返回 503 与维护提示；可以只对部分路由生效，
+// 可以附带 Retry-After。状态只保存在内存中，重启后恢复为关闭
+
+use crate::metrics;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+
+// 维护模式设置，即管理接口 PUT /maintenance 的请求体
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceMode {
+    #[serde(default = "default_message")]
+    pub message: String, // 返回给客户端的提示
+    #[serde(default)]
+    pub retry_after_secs: Option<u64>, // Retry-After 响应头(秒)，不设置则不返回
+    #[serde(default)]
+    pub routes: Vec<String>, // 只对这些路由生效，缺省为所有路由
+    #[serde(default)]
+    pub since: Option<String>, // 开启时间(RFC 3339)，由代理填写
+}
+
+// 以下函数为维护模式提供默认值
+fn default_message() -> String {
+    "服务维护中，请稍后再试".to_string()
+}
+
+// 当前的维护模式，关闭时为 None
+#[derive(Debug, Default)]
+pub struct Maintenance(RwLock<Option<MaintenanceMode>>);
+
+impl Maintenance {
+    // 当前设置
+    pub fn get(&self) -> Option<MaintenanceMode> {
+        self.0.read().unwrap().clone()
+    }
+
+    // 开启维护模式，重复开启时替换原来的设置
+    pub fn enable(&self, mut mode: MaintenanceMode) -> MaintenanceMode {
+        mode.since = Some(chrono::Utc::now().to_rfc3339());
+        *self.0.write().unwrap() = Some(mode.clone());
+        mode
+    }
+
+    // 关闭维护模式，返回之前是否已开启
+    pub fn disable(&self) -> bool {
+        self.0.write().unwrap().take().is_some()
+    }
+
+    // 路由处于维护模式时返回 503 响应
+    pub fn response(&self, route: &str) -> Option<HttpResponse> {
+        let guard = self.0.read().unwrap();
+        let mode = guard.as_ref()?;
+        if !mode.routes.is_empty() && !mode.routes.iter().any(|r| r == route) {
+            return None;
+        }
+        metrics::counter_inc("proxy_maintenance_responses_total", &[("route", route)]);
+        let mut resp = HttpResponse::ServiceUnavailable();
+        resp.content_type("text/plain; charset=utf-8");
+        if let Some(secs) = mode.retry_after_secs {
+            resp.insert_header(("Retry-After", secs.to_string()));
+        }
+        Some(resp.body(mode.message.clone()))
+    }
+}
@@ -0,0 +1,55 @@
+// ==================== 上游请求的内部标记 ====================
+//
+// 发送 PROXY 协议头的请求以及 FastCGI、uwsgi 请求不由 HTTP 客户端发送，构建请求时确定的连接信息
+// （客户端地址、CGI 参数、Unix 套接字等）要经过重试、对冲与超时控制带到发送处。HTTP 客户端的请求
+// 没有扩展字段，这些信息保存在进程内的表中，请求上只带一个随机生成的键；客户端猜不到有效的键，
+// 构建上游请求时还会去掉客户端发送的所有 x-rust-proxy-* 请求头。表中的条目在客户端请求结束时删除
+
+use actix_web::{HttpMessage, HttpRequest};
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+
+// 内部请求头的前缀，客户端发送的同名请求头不会转发
+pub const PREFIX: &str = "x-rust-proxy-";
+
+type Entry = Arc<dyn Any + Send + Sync>;
+
+// 标记的键与内容
+static ENTRIES: LazyLock<Mutex<HashMap<String, Entry>>> = LazyLock::new(Default::default);
+
+// 客户端请求创建的标记，保存在请求扩展中，随请求一起释放时删除表中的条目
+#[derive(Default)]
+struct Owned(Vec<String>);
+
+impl Drop for Owned {
+    fn drop(&mut self) {
+        let mut entries = ENTRIES.lock().unwrap();
+        for key in &self.0 {
+            entries.remove(key);
+        }
+    }
+}
+
+// 保存标记的内容，并在发往上游的请求上写入它的键
+pub fn insert<T: Any + Send + Sync>(
+    req: &HttpRequest,
+    builder: reqwest::RequestBuilder,
+    header: &'static str,
+    value: T,
+) -> reqwest::RequestBuilder {
+    let key = uuid::Uuid::new_v4().simple().to_string();
+    ENTRIES.lock().unwrap().insert(key.clone(), Arc::new(value));
+    req.extensions_mut()
+        .get_or_insert_with(Owned::default)
+        .0
+        .push(key.clone());
+    builder.header(header, key)
+}
+
+// 去掉请求上的标记并取出内容；键无效或内容类型不符时返回 None，请求照常发送
+pub fn take<T: Any + Send + Sync>(request: &mut reqwest::Request, header: &str) -> Option<Arc<T>> {
+    let key = request.headers_mut().remove(header)?; // 同时去掉所有同名的值
+    let entry = ENTRIES.lock().unwrap().get(key.to_str().ok()?)?.clone();
+    entry.downcast().ok()
+}
@@ -0,0 +1,125 @@
+// ==================== 方法覆盖 ====================
+//
+// 有的客户端或防火墙只允许 GET 与 POST，惯例是用 POST 加 X-HTTP-Method-Override: DELETE 传递真正的方法。
+// 路由配置 [routes.method_override] 后，POST 请求带有该请求头时改用其中的方法处理与转发，
+// 之后的缓存、幂等键、重试、OpenAPI 校验与访问日志看到的都是覆盖后的方法；只接受 methods 中的方法，
+// 其他值返回 400，避免借此把请求变成 CONNECT、TRACE 等。该请求头不转发给上游，上游自身的方法覆盖不会生效。
+// 改写请求方法需要独占请求，中间件位于请求ID之内、其他中间件之外，拒绝在路由处理时进行，
+// 与其他被拒绝的请求一样写入访问日志与审计日志
+
+use crate::{AppState, ProxyError, metrics};
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::http::header::HeaderName;
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, HttpRequest, web};
+use serde::{Deserialize, Serialize};
+
+// 方法覆盖配置：对应配置文件中的 [routes.method_override]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MethodOverrideConfig {
+    #[serde(default = "default_header")]
+    pub header: String, // 携带真正方法的请求头
+    #[serde(default = "default_methods")]
+    pub methods: Vec<String>, // 允许覆盖为的方法
+}
+
+// 以下函数为方法覆盖提供默认值
+fn default_header() -> String {
+    "X-HTTP-Method-Override".to_string()
+}
+
+fn default_methods() -> Vec<String> {
+    vec!["PUT".to_string(), "PATCH".to_string(), "DELETE".to_string()]
+}
+
+// 方法覆盖策略
+#[derive(Debug)]
+pub struct MethodOverride {
+    header: HeaderName,
+    methods: Vec<Method>,
+}
+
+impl MethodOverride {
+    // 从配置构建，请求头名称或方法无效时启动失败；CONNECT 与 TRACE 不能出现在 methods 中
+    pub fn new(config: &MethodOverrideConfig) -> Result<Self, String> {
+        let header = HeaderName::from_bytes(config.header.trim().as_bytes())
+            .map_err(|_| format!("无效的请求头名称: {}", config.header))?;
+        let methods = config
+            .methods
+            .iter()
+            .map(|m| {
+                let method = Method::from_bytes(m.trim().to_ascii_uppercase().as_bytes())
+                    .map_err(|_| format!("无效的请求方法: {}", m))?;
+                match method {
+                    Method::CONNECT | Method::TRACE => Err(format!("方法覆盖不能使用 {}", method)),
+                    method => Ok(method),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(MethodOverride { header, methods })
+    }
+
+    // 解析请求头中的方法；为空或与 POST 相同时返回 None，不允许的方法原样作为错误返回
+    fn resolve(&self, value: &str) -> Result<Option<Method>, String> {
+        let value = value.trim().to_ascii_uppercase();
+        if value.is_empty() || value == "POST" {
+            return Ok(None);
+        }
+        match self.methods.iter().find(|m| m.as_str() == value) {
+            Some(method) => Ok(Some(method.clone())),
+            None => Err(value),
+        }
+    }
+}
+
+// 被拒绝的方法覆盖，保存在请求扩展中
+#[derive(Debug, Clone)]
+struct Rejected(String);
+
+// 请求的方法覆盖被拒绝时返回错误
+pub fn rejected(req: &HttpRequest) -> Option<ProxyError> {
+    req.extensions()
+        .get::<Rejected>()
+        .map(|r| ProxyError::MethodOverrideNotAllowed(r.0.clone()))
+}
+
+// 方法覆盖中间件：在路由处理之前改写请求方法并去掉覆盖请求头
+pub async fn middleware(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let Some(state) = req.app_data::<web::Data<AppState>>().cloned() else {
+        return next.call(req).await;
+    };
+    let Some(route) = crate::listeners::find_route(req.request(), &state.routes.load()) else {
+        return next.call(req).await;
+    };
+    let Some(policy) = &route.method_override else {
+        return next.call(req).await;
+    };
+    let value = req.headers_mut().remove(&policy.header).next();
+    if req.method() != Method::POST {
+        return next.call(req).await;
+    }
+    let Some(value) = value else {
+        return next.call(req).await;
+    };
+    let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+    match policy.resolve(&value) {
+        Ok(Some(method)) => {
+            log::debug!("路由 {} 的请求方法覆盖为 {}", route.name, method);
+            metrics::counter_inc(
+                "proxy_method_overrides_total",
+                &[("route", &route.name), ("method", method.as_str())],
+            );
+            req.head_mut().method = method;
+        }
+        Ok(None) => {}
+        Err(method) => {
+            req.extensions_mut().insert(Rejected(method));
+        }
+    }
+    next.call(req).await
+}
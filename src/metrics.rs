@@ -0,0 +1,422 @@
+// ==================== 指标 ====================
+//
+// 进程内的指标注册表，支持计数器、仪表和直方图，并以Prometheus文本格式导出；
+// 所有指标的说明集中登记在 DESCRIPTIONS 中。每个指标的标签组合数有上限，
+// 超出后新的组合统一归入标签值为 "other" 的序列，防止标签基数失控占满内存；
+// 配置了 StatsD 时每次更新同时推送
+
+use crate::statsd::{self, Sample};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+// 指标名称与说明
+const DESCRIPTIONS: &[(&str, &str)] = &[
+    ("proxy_inflight_requests", "正在处理的代理请求数"),
+    ("proxy_queue_depth", "等待并发许可的排队请求数"),
+    ("proxy_queue_wait_seconds", "请求在队列中等待的时间"),
+    ("proxy_queue_rejected_total", "因并发限制被拒绝的请求数"),
+    ("proxy_shed_total", "因降载被拒绝的低优先级请求数"),
+    ("proxy_panics_total", "处理请求时发生panic的次数"),
+    ("proxy_requests_total", "按路由和状态码统计的请求数"),
+    ("proxy_request_duration_seconds", "按路由统计的请求处理时间"),
+    (
+        "proxy_upstream_requests_total",
+        "按路由、上游和结果统计的上游请求数",
+    ),
+    (
+        "proxy_upstream_duration_seconds",
+        "按路由和上游统计的上游响应头延迟",
+    ),
+    ("proxy_connections_accepted_total", "代理监听接受的连接数"),
+    (
+        "proxy_tls_handshake_failures_total",
+        "入站 TLS 握手失败的连接数",
+    ),
+    (
+        "proxy_protocol_rejected_total",
+        "PROXY 协议头缺失、无效或来自不可信地址而关闭的连接数",
+    ),
+    (
+        "proxy_protocol_sent_total",
+        "向上游发送 PROXY 协议头的连接数",
+    ),
+    (
+        "proxy_forward_requests_total",
+        "按方式(http/tunnel/connect/mitm)统计的正向代理请求与 CONNECT 隧道数",
+    ),
+    ("proxy_connections_active", "代理监听当前的客户端连接数"),
+    (
+        "proxy_upstream_inflight_requests",
+        "按路由统计的正在进行的上游请求数",
+    ),
+    ("proxy_dns_resolution_seconds", "上游域名的DNS解析耗时"),
+    ("proxy_dns_failures_total", "上游域名DNS解析失败次数"),
+    (
+        "proxy_cache_requests_total",
+        "按路由和结果(hit/miss/stale/revalidated)统计的缓存请求数",
+    ),
+    (
+        "proxy_cache_hit_ratio",
+        "按路由统计的缓存命中率（启动以来）",
+    ),
+    ("proxy_cache_entries", "按路由和存储层统计的缓存条目数"),
+    (
+        "proxy_cache_size_bytes",
+        "按路由和存储层统计的缓存占用字节数",
+    ),
+    (
+        "proxy_cache_evictions_total",
+        "按路由和存储层统计的缓存淘汰数",
+    ),
+    (
+        "proxy_maintenance_responses_total",
+        "维护模式下按路由统计的503响应数",
+    ),
+    ("proxy_invalid_body_total", "按路由统计的请求体校验失败数"),
+    (
+        "proxy_openapi_requests_total",
+        "按路由、OpenAPI 操作与校验结果(valid/invalid/unknown)统计的请求数",
+    ),
+    (
+        "proxy_openapi_invalid_responses_total",
+        "按路由与 OpenAPI 操作统计的不符合描述的上游响应数",
+    ),
+    (
+        "proxy_compose_requests_total",
+        "按路由、子请求与结果(ok/skipped/failed)统计的响应组合子请求数",
+    ),
+    ("proxy_http10_requests_total", "HTTP/1.0 客户端的请求数"),
+    (
+        "proxy_fanout_requests_total",
+        "按路由与汇总结果(ok/partial/failed)统计的广播请求数",
+    ),
+    (
+        "proxy_graphql_requests_total",
+        "按路由、GraphQL 操作与检查结果(allowed/rejected/invalid)统计的请求数",
+    ),
+    (
+        "proxy_graphql_responses_total",
+        "按路由、GraphQL 操作与状态码统计的响应数",
+    ),
+    (
+        "proxy_graphql_duration_seconds",
+        "按路由与 GraphQL 操作统计的请求处理时间",
+    ),
+    (
+        "proxy_decompressed_requests_total",
+        "按路由与编码统计的转发前解压的请求数",
+    ),
+    (
+        "proxy_privacy_stripped_total",
+        "按类型(header/cookie)统计的隐私过滤去掉的请求头与 Cookie 数",
+    ),
+    (
+        "proxy_esi_fragments_total",
+        "按路由与结果(hit/miss/error)统计的 ESI 片段数",
+    ),
+    (
+        "proxy_method_overrides_total",
+        "按路由与覆盖后的方法统计的方法覆盖请求数",
+    ),
+    (
+        "proxy_blocked_content_types_total",
+        "按路由与方向(request/response)统计的被内容类型规则拒绝的请求与响应数",
+    ),
+    (
+        "proxy_compressed_responses_total",
+        "按编码统计的代理压缩的响应数",
+    ),
+    (
+        "proxy_compression_saved_bytes_total",
+        "按编码统计的压缩节省的字节数",
+    ),
+    (
+        "proxy_feature_enabled",
+        "功能开关当前是否生效(1/0)，含管理接口的覆盖",
+    ),
+];
+
+// 标签值超出上限后使用的占位值
+const OVERFLOW_VALUE: &str = "other";
+
+// 指标配置：对应配置文件中的 [metrics]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MetricsConfig {
+    #[serde(default = "default_max_series")]
+    pub max_series_per_metric: usize, // 每个指标最多的标签组合数
+    #[serde(default = "default_true")]
+    pub upstream_labels: bool, // 是否按上游实例打标签，关闭后上游标签统一为 "all"
+    #[serde(default)]
+    pub statsd: statsd::StatsdConfig, // StatsD 推送（可选）
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            max_series_per_metric: default_max_series(),
+            upstream_labels: true,
+            statsd: statsd::StatsdConfig::default(),
+        }
+    }
+}
+
+// 以下函数为指标配置提供默认值
+fn default_max_series() -> usize {
+    1000
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// 运行时的指标设置，启动时由 configure 写入
+static MAX_SERIES: AtomicUsize = AtomicUsize::new(1000);
+static UPSTREAM_LABELS: AtomicBool = AtomicBool::new(true);
+
+// 应用指标配置
+pub fn configure(config: &MetricsConfig) {
+    MAX_SERIES.store(config.max_series_per_metric.max(1), Ordering::Relaxed);
+    UPSTREAM_LABELS.store(config.upstream_labels, Ordering::Relaxed);
+    statsd::start(&config.statsd);
+}
+
+// 上游标签值：按配置返回上游地址或统一的 "all"
+pub fn upstream_label(upstream: &str) -> &str {
+    if UPSTREAM_LABELS.load(Ordering::Relaxed) {
+        upstream
+    } else {
+        "all"
+    }
+}
+
+// 直方图默认分桶(秒)
+const BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+pub type Labels = Vec<(String, String)>;
+
+// 直方图数据
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    counts: Vec<u64>, // 每个分桶的累计计数
+    sum: f64,         // 观测值总和
+    count: u64,       // 观测次数
+}
+
+// 单个指标序列的值
+#[derive(Debug, Clone)]
+enum Series {
+    Counter(u64),
+    Gauge(f64),
+    Histogram(Histogram),
+}
+
+// 指标注册表：名称 -> 标签 -> 值
+#[derive(Default)]
+pub struct Registry {
+    families: Mutex<BTreeMap<String, BTreeMap<Labels, Series>>>,
+    overflowed: Mutex<BTreeSet<String>>, // 已达到标签组合上限的指标，只告警一次
+}
+
+// 全局指标注册表
+static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::default);
+
+fn to_labels(labels: &[(&str, &str)]) -> Labels {
+    labels
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+// 在注册表中找到（或创建）指标序列并修改，f 返回这次更新推送给 StatsD 的值
+fn update(
+    name: &str,
+    labels: &[(&str, &str)],
+    init: Series,
+    f: impl FnOnce(&mut Series) -> Sample,
+) {
+    let mut families = REGISTRY.families.lock().unwrap();
+    let family = families.entry(name.to_string()).or_default();
+    let mut labels = to_labels(labels);
+    // 新的标签组合超出上限时，归入所有标签值为 "other" 的序列
+    if !family.contains_key(&labels) && family.len() >= MAX_SERIES.load(Ordering::Relaxed) {
+        if REGISTRY.overflowed.lock().unwrap().insert(name.to_string()) {
+            log::warn!(
+                "指标 {} 的标签组合数已达上限 {}，新的组合将归入 \"{}\"",
+                name,
+                family.len(),
+                OVERFLOW_VALUE
+            );
+        }
+        for (_, value) in labels.iter_mut() {
+            *value = OVERFLOW_VALUE.to_string();
+        }
+    }
+    if !family.contains_key(&labels) {
+        family.insert(labels.clone(), init);
+    }
+    let Some(series) = family.get_mut(&labels) else {
+        return;
+    };
+    let sample = f(series);
+    drop(families);
+    statsd::emit(name, &labels, sample);
+}
+
+// 计数器增加指定值
+pub fn counter_add(name: &str, labels: &[(&str, &str)], value: u64) {
+    update(name, labels, Series::Counter(0), |series| {
+        if let Series::Counter(v) = series {
+            *v += value;
+        }
+        Sample::Count(value)
+    });
+}
+
+// 计数器加1
+pub fn counter_inc(name: &str, labels: &[(&str, &str)]) {
+    counter_add(name, labels, 1);
+}
+
+// 设置仪表的值
+pub fn gauge_set(name: &str, labels: &[(&str, &str)], value: f64) {
+    update(name, labels, Series::Gauge(0.0), |series| {
+        if let Series::Gauge(v) = series {
+            *v = value;
+        }
+        Sample::Gauge(value)
+    });
+}
+
+// 仪表增加指定值（可为负数）
+pub fn gauge_add(name: &str, labels: &[(&str, &str)], delta: f64) {
+    update(name, labels, Series::Gauge(0.0), |series| match series {
+        Series::Gauge(v) => {
+            *v += delta;
+            Sample::Gauge(*v)
+        }
+        _ => Sample::Gauge(delta),
+    });
+}
+
+// 记录一次直方图观测(秒)
+pub fn histogram_observe(name: &str, labels: &[(&str, &str)], value: f64) {
+    let init = Series::Histogram(Histogram {
+        counts: vec![0; BUCKETS.len()],
+        ..Histogram::default()
+    });
+    update(name, labels, init, |series| {
+        if let Series::Histogram(h) = series {
+            for (count, bound) in h.counts.iter_mut().zip(BUCKETS) {
+                if value <= *bound {
+                    *count += 1;
+                }
+            }
+            h.sum += value;
+            h.count += 1;
+        }
+        Sample::Timing(value)
+    });
+}
+
+// 读取计数器各个序列的当前值
+pub fn counter_values(name: &str) -> Vec<(Labels, u64)> {
+    let families = REGISTRY.families.lock().unwrap();
+    let Some(family) = families.get(name) else {
+        return Vec::new();
+    };
+    family
+        .iter()
+        .filter_map(|(labels, series)| match series {
+            Series::Counter(v) => Some((labels.clone(), *v)),
+            _ => None,
+        })
+        .collect()
+}
+
+// 将标签格式化为 {k="v",...}，extra用于直方图的le标签
+fn format_labels(labels: &Labels, extra: Option<(&str, &str)>) -> String {
+    let mut parts: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+        .collect();
+    if let Some((k, v)) = extra {
+        parts.push(format!("{}=\"{}\"", k, v));
+    }
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", parts.join(","))
+    }
+}
+
+// 转义标签值中的特殊字符
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// 以Prometheus文本格式导出所有指标
+pub fn render_prometheus() -> String {
+    let families = REGISTRY.families.lock().unwrap();
+    let mut out = String::new();
+    for (name, series) in families.iter() {
+        let help = DESCRIPTIONS
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, h)| *h)
+            .unwrap_or("");
+        let kind = match series.values().next() {
+            Some(Series::Counter(_)) => "counter",
+            Some(Series::Gauge(_)) => "gauge",
+            Some(Series::Histogram(_)) => "histogram",
+            None => continue,
+        };
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for (labels, value) in series {
+            match value {
+                Series::Counter(v) => {
+                    let _ = writeln!(out, "{}{} {}", name, format_labels(labels, None), v);
+                }
+                Series::Gauge(v) => {
+                    let _ = writeln!(out, "{}{} {}", name, format_labels(labels, None), v);
+                }
+                Series::Histogram(h) => {
+                    for (count, bound) in h.counts.iter().zip(BUCKETS) {
+                        let le = bound.to_string();
+                        let _ = writeln!(
+                            out,
+                            "{}_bucket{} {}",
+                            name,
+                            format_labels(labels, Some(("le", &le))),
+                            count
+                        );
+                    }
+                    let _ = writeln!(
+                        out,
+                        "{}_bucket{} {}",
+                        name,
+                        format_labels(labels, Some(("le", "+Inf"))),
+                        h.count
+                    );
+                    let _ = writeln!(out, "{}_sum{} {}", name, format_labels(labels, None), h.sum);
+                    let _ = writeln!(
+                        out,
+                        "{}_count{} {}",
+                        name,
+                        format_labels(labels, None),
+                        h.count
+                    );
+                }
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,67 @@
+// ==================== 配置版本与迁移 ====================
+//
+// 配置顶层的 version 标明配置的布局版本，没有写时为 1（引入版本号之前的配置）。读取时把旧版本的布局
+// 依次迁移到当前版本，已有的配置不需要修改也能继续使用；每次迁移都留下一条过时提示，启动、热加载时写入日志，
+// check 与 print-config 子命令输出到标准错误。版本高于当前版本的配置来自更新的程序，直接拒绝。
+// 迁移在合并 include、配置环境与远程配置之后、应用环境变量与命令行参数之前进行
+
+use config::{Map, Value, ValueKind};
+
+// 当前的配置版本
+pub const CURRENT: u32 = 2;
+
+// 把配置迁移到当前版本，返回过时提示；迁移后 version 为当前版本
+pub fn migrate(table: &mut Map<String, Value>) -> Result<Vec<String>, String> {
+    let version = match table.get("version") {
+        None => 1,
+        Some(value) => match value.clone().into_int() {
+            Ok(v) if (1..=CURRENT as i64).contains(&v) => v as u32,
+            Ok(v) if v > CURRENT as i64 => {
+                return Err(format!(
+                    "配置版本 {} 高于本程序支持的版本 {}，请升级 rust_proxy",
+                    v, CURRENT
+                ));
+            }
+            _ => return Err(format!("无效的配置版本: {}", value)),
+        },
+    };
+    let mut notes = Vec::new();
+    if version < 2 {
+        v1_to_v2(table, &mut notes);
+    }
+    table.insert("version".to_string(), Value::from(CURRENT));
+    Ok(notes)
+}
+
+// 版本 1 → 2：没有 [[routes]] 时，由 [proxy] 的 path_prefix、retry 和全局 [target] 组成唯一的路由。
+// 迁移为名为 default 的路由，路由不写 target，仍使用全局 [target]（--target 参数照常覆盖）；
+// 配置中既没有 [proxy] 也没有 [target] 时（只用命令行参数运行）不算旧的写法，不做迁移
+fn v1_to_v2(table: &mut Map<String, Value>, notes: &mut Vec<String>) {
+    let has_routes = table
+        .get("routes")
+        .is_some_and(|routes| !matches!(&routes.kind, ValueKind::Array(a) if a.is_empty()));
+    if has_routes || !(table.contains_key("proxy") || table.contains_key("target")) {
+        return;
+    }
+    let mut proxy = table
+        .remove("proxy")
+        .and_then(|p| p.into_table().ok())
+        .unwrap_or_default();
+    let mut route = Map::new();
+    route.insert("name".to_string(), Value::from("default"));
+    let prefix = proxy.remove("path_prefix").unwrap_or(Value::from("/"));
+    notes.push(format!(
+        "配置版本 1 的单一目标写法（[proxy] path_prefix 与 [target]）已过时，已迁移为名为 default、\
+         路径前缀为 {} 的 [[routes]]；请改用 [[routes]] 并在配置顶层写上 version = {}",
+        prefix, CURRENT
+    ));
+    route.insert("path_prefix".to_string(), prefix);
+    if let Some(retry) = proxy.remove("retry") {
+        notes.push("[proxy.retry] 已过时，已迁移为路由 default 的 retry".to_string());
+        route.insert("retry".to_string(), retry);
+    }
+    if !proxy.is_empty() {
+        table.insert("proxy".to_string(), Value::from(proxy));
+    }
+    table.insert("routes".to_string(), Value::from(vec![Value::from(route)]));
+}
@@ -0,0 +1,665 @@
+// ==================== OpenAPI 校验 ====================
+//
+// 路由的 [routes.openapi] 指定一份 OpenAPI 3.x 描述（JSON 或 YAML），请求按描述中的路径模板与方法
+// 匹配到操作：描述中没有的路径返回 404，路径存在但方法不存在返回 405 并给出 Allow；匹配到的请求
+// 校验路径、查询与请求头参数以及 JSON 请求体，不符合的请求返回 400 并列出每个问题。
+// responses 为 log 或 reject 时同样校验上游的响应：状态码是否在描述中、JSON 响应体是否符合 Schema，
+// 不符合时记录日志或返回 502。每个操作的请求数与校验结果按 operationId 记录在指标中。
+// 3.0 的 nullable 在编译前转换为 JSON Schema 的 null 类型，组件中的 $ref 在整份描述内解析
+
+use crate::ProxyError;
+use actix_web::http::Method;
+use actix_web::{HttpMessage, HttpRequest};
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// OpenAPI 校验配置：对应配置文件中的 [routes.openapi]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OpenApiConfig {
+    pub file: String, // OpenAPI 描述文件，扩展名为 .yaml/.yml 时按 YAML 解析，否则按 JSON
+    #[serde(default)]
+    pub base_path: String, // 描述中的路径相对于该前缀，如 "/api/v1"
+    #[serde(default = "default_reject_unknown")]
+    pub reject_unknown: bool, // 拒绝描述中没有的路径与方法，为 false 时原样转发
+    #[serde(default)]
+    pub responses: ResponseCheck, // 上游响应的校验方式
+    #[serde(default = "default_max_errors")]
+    pub max_errors: usize, // 错误响应中最多列出的问题数
+}
+
+// 上游响应的校验方式
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseCheck {
+    #[default]
+    Off, // 不校验
+    Log,    // 不符合时记录警告日志与指标，响应照常返回
+    Reject, // 不符合时返回 502
+}
+
+// 以下函数为 OpenAPI 校验提供默认值
+fn default_reject_unknown() -> bool {
+    true
+}
+
+fn default_max_errors() -> usize {
+    10
+}
+
+// 匹配到的操作在路由中的序号，存入请求的扩展数据，响应校验时使用
+#[derive(Debug, Clone, Copy)]
+pub struct Matched(pub usize);
+
+// 路径模板中的一段
+#[derive(Debug)]
+enum Segment {
+    Literal(String), // 固定的路径段
+    Param(String),   // {name} 形式的路径参数
+}
+
+// 参数的位置
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Location {
+    Path,
+    Query,
+    Header,
+}
+
+impl Location {
+    fn name(self) -> &'static str {
+        match self {
+            Location::Path => "path",
+            Location::Query => "query",
+            Location::Header => "header",
+        }
+    }
+}
+
+// 编译后的参数
+struct Param {
+    name: String,
+    location: Location,
+    required: bool,
+    kind: Value, // 解析 $ref 后的 Schema，用于把字符串转换为对应的类型
+    validator: Option<jsonschema::Validator>,
+}
+
+// 编译后的操作
+struct Operation {
+    id: String, // operationId，没有时为 "METHOD /path"
+    method: Method,
+    segments: Vec<Segment>,
+    params: Vec<Param>,
+    body: Option<(bool, Option<jsonschema::Validator>)>, // 请求体是否必需，以及 JSON 请求体的 Schema
+    responses: Vec<(String, Option<jsonschema::Validator>)>, // 状态码（200、2XX 或 default）与 JSON 响应体的 Schema
+}
+
+impl Operation {
+    // 路径匹配时返回路径参数；固定段越多越优先
+    fn captures<'a>(&self, parts: &[&'a str]) -> Option<Vec<(&str, &'a str)>> {
+        if parts.len() != self.segments.len() {
+            return None;
+        }
+        let mut captures = Vec::new();
+        for (segment, part) in self.segments.iter().zip(parts) {
+            match segment {
+                Segment::Literal(literal) if literal == part => {}
+                Segment::Param(name) if !part.is_empty() => captures.push((name.as_str(), *part)),
+                _ => return None,
+            }
+        }
+        Some(captures)
+    }
+
+    fn literals(&self) -> usize {
+        self.segments
+            .iter()
+            .filter(|s| matches!(s, Segment::Literal(_)))
+            .count()
+    }
+}
+
+// 编译后的 OpenAPI 描述
+pub struct OpenApi {
+    operations: Vec<Operation>,
+    base_path: String,
+    reject_unknown: bool,
+    responses: ResponseCheck,
+    max_errors: usize,
+}
+
+impl std::fmt::Debug for OpenApi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenApi")
+            .field("operations", &self.operations.len())
+            .field("base_path", &self.base_path)
+            .finish_non_exhaustive()
+    }
+}
+
+// 编译描述时使用的上下文
+struct Spec {
+    root: Value,
+    components: Value,
+    draft: jsonschema::Draft,
+    nullable: bool, // 3.0 的 nullable 需要转换
+}
+
+impl Spec {
+    // 解析以 #/ 开头的 $ref，最多跟随 32 层
+    fn resolve<'a>(&'a self, mut value: &'a Value) -> Result<&'a Value, String> {
+        for _ in 0..32 {
+            let Some(reference) = value.get("$ref").and_then(Value::as_str) else {
+                return Ok(value);
+            };
+            value = reference
+                .strip_prefix('#')
+                .and_then(|pointer| self.root.pointer(pointer))
+                .ok_or_else(|| format!("无法解析 $ref: {}", reference))?;
+        }
+        Err("$ref 嵌套过深".to_string())
+    }
+
+    // 编译 Schema，其中的 $ref 在描述的 components 中解析
+    fn compile(&self, schema: &Value) -> Result<jsonschema::Validator, String> {
+        let mut document = serde_json::json!({
+            "components": self.components,
+            "allOf": [schema],
+        });
+        if self.nullable {
+            convert_nullable(&mut document);
+        }
+        jsonschema::options()
+            .with_draft(self.draft)
+            .build(&document)
+            .map_err(|e| format!("无效的 Schema: {}", e))
+    }
+
+    // JSON 内容的 Schema：取第一个 application/json 或 *+json 的媒体类型
+    fn json_schema(
+        &self,
+        content: Option<&Value>,
+    ) -> Result<Option<jsonschema::Validator>, String> {
+        let schema = content
+            .and_then(Value::as_object)
+            .and_then(|content| {
+                content
+                    .iter()
+                    .find(|(media_type, _)| is_json(media_type))
+                    .map(|(_, media)| media)
+            })
+            .and_then(|media| media.get("schema"));
+        schema.map(|schema| self.compile(schema)).transpose()
+    }
+
+    fn param(&self, value: &Value) -> Result<Option<Param>, String> {
+        let value = self.resolve(value)?;
+        let name = value
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or("参数缺少 name")?;
+        let location = match value.get("in").and_then(Value::as_str) {
+            Some("path") => Location::Path,
+            Some("query") => Location::Query,
+            Some("header") => Location::Header,
+            _ => return Ok(None), // cookie 参数不校验
+        };
+        let schema = value.get("schema");
+        Ok(Some(Param {
+            name: name.to_string(),
+            location,
+            required: location == Location::Path
+                || value.get("required").and_then(Value::as_bool) == Some(true),
+            kind: schema
+                .map(|s| self.resolve(s).cloned())
+                .transpose()?
+                .unwrap_or(Value::Null),
+            validator: schema.map(|s| self.compile(s)).transpose()?,
+        }))
+    }
+
+    fn operation(
+        &self,
+        path: &str,
+        method: &str,
+        item: &Value,
+        operation: &Value,
+    ) -> Result<Operation, String> {
+        let method_upper = method.to_ascii_uppercase();
+        let id = operation
+            .get("operationId")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{} {}", method_upper, path));
+        // 操作的参数覆盖路径上同名同位置的参数
+        let mut params: Vec<Param> = Vec::new();
+        for value in [operation, item]
+            .iter()
+            .filter_map(|v| v.get("parameters").and_then(Value::as_array))
+            .flatten()
+        {
+            if let Some(param) = self.param(value)?
+                && !params
+                    .iter()
+                    .any(|p| p.name == param.name && p.location == param.location)
+            {
+                params.push(param);
+            }
+        }
+        let body = operation
+            .get("requestBody")
+            .map(|body| -> Result<_, String> {
+                let body = self.resolve(body)?;
+                let required = body.get("required").and_then(Value::as_bool) == Some(true);
+                Ok((required, self.json_schema(body.get("content"))?))
+            })
+            .transpose()?;
+        let mut responses = Vec::new();
+        for (status, response) in operation
+            .get("responses")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+        {
+            let response = self.resolve(response)?;
+            responses.push((
+                status.to_ascii_uppercase(),
+                self.json_schema(response.get("content"))?,
+            ));
+        }
+        let segments = path
+            .trim_start_matches('/')
+            .split('/')
+            .map(
+                |part| match part.strip_prefix('{').and_then(|p| p.strip_suffix('}')) {
+                    Some(name) => Segment::Param(name.to_string()),
+                    None => Segment::Literal(part.to_string()),
+                },
+            )
+            .collect();
+        Ok(Operation {
+            id,
+            method: Method::from_bytes(method_upper.as_bytes())
+                .map_err(|_| format!("无效的请求方法: {}", method))?,
+            segments,
+            params,
+            body,
+            responses,
+        })
+    }
+}
+
+// 描述中可以出现的请求方法
+const METHODS: [&str; 8] = [
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+impl OpenApi {
+    // 读取并编译描述，文件不存在、不是 OpenAPI 3.x 或其中的 Schema 无效时启动失败
+    pub fn new(config: &OpenApiConfig) -> Result<Self, String> {
+        let text = std::fs::read_to_string(&config.file)
+            .map_err(|e| format!("无法读取 OpenAPI 描述 {}: {}", config.file, e))?;
+        let yaml = config.file.ends_with(".yaml") || config.file.ends_with(".yml");
+        let root = match yaml {
+            true => parse_yaml(&text),
+            false => serde_json::from_str(&text).map_err(|e| e.to_string()),
+        }
+        .map_err(|e| format!("无法解析 OpenAPI 描述 {}: {}", config.file, e))?;
+        let version = root.get("openapi").and_then(Value::as_str).unwrap_or("");
+        if !version.starts_with("3.") {
+            return Err(format!(
+                "OpenAPI 描述 {} 的版本为 {:?}，只支持 OpenAPI 3.x",
+                config.file, version
+            ));
+        }
+        let spec = Spec {
+            components: root.get("components").cloned().unwrap_or(Value::Null),
+            draft: match version.starts_with("3.0") {
+                true => jsonschema::Draft::Draft4,
+                false => jsonschema::Draft::Draft202012,
+            },
+            nullable: version.starts_with("3.0"),
+            root,
+        };
+        let mut operations = Vec::new();
+        for (path, item) in spec
+            .root
+            .get("paths")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+        {
+            let item = spec.resolve(item)?;
+            for method in METHODS {
+                if let Some(operation) = item.get(method) {
+                    operations.push(
+                        spec.operation(path, method, item, operation)
+                            .map_err(|e| format!("{} {} {}: {}", config.file, method, path, e))?,
+                    );
+                }
+            }
+        }
+        if !config.base_path.is_empty() && !config.base_path.starts_with('/') {
+            return Err(format!(
+                "OpenAPI 的 base_path 必须以/开头: {}",
+                config.base_path
+            ));
+        }
+        Ok(OpenApi {
+            operations,
+            base_path: config.base_path.trim_end_matches('/').to_string(),
+            reject_unknown: config.reject_unknown,
+            responses: config.responses,
+            max_errors: config.max_errors.max(1),
+        })
+    }
+
+    // 匹配并校验请求，匹配到的操作存入请求的扩展数据
+    pub fn check(&self, req: &HttpRequest, body: &[u8], route: &str) -> Result<(), ProxyError> {
+        let path = req.path();
+        let Some(relative) = path
+            .strip_prefix(self.base_path.as_str())
+            .filter(|rest| rest.starts_with('/'))
+        else {
+            return self.unmatched(req, route, None);
+        };
+        let parts: Vec<&str> = relative.trim_start_matches('/').split('/').collect();
+        let candidates: Vec<(usize, Vec<(&str, &str)>)> = self
+            .operations
+            .iter()
+            .enumerate()
+            .filter_map(|(i, op)| op.captures(&parts).map(|c| (i, c)))
+            .collect();
+        let Some(best) = candidates
+            .iter()
+            .map(|(i, _)| self.operations[*i].literals())
+            .max()
+        else {
+            return self.unmatched(req, route, None);
+        };
+        let found = candidates.iter().find(|(i, _)| {
+            self.operations[*i].literals() == best && self.operations[*i].method == req.method()
+        });
+        let Some((index, captures)) = found else {
+            let allow: Vec<String> = candidates
+                .iter()
+                .filter(|(i, _)| self.operations[*i].literals() == best)
+                .map(|(i, _)| self.operations[*i].method.to_string())
+                .collect();
+            return self.unmatched(req, route, Some(allow.join(", ")));
+        };
+        let operation = &self.operations[*index];
+        req.extensions_mut().insert(Matched(*index));
+
+        let mut problems = Vec::new();
+        let query = actix_web::web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+            .map(|q| q.into_inner())
+            .unwrap_or_default();
+        for param in &operation.params {
+            let raw: Vec<&str> = match param.location {
+                Location::Path => captures
+                    .iter()
+                    .filter(|(name, _)| *name == param.name)
+                    .map(|(_, v)| *v)
+                    .collect(),
+                Location::Query => query
+                    .iter()
+                    .filter(|(name, _)| *name == param.name)
+                    .map(|(_, v)| v.as_str())
+                    .collect(),
+                Location::Header => req
+                    .headers()
+                    .get_all(param.name.as_str())
+                    .filter_map(|v| v.to_str().ok())
+                    .collect(),
+            };
+            let label = format!("{}.{}", param.location.name(), param.name);
+            if raw.is_empty() {
+                if param.required {
+                    problems.push(format!("{}: 缺少必需的参数", label));
+                }
+                continue;
+            }
+            if let Some(validator) = &param.validator {
+                let value = coerce(&raw, &param.kind);
+                problems.extend(
+                    validator
+                        .iter_errors(&value)
+                        .map(|e| format!("{}: {}", label, e)),
+                );
+            }
+        }
+        match &operation.body {
+            Some((true, _)) if body.is_empty() => {
+                problems.push("body: 缺少必需的请求体".to_string())
+            }
+            Some((_, Some(validator))) if !body.is_empty() && is_json(&request_media_type(req)) => {
+                problems.extend(validate_json(validator, body, "body"));
+            }
+            _ => {}
+        }
+
+        let result = if problems.is_empty() {
+            "valid"
+        } else {
+            "invalid"
+        };
+        crate::metrics::counter_inc(
+            "proxy_openapi_requests_total",
+            &[
+                ("route", route),
+                ("operation", &operation.id),
+                ("result", result),
+            ],
+        );
+        if problems.is_empty() {
+            return Ok(());
+        }
+        problems.truncate(self.max_errors);
+        log::info!(
+            "路由 {} 的请求不符合 OpenAPI 操作 {}: {}",
+            route,
+            operation.id,
+            problems.join("; ")
+        );
+        Err(ProxyError::InvalidRequest(problems))
+    }
+
+    // 描述中没有匹配的路径（allow 为 None）或方法
+    fn unmatched(
+        &self,
+        req: &HttpRequest,
+        route: &str,
+        allow: Option<String>,
+    ) -> Result<(), ProxyError> {
+        crate::metrics::counter_inc(
+            "proxy_openapi_requests_total",
+            &[("route", route), ("operation", ""), ("result", "unknown")],
+        );
+        if !self.reject_unknown {
+            return Ok(());
+        }
+        let request = format!("{} {}", req.method(), req.path());
+        log::info!("路由 {} 的请求不在 OpenAPI 描述中: {}", route, request);
+        Err(match allow {
+            Some(allow) => ProxyError::MethodNotInSpec(request, allow),
+            None => ProxyError::NotInSpec(request),
+        })
+    }
+
+    // 校验上游响应；responses 为 off、请求没有匹配到操作或校验通过时返回 Ok
+    pub fn check_response(
+        &self,
+        req: &HttpRequest,
+        route: &str,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<(), ProxyError> {
+        if self.responses == ResponseCheck::Off {
+            return Ok(());
+        }
+        let Some(Matched(index)) = req.extensions().get::<Matched>().copied() else {
+            return Ok(());
+        };
+        let operation = &self.operations[index];
+        let code = status.as_u16().to_string();
+        let range = format!("{}XX", status.as_u16() / 100);
+        let declared = [code.as_str(), range.as_str(), "DEFAULT"]
+            .iter()
+            .find_map(|key| operation.responses.iter().find(|(s, _)| s == key));
+        let mut problems = match declared {
+            None if !operation.responses.is_empty() => {
+                vec![format!("status: 状态码 {} 不在描述中", code)]
+            }
+            Some((_, Some(validator)))
+                if !crate::sub_filter::encoded(headers)
+                    && is_json(&crate::sub_filter::media_type(headers)) =>
+            {
+                validate_json(validator, body, "body")
+            }
+            _ => Vec::new(),
+        };
+        if problems.is_empty() {
+            return Ok(());
+        }
+        problems.truncate(self.max_errors);
+        crate::metrics::counter_inc(
+            "proxy_openapi_invalid_responses_total",
+            &[("route", route), ("operation", &operation.id)],
+        );
+        log::warn!(
+            "路由 {} 的上游响应不符合 OpenAPI 操作 {}: {}",
+            route,
+            operation.id,
+            problems.join("; ")
+        );
+        match self.responses {
+            ResponseCheck::Reject => Err(ProxyError::InvalidResponse(problems)),
+            _ => Ok(()),
+        }
+    }
+}
+
+// 校验 JSON，问题以“前缀/位置: 原因”列出
+fn validate_json(validator: &jsonschema::Validator, body: &[u8], prefix: &str) -> Vec<String> {
+    let instance: Value = match serde_json::from_slice(body) {
+        Ok(instance) => instance,
+        Err(e) => return vec![format!("{}: 不是合法的 JSON: {}", prefix, e)],
+    };
+    validator
+        .iter_errors(&instance)
+        .map(|e| format!("{}{}: {}", prefix, e.instance_path(), e))
+        .collect()
+}
+
+// 按 Schema 的类型把参数的字符串值转换为 JSON 值，无法转换时保留字符串，由校验报告类型错误
+fn coerce(raw: &[&str], kind: &Value) -> Value {
+    let types: Vec<&str> = match kind.get("type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(ts)) => ts.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if types.contains(&"array") {
+        let items = kind.get("items").unwrap_or(&Value::Null);
+        // 多次出现的参数各为一个元素，否则按逗号分隔
+        let values: Vec<&str> = match raw {
+            [single] => single.split(',').collect(),
+            _ => raw.to_vec(),
+        };
+        return Value::Array(values.iter().map(|v| coerce(&[v], items)).collect());
+    }
+    let raw = raw[0];
+    if types.contains(&"integer")
+        && let Ok(n) = raw.parse::<i64>()
+    {
+        return Value::from(n);
+    }
+    if types.contains(&"number")
+        && let Some(n) = raw
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+    {
+        return Value::Number(n);
+    }
+    if types.contains(&"boolean") && (raw == "true" || raw == "false") {
+        return Value::Bool(raw == "true");
+    }
+    Value::String(raw.to_string())
+}
+
+// 3.0 的 nullable: true 转换为 JSON Schema 中允许 null
+fn convert_nullable(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            if map.remove("nullable") == Some(Value::Bool(true)) {
+                if let Some(Value::String(kind)) = map.get("type").cloned() {
+                    map.insert("type".to_string(), serde_json::json!([kind, "null"]));
+                }
+                if let Some(Value::Array(values)) = map.get_mut("enum") {
+                    values.push(Value::Null);
+                }
+            }
+            map.values_mut().for_each(convert_nullable);
+        }
+        Value::Array(items) => items.iter_mut().for_each(convert_nullable),
+        _ => {}
+    }
+}
+
+// 请求的媒体类型，小写且不含参数
+fn request_media_type(req: &HttpRequest) -> String {
+    req.headers()
+        .get(actix_web::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+// 媒体类型是否为 JSON
+fn is_json(media_type: &str) -> bool {
+    let media_type = media_type.split(';').next().unwrap_or("").trim();
+    media_type.eq_ignore_ascii_case("application/json")
+        || media_type.to_ascii_lowercase().ends_with("+json")
+}
+
+// 把 YAML 文档转换为 JSON 值，映射的键转换为字符串
+fn parse_yaml(text: &str) -> Result<Value, String> {
+    use yaml_rust::Yaml;
+    fn convert(yaml: &Yaml) -> Value {
+        match yaml {
+            Yaml::Real(s) => s
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(Value::Number)
+                .unwrap_or_else(|| Value::String(s.clone())),
+            Yaml::Integer(n) => Value::from(*n),
+            Yaml::String(s) => Value::String(s.clone()),
+            Yaml::Boolean(b) => Value::Bool(*b),
+            Yaml::Array(items) => Value::Array(items.iter().map(convert).collect()),
+            Yaml::Hash(hash) => Value::Object(
+                hash.iter()
+                    .map(|(k, v)| {
+                        let key = match k {
+                            Yaml::String(s) | Yaml::Real(s) => s.clone(),
+                            Yaml::Integer(n) => n.to_string(),
+                            Yaml::Boolean(b) => b.to_string(),
+                            _ => String::new(),
+                        };
+                        (key, convert(v))
+                    })
+                    .collect::<Map<String, Value>>(),
+            ),
+            _ => Value::Null,
+        }
+    }
+    let docs = yaml_rust::YamlLoader::load_from_str(text).map_err(|e| e.to_string())?;
+    docs.first()
+        .map(convert)
+        .ok_or_else(|| "YAML 文档为空".to_string())
+}
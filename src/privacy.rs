@@ -0,0 +1,195 @@
+// ==================== 隐私过滤 ====================
+//
+// 面向注重隐私的部署，[privacy] 启用后在转发之前去掉客户端请求中可用于跟踪与指纹识别的信息：
+// headers 中的请求头（名称可以用 * 结尾匹配前缀，如 X-Tracking-*），client_hints 为 true 时的
+// Sec-CH-* 与 DPR、Viewport-Width 等旧式客户端提示，以及 Cookie 中名称与 cookies 匹配的条目
+// （如 _ga、_fbp 等统计与广告 Cookie，去掉后 Cookie 为空时整个请求头不转发）。
+// 过滤作用于最终发往上游的请求头，在客户端信息与 Via 之后、路由的请求头规则之前进行：
+// 把 X-Forwarded-For 等写入 headers 即可不向上游透露客户端地址，路由的规则仍然可以重新添加
+
+use reqwest::header::{COOKIE, HeaderMap, HeaderValue};
+use serde::{Deserialize, Serialize};
+
+// 隐私过滤配置：对应配置文件中的 [privacy]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrivacyConfig {
+    #[serde(default)]
+    pub enabled: bool, // 是否启用隐私过滤
+    #[serde(default = "default_headers")]
+    pub headers: Vec<String>, // 去掉的请求头，* 结尾匹配前缀
+    #[serde(default = "default_true")]
+    pub client_hints: bool, // 是否去掉客户端提示请求头
+    #[serde(default = "default_cookies")]
+    pub cookies: Vec<String>, // 去掉的 Cookie 名称，* 结尾匹配前缀
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        PrivacyConfig {
+            enabled: false,
+            headers: default_headers(),
+            client_hints: true,
+            cookies: default_cookies(),
+        }
+    }
+}
+
+// 以下函数为隐私过滤提供默认值
+fn default_headers() -> Vec<String> {
+    ["X-Client-Data", "X-UIDH", "X-ATT-DeviceId", "X-Wap-Profile"]
+        .iter()
+        .map(|h| h.to_string())
+        .collect()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_cookies() -> Vec<String> {
+    [
+        "_ga", "_ga_*", "_gid", "_gat*", "_gcl_*", "_fbp", "_fbc", "__utm*", "_hj*",
+    ]
+    .iter()
+    .map(|c| c.to_string())
+    .collect()
+}
+
+// 旧式客户端提示请求头，Sec-CH-* 按前缀匹配
+const CLIENT_HINTS: [&str; 8] = [
+    "device-memory",
+    "dpr",
+    "width",
+    "viewport-width",
+    "downlink",
+    "ect",
+    "rtt",
+    "save-data",
+];
+
+// 名称规则：精确匹配或以 * 结尾的前缀
+#[derive(Debug)]
+struct Pattern {
+    name: String,
+    prefix: bool,
+}
+
+impl Pattern {
+    fn parse(pattern: &str, lowercase: bool) -> Self {
+        let pattern = pattern.trim();
+        let pattern = match lowercase {
+            true => pattern.to_ascii_lowercase(),
+            false => pattern.to_string(),
+        };
+        match pattern.strip_suffix('*') {
+            Some(prefix) => Pattern {
+                name: prefix.to_string(),
+                prefix: true,
+            },
+            None => Pattern {
+                name: pattern,
+                prefix: false,
+            },
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self.prefix {
+            true => name.starts_with(&self.name),
+            false => name == self.name,
+        }
+    }
+}
+
+// 隐私过滤策略
+#[derive(Debug)]
+pub struct Privacy {
+    enabled: bool,
+    headers: Vec<Pattern>, // 请求头名称为小写
+    cookies: Vec<Pattern>, // Cookie 名称区分大小写
+}
+
+impl Privacy {
+    // 从配置构建，名称为空时启动失败
+    pub fn new(config: &PrivacyConfig) -> Result<Self, String> {
+        if let Some(empty) = config
+            .headers
+            .iter()
+            .chain(&config.cookies)
+            .find(|p| p.trim().is_empty() || p.trim() == "*")
+        {
+            return Err(format!("隐私过滤的名称不能为空或只有 *: {:?}", empty));
+        }
+        let mut headers: Vec<Pattern> = config
+            .headers
+            .iter()
+            .map(|h| Pattern::parse(h, true))
+            .collect();
+        if config.client_hints {
+            headers.push(Pattern::parse("sec-ch-*", true));
+            headers.extend(CLIENT_HINTS.iter().map(|h| Pattern::parse(h, true)));
+        }
+        Ok(Privacy {
+            enabled: config.enabled,
+            headers,
+            cookies: config
+                .cookies
+                .iter()
+                .map(|c| Pattern::parse(c, false))
+                .collect(),
+        })
+    }
+
+    // 去掉发往上游的请求头中的跟踪信息
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        if !self.enabled {
+            return;
+        }
+        let names: Vec<_> = headers
+            .keys()
+            .filter(|name| self.headers.iter().any(|p| p.matches(name.as_str())))
+            .cloned()
+            .collect();
+        for name in &names {
+            headers.remove(name);
+        }
+        if !names.is_empty() {
+            crate::metrics::counter_add(
+                "proxy_privacy_stripped_total",
+                &[("kind", "header")],
+                names.len() as u64,
+            );
+        }
+        if self.cookies.is_empty() || !headers.contains_key(COOKIE) {
+            return;
+        }
+        // HTTP/2 的 Cookie 可以分成多个请求头，转发时合并为一个
+        let mut removed = 0;
+        let mut kept = Vec::new();
+        for value in headers.get_all(COOKIE) {
+            let Ok(value) = value.to_str() else { continue };
+            for pair in value.split(';').map(str::trim).filter(|p| !p.is_empty()) {
+                let name = pair.split_once('=').map_or(pair, |(n, _)| n).trim();
+                match self.cookies.iter().any(|p| p.matches(name)) {
+                    true => removed += 1,
+                    false => kept.push(pair.to_string()),
+                }
+            }
+        }
+        if removed == 0 {
+            return;
+        }
+        let cookie = kept.join("; ");
+        headers.remove(COOKIE);
+        if let Ok(value) = HeaderValue::from_str(&cookie)
+            && !cookie.is_empty()
+        {
+            headers.insert(COOKIE, value);
+        }
+        crate::metrics::counter_add(
+            "proxy_privacy_stripped_total",
+            &[("kind", "cookie")],
+            removed,
+        );
+    }
+}
@@ -0,0 +1,89 @@
+// ==================== 启动探测 ====================
+//
+// [startup_probe] 在开始监听之前解析并连接所有路由的上游（含故障转移目标），把主机名写错、端口不对的
+// 配置在流量到来之前暴露出来。on_failure = "warn" 时每个不可达的上游记录一条警告后照常启动；
+// "fail" 时只要有上游不可达就拒绝启动，平滑升级启动的新进程因此退出，旧进程继续运行。
+// 只在启动时探测，热加载不探测；运行期间的上游状态见就绪检查 /readyz
+
+use crate::routes::RouteTable;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// 启动探测配置：对应配置文件中的 [startup_probe]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProbeConfig {
+    #[serde(default)]
+    pub enabled: bool, // 是否在启动时探测上游
+    #[serde(default)]
+    pub on_failure: OnFailure, // 有上游不可达时的处理
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64, // 每个上游的解析与连接超时(毫秒)
+}
+
+// 有上游不可达时的处理
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OnFailure {
+    #[default]
+    Warn, // 记录警告，照常启动
+    Fail, // 拒绝启动
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        ProbeConfig {
+            enabled: false,
+            on_failure: OnFailure::Warn,
+            timeout_ms: default_timeout_ms(),
+        }
+    }
+}
+
+// 以下函数为启动探测提供默认值
+fn default_timeout_ms() -> u64 {
+    2000
+}
+
+// 探测所有上游；on_failure 为 fail 且有上游不可达时返回错误
+pub async fn run(config: &ProbeConfig, routes: &RouteTable) -> Result<(), String> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let results = crate::health::probe(routes, Duration::from_millis(config.timeout_ms)).await;
+    let mut unreachable = 0;
+    for (addr, result) in &results {
+        let Err(e) = result else {
+            continue;
+        };
+        unreachable += 1;
+        let owners: Vec<&str> = routes
+            .iter()
+            .filter(|route| {
+                route
+                    .targets()
+                    .iter()
+                    .chain(route.failover.as_ref())
+                    .any(|t| crate::addr::join(&t.host, t.port) == *addr)
+            })
+            .map(|route| route.name.as_str())
+            .collect();
+        log::warn!(
+            "启动探测: 路由 {} 的上游 {} 不可达: {}",
+            owners.join(", "),
+            addr,
+            e
+        );
+    }
+    if unreachable == 0 {
+        log::info!("启动探测: {} 个上游均可达", results.len());
+        return Ok(());
+    }
+    match config.on_failure {
+        OnFailure::Warn => Ok(()),
+        OnFailure::Fail => Err(format!(
+            "启动探测: {} 个上游中有 {} 个不可达，拒绝启动（[startup_probe] on_failure = \"fail\"）",
+            results.len(),
+            unreachable
+        )),
+    }
+}
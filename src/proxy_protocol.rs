@@ -0,0 +1,314 @@
+// ==================== PROXY 协议 ====================
+//
+// 在 TCP 模式的 HAProxy、AWS NLB 等负载均衡器之后运行时，连接的对端地址是负载均衡器，
+// 真实的客户端地址由连接开头的 PROXY 协议头（v1 文本格式或 v2 二进制格式）传递。
+// 监听配置 proxy_protocol 后，每个连接在 TLS 握手与 HTTP 解析之前先读取该协议头，
+// 其中的源地址作为客户端地址，访问日志、访问控制、X-Forwarded-For 与审计日志看到的都是真实客户端。
+// 启用后没有协议头、格式错误或超时的连接直接关闭；trusted 不为空时只接受来自这些网段的连接，
+// 避免客户端绕过负载均衡器直连时伪造地址。v2 的 LOCAL 命令（负载均衡器自身的健康检查）与
+// v1 的 UNKNOWN 保留 TCP 的对端地址。
+// 反方向上，路由配置 send_proxy_protocol 后，发往上游的每个请求单独建立连接，先发送 v2 协议头
+// （客户端地址与客户端连接的本地地址）再发送请求，供同样需要四层客户端地址的下一级 HAProxy 使用。
+// HTTP 客户端不能在连接上写入额外的数据，这类请求在构建时带上内部标记（见 marker.rs），发送时改用独立的 HTTP/1.1 连接
+
+use crate::ProxyError;
+use crate::acl::IpNet;
+use actix_web::HttpRequest;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+// PROXY 协议配置：对应 [server] 或 [[listeners]] 中的 proxy_protocol
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ProxyProtocolConfig {
+    #[serde(default)]
+    pub trusted: Vec<String>, // 允许连接的负载均衡器网段，为空表示不限制
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64, // 等待协议头的超时(毫秒)
+}
+
+// 以下函数为 PROXY 协议配置提供默认值
+fn default_timeout_ms() -> u64 {
+    5000
+}
+
+// v2 协议头的签名
+const SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+// v1 协议头的最大长度（含 \r\n）
+const MAX_V1_LEN: usize = 107;
+
+// 监听的 PROXY 协议处理
+#[derive(Debug)]
+pub struct ProxyProtocol {
+    trusted: Vec<IpNet>,
+    timeout: Duration,
+}
+
+impl ProxyProtocol {
+    // 从配置构建，网段无效时启动失败
+    pub fn new(config: &ProxyProtocolConfig) -> Result<Self, String> {
+        let trusted = config
+            .trusted
+            .iter()
+            .map(|s| IpNet::parse(s).ok_or_else(|| format!("无效的 PROXY 协议可信网段: {}", s)))
+            .collect::<Result<_, _>>()?;
+        Ok(ProxyProtocol {
+            trusted,
+            timeout: Duration::from_millis(config.timeout_ms),
+        })
+    }
+
+    // 读取连接开头的协议头，返回其中的客户端地址；没有携带地址时返回 None，使用 TCP 的对端地址
+    pub async fn accept<R: AsyncRead + Unpin>(
+        &self,
+        io: &mut R,
+        peer: Option<SocketAddr>,
+    ) -> Result<Option<SocketAddr>, String> {
+        if !self.trusted.is_empty()
+            && !peer.is_some_and(|p| self.trusted.iter().any(|net| net.contains(p.ip())))
+        {
+            return Err("对端不在可信网段中".to_string());
+        }
+        match tokio::time::timeout(self.timeout, read_header(io)).await {
+            Ok(result) => result,
+            Err(_) => Err(format!("{}毫秒内没有收到协议头", self.timeout.as_millis())),
+        }
+    }
+}
+
+// v1 与 v2 的协议头都不短于 12 字节（最短的 v1 为 "PROXY UNKNOWN\r\n"），先读取 12 字节区分版本，
+// 之后只读取协议头本身，不多读 HTTP 请求的数据
+async fn read_header<R: AsyncRead + Unpin>(io: &mut R) -> Result<Option<SocketAddr>, String> {
+    let mut start = [0u8; 12];
+    io.read_exact(&mut start).await.map_err(|e| e.to_string())?;
+    if start == SIGNATURE {
+        let mut fixed = [0u8; 4];
+        io.read_exact(&mut fixed).await.map_err(|e| e.to_string())?;
+        let len = u16::from_be_bytes([fixed[2], fixed[3]]) as usize;
+        let mut body = vec![0u8; len];
+        io.read_exact(&mut body).await.map_err(|e| e.to_string())?;
+        return parse_v2(fixed[0], fixed[1], &body);
+    }
+    if !start.starts_with(b"PROXY ") {
+        return Err("连接开头不是 PROXY 协议头".to_string());
+    }
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= MAX_V1_LEN {
+            return Err("v1 协议头过长".to_string());
+        }
+        line.push(io.read_u8().await.map_err(|e| e.to_string())?);
+    }
+    let line = std::str::from_utf8(&line[..line.len() - 2]).map_err(|_| "v1 协议头不是文本")?;
+    parse_v1(line)
+}
+
+// v1：PROXY TCP4|TCP6 源地址 目标地址 源端口 目标端口，或 PROXY UNKNOWN
+fn parse_v1(line: &str) -> Result<Option<SocketAddr>, String> {
+    let fields: Vec<&str> = line.split(' ').collect();
+    match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", family @ ("TCP4" | "TCP6"), source, _, port, _] => {
+            let ip: IpAddr = source
+                .parse()
+                .map_err(|_| format!("无效的源地址: {}", source))?;
+            if ip.is_ipv4() != (*family == "TCP4") {
+                return Err(format!("源地址 {} 与 {} 不符", source, family));
+            }
+            let port: u16 = port
+                .parse()
+                .map_err(|_| format!("无效的源端口: {}", port))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(format!("无效的 v1 协议头: {:?}", line)),
+    }
+}
+
+// v2：版本与命令、地址族与传输协议、地址块（其后的 TLV 忽略）
+fn parse_v2(version_command: u8, family: u8, body: &[u8]) -> Result<Option<SocketAddr>, String> {
+    if version_command >> 4 != 2 {
+        return Err(format!("不支持的协议版本: {}", version_command >> 4));
+    }
+    match version_command & 0x0f {
+        0 => return Ok(None), // LOCAL
+        1 => {}               // PROXY
+        command => return Err(format!("未知的命令: {}", command)),
+    }
+    let port = |at: usize| u16::from_be_bytes([body[at], body[at + 1]]);
+    match family >> 4 {
+        1 if body.len() >= 12 => {
+            let ip = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            Ok(Some(SocketAddr::new(ip.into(), port(8))))
+        }
+        2 if body.len() >= 36 => {
+            let octets: [u8; 16] = body[..16].try_into().unwrap_or_default();
+            Ok(Some(SocketAddr::new(
+                Ipv6Addr::from(octets).into(),
+                port(32),
+            )))
+        }
+        1 | 2 => Err("v2 协议头的地址块过短".to_string()),
+        _ => Ok(None), // AF_UNSPEC 与 AF_UNIX 没有可用的 IP 地址
+    }
+}
+
+// ==================== 向上游发送 ====================
+
+// 需要发送协议头的请求带有该内部请求头，值为标记的键，发送前去掉
+const MARK: &str = "x-rust-proxy-send-proxy-protocol";
+
+// 自行建立连接的响应没有 URL，HTTP 客户端以该主机名占位
+pub const UNKNOWN_URL_HOST: &str = "no.url.provided.local";
+
+// 为发往上游的请求加上标记；客户端地址未知时发送 LOCAL 命令
+pub fn mark(
+    builder: reqwest::RequestBuilder,
+    req: &HttpRequest,
+    accept_invalid_certs: bool,
+) -> reqwest::RequestBuilder {
+    let upstream = Upstream {
+        source: crate::addr::peer_addr(req),
+        destination: crate::listeners::local(req).addr,
+        accept_invalid_certs,
+    };
+    crate::marker::insert(req, builder, MARK, upstream)
+}
+
+// 需要发送协议头的上游请求
+#[derive(Debug, Clone, Copy)]
+pub struct Upstream {
+    source: Option<SocketAddr>,
+    destination: SocketAddr,
+    accept_invalid_certs: bool,
+}
+
+// 取出请求的标记，没有标记的请求照常由 HTTP 客户端发送
+pub fn take(request: &mut reqwest::Request) -> Option<Upstream> {
+    crate::marker::take::<Upstream>(request, MARK).map(|upstream| *upstream)
+}
+
+impl Upstream {
+    // 建立连接、发送协议头与请求，返回响应头；请求的总超时覆盖从建立连接到收到响应头
+    pub async fn send(self, request: reqwest::Request) -> Result<reqwest::Response, ProxyError> {
+        let Some(limit) = request.timeout().copied() else {
+            return self.exchange(request).await;
+        };
+        tokio::time::timeout(limit, self.exchange(request))
+            .await
+            .map_err(|_| {
+                ProxyError::UpstreamTimeout(format!("{}毫秒内未收到响应头", limit.as_millis()))
+            })?
+    }
+
+    async fn exchange(self, request: reqwest::Request) -> Result<reqwest::Response, ProxyError> {
+        let url = request.url().clone();
+        let host = url.host_str().unwrap_or_default();
+        let host = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let port = url.port_or_known_default().unwrap_or(80);
+        let mut stream = TcpStream::connect((host.as_str(), port))
+            .await
+            .map_err(|e| {
+                ProxyError::UpstreamConnectFailed(format!(
+                    "连接 {} 失败: {}",
+                    crate::addr::join(&host, port),
+                    e
+                ))
+            })?;
+        stream
+            .write_all(&header_v2(self.source, self.destination))
+            .await
+            .map_err(|e| ProxyError::UpstreamConnectFailed(format!("发送协议头失败: {}", e)))?;
+        crate::metrics::counter_inc("proxy_protocol_sent_total", &[]);
+        if url.scheme() != "https" {
+            return send_http1(stream, request).await;
+        }
+        let connector = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(self.accept_invalid_certs)
+            .build()
+            .map_err(|e| ProxyError::UpstreamConnectFailed(e.to_string()))?;
+        let stream = tokio_native_tls::TlsConnector::from(connector)
+            .connect(&host, stream)
+            .await
+            .map_err(|e| ProxyError::UpstreamConnectFailed(format!("TLS 握手失败: {}", e)))?;
+        send_http1(stream, request).await
+    }
+}
+
+// 在已建立的连接上发送一个 HTTP/1.1 请求；连接在响应体读完后关闭，不会被其他客户端的请求复用
+async fn send_http1<S>(
+    stream: S,
+    request: reqwest::Request,
+) -> Result<reqwest::Response, ProxyError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let failed = |e: hyper::Error| ProxyError::UpstreamConnectFailed(e.to_string());
+    let (mut sender, connection) = hyper::client::conn::handshake(stream)
+        .await
+        .map_err(failed)?;
+    actix_web::rt::spawn(async move {
+        if let Err(e) = connection.await {
+            log::debug!("发送 PROXY 协议头的上游连接出错: {}", e);
+        }
+    });
+    let url = request.url();
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let body = request
+        .body()
+        .and_then(|b| b.as_bytes())
+        .map_or_else(hyper::Body::empty, |b| hyper::Body::from(b.to_vec()));
+    let mut outgoing = hyper::Request::builder()
+        .method(request.method().clone())
+        .uri(path)
+        .body(body)
+        .map_err(|e| ProxyError::RequestBuilderError(e.to_string()))?;
+    *outgoing.headers_mut() = request.headers().clone();
+    if !outgoing.headers().contains_key(reqwest::header::HOST) {
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let host = reqwest::header::HeaderValue::from_str(&host)
+            .map_err(|_| ProxyError::InvalidHeader("host".to_string()))?;
+        outgoing.headers_mut().insert(reqwest::header::HOST, host);
+    }
+    let response = sender.send_request(outgoing).await.map_err(failed)?;
+    Ok(reqwest::Response::from(response))
+}
+
+// v2 协议头：PROXY 命令与 TCP 地址，两个地址的地址族不同时都以 IPv6 表示；客户端地址未知时为 LOCAL 命令
+fn header_v2(source: Option<SocketAddr>, destination: SocketAddr) -> Vec<u8> {
+    let mut header = SIGNATURE.to_vec();
+    let Some(source) = source else {
+        header.extend_from_slice(&[0x20, 0x00, 0, 0]);
+        return header;
+    };
+    match (source.ip(), destination.ip()) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            header.extend_from_slice(&[0x21, 0x11, 0, 12]);
+            header.extend_from_slice(&src.octets());
+            header.extend_from_slice(&dst.octets());
+        }
+        (src, dst) => {
+            let v6 = |ip: IpAddr| match ip {
+                IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+                IpAddr::V6(v6) => v6,
+            };
+            header.extend_from_slice(&[0x21, 0x21, 0, 36]);
+            header.extend_from_slice(&v6(src).octets());
+            header.extend_from_slice(&v6(dst).octets());
+        }
+    }
+    header.extend_from_slice(&source.port().to_be_bytes());
+    header.extend_from_slice(&destination.port().to_be_bytes());
+    header
+}
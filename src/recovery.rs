@@ -0,0 +1,101 @@
+// ==================== panic恢复 ====================
+//
+// 代理路径中发生panic时，捕获panic并返回带请求ID的500响应，同时记录panic信息和调用栈，
+// 避免panic扩散到actix工作线程，导致该线程上其他正在处理的连接被中断；启用 Sentry 时 panic 同时上报
+
+use crate::access_log::RequestInfo;
+use crate::{metrics, request_id, sentry};
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, HttpResponse};
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+thread_local! {
+    // 请求处理中最近一次panic的信息与调用栈，由恢复中间件在同一线程上取出并上报
+    static LAST_PANIC: RefCell<Option<(String, String)>> = const { RefCell::new(None) };
+}
+
+// 安装panic钩子：请求处理中的panic连同请求ID和调用栈写入日志，其余panic交给默认钩子
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let backtrace = Backtrace::force_capture().to_string();
+        match request_id::current() {
+            Some(id) => {
+                log::error!(
+                    "请求 {} 处理时发生panic: {}\n调用栈:\n{}",
+                    id,
+                    info,
+                    backtrace
+                );
+                LAST_PANIC.set(Some((info.to_string(), backtrace)));
+            }
+            None => {
+                sentry::capture_panic(None, &info.to_string(), &backtrace);
+                default_hook(info)
+            }
+        }
+    }));
+}
+
+// 捕获内部future在poll时发生的panic
+struct CatchUnwind<F> {
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, ()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = &mut self.inner;
+        match panic::catch_unwind(AssertUnwindSafe(|| inner.as_mut().poll(cx))) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(_) => Poll::Ready(Err(())),
+        }
+    }
+}
+
+// panic恢复中间件：注册在请求ID中间件的内层，其余业务中间件的外层
+pub async fn middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let request_id = request_id::current().unwrap_or_default();
+    let http_req = req.request().clone();
+    let handled = CatchUnwind {
+        inner: Box::pin(next.call(req)),
+    };
+    match handled.await {
+        Ok(result) => result.map(ServiceResponse::map_into_boxed_body),
+        Err(()) => {
+            metrics::counter_inc("proxy_panics_total", &[]);
+            if let Some((message, backtrace)) = LAST_PANIC.take() {
+                let info = http_req
+                    .extensions()
+                    .get::<RequestInfo>()
+                    .cloned()
+                    .unwrap_or_default();
+                sentry::capture_panic(Some((&http_req, &info)), &message, &backtrace);
+            }
+            log::error!(
+                "请求 {} {} {} 处理失败，已返回500",
+                request_id,
+                http_req.method(),
+                http_req.path()
+            );
+            let resp = HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "服务器内部错误",
+                "details": "处理请求时发生内部错误",
+                "request_id": request_id
+            }));
+            Ok(ServiceResponse::new(http_req, resp))
+        }
+    }
+}
@@ -0,0 +1,271 @@
+// ==================== Redis 客户端 ====================
+//
+// 最小化的 Redis 客户端：通过 RESP 协议发送命令，连接按需建立并放回连接池复用，
+// 建立连接时按配置执行 AUTH 和 SELECT；每条命令有超时，出错的连接直接丢弃
+
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+// Redis 连接配置
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RedisConfig {
+    #[serde(default = "default_address")]
+    pub address: String, // Redis 地址
+    #[serde(default)]
+    pub username: Option<String>, // ACL 用户名，缺省使用 default 用户
+    #[serde(default)]
+    pub password: Option<String>, // 密码，可以写成 "env:REDIS_PASSWORD"
+    #[serde(default)]
+    pub db: u32, // 数据库编号
+    #[serde(default = "default_key_prefix")]
+    pub key_prefix: String, // 所有键的前缀，多个代理集群共用一个 Redis 时用于区分
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64, // 每条命令(含建立连接)的超时(毫秒)
+    #[serde(default = "default_pool_size")]
+    pub pool_size: usize, // 连接池中保留的空闲连接数
+}
+
+// 以下函数为 Redis 配置提供默认值
+fn default_address() -> String {
+    "127.0.0.1:6379".to_string()
+}
+
+fn default_key_prefix() -> String {
+    "rust_proxy:".to_string()
+}
+
+fn default_timeout_ms() -> u64 {
+    200
+}
+
+fn default_pool_size() -> usize {
+    8
+}
+
+// 命令的应答，错误应答转换为 Err
+#[derive(Debug)]
+pub enum Reply {
+    Nil,
+    Status, // 状态应答，如 OK
+    Int(i64),
+    Bulk(Vec<u8>),
+    Array(Vec<Reply>),
+}
+
+type Connection = BufReader<TcpStream>;
+
+#[derive(Debug)]
+pub struct RedisClient {
+    config: RedisConfig,
+    pool: Mutex<Vec<Connection>>, // 空闲连接
+}
+
+impl RedisClient {
+    pub fn new(config: &RedisConfig) -> Self {
+        RedisClient {
+            config: config.clone(),
+            pool: Mutex::new(Vec::new()),
+        }
+    }
+
+    // 加上前缀的键
+    pub fn key(&self, key: &str) -> Vec<u8> {
+        format!("{}{}", self.config.key_prefix, key).into_bytes()
+    }
+
+    // 执行一条命令
+    pub async fn command(&self, args: &[&[u8]]) -> Result<Reply, String> {
+        let mut replies = self.pipeline(&[args]).await?;
+        Ok(replies.pop().unwrap_or(Reply::Nil))
+    }
+
+    // 一次发送多条命令再依次读取应答；超时或出错时连接不再放回连接池，
+    // 某条命令返回错误时整体返回该错误
+    pub async fn pipeline(&self, commands: &[&[&[u8]]]) -> Result<Vec<Reply>, String> {
+        let timeout = Duration::from_millis(self.config.timeout_ms);
+        let run = async {
+            let pooled = self.pool.lock().unwrap().pop();
+            let mut conn = match pooled {
+                Some(conn) => conn,
+                None => self.connect().await?,
+            };
+            let replies = execute(&mut conn, commands).await?;
+            let mut pool = self.pool.lock().unwrap();
+            if pool.len() < self.config.pool_size {
+                pool.push(conn);
+            }
+            replies.into_iter().collect()
+        };
+        tokio::time::timeout(timeout, run)
+            .await
+            .unwrap_or_else(|_| Err(format!("Redis 命令超时({}ms)", self.config.timeout_ms)))
+    }
+
+    // 建立连接并完成认证和选择数据库
+    async fn connect(&self) -> Result<Connection, String> {
+        let stream = TcpStream::connect(&self.config.address)
+            .await
+            .map_err(|e| format!("无法连接 Redis {}: {}", self.config.address, e))?;
+        let _ = stream.set_nodelay(true);
+        let mut conn = BufReader::new(stream);
+        if let Some(password) = &self.config.password {
+            let mut auth: Vec<&[u8]> = vec![b"AUTH"];
+            if let Some(user) = &self.config.username {
+                auth.push(user.as_bytes());
+            }
+            auth.push(password.as_bytes());
+            execute(&mut conn, &[&auth])
+                .await?
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("Redis 认证失败: {}", e))?;
+        }
+        if self.config.db != 0 {
+            let db = self.config.db.to_string();
+            execute(&mut conn, &[&[b"SELECT", db.as_bytes()]])
+                .await?
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()?;
+        }
+        Ok(conn)
+    }
+
+    // GET，键不存在时返回 None
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        match self.command(&[b"GET", key]).await? {
+            Reply::Bulk(value) => Ok(Some(value)),
+            _ => Ok(None),
+        }
+    }
+
+    // 用 SCAN 遍历与模式匹配的键
+    pub async fn scan(&self, pattern: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+        let mut keys = Vec::new();
+        let mut cursor = b"0".to_vec();
+        loop {
+            let reply = self
+                .command(&[b"SCAN", &cursor, b"MATCH", pattern, b"COUNT", b"1000"])
+                .await?;
+            let Reply::Array(mut items) = reply else {
+                return Err("SCAN 应答格式错误".to_string());
+            };
+            if let (Some(Reply::Array(batch)), Some(Reply::Bulk(next))) = (items.pop(), items.pop())
+            {
+                keys.extend(batch.into_iter().filter_map(|key| match key {
+                    Reply::Bulk(key) => Some(key),
+                    _ => None,
+                }));
+                cursor = next;
+            } else {
+                return Err("SCAN 应答格式错误".to_string());
+            }
+            if cursor == b"0" {
+                return Ok(keys);
+            }
+        }
+    }
+
+    // SMEMBERS，集合不存在时为空
+    pub async fn smembers(&self, key: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+        match self.command(&[b"SMEMBERS", key]).await? {
+            Reply::Array(items) => Ok(items
+                .into_iter()
+                .filter_map(|item| match item {
+                    Reply::Bulk(value) => Some(value),
+                    _ => None,
+                })
+                .collect()),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    // DEL，返回删除的键数
+    pub async fn del(&self, keys: &[&[u8]]) -> Result<i64, String> {
+        let mut args: Vec<&[u8]> = vec![b"DEL"];
+        args.extend_from_slice(keys);
+        match self.command(&args).await? {
+            Reply::Int(n) => Ok(n),
+            _ => Ok(0),
+        }
+    }
+}
+
+// 在连接上发送命令并读取应答；外层错误表示连接已不可用，内层错误为 Redis 返回的错误应答
+async fn execute(
+    conn: &mut Connection,
+    commands: &[&[&[u8]]],
+) -> Result<Vec<Result<Reply, String>>, String> {
+    let mut request = Vec::new();
+    for args in commands {
+        request.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+        for arg in args.iter() {
+            request.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            request.extend_from_slice(arg);
+            request.extend_from_slice(b"\r\n");
+        }
+    }
+    conn.get_mut()
+        .write_all(&request)
+        .await
+        .map_err(|e| format!("Redis 写入失败: {}", e))?;
+    let mut replies = Vec::with_capacity(commands.len());
+    for _ in commands {
+        replies.push(read_reply(conn).await?);
+    }
+    Ok(replies)
+}
+
+// 读取一个 RESP 应答，数组应答递归读取其中的元素
+async fn read_reply(conn: &mut Connection) -> Result<Result<Reply, String>, String> {
+    let mut line = Vec::new();
+    let n = conn
+        .read_until(b'\n', &mut line)
+        .await
+        .map_err(|e| format!("Redis 读取失败: {}", e))?;
+    if n < 3 || !line.ends_with(b"\r\n") {
+        return Err("Redis 连接已关闭或应答不完整".to_string());
+    }
+    let text = String::from_utf8_lossy(&line[1..line.len() - 2]).to_string();
+    let number = || {
+        text.parse::<i64>()
+            .map_err(|_| format!("Redis 应答格式错误: {}", text))
+    };
+    let reply = match line[0] {
+        b'+' => Reply::Status,
+        b'-' => return Ok(Err(format!("Redis 返回错误: {}", text))),
+        b':' => Reply::Int(number()?),
+        b'$' => {
+            let Ok(len) = usize::try_from(number()?) else {
+                return Ok(Ok(Reply::Nil));
+            };
+            let mut value = vec![0; len + 2];
+            conn.read_exact(&mut value)
+                .await
+                .map_err(|e| format!("Redis 读取失败: {}", e))?;
+            value.truncate(len);
+            Reply::Bulk(value)
+        }
+        b'*' => {
+            let Ok(len) = usize::try_from(number()?) else {
+                return Ok(Ok(Reply::Nil));
+            };
+            let mut items = Vec::with_capacity(len);
+            let mut error = None;
+            for _ in 0..len {
+                match Box::pin(read_reply(conn)).await? {
+                    Ok(item) => items.push(item),
+                    Err(e) => error = Some(e),
+                }
+            }
+            if let Some(e) = error {
+                return Ok(Err(e));
+            }
+            Reply::Array(items)
+        }
+        _ => return Err(format!("Redis 应答格式错误: {}", text)),
+    };
+    Ok(Ok(reply))
+}
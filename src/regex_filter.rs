@@ -0,0 +1,106 @@
+// ==================== 响应体正则替换 ====================
+//
+// [routes.regex_filter] 对指定内容类型的响应体按正则表达式替换，用于临时的修补，
+// 如遮盖错误页中的内部主机名、去掉调试信息。规则按顺序执行，后面的规则作用于前面替换后的结果；
+// replace 中可以用 $1、${name} 引用捕获组。statuses 可以只处理部分状态码（如只处理错误页）；
+// 超过 max_body_kb 的响应体不做替换，避免大响应消耗 CPU。响应体由代理完整读取后再替换，
+// 匹配不受网络数据块边界的影响；和 sub_filter 一样，启用后发往上游的请求去掉 Accept-Encoding
+
+use regex::bytes::Regex;
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+// 响应体正则替换配置：对应配置文件中的 [routes.regex_filter]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RegexFilterConfig {
+    #[serde(default)]
+    pub rules: Vec<RegexRule>, // 替换规则，按顺序执行
+    #[serde(default = "default_types")]
+    pub types: Vec<String>, // 进行替换的内容类型，不含参数
+    #[serde(default)]
+    pub statuses: Vec<u16>, // 只处理这些状态码的响应，为空表示全部
+    #[serde(default = "default_max_body_kb")]
+    pub max_body_kb: u64, // 超过该大小的响应体原样转发
+}
+
+// 正则替换规则
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RegexRule {
+    pub pattern: String, // 正则表达式，如 "[a-z0-9-]+\\.internal\\.corp"
+    pub replace: String, // 替换为的内容，可以用 $1、${name} 引用捕获组
+}
+
+// 以下函数为响应体正则替换提供默认值
+fn default_types() -> Vec<String> {
+    vec![
+        "text/html".to_string(),
+        "text/plain".to_string(),
+        "application/json".to_string(),
+    ]
+}
+
+fn default_max_body_kb() -> u64 {
+    1024
+}
+
+// 编译后的正则替换规则
+#[derive(Debug)]
+pub struct RegexFilter {
+    rules: Vec<(Regex, String)>,
+    types: Vec<String>, // 小写
+    statuses: Vec<u16>,
+    max_body: usize,
+}
+
+impl RegexFilter {
+    // 从配置构建，正则表达式无效时启动失败
+    pub fn new(config: &RegexFilterConfig) -> Result<Self, String> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| {
+                Regex::new(&rule.pattern)
+                    .map(|regex| (regex, rule.replace.clone()))
+                    .map_err(|e| format!("无效的响应体替换正则 {}: {}", rule.pattern, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(RegexFilter {
+            rules,
+            types: config
+                .types
+                .iter()
+                .map(|t| t.trim().to_ascii_lowercase())
+                .collect(),
+            statuses: config.statuses.clone(),
+            max_body: (config.max_body_kb * 1024) as usize,
+        })
+    }
+
+    // 响应是否需要替换：状态码与内容类型匹配且没有压缩
+    pub fn applies(&self, status: StatusCode, headers: &HeaderMap) -> bool {
+        (self.statuses.is_empty() || self.statuses.contains(&status.as_u16()))
+            && !crate::sub_filter::encoded(headers)
+            && self.types.contains(&crate::sub_filter::media_type(headers))
+    }
+
+    // 替换响应体；响应体超过大小上限或没有任何匹配时返回 None
+    pub fn apply(&self, body: &[u8]) -> Option<Vec<u8>> {
+        if body.len() > self.max_body {
+            log::debug!(
+                "响应体 {} 字节超过正则替换上限 {} 字节，原样转发",
+                body.len(),
+                self.max_body
+            );
+            return None;
+        }
+        let mut out: Option<Vec<u8>> = None;
+        for (regex, replace) in &self.rules {
+            let current = out.as_deref().unwrap_or(body);
+            if regex.is_match(current) {
+                out = Some(regex.replace_all(current, replace.as_bytes()).into_owned());
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,278 @@
+// ==================== 配置热加载 ====================
+//
+// 收到 SIGHUP 或检测到配置文件修改时重新读取配置，不重启进程、不断开连接即可更新路由与上游、
+// 访问控制、并发限制、重试预算、截止时间、降载、Server-Timing 和日志级别。新配置的各个组件全部
+// 构建成功后才一起替换，任何一项无效都保留旧配置；正在处理的请求继续使用替换前的组件直到完成。
+// 配置没有变化的路由沿用原来的实例，其缓存、熔断器和幂等记录不受影响。
+// 监听地址、管理服务、访问日志等在启动时就已固定的配置修改后只记录警告，重启后生效
+
+use crate::AppState;
+use actix_web::web;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+// 热加载配置：对应配置文件中的 [reload]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReloadConfig {
+    #[serde(default = "default_watch")]
+    pub watch: bool, // 是否监视配置文件的修改，关闭后只响应 SIGHUP
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64, // 检查配置文件修改时间的间隔(毫秒)
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        ReloadConfig {
+            watch: default_watch(),
+            poll_interval_ms: default_poll_interval_ms(),
+        }
+    }
+}
+
+// 以下函数为热加载配置提供默认值
+fn default_watch() -> bool {
+    true
+}
+
+fn default_poll_interval_ms() -> u64 {
+    2000
+}
+
+// 可以在运行时整体替换的组件；读取时得到当前实例的引用计数，替换不影响已取得实例的请求
+#[derive(Debug)]
+pub struct Swap<T>(RwLock<Arc<T>>);
+
+impl<T> Swap<T> {
+    pub fn new(value: T) -> Self {
+        Swap(RwLock::new(Arc::new(value)))
+    }
+
+    // 当前实例
+    pub fn load(&self) -> Arc<T> {
+        self.0.read().unwrap().clone()
+    }
+
+    // 替换为新的实例
+    pub fn store(&self, value: T) {
+        *self.0.write().unwrap() = Arc::new(value);
+    }
+}
+
+// 启动热加载：监听 SIGHUP 与远程配置的变化，启用 watch 时定期检查配置文件（含 include 的文件）的修改时间
+pub fn start(state: web::Data<AppState>, config: &ReloadConfig) {
+    crate::remote::start(state.clone());
+    #[cfg(unix)]
+    {
+        let state = state.clone();
+        tokio::spawn(async move {
+            use tokio::signal::unix::{SignalKind, signal};
+            let mut hangup = match signal(SignalKind::hangup()) {
+                Ok(hangup) => hangup,
+                Err(e) => {
+                    log::warn!("无法监听 SIGHUP，只能通过修改配置文件触发热加载: {}", e);
+                    return;
+                }
+            };
+            while hangup.recv().await.is_some() {
+                log::info!("收到 SIGHUP，重新加载配置");
+                apply(&state).await;
+            }
+        });
+    }
+    if !config.watch {
+        return;
+    }
+    let interval = Duration::from_millis(config.poll_interval_ms.max(100));
+    tokio::spawn(async move {
+        let mut last = modified(&state.config.load().config_files);
+        loop {
+            tokio::time::sleep(interval).await;
+            let current = modified(&state.config.load().config_files);
+            if current == last {
+                continue;
+            }
+            last = current;
+            log::info!("配置文件已修改，重新加载配置");
+            apply(&state).await;
+        }
+    });
+}
+
+// 重新加载并记录结果，由 systemd 管理时同时通知重新加载的开始与结束
+pub async fn apply(state: &AppState) {
+    crate::systemd::reloading();
+    match reload(state).await {
+        Ok(()) => {
+            log::info!("配置已重新加载");
+            crate::systemd::reloaded("配置已重新加载");
+        }
+        Err(e) => {
+            log::error!("新配置无效，继续使用原配置: {}", e);
+            crate::systemd::reloaded("新配置无效，继续使用原配置");
+        }
+    }
+}
+
+// 主配置文件及其包含的文件的修改时间，文件不存在时为None
+fn modified(paths: &[String]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+// 读取配置并构建新的组件，全部成功后再替换
+pub async fn reload(state: &AppState) -> Result<(), String> {
+    let config = crate::load_config().await.map_err(|e| e.to_string())?;
+    let old = state.config.load();
+    if config.deprecations != old.deprecations {
+        for note in &config.deprecations {
+            log::warn!("{}", note);
+        }
+    }
+    let changed =
+        |a: &dyn std::fmt::Debug, b: &dyn std::fmt::Debug| format!("{:?}", a) != format!("{:?}", b);
+
+    // 1. 构建新的组件，任何一项失败都不替换
+    // 目标服务器的 proxy_url 同样影响客户端
+    let proxy = crate::upstream_proxy::UpstreamProxy::new(&config)?;
+    let proxy_changed = changed(&proxy, &crate::upstream_proxy::UpstreamProxy::new(&old)?);
+    let client = match changed(&config.request, &old.request) || proxy_changed {
+        true => Some(
+            crate::build_client(
+                &config.request,
+                &std::sync::Arc::new(proxy),
+                config.request.connect_timeout_ms.map(Duration::from_millis),
+            )
+            .map_err(|e| e.to_string())?,
+        ),
+        false => None,
+    };
+    let routes = crate::routes::RouteTable::rebuild(&config, &state.routes.load())?;
+    state.upstreams.apply(&routes); // 运行时注册与注销的上游对新的路由表同样生效
+    let acl = crate::acl::Acl::new(&config.acl)?;
+    let deadline = crate::deadline::DeadlinePolicy::new(&config.deadline)?;
+    let forwarded = crate::forwarded::ForwardedPolicy::new(&config.forwarded)?;
+    let via = crate::via::ViaPolicy::new(&config.via)?;
+    let hop_by_hop = crate::hop_by_hop::HopByHop::new(&config.hop_by_hop)?;
+    let compression = crate::compression::Compression::new(&config.compression)?;
+    let error_pages = crate::error_pages::ErrorPages::new(&config.error_pages)?;
+    let privacy = crate::privacy::Privacy::new(&config.privacy)?;
+    // 未设置 RUST_LOG 时日志级别跟随配置文件
+    let level = (std::env::var("RUST_LOG").is_err() && config.log.level != old.log.level)
+        .then(|| config.log.level.clone());
+    if let Some(level) = &level {
+        crate::log_level::validate(level)?;
+    }
+
+    // 2. 替换组件；有内部状态的组件只在配置变化时替换
+    if let Some(client) = client {
+        state.client.store(client);
+    }
+    state.routes.store(routes);
+    state.acl.store(acl);
+    state.deadline.store(deadline);
+    state.forwarded.store(forwarded);
+    state.via.store(via);
+    state.hop_by_hop.store(hop_by_hop);
+    state.compression.store(compression);
+    state.error_pages.store(error_pages);
+    state.privacy.store(privacy);
+    if changed(&config.concurrency, &old.concurrency) {
+        state
+            .limiter
+            .store(crate::limiter::ConcurrencyLimiter::new(&config.concurrency));
+    }
+    if changed(&config.retry_budget, &old.retry_budget) {
+        state
+            .budget
+            .store(crate::retry::RetryBudget::new(&config.retry_budget));
+    }
+    if changed(&config.load_shedding, &old.load_shedding) {
+        state
+            .shedder
+            .store(crate::shedding::LoadShedder::new(&config.load_shedding));
+    }
+    state.server_timing.store(config.server_timing.clone());
+    state.http10.store(config.http10.clone());
+    state.features.configure(&config.features);
+    if let Some(level) = &level {
+        crate::log_level::set_default(level)?;
+    }
+    for route in state.routes.load().iter() {
+        log::info!(
+            "路由 {}: {} -> {}",
+            route.name,
+            route.path_prefix,
+            route.targets_display()
+        );
+    }
+
+    // 3. 启动时已固定的配置只提示需要重启；管理接口的令牌可以热加载，不计入 [admin] 的修改
+    let admin = crate::admin::AdminConfig {
+        token: old.admin.token.clone(),
+        ..config.admin.clone()
+    };
+    let fixed: [(&str, &dyn std::fmt::Debug, &dyn std::fmt::Debug); 16] = [
+        ("server", &config.server, &old.server),
+        ("listeners", &config.listeners, &old.listeners),
+        ("forward_proxy", &config.forward_proxy, &old.forward_proxy),
+        ("admin", &admin, &old.admin),
+        ("audit", &config.audit, &old.audit),
+        ("metrics", &config.metrics, &old.metrics),
+        ("tracing", &config.tracing, &old.tracing),
+        ("access_log", &config.access_log, &old.access_log),
+        ("capture", &config.capture, &old.capture),
+        ("sentry", &config.sentry, &old.sentry),
+        ("reload", &config.reload, &old.reload),
+        ("remote_config", &config.remote_config, &old.remote_config),
+        ("upstreams", &config.upstreams, &old.upstreams),
+        ("log.sinks", &config.log.sinks, &old.log.sinks),
+        (
+            "log.slow_request_ms",
+            &config.log.slow_request_ms,
+            &old.log.slow_request_ms,
+        ),
+        (
+            "log.redact_headers",
+            &config.log.redact_headers,
+            &old.log.redact_headers,
+        ),
+    ];
+    for (section, new, old) in fixed {
+        if changed(new, old) {
+            log::warn!("配置 [{}] 的修改需要重启才能生效", section);
+        }
+    }
+    if config.admin.token != old.admin.token {
+        match config.admin.token {
+            Some(_) => log::info!("管理接口的访问令牌已更新"),
+            None => log::warn!("管理接口的访问令牌已去掉，任何能访问管理端口的客户端都可以调用"),
+        }
+    }
+    // 固定的配置保留启动时的值：其中按请求读取的项（如 server.title_case_headers）不会在重启前
+    // 部分生效，管理接口 /config 显示的也是实际运行的值
+    let mut config = config;
+    config.server = old.server.clone();
+    config.listeners = old.listeners.clone();
+    config.forward_proxy = old.forward_proxy.clone();
+    config.admin = crate::admin::AdminConfig {
+        token: config.admin.token.clone(),
+        ..old.admin.clone()
+    };
+    config.audit = old.audit.clone();
+    config.metrics = old.metrics.clone();
+    config.tracing = old.tracing.clone();
+    config.access_log = old.access_log.clone();
+    config.capture = old.capture.clone();
+    config.sentry = old.sentry.clone();
+    config.reload = old.reload.clone();
+    config.remote_config = old.remote_config.clone();
+    config.upstreams = old.upstreams.clone();
+    config.log.sinks = old.log.sinks.clone();
+    config.log.slow_request_ms = old.log.slow_request_ms;
+    config.log.redact_headers = old.log.redact_headers.clone();
+    state.config.store(config);
+    Ok(())
+}
@@ -0,0 +1,355 @@
+// ==================== 远程配置 ====================
+//
+// [remote_config] 从 Consul 或 etcd 的 KV 存储读取一个键，按 format 解析后覆盖本地配置文件
+// （环境变量与命令行参数仍然优先），用于集中管理多台代理的配置。启用 watch 时持续等待该键的变化：
+// Consul 使用阻塞查询，etcd 使用 v3 HTTP 网关的 watch 流，键被修改后立即热加载，不需要轮询。
+// 读取失败时沿用上次成功读取的内容；首次读取失败时 required 决定启动失败还是只使用本地配置
+
+use crate::{AppState, ProxyError};
+use actix_web::web;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use config::{Config, ConfigError, File, FileFormat, Map, Source, Value};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// 远程配置：对应配置文件中的 [remote_config]，只能写在本地配置文件或环境变量中
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RemoteConfig {
+    pub kind: RemoteKind, // KV 存储类型：consul 或 etcd
+    pub address: String,  // KV 存储的地址，如 http://127.0.0.1:8500
+    pub key: String,      // 保存配置内容的键，如 rust_proxy/config
+    #[serde(default = "default_format")]
+    pub format: String, // 配置内容的格式：toml、yaml 或 json
+    #[serde(default)]
+    pub token: Option<String>, // 访问令牌：Consul 的 ACL token 或 etcd 的认证 token
+    #[serde(default = "default_watch")]
+    pub watch: bool, // 是否监视键的变化并自动热加载
+    #[serde(default = "default_required")]
+    pub required: bool, // 启动时读取失败是否退出，关闭时只使用本地配置启动
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64, // 读取的超时(毫秒)
+    #[serde(default = "default_wait_secs")]
+    pub wait_secs: u64, // Consul 阻塞查询的最长等待时间(秒)，超时后重新发起
+    #[serde(default = "default_retry_interval_ms")]
+    pub retry_interval_ms: u64, // 监视出错后重试的间隔(毫秒)
+}
+
+// KV 存储类型
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RemoteKind {
+    Consul, // Consul KV（/v1/kv）
+    Etcd,   // etcd v3 HTTP 网关（/v3/kv/range 与 /v3/watch）
+}
+
+// 以下函数为远程配置提供默认值
+fn default_format() -> String {
+    "toml".to_string()
+}
+
+fn default_watch() -> bool {
+    true
+}
+
+fn default_required() -> bool {
+    true
+}
+
+fn default_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_wait_secs() -> u64 {
+    300
+}
+
+fn default_retry_interval_ms() -> u64 {
+    5000
+}
+
+// 最近一次读取的结果
+#[derive(Default)]
+struct Fetched {
+    content: Option<Map<String, Value>>, // 上次成功解析的配置，读取失败时沿用
+    version: u64,  // 键的修改版本：Consul 的 ModifyIndex，etcd 的 mod_revision
+    revision: u64, // 读取时 KV 存储的版本：Consul 的 X-Consul-Index，etcd 的 header.revision
+}
+
+static FETCHED: Mutex<Fetched> = Mutex::new(Fetched {
+    content: None,
+    version: 0,
+    revision: 0,
+});
+
+// 读取并解析远程配置；读取失败时沿用上次成功读取的内容
+pub async fn load(config: &RemoteConfig) -> Result<Map<String, Value>, ProxyError> {
+    let format = format(&config.format).map_err(error)?;
+    let client = reqwest::Client::new();
+    let fetched = match config.kind {
+        RemoteKind::Consul => fetch_consul(&client, config).await,
+        RemoteKind::Etcd => fetch_etcd(&client, config).await,
+    };
+    let (content, version, revision) = match fetched {
+        Ok(fetched) => fetched,
+        Err(e) => {
+            let message = format!("无法读取远程配置 {}: {}", config.key, e);
+            return match FETCHED.lock().unwrap().content.clone() {
+                Some(content) => {
+                    log::warn!("{}，沿用上次读取的内容", message);
+                    Ok(content)
+                }
+                None if !config.required => {
+                    log::warn!("{}，只使用本地配置", message);
+                    Ok(Map::new())
+                }
+                None => Err(error(message)),
+            };
+        }
+    };
+    // 先记录版本，内容无效时监视也不会反复加载同一个版本
+    {
+        let mut fetched = FETCHED.lock().unwrap();
+        fetched.version = version;
+        fetched.revision = revision;
+    }
+    let table = Config::builder()
+        .add_source(File::from_str(&content, format))
+        .build()
+        .and_then(|c| c.collect())
+        .map_err(|e| error(format!("远程配置 {} 无效: {}", config.key, e)))?;
+    if table.contains_key("include") {
+        return Err(error(format!("远程配置 {} 不支持 include", config.key)));
+    }
+    if table.contains_key("remote_config") {
+        return Err(error(format!(
+            "远程配置 {} 不能包含 [remote_config]",
+            config.key
+        )));
+    }
+    FETCHED.lock().unwrap().content = Some(table.clone());
+    log::debug!("已读取远程配置 {}（版本 {}）", config.key, version);
+    Ok(table)
+}
+
+fn format(format: &str) -> Result<FileFormat, String> {
+    match format.to_ascii_lowercase().as_str() {
+        "toml" => Ok(FileFormat::Toml),
+        "yaml" | "yml" => Ok(FileFormat::Yaml),
+        "json" => Ok(FileFormat::Json),
+        _ => Err(format!(
+            "remote_config.format 只支持 toml、yaml 或 json: {}",
+            format
+        )),
+    }
+}
+
+fn error(message: String) -> ProxyError {
+    ProxyError::ConfigError(ConfigError::Message(message))
+}
+
+// Consul 的一个键：GET /v1/kv/<key> 返回的数组元素
+#[derive(Deserialize)]
+struct ConsulEntry {
+    #[serde(rename = "ModifyIndex")]
+    modify_index: u64,
+    #[serde(rename = "Value")]
+    value: Option<String>, // base64 编码的内容，空值时为 null
+}
+
+// 读取 Consul 的键，index 不为 0 时为阻塞查询，KV 存储的版本超过 index 或等待超时后返回
+async fn consul_get(
+    client: &reqwest::Client,
+    config: &RemoteConfig,
+    index: u64,
+    timeout: Duration,
+) -> Result<(Option<ConsulEntry>, u64), String> {
+    let url = format!(
+        "{}/v1/kv/{}",
+        config.address.trim_end_matches('/'),
+        config.key.trim_start_matches('/')
+    );
+    let mut request = client.get(&url).timeout(timeout);
+    if index > 0 {
+        request = request.query(&[
+            ("index", index.to_string()),
+            ("wait", format!("{}s", config.wait_secs)),
+        ]);
+    }
+    if let Some(token) = &config.token {
+        request = request.header("X-Consul-Token", token);
+    }
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    let revision = response
+        .headers()
+        .get("X-Consul-Index")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok((None, revision));
+    }
+    let entries: Vec<ConsulEntry> = response
+        .error_for_status()
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok((entries.into_iter().next(), revision))
+}
+
+async fn fetch_consul(
+    client: &reqwest::Client,
+    config: &RemoteConfig,
+) -> Result<(String, u64, u64), String> {
+    let timeout = Duration::from_millis(config.timeout_ms);
+    let (entry, revision) = consul_get(client, config, 0, timeout).await?;
+    let entry = entry.ok_or("键不存在")?;
+    let content = decode(entry.value.as_deref().unwrap_or_default())?;
+    Ok((content, entry.modify_index, revision))
+}
+
+// etcd v3 HTTP 网关的请求，键与值都是 base64 编码，64 位整数以字符串表示
+async fn etcd_post(
+    client: &reqwest::Client,
+    config: &RemoteConfig,
+    path: &str,
+    body: serde_json::Value,
+    timeout: Option<Duration>,
+) -> Result<reqwest::Response, String> {
+    let url = format!("{}{}", config.address.trim_end_matches('/'), path);
+    let mut request = client.post(&url).json(&body);
+    if let Some(timeout) = timeout {
+        request = request.timeout(timeout);
+    }
+    if let Some(token) = &config.token {
+        request = request.header("Authorization", token);
+    }
+    request
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())
+}
+
+async fn fetch_etcd(
+    client: &reqwest::Client,
+    config: &RemoteConfig,
+) -> Result<(String, u64, u64), String> {
+    let timeout = Duration::from_millis(config.timeout_ms);
+    let body = serde_json::json!({ "key": STANDARD.encode(&config.key) });
+    let response: serde_json::Value =
+        etcd_post(client, config, "/v3/kv/range", body, Some(timeout))
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+    let number =
+        |value: &serde_json::Value| value.as_str().and_then(|v| v.parse().ok()).unwrap_or(0);
+    let revision = number(&response["header"]["revision"]);
+    let kv = response["kvs"].get(0).ok_or("键不存在")?;
+    let content = decode(kv["value"].as_str().unwrap_or_default())?;
+    Ok((content, number(&kv["mod_revision"]), revision))
+}
+
+fn decode(value: &str) -> Result<String, String> {
+    let bytes = STANDARD
+        .decode(value)
+        .map_err(|e| format!("无法解码键的值: {}", e))?;
+    String::from_utf8(bytes).map_err(|_| "键的值不是 UTF-8 文本".to_string())
+}
+
+// 启用 watch 时启动监视，键被修改后热加载配置
+pub fn start(state: web::Data<AppState>) {
+    let Some(config) = state.config.load().remote_config.clone() else {
+        return;
+    };
+    if !config.watch {
+        return;
+    }
+    log::info!(
+        "监视远程配置: {:?} {} {}",
+        config.kind,
+        config.address,
+        config.key
+    );
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        let retry = Duration::from_millis(config.retry_interval_ms.max(100));
+        loop {
+            let changed = match config.kind {
+                RemoteKind::Consul => watch_consul(&client, &config).await,
+                RemoteKind::Etcd => watch_etcd(&client, &config).await,
+            };
+            match changed {
+                Ok(true) => {
+                    log::info!("远程配置 {} 已修改，重新加载配置", config.key);
+                    crate::reload::apply(&state).await;
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    log::warn!("监视远程配置 {} 失败，稍后重试: {}", config.key, e);
+                    tokio::time::sleep(retry).await;
+                }
+            }
+        }
+    });
+}
+
+// 等待一次 Consul 阻塞查询返回，键的版本与上次读取的不同时返回 true
+async fn watch_consul(client: &reqwest::Client, config: &RemoteConfig) -> Result<bool, String> {
+    let index = FETCHED.lock().unwrap().revision.max(1);
+    // 超时比阻塞查询的等待时间长，Consul 会在等待时间上加少量随机抖动
+    let timeout = Duration::from_secs(config.wait_secs + config.wait_secs / 16 + 10);
+    let (entry, revision) = consul_get(client, config, index, timeout).await?;
+    let mut fetched = FETCHED.lock().unwrap();
+    // 版本变小说明 Consul 重建了数据，从头开始
+    fetched.revision = if revision < index { 0 } else { revision };
+    let entry = entry.ok_or("键不存在")?;
+    // 记录看到的版本，热加载失败时不会反复加载同一个版本
+    let changed = entry.modify_index != fetched.version;
+    fetched.version = entry.modify_index;
+    Ok(changed)
+}
+
+// 在 etcd 的 watch 流上等待键的修改事件，收到事件时返回 true
+async fn watch_etcd(client: &reqwest::Client, config: &RemoteConfig) -> Result<bool, String> {
+    let start = FETCHED.lock().unwrap().revision + 1;
+    let body = serde_json::json!({
+        "create_request": { "key": STANDARD.encode(&config.key), "start_revision": start.to_string() }
+    });
+    let mut response = etcd_post(client, config, "/v3/watch", body, None).await?;
+    let mut buffer = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        buffer.extend_from_slice(&chunk);
+        // 每个事件是一行 JSON
+        while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let Ok(message) = serde_json::from_slice::<serde_json::Value>(&line) else {
+                continue;
+            };
+            let result = &message["result"];
+            if result["canceled"].as_bool() == Some(true) {
+                // 起始版本已被压缩时重新读取一次，之后从新的版本开始监视
+                return match result["compact_revision"].as_str() {
+                    Some(_) => Ok(true),
+                    None => Err(format!(
+                        "watch 被取消: {}",
+                        result["cancel_reason"].as_str().unwrap_or_default()
+                    )),
+                };
+            }
+            if result["events"].as_array().is_some_and(|e| !e.is_empty()) {
+                // 记录看到的版本，热加载失败时不会反复收到同一个事件
+                let revision = result["header"]["revision"]
+                    .as_str()
+                    .and_then(|v| v.parse().ok());
+                let mut fetched = FETCHED.lock().unwrap();
+                fetched.revision = fetched.revision.max(revision.unwrap_or(start));
+                return Ok(true);
+            }
+        }
+    }
+    Err("watch 连接已断开".to_string())
+}
@@ -0,0 +1,75 @@
+// ==================== 请求ID ====================
+//
+// 每个请求使用客户端传入的 X-Request-Id，没有时生成UUID；请求ID会传给上游、写回响应头，
+// 并出现在处理该请求期间的每一行日志和每个错误响应体中，便于跨服务关联排查问题
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// 请求ID所在的请求头与响应头
+pub const HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+// 客户端传入的请求ID最大长度，超出时重新生成
+const MAX_LEN: usize = 128;
+
+thread_local! {
+    // 当前线程正在处理的请求ID，供日志格式化、错误响应和panic钩子读取
+    static CURRENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// 当前正在处理的请求ID，不在请求处理过程中时返回None
+pub fn current() -> Option<String> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+// 在poll内部future期间把请求ID设为当前请求ID
+struct Scoped<F> {
+    inner: Pin<Box<F>>,
+    id: String,
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let previous = CURRENT.with(|current| current.replace(Some(this.id.clone())));
+        let result = this.inner.as_mut().poll(cx);
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+        result
+    }
+}
+
+// 读取客户端传入的请求ID，缺失、为空或过长时生成新的UUID
+fn resolve(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(&HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= MAX_LEN)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+// 请求ID中间件：需要注册在其他业务中间件的外层
+pub async fn middleware(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let id = resolve(&req);
+    let value = HeaderValue::from_str(&id).expect("请求ID只包含可见ASCII字符");
+    // 写回请求头，转发时随其他请求头一起传给上游
+    req.headers_mut().insert(HEADER, value.clone());
+    let mut res = Scoped {
+        inner: Box::pin(next.call(req)),
+        id,
+    }
+    .await?;
+    res.headers_mut().insert(HEADER, value);
+    Ok(res)
+}
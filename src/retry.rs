@@ -0,0 +1,277 @@
+// ==================== 重试策略 ====================
+//
+// 对幂等方法在连接失败、超时或指定状态码(默认502/503/504)时重试，
+// 重试间隔按指数退避增长，并可叠加随机抖动以避免多个请求同时重试；
+// 全局重试预算限制滑动窗口内重试请求占总流量的比例，防止上游故障时被重试流量放大压垮
+
+use crate::ProxyError;
+use crate::deadline::{Deadline, DeadlinePolicy};
+use crate::routes::Route;
+use crate::timeouts;
+use rand::Rng;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// 重试配置，可在 [proxy.retry] 或每个路由的 [routes.retry] 中设置
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RetryConfig {
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32, // 最大尝试次数（包含首次请求）
+    #[serde(default = "default_base_delay_ms")]
+    pub base_delay_ms: u64, // 首次重试前的等待时间(毫秒)，之后每次翻倍
+    #[serde(default = "default_max_delay_ms")]
+    pub max_delay_ms: u64, // 单次等待时间上限(毫秒)
+    #[serde(default = "default_true")]
+    pub jitter: bool, // 是否在等待时间上叠加随机抖动
+    #[serde(default)]
+    pub per_try_timeout_ms: Option<u64>, // 单次尝试的超时时间(毫秒)，缺省使用全局超时
+    #[serde(default = "default_true")]
+    pub retry_on_connect_error: bool, // 连接失败时是否重试
+    #[serde(default = "default_true")]
+    pub retry_on_timeout: bool, // 超时时是否重试
+    #[serde(default = "default_retry_on_status")]
+    pub retry_on_status: Vec<u16>, // 触发重试的上游状态码
+}
+
+// 以下函数为重试配置提供默认值
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_base_delay_ms() -> u64 {
+    100
+}
+
+fn default_max_delay_ms() -> u64 {
+    2000
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_retry_on_status() -> Vec<u16> {
+    vec![502, 503, 504]
+}
+
+impl RetryConfig {
+    // 计算第attempt次失败后的等待时间：base * 2^(attempt-1)，不超过上限
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base_delay_ms
+            .saturating_mul(1u64 << (attempt - 1).min(32));
+        let delay = exp.min(self.max_delay_ms);
+        let delay = if self.jitter && delay > 0 {
+            // 在 [delay/2, delay] 范围内随机取值
+            rand::thread_rng().gen_range(delay / 2..=delay)
+        } else {
+            delay
+        };
+        Duration::from_millis(delay)
+    }
+}
+
+// ==================== 重试预算 ====================
+
+// 全局重试预算配置：对应配置文件中的 [retry_budget]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RetryBudgetConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool, // 是否启用重试预算
+    #[serde(default = "default_budget_ratio")]
+    pub ratio: f64, // 窗口内重试数占请求数的最大比例，如0.2表示20%
+    #[serde(default = "default_budget_window_secs")]
+    pub window_secs: u64, // 滑动窗口长度(秒)
+    #[serde(default = "default_min_retries_per_sec")]
+    pub min_retries_per_sec: u64, // 低流量时每秒始终允许的重试数
+}
+
+impl Default for RetryBudgetConfig {
+    fn default() -> Self {
+        RetryBudgetConfig {
+            enabled: true,
+            ratio: default_budget_ratio(),
+            window_secs: default_budget_window_secs(),
+            min_retries_per_sec: default_min_retries_per_sec(),
+        }
+    }
+}
+
+fn default_budget_ratio() -> f64 {
+    0.2
+}
+
+fn default_budget_window_secs() -> u64 {
+    10
+}
+
+fn default_min_retries_per_sec() -> u64 {
+    3
+}
+
+// 滑动窗口中的一个1秒桶
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    second: u64,   // 桶对应的Unix时间(秒)
+    requests: u64, // 该秒内的请求数
+    retries: u64,  // 该秒内的重试数
+}
+
+// 全局重试预算：按秒分桶统计请求数和重试数
+pub struct RetryBudget {
+    config: RetryBudgetConfig,
+    buckets: Mutex<Vec<Bucket>>,
+}
+
+impl RetryBudget {
+    pub fn new(config: &RetryBudgetConfig) -> Self {
+        let slots = config.window_secs.max(1) as usize;
+        RetryBudget {
+            config: config.clone(),
+            buckets: Mutex::new(vec![Bucket::default(); slots]),
+        }
+    }
+
+    // 取得当前秒对应的桶，过期的桶会被清零复用
+    fn with_current<R>(&self, f: impl FnOnce(&mut Bucket, &[Bucket]) -> R) -> R {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut buckets = self.buckets.lock().unwrap();
+        let slots = buckets.len() as u64;
+        let index = (now % slots) as usize;
+        if buckets[index].second != now {
+            buckets[index] = Bucket {
+                second: now,
+                ..Bucket::default()
+            };
+        }
+        // 复制一份窗口快照用于统计，再修改当前桶
+        let window: Vec<Bucket> = buckets
+            .iter()
+            .filter(|b| now.saturating_sub(b.second) < slots)
+            .copied()
+            .collect();
+        f(&mut buckets[index], &window)
+    }
+
+    // 记录一次请求（首次尝试）
+    fn record_request(&self) {
+        if self.config.enabled {
+            self.with_current(|bucket, _| bucket.requests += 1);
+        }
+    }
+
+    // 申请一次重试额度，超出预算时返回false
+    pub fn try_acquire(&self) -> bool {
+        if !self.config.enabled {
+            return true;
+        }
+        let ratio = self.config.ratio;
+        let floor = self.config.min_retries_per_sec * self.config.window_secs.max(1);
+        self.with_current(|bucket, window| {
+            let requests: u64 = window.iter().map(|b| b.requests).sum();
+            let retries: u64 = window.iter().map(|b| b.retries).sum();
+            let allowed = ((requests as f64 * ratio) as u64).max(floor);
+            if retries < allowed {
+                bucket.retries += 1;
+                true
+            } else {
+                false
+            }
+        })
+    }
+}
+
+// 幂等方法才允许重试，避免重复提交POST等请求
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE | Method::TRACE
+    )
+}
+
+// 按路由的重试策略发送请求，返回最后一次尝试的结果；每次尝试都按当时的剩余时间设置超时与
+// 传给上游的截止时间，单次超时不超过剩余时间，剩余时间不够等待退避间隔时不再重试
+pub async fn send(
+    route: &Route,                    // 匹配的路由，提供重试策略与超时设置
+    budget: &RetryBudget,             // 全局重试预算
+    method: &Method,                  // 请求方法
+    request: reqwest::RequestBuilder, // 构建好的代理请求
+    deadlines: &DeadlinePolicy,       // 截止时间策略
+    deadline: &Deadline,              // 请求截止时间
+) -> Result<reqwest::Response, ProxyError> {
+    budget.record_request();
+    let header_timeout = route.timeouts.header;
+    let Some(policy) = route.retry.as_ref().filter(|_| is_idempotent(method)) else {
+        return timeouts::send(deadlines.apply(deadline, request), header_timeout).await;
+    };
+
+    let mut attempt = 1;
+    loop {
+        // 请求体为内存中的字节，可以安全地复制；无法复制时退化为不重试
+        let Some(current) = request.try_clone() else {
+            return timeouts::send(deadlines.apply(deadline, request), header_timeout).await;
+        };
+        let mut current = deadlines.apply(deadline, current);
+        if let Some(ms) = policy.per_try_timeout_ms {
+            current = current.timeout(Duration::from_millis(ms).min(deadline.remaining()));
+        }
+
+        let result = timeouts::send(current, header_timeout).await;
+        let reason = match &result {
+            Ok(resp) if policy.retry_on_status.contains(&resp.status().as_u16()) => {
+                Some(format!("状态码 {}", resp.status()))
+            }
+            Err(ProxyError::RequestError(e)) if policy.retry_on_connect_error && e.is_connect() => {
+                Some(format!("连接失败: {}", e))
+            }
+            // 自行建立连接的请求（PROXY 协议、FastCGI、uwsgi）
+            Err(ProxyError::UpstreamConnectFailed(detail)) if policy.retry_on_connect_error => {
+                Some(format!("连接失败: {}", detail))
+            }
+            Err(ProxyError::RequestError(e)) if policy.retry_on_timeout && e.is_timeout() => {
+                Some("请求超时".to_string())
+            }
+            Err(ProxyError::UpstreamTimeout(detail)) if policy.retry_on_timeout => {
+                Some(detail.clone())
+            }
+            _ => None,
+        };
+
+        match reason {
+            Some(reason) if attempt < policy.max_attempts => {
+                // 先确认剩余时间足够等待退避，确定会重试时才占用重试预算
+                let delay = policy.backoff(attempt);
+                if deadline.remaining() <= delay {
+                    log::warn!(
+                        "第{}次请求失败({})，剩余时间不足以等待重试，不再重试",
+                        attempt,
+                        reason
+                    );
+                    return result;
+                }
+                if !budget.try_acquire() {
+                    log::warn!(
+                        "第{}次请求失败({})，重试预算已耗尽，不再重试",
+                        attempt,
+                        reason
+                    );
+                    return result;
+                }
+                log::warn!(
+                    "第{}次请求失败({})，{}毫秒后重试",
+                    attempt,
+                    reason,
+                    delay.as_millis()
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            _ => return result,
+        }
+    }
+}
@@ -0,0 +1,148 @@
+// ==================== 滚动日志文件 ====================
+//
+// 追加写入的日志文件，按大小或时间滚动：当前文件重命名为 `<路径>.<时间戳>` 后重新创建，
+// 并只保留最近的若干个历史文件，进程可以长期运行而无需外部的日志切割工具
+
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// 按时间滚动的周期
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RotateInterval {
+    #[default]
+    Never, // 不按时间滚动
+    Hourly,
+    Daily,
+}
+
+impl RotateInterval {
+    // 当前时间所在的周期，周期变化时触发滚动
+    fn period(&self) -> Option<String> {
+        let now = chrono::Local::now();
+        match self {
+            RotateInterval::Never => None,
+            RotateInterval::Hourly => Some(now.format("%Y%m%d%H").to_string()),
+            RotateInterval::Daily => Some(now.format("%Y%m%d").to_string()),
+        }
+    }
+}
+
+// 滚动配置，嵌入到各个日志配置中
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RotationConfig {
+    #[serde(default)]
+    pub max_size_mb: Option<u64>, // 单个文件的最大大小(MB)，超过后滚动
+    #[serde(default)]
+    pub rotate: RotateInterval, // 按时间滚动的周期: never、hourly、daily
+    #[serde(default = "default_max_files")]
+    pub max_files: usize, // 保留的历史文件数
+}
+
+impl Default for RotationConfig {
+    fn default() -> Self {
+        RotationConfig {
+            max_size_mb: None,
+            rotate: RotateInterval::Never,
+            max_files: default_max_files(),
+        }
+    }
+}
+
+fn default_max_files() -> usize {
+    7
+}
+
+// 打开中的文件及其状态
+struct State {
+    file: File,
+    size: u64,              // 当前文件大小
+    period: Option<String>, // 当前文件所属的时间周期
+}
+
+// 滚动日志文件
+pub struct RotatingFile {
+    path: PathBuf,
+    config: RotationConfig,
+    state: Mutex<State>,
+}
+
+impl RotatingFile {
+    // 打开（或创建）日志文件
+    pub fn open(path: &str, config: &RotationConfig) -> io::Result<Self> {
+        let path = PathBuf::from(path);
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            config: config.clone(),
+            state: Mutex::new(State {
+                file,
+                size,
+                period: config.rotate.period(),
+            }),
+        })
+    }
+
+    // 写入一行，必要时先滚动
+    pub fn write_line(&self, line: &str) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let len = line.len() as u64 + 1;
+        let period = self.config.rotate.period();
+        let too_large = self
+            .config
+            .max_size_mb
+            .is_some_and(|mb| state.size > 0 && state.size + len > mb * 1024 * 1024);
+        if too_large || period != state.period {
+            self.rotate(&mut state)?;
+            state.period = period;
+        }
+        state.file.write_all(format!("{}\n", line).as_bytes())?;
+        state.size += len;
+        Ok(())
+    }
+
+    // 把当前文件重命名为带时间戳的历史文件，重新创建当前文件并清理过期的历史文件
+    fn rotate(&self, state: &mut State) -> io::Result<()> {
+        state.file.flush()?;
+        let suffix = chrono::Local::now().format("%Y%m%d-%H%M%S%.3f");
+        let rotated = PathBuf::from(format!("{}.{}", self.path.display(), suffix));
+        fs::rename(&self.path, &rotated)?;
+        state.file = open_append(&self.path)?;
+        state.size = 0;
+        self.prune()
+    }
+
+    // 只保留最近的 max_files 个历史文件
+    fn prune(&self) -> io::Result<()> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let Some(name) = self.path.file_name().and_then(|n| n.to_str()) else {
+            return Ok(());
+        };
+        let prefix = format!("{}.", name);
+        let mut rotated: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+            .map(|entry| entry.path())
+            .collect();
+        // 时间戳后缀按字典序即按时间排序
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(self.config.max_files);
+        for old in &rotated[..excess] {
+            if let Err(e) = fs::remove_file(old) {
+                log::warn!("删除历史日志文件 {} 失败: {}", old.display(), e);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
@@ -0,0 +1,468 @@
+// ==================== 路由表 ====================
+//
+// 每个路由由路径前缀匹配，可以指定独立的目标服务器（多个时轮询）、重试和对冲策略；
+// 未配置 [[routes]] 时，由 [proxy] 与 [target] 生成一条名为 default 的路由，保持原有行为
+
+use crate::breaker::{BreakerConfig, CircuitBreaker};
+use crate::cache::{CacheConfig, ResponseCache};
+use crate::compose::{Compose, ComposeConfig};
+use crate::content_filter::{ContentFilter, ContentTypesConfig};
+use crate::cookies::{CookieRewrite, CookieRewriteConfig};
+use crate::decompression::{Decompression, DecompressionConfig};
+use crate::esi::{Esi, EsiConfig};
+use crate::fallback::{Fallback, FallbackConfig};
+use crate::fanout::FanoutConfig;
+use crate::fastcgi::{FastCgi, FastCgiConfig};
+use crate::graphql::{Graphql, GraphqlConfig};
+use crate::grpc_health::{GrpcHealth, GrpcHealthConfig};
+use crate::header_rules::{HeaderRules, HeaderRulesConfig};
+use crate::hedge::{HedgeConfig, LatencyWindow};
+use crate::idempotency::{IdempotencyConfig, IdempotencyStore};
+use crate::json_transform::{JsonTransform, JsonTransformConfig};
+use crate::location::{LocationRewrite, LocationRewriteConfig};
+use crate::method_override::{MethodOverride, MethodOverrideConfig};
+use crate::openapi::{OpenApi, OpenApiConfig};
+use crate::regex_filter::{RegexFilter, RegexFilterConfig};
+use crate::reload::Swap;
+use crate::retry::RetryConfig;
+use crate::schema::{BodySchema, SchemaConfig};
+use crate::sub_filter::{SubFilter, SubFilterConfig};
+use crate::timeouts::{TimeoutConfig, Timeouts};
+use crate::upstream_proxy::UpstreamProxy;
+use crate::uwsgi::{Uwsgi, UwsgiConfig};
+use crate::xml_gateway::{XmlGateway, XmlGatewayConfig};
+use crate::{AppConfig, TargetConfig};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// 路由配置：对应配置文件中的 [[routes]]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RouteConfig {
+    pub name: String,        // 路由名称，用于日志和审计
+    pub path_prefix: String, // 匹配的URL路径前缀
+    #[serde(default)]
+    pub target: Option<TargetConfig>, // 目标服务器，缺省使用全局 [target]
+    #[serde(default)]
+    pub targets: Vec<TargetConfig>, // 多个目标服务器，按轮询选择，配置后忽略target
+    #[serde(default)]
+    pub retry: Option<RetryConfig>, // 重试策略，缺省不重试
+    #[serde(default)]
+    pub hedge: Option<HedgeConfig>, // 对冲策略，需要至少两个目标服务器
+    #[serde(default)]
+    pub fanout: Option<FanoutConfig>, // 把请求复制给全部目标服务器，返回汇总的结果
+    #[serde(default)]
+    pub compose: Option<ComposeConfig>, // 并发发出多个子请求，按模板组合为一个响应
+    #[serde(default)]
+    pub timeouts: Option<TimeoutConfig>, // 超时覆盖，缺省使用 [request] 中的全局值
+    #[serde(default)]
+    pub failover_target: Option<TargetConfig>, // 主目标不可达或熔断时使用的备用目标
+    #[serde(default)]
+    pub proxy_url: Option<String>, // 覆盖 [request] 的上游代理，"direct" 表示直接连接
+    #[serde(default)]
+    pub send_proxy_protocol: bool, // 连接上游时先发送 PROXY 协议 v2 头，传递客户端地址
+    #[serde(default)]
+    pub fastcgi: Option<FastCgiConfig>, // protocol 为 fastcgi 的目标使用的脚本路径与参数
+    #[serde(default)]
+    pub uwsgi: Option<UwsgiConfig>, // protocol 为 uwsgi 的目标使用的挂载路径与参数
+    #[serde(default)]
+    pub circuit_breaker: Option<BreakerConfig>, // 熔断策略，缺省不熔断
+    #[serde(default)]
+    pub grpc_health_check: Option<GrpcHealthConfig>, // 就绪检查与启动探测改用 gRPC 健康检查
+    #[serde(default)]
+    pub fallback: Option<FallbackConfig>, // 上游出错时的降级响应
+    #[serde(default)]
+    pub idempotency: Option<IdempotencyConfig>, // 按幂等键合并重复请求，缺省不启用
+    #[serde(default)]
+    pub cache: Option<CacheConfig>, // GET/HEAD 响应缓存，缺省不启用
+    #[serde(default)]
+    pub host_header: HostHeader, // 发往上游的 Host 请求头
+    #[serde(default)]
+    pub request_headers: Option<HeaderRulesConfig>, // 发往上游的请求头的增删改规则
+    #[serde(default)]
+    pub response_headers: Option<HeaderRulesConfig>, // 返回客户端的响应头的增删改规则
+    #[serde(default)]
+    pub location_rewrite: LocationRewriteConfig, // 指向目标服务器的 Location 的改写，缺省启用
+    #[serde(default)]
+    pub cookie_rewrite: Option<CookieRewriteConfig>, // Set-Cookie 中 Domain 与 Path 的改写
+    #[serde(default)]
+    pub sub_filter: Option<SubFilterConfig>, // 文本响应体中的字符串替换
+    #[serde(default)]
+    pub json_transform: Option<JsonTransformConfig>, // JSON 响应体的字段改写
+    #[serde(default)]
+    pub regex_filter: Option<RegexFilterConfig>, // 响应体的正则替换
+    #[serde(default)]
+    pub request_decompression: Option<DecompressionConfig>, // 转发前解压请求体
+    #[serde(default)]
+    pub request_schema: Option<SchemaConfig>, // 请求体的 JSON Schema 校验
+    #[serde(default)]
+    pub openapi: Option<OpenApiConfig>, // 按 OpenAPI 描述校验请求与响应
+    #[serde(default)]
+    pub graphql: Option<GraphqlConfig>, // GraphQL 操作的允许范围与上限
+    #[serde(default)]
+    pub content_types: Option<ContentTypesConfig>, // 按内容类型拒绝请求与响应
+    #[serde(default)]
+    pub xml_gateway: Option<XmlGatewayConfig>, // JSON 与 XML 之间的转换
+    #[serde(default)]
+    pub method_override: Option<MethodOverrideConfig>, // 按 X-HTTP-Method-Override 改写 POST 请求的方法
+    #[serde(default)]
+    pub esi: Option<EsiConfig>, // 展开 HTML 响应中的 ESI 标签
+}
+
+// 发往上游的 Host 请求头
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HostHeader {
+    #[default]
+    Rewrite, // 改写为目标服务器的地址
+    Preserve, // 保留客户端请求的 Host，适合按虚拟主机区分站点的上游
+}
+
+// 运行时路由
+#[derive(Debug)]
+pub struct Route {
+    pub name: String,                                 // 路由名称
+    pub path_prefix: String,                          // 规范化后的路径前缀（不含末尾的/）
+    pub configured: Vec<TargetConfig>,                // 配置中的目标服务器
+    targets: Swap<Vec<TargetConfig>>, // 生效的目标服务器（至少一个），含管理接口的增减
+    pub retry: Option<RetryConfig>,   // 重试策略
+    pub hedge: Option<HedgeConfig>,   // 对冲策略
+    pub fanout: Option<FanoutConfig>, // 请求广播
+    pub compose: Option<Compose>,     // 响应组合
+    pub timeouts: Timeouts,           // 合并后的超时设置
+    pub client: Option<Client>,       // 连接超时与全局不同时使用的独立HTTP客户端
+    pub failover: Option<TargetConfig>, // 故障转移目标
+    pub send_proxy_protocol: bool,    // 是否向上游发送 PROXY 协议头
+    pub fastcgi: FastCgi,             // FastCGI 目标的设置，没有配置时使用默认值
+    pub uwsgi: Uwsgi,                 // uwsgi 目标的设置，没有配置时使用默认值
+    pub breaker: CircuitBreaker,      // 主目标的熔断器
+    pub grpc_health: Option<GrpcHealth>, // gRPC 健康检查，缺省只检查 TCP 连接
+    pub fallback: Option<Fallback>,   // 降级响应
+    pub idempotency: Option<IdempotencyStore>, // 幂等键去重记录
+    pub cache: Option<ResponseCache>, // 响应缓存
+    pub host_header: HostHeader,      // 发往上游的 Host 请求头
+    pub request_headers: Option<HeaderRules>, // 请求头规则
+    pub response_headers: Option<HeaderRules>, // 响应头规则
+    pub location_rewrite: LocationRewrite, // Location 改写
+    pub cookie_rewrite: Option<CookieRewrite>, // Cookie 改写
+    pub sub_filter: Option<SubFilter>, // 响应体替换
+    pub json_transform: Option<JsonTransform>, // JSON 响应改写
+    pub regex_filter: Option<RegexFilter>, // 响应体正则替换
+    pub request_decompression: Option<Decompression>, // 请求体解压
+    pub request_schema: Option<BodySchema>, // 请求体校验
+    pub openapi: Option<OpenApi>,     // OpenAPI 校验
+    pub graphql: Option<Graphql>,     // GraphQL 检查
+    pub content_types: Option<ContentFilter>, // 内容类型过滤
+    pub xml_gateway: Option<XmlGateway>, // XML 网关
+    pub method_override: Option<MethodOverride>, // 方法覆盖
+    pub esi: Option<Esi>,             // ESI 片段组装
+    pub latency: LatencyWindow,       // 近期请求延迟，用于计算对冲等待时间
+    next: AtomicUsize,                // 轮询计数器
+    fingerprint: String,              // 构建该路由的配置，热加载时判断路由是否变化
+}
+
+impl Route {
+    // 判断请求路径是否属于该路由：与前缀相同，或以"前缀/"开头
+    fn matches(&self, path: &str) -> bool {
+        match path.strip_prefix(&self.path_prefix) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/') || self.path_prefix.is_empty(),
+            None => false,
+        }
+    }
+
+    // 当前生效的目标服务器；一个请求内使用同一份列表，不受运行时增减的影响
+    pub fn targets(&self) -> Arc<Vec<TargetConfig>> {
+        self.targets.load()
+    }
+
+    // 替换生效的目标服务器
+    pub fn set_targets(&self, targets: Vec<TargetConfig>) {
+        self.targets.store(targets);
+    }
+
+    // 从 targets 中轮询选择一个目标服务器，返回其下标
+    pub fn pick_target(&self, targets: &[TargetConfig]) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed) % targets.len()
+    }
+
+    // 选择与给定下标不同的另一个目标服务器（只有一个时返回None）
+    pub fn other_target(&self, targets: &[TargetConfig], index: usize) -> Option<usize> {
+        (targets.len() > 1).then(|| (index + 1) % targets.len())
+    }
+
+    // 是否改写响应体，改写需要未压缩的响应
+    pub fn transforms_body(&self) -> bool {
+        self.sub_filter.is_some()
+            || self.json_transform.is_some()
+            || self.regex_filter.is_some()
+            || self.xml_gateway.is_some()
+            || self.esi.is_some()
+            || self.compose.is_some()
+    }
+
+    // 所有目标服务器的基础URL，用于日志
+    pub fn targets_display(&self) -> String {
+        self.targets()
+            .iter()
+            .map(|t| t.base_url())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+// 路由表，按前缀长度从长到短排列，保证最长前缀优先匹配
+#[derive(Debug)]
+pub struct RouteTable {
+    routes: Vec<Arc<Route>>,
+}
+
+impl RouteTable {
+    // 根据应用配置构建路由表
+    pub fn new(config: &AppConfig) -> Result<Self, String> {
+        Self::build(config, None)
+    }
+
+    // 热加载时构建新的路由表，配置没有变化的路由沿用旧路由表中的实例（保留缓存、熔断器等状态）
+    pub fn rebuild(config: &AppConfig, old: &RouteTable) -> Result<Self, String> {
+        Self::build(config, Some(old))
+    }
+
+    fn build(config: &AppConfig, old: Option<&RouteTable>) -> Result<Self, String> {
+        let configs = if config.routes.is_empty() {
+            vec![RouteConfig {
+                name: "default".to_string(),
+                path_prefix: config.proxy.path_prefix.clone(),
+                retry: config.proxy.retry.clone(),
+                ..RouteConfig::default()
+            }]
+        } else {
+            config.routes.clone()
+        };
+
+        let mut routes: Vec<Arc<Route>> = Vec::with_capacity(configs.len());
+        for route in configs {
+            if !route.path_prefix.is_empty() && !route.path_prefix.starts_with('/') {
+                return Err(format!(
+                    "路由 {} 的路径前缀必须以/开头: {}",
+                    route.name, route.path_prefix
+                ));
+            }
+            if routes.iter().any(|r| r.name == route.name) {
+                return Err(format!("路由名称重复: {}", route.name));
+            }
+            // 路由依赖全局的 [target] 与 [request]，三者都没有变化时才沿用；Schema、OpenAPI 与 XML 模板文件的内容同样计入
+            let mut fingerprint = format!("{:?}{:?}{:?}", route, config.target, config.request);
+            let files = [
+                route.request_schema.as_ref().map(|s| &s.file),
+                route.openapi.as_ref().map(|o| &o.file),
+                route
+                    .xml_gateway
+                    .as_ref()
+                    .and_then(|g| g.request_file.as_ref()),
+            ];
+            for file in files.into_iter().flatten() {
+                fingerprint.push_str(&std::fs::read_to_string(file).unwrap_or_default());
+            }
+            if let Some(existing) = old
+                .into_iter()
+                .flat_map(|old| old.iter())
+                .find(|r| r.name == route.name && r.fingerprint == fingerprint)
+            {
+                routes.push(existing.clone());
+                continue;
+            }
+            let targets = if route.targets.is_empty() {
+                vec![route.target.unwrap_or_else(|| config.target.clone())]
+            } else {
+                route.targets
+            };
+            if route.hedge.is_some() && targets.len() < 2 {
+                log::warn!("路由 {} 只有一个目标服务器，对冲策略不会生效", route.name);
+            }
+            if route.fanout.is_some() && route.compose.is_some() {
+                return Err(format!(
+                    "路由 {}: fanout 与 compose 不能同时配置",
+                    route.name
+                ));
+            }
+            if route.fanout.is_some() && (route.hedge.is_some() || route.failover_target.is_some())
+            {
+                log::warn!("路由 {} 广播请求，对冲与故障转移不会生效", route.name);
+            }
+            // 连接超时与上游代理只能在客户端级别设置，路由覆盖时为其单独构建客户端
+            let timeouts = Timeouts::resolve(&config.request, route.timeouts.as_ref());
+            let connect_ms = route.timeouts.as_ref().and_then(|t| t.connect_ms);
+            let client = match (connect_ms, &route.proxy_url) {
+                (None, None) => None,
+                _ => {
+                    let proxy = UpstreamProxy::new(config)?
+                        .for_route(route.proxy_url.as_deref())
+                        .map_err(|e| format!("路由 {}: {}", route.name, e))?;
+                    Some(
+                        crate::build_client(&config.request, &Arc::new(proxy), timeouts.connect)
+                            .map_err(|e| {
+                                format!("路由 {} 的HTTP客户端构建失败: {}", route.name, e)
+                            })?,
+                    )
+                }
+            };
+            let grpc_health = route
+                .grpc_health_check
+                .as_ref()
+                .map(|check| {
+                    GrpcHealth::new(
+                        check,
+                        targets.iter().chain(route.failover_target.as_ref()),
+                        config.request.accept_invalid_certs,
+                    )
+                })
+                .transpose()
+                .map_err(|e| format!("路由 {}: {}", route.name, e))?;
+            routes.push(Arc::new(Route {
+                path_prefix: route.path_prefix.trim_end_matches('/').to_string(),
+                targets: Swap::new(targets.clone()),
+                configured: targets,
+                retry: route.retry,
+                hedge: route.hedge,
+                fanout: route.fanout,
+                compose: route
+                    .compose
+                    .as_ref()
+                    .map(Compose::new)
+                    .transpose()
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                timeouts,
+                client,
+                failover: route.failover_target,
+                send_proxy_protocol: route.send_proxy_protocol,
+                fastcgi: FastCgi::new(&route.fastcgi.clone().unwrap_or_default())
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                uwsgi: Uwsgi::new(&route.uwsgi.clone().unwrap_or_default())
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                breaker: CircuitBreaker::new(&route.name, route.circuit_breaker),
+                grpc_health,
+                fallback: route
+                    .fallback
+                    .as_ref()
+                    .map(Fallback::new)
+                    .transpose()
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                idempotency: route.idempotency.as_ref().map(IdempotencyStore::new),
+                cache: route
+                    .cache
+                    .as_ref()
+                    .filter(|cache| cache.enabled)
+                    .map(|cache| ResponseCache::new(&route.name, cache))
+                    .transpose()
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                host_header: route.host_header,
+                request_headers: route
+                    .request_headers
+                    .as_ref()
+                    .map(HeaderRules::new)
+                    .transpose()
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                response_headers: route
+                    .response_headers
+                    .as_ref()
+                    .map(HeaderRules::new)
+                    .transpose()
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                location_rewrite: LocationRewrite::new(&route.location_rewrite)
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                cookie_rewrite: route
+                    .cookie_rewrite
+                    .as_ref()
+                    .map(CookieRewrite::new)
+                    .transpose()
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                sub_filter: route
+                    .sub_filter
+                    .as_ref()
+                    .map(SubFilter::new)
+                    .transpose()
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                json_transform: route
+                    .json_transform
+                    .as_ref()
+                    .map(JsonTransform::new)
+                    .transpose()
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                regex_filter: route
+                    .regex_filter
+                    .as_ref()
+                    .map(RegexFilter::new)
+                    .transpose()
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                request_decompression: route.request_decompression.as_ref().map(Decompression::new),
+                request_schema: route
+                    .request_schema
+                    .as_ref()
+                    .map(BodySchema::new)
+                    .transpose()
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                openapi: route
+                    .openapi
+                    .as_ref()
+                    .map(OpenApi::new)
+                    .transpose()
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                graphql: route
+                    .graphql
+                    .as_ref()
+                    .map(Graphql::new)
+                    .transpose()
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                content_types: route
+                    .content_types
+                    .as_ref()
+                    .map(ContentFilter::new)
+                    .transpose()
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                xml_gateway: route
+                    .xml_gateway
+                    .as_ref()
+                    .map(XmlGateway::new)
+                    .transpose()
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                method_override: route
+                    .method_override
+                    .as_ref()
+                    .map(MethodOverride::new)
+                    .transpose()
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                esi: route
+                    .esi
+                    .as_ref()
+                    .map(Esi::new)
+                    .transpose()
+                    .map_err(|e| format!("路由 {}: {}", route.name, e))?,
+                latency: LatencyWindow::default(),
+                next: AtomicUsize::new(0),
+                fingerprint,
+                name: route.name,
+            }));
+        }
+        routes.sort_by_key(|r| std::cmp::Reverse(r.path_prefix.len()));
+        Ok(RouteTable { routes })
+    }
+
+    // 查找与请求路径匹配的路由
+    pub fn find(&self, path: &str) -> Option<Arc<Route>> {
+        self.find_in(path, &[])
+    }
+
+    // 只在名称属于 scope 的路由中查找，scope 为空时查找所有路由
+    pub fn find_in(&self, path: &str, scope: &[String]) -> Option<Arc<Route>> {
+        self.routes
+            .iter()
+            .find(|r| (scope.is_empty() || scope.contains(&r.name)) && r.matches(path))
+            .cloned()
+    }
+
+    // 遍历所有路由
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Route>> {
+        self.routes.iter()
+    }
+}
@@ -0,0 +1,94 @@
+// ==================== 请求体校验 ====================
+//
+// 路由的 [routes.request_schema] 指定一个 JSON Schema 文件，methods 中的请求在转发之前校验请求体，
+// 不是合法的 JSON 或不符合 Schema 的请求直接返回 400，响应中列出每个问题的位置与原因，
+// 不再到达上游；没有输入校验的旧服务因此在边缘获得校验。校验在缓存、幂等与并发限制之前进行，
+// 被拒绝的请求不占用并发许可。Schema 在启动与热加载时编译，文件内容变化后热加载即重新编译
+
+use crate::ProxyError;
+use actix_web::http::Method;
+use serde::{Deserialize, Serialize};
+
+// 请求体校验配置：对应配置文件中的 [routes.request_schema]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SchemaConfig {
+    pub file: String, // JSON Schema 文件路径
+    #[serde(default = "default_methods")]
+    pub methods: Vec<String>, // 校验请求体的方法
+    #[serde(default = "default_max_errors")]
+    pub max_errors: usize, // 400 响应中最多列出的问题数
+}
+
+// 以下函数为请求体校验提供默认值
+fn default_methods() -> Vec<String> {
+    vec!["POST".to_string(), "PUT".to_string(), "PATCH".to_string()]
+}
+
+fn default_max_errors() -> usize {
+    10
+}
+
+// 编译后的请求体校验
+pub struct BodySchema {
+    validator: jsonschema::Validator,
+    methods: Vec<Method>,
+    max_errors: usize,
+}
+
+impl std::fmt::Debug for BodySchema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BodySchema")
+            .field("methods", &self.methods)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BodySchema {
+    // 读取并编译 Schema，文件不存在、不是 JSON 或不是合法的 Schema 时启动失败
+    pub fn new(config: &SchemaConfig) -> Result<Self, String> {
+        let text = std::fs::read_to_string(&config.file)
+            .map_err(|e| format!("无法读取 JSON Schema 文件 {}: {}", config.file, e))?;
+        let schema: serde_json::Value = serde_json::from_str(&text)
+            .map_err(|e| format!("JSON Schema 文件 {} 不是合法的 JSON: {}", config.file, e))?;
+        let validator = jsonschema::validator_for(&schema)
+            .map_err(|e| format!("无效的 JSON Schema {}: {}", config.file, e))?;
+        let methods = config
+            .methods
+            .iter()
+            .map(|m| {
+                Method::from_bytes(m.trim().to_ascii_uppercase().as_bytes())
+                    .map_err(|_| format!("无效的请求方法: {}", m))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(BodySchema {
+            validator,
+            methods,
+            max_errors: config.max_errors.max(1),
+        })
+    }
+
+    // 校验请求体，问题以“位置: 原因”列出，位置为 JSON Pointer
+    pub fn check(&self, method: &Method, body: &[u8]) -> Result<(), ProxyError> {
+        if !self.methods.contains(method) {
+            return Ok(());
+        }
+        let instance: serde_json::Value = serde_json::from_slice(body)
+            .map_err(|e| ProxyError::InvalidBody(vec![format!("请求体不是合法的 JSON: {}", e)]))?;
+        let problems: Vec<String> = self
+            .validator
+            .iter_errors(&instance)
+            .take(self.max_errors)
+            .map(|e| {
+                let path = e.instance_path().to_string();
+                match path.is_empty() {
+                    true => format!("/: {}", e),
+                    false => format!("{}: {}", path, e),
+                }
+            })
+            .collect();
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(ProxyError::InvalidBody(problems)),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+// ==================== 密钥解析 ====================
+//
+// 配置中的任意字符串值都可以写成以下形式，从而避免把令牌、密钥和密码直接写进 config.toml：
+//
+// - `env:VAR_NAME`                 从环境变量读取
+// - `file:/run/secrets/x`          从文件读取（去掉末尾换行）
+// - `vault:secret/data/app#field`  从 HashiCorp Vault 读取（需要 VAULT_ADDR 与 VAULT_TOKEN 环境变量）
+
+use crate::ProxyError;
+use config::{Map, Value, ValueKind};
+
+// 递归解析配置树中的所有密钥引用
+pub async fn resolve(table: Map<String, Value>) -> Result<Map<String, Value>, ProxyError> {
+    let client = reqwest::Client::new(); // Vault 查询共用一个客户端
+    let mut resolved = Map::new();
+    for (key, value) in table {
+        let value = resolve_value(&client, &key, value).await?;
+        resolved.insert(key, value);
+    }
+    Ok(resolved)
+}
+
+// 解析单个配置值；表和数组会继续向下递归
+async fn resolve_value(
+    client: &reqwest::Client,
+    path: &str, // 配置键路径，仅用于错误提示
+    value: Value,
+) -> Result<Value, ProxyError> {
+    let kind = match value.kind {
+        ValueKind::String(s) => ValueKind::String(resolve_str(client, path, s).await?),
+        ValueKind::Table(table) => {
+            let mut resolved = Map::new();
+            for (key, child) in table {
+                let child_path = format!("{}.{}", path, key);
+                let child = Box::pin(resolve_value(client, &child_path, child)).await?;
+                resolved.insert(key, child);
+            }
+            ValueKind::Table(resolved)
+        }
+        ValueKind::Array(array) => {
+            let mut resolved = Vec::with_capacity(array.len());
+            for (index, child) in array.into_iter().enumerate() {
+                let child_path = format!("{}[{}]", path, index);
+                resolved.push(Box::pin(resolve_value(client, &child_path, child)).await?);
+            }
+            ValueKind::Array(resolved)
+        }
+        other => other, // 数字、布尔值等原样保留
+    };
+    Ok(Value::new(None, kind))
+}
+
+// 解析单个字符串：不是密钥引用的值原样返回
+async fn resolve_str(
+    client: &reqwest::Client,
+    path: &str,
+    value: String,
+) -> Result<String, ProxyError> {
+    if let Some(var) = value.strip_prefix("env:") {
+        std::env::var(var)
+            .map_err(|_| ProxyError::SecretError(format!("{}: 环境变量 {} 未设置", path, var)))
+    } else if let Some(file) = value.strip_prefix("file:") {
+        let content = std::fs::read_to_string(file).map_err(|e| {
+            ProxyError::SecretError(format!("{}: 读取文件 {} 失败: {}", path, file, e))
+        })?;
+        Ok(content.trim_end_matches(['\r', '\n']).to_string())
+    } else if let Some(reference) = value.strip_prefix("vault:") {
+        resolve_vault(client, path, reference).await
+    } else {
+        Ok(value)
+    }
+}
+
+// 从 Vault 读取密钥，引用格式为 `<路径>#<字段>`，同时兼容 KV v1 与 KV v2 引擎
+async fn resolve_vault(
+    client: &reqwest::Client,
+    path: &str,
+    reference: &str,
+) -> Result<String, ProxyError> {
+    let (secret_path, field) = reference.split_once('#').ok_or_else(|| {
+        ProxyError::SecretError(format!(
+            "{}: Vault 引用缺少字段名，应为 vault:<路径>#<字段>",
+            path
+        ))
+    })?;
+    let addr = std::env::var("VAULT_ADDR")
+        .map_err(|_| ProxyError::SecretError(format!("{}: 环境变量 VAULT_ADDR 未设置", path)))?;
+    let token = std::env::var("VAULT_TOKEN")
+        .map_err(|_| ProxyError::SecretError(format!("{}: 环境变量 VAULT_TOKEN 未设置", path)))?;
+
+    let url = format!(
+        "{}/v1/{}",
+        addr.trim_end_matches('/'),
+        secret_path.trim_start_matches('/')
+    );
+    let body: serde_json::Value = client
+        .get(&url)
+        .header("X-Vault-Token", token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    // KV v2 的数据位于 data.data，KV v1 位于 data
+    let data = &body["data"];
+    let secret = data["data"].get(field).or_else(|| data.get(field));
+    match secret {
+        Some(serde_json::Value::String(s)) => Ok(s.clone()),
+        Some(other) => Ok(other.to_string()),
+        None => Err(ProxyError::SecretError(format!(
+            "{}: Vault 路径 {} 中不存在字段 {}",
+            path, secret_path, field
+        ))),
+    }
+}
@@ -0,0 +1,252 @@
+// ==================== Sentry 错误上报 ====================
+//
+// 配置了 DSN 时，把返回5xx的 ProxyError 和请求处理中的 panic 作为事件上报到 Sentry，
+// 事件带上路由、上游、请求ID和脱敏后的请求信息；上报使用 Sentry 的 envelope 接口，
+// 在后台发送，队列满或发送失败时丢弃，不影响代理流量
+
+use crate::access_log::RequestInfo;
+use crate::{log_sink, redact, request_id};
+use actix_web::HttpRequest;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+// Sentry 配置：对应配置文件中的 [sentry]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SentryConfig {
+    #[serde(default)]
+    pub dsn: Option<String>, // 项目 DSN，如 "https://<key>@o0.ingest.sentry.io/<project>"，不设置则不上报
+    #[serde(default)]
+    pub environment: Option<String>, // 环境名，如 "production"
+    #[serde(default = "default_release")]
+    pub release: String, // 版本号，缺省为程序版本
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: f64, // 错误事件的上报比例(0~1)，panic 总是上报
+    #[serde(default)]
+    pub capture_client_errors: bool, // 是否同时上报返回4xx的错误（如请求头无效、访问被拒绝）
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize, // 等待发送的最大事件数，超出时丢弃
+}
+
+impl Default for SentryConfig {
+    fn default() -> Self {
+        SentryConfig {
+            dsn: None,
+            environment: None,
+            release: default_release(),
+            sample_rate: default_sample_rate(),
+            capture_client_errors: false,
+            queue_size: default_queue_size(),
+        }
+    }
+}
+
+// 以下函数为 Sentry 配置提供默认值
+fn default_release() -> String {
+    format!("rust_proxy@{}", env!("CARGO_PKG_VERSION"))
+}
+
+fn default_sample_rate() -> f64 {
+    1.0
+}
+
+fn default_queue_size() -> usize {
+    100
+}
+
+// 运行中的上报器
+struct Reporter {
+    config: SentryConfig,
+    sender: mpsc::Sender<serde_json::Value>,
+}
+
+static REPORTER: OnceLock<Reporter> = OnceLock::new();
+
+// 配置了 DSN 时在后台启动上报任务，DSN 无效时返回错误
+pub fn start(config: &SentryConfig) -> Result<(), String> {
+    let Some(dsn) = &config.dsn else {
+        return Ok(());
+    };
+    let (endpoint, auth) = parse_dsn(dsn)?;
+    let (sender, receiver) = mpsc::channel(config.queue_size.max(1));
+    let reporter = Reporter {
+        config: config.clone(),
+        sender,
+    };
+    if REPORTER.set(reporter).is_ok() {
+        log::info!("Sentry 错误上报: {}", endpoint);
+        tokio::spawn(run(endpoint, auth, dsn.clone(), receiver));
+    }
+    Ok(())
+}
+
+// 从 DSN 得到 envelope 接口地址与认证头
+pub fn parse_dsn(dsn: &str) -> Result<(String, String), String> {
+    let url = reqwest::Url::parse(dsn).map_err(|e| format!("无效的 Sentry DSN: {}", e))?;
+    let key = url.username();
+    let host = url.host_str().unwrap_or("");
+    let path = url.path().trim_end_matches('/');
+    let (prefix, project) = path.rsplit_once('/').unwrap_or(("", path));
+    if key.is_empty() || host.is_empty() || project.is_empty() {
+        return Err("无效的 Sentry DSN: 缺少公钥、主机或项目ID".to_string());
+    }
+    let port = url.port().map(|p| format!(":{}", p)).unwrap_or_default();
+    let endpoint = format!(
+        "{}://{}{}{}/api/{}/envelope/",
+        url.scheme(),
+        host,
+        port,
+        prefix,
+        project
+    );
+    let auth = format!(
+        "Sentry sentry_version=7, sentry_client=rust_proxy/{}, sentry_key={}",
+        env!("CARGO_PKG_VERSION"),
+        key
+    );
+    Ok((endpoint, auth))
+}
+
+// 上报一次请求处理错误；错误类型取 ProxyError 的变体名，同一路由的同类错误归为一组
+pub fn capture_error(
+    req: &HttpRequest,
+    info: &RequestInfo,
+    status: u16,
+    kind: &str,
+    message: &str,
+) {
+    let Some(reporter) = REPORTER.get() else {
+        return;
+    };
+    if status < 500 && !reporter.config.capture_client_errors {
+        return;
+    }
+    if rand::random::<f64>() >= reporter.config.sample_rate {
+        return;
+    }
+    let mut event = event(
+        &reporter.config,
+        if status >= 500 { "error" } else { "warning" },
+    );
+    event["exception"] = serde_json::json!({
+        "values": [{"type": kind, "value": message}]
+    });
+    event["fingerprint"] = serde_json::json!([kind, info.route.as_deref().unwrap_or("-")]);
+    add_request(&mut event, req, info);
+    event["tags"]["status"] = status.to_string().into();
+    let _ = reporter.sender.try_send(event);
+}
+
+// 上报一次 panic，请求处理之外的 panic 没有请求信息
+pub fn capture_panic(req: Option<(&HttpRequest, &RequestInfo)>, message: &str, backtrace: &str) {
+    let Some(reporter) = REPORTER.get() else {
+        return;
+    };
+    let mut event = event(&reporter.config, "fatal");
+    event["exception"] = serde_json::json!({
+        "values": [{"type": "panic", "value": message, "mechanism": {"type": "panic", "handled": false}}]
+    });
+    event["extra"]["backtrace"] = backtrace.into();
+    if let Some((req, info)) = req {
+        add_request(&mut event, req, info);
+    }
+    let _ = reporter.sender.try_send(event);
+}
+
+// 事件的公共字段
+fn event(config: &SentryConfig, level: &str) -> serde_json::Value {
+    let mut tags = serde_json::Map::new();
+    if let Some(id) = request_id::current() {
+        tags.insert("request_id".to_string(), id.into());
+    }
+    serde_json::json!({
+        "event_id": uuid::Uuid::new_v4().simple().to_string(),
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "platform": "native",
+        "level": level,
+        "logger": "rust_proxy",
+        "server_name": log_sink::hostname(),
+        "release": config.release,
+        "environment": config.environment,
+        "tags": tags,
+        "extra": {},
+    })
+}
+
+// 附加路由、上游和脱敏后的请求信息，不包含请求体与查询参数
+fn add_request(event: &mut serde_json::Value, req: &HttpRequest, info: &RequestInfo) {
+    let headers: BTreeMap<&str, &str> = req
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value = if redact::is_sensitive(name.as_str()) {
+                redact::REDACTED
+            } else {
+                value.to_str().unwrap_or("")
+            };
+            (name.as_str(), value)
+        })
+        .collect();
+    event["request"] = serde_json::json!({
+        "method": req.method().as_str(),
+        "url": format!("{}://{}{}", crate::listeners::local(req).scheme(), req.connection_info().host(), req.path()),
+        "headers": headers,
+    });
+    if let Some(ip) = crate::addr::peer_ip(req) {
+        event["user"] = serde_json::json!({"ip_address": ip.to_string()});
+    }
+    if let Some(route) = &info.route {
+        event["tags"]["route"] = route.as_str().into();
+    }
+    if let Some(upstream) = info.upstreams.last() {
+        event["tags"]["upstream"] = upstream.addr.as_str().into();
+    }
+    let attempts: Vec<serde_json::Value> = info
+        .upstreams
+        .iter()
+        .map(|u| serde_json::json!({"addr": u.addr, "status": u.status, "ms": u.time.as_millis()}))
+        .collect();
+    event["extra"]["upstream_attempts"] = attempts.into();
+}
+
+// 后台上报任务：逐个发送事件
+async fn run(
+    endpoint: String,
+    auth: String,
+    dsn: String,
+    mut receiver: mpsc::Receiver<serde_json::Value>,
+) {
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            log::error!("Sentry 客户端构建失败: {}", e);
+            return;
+        }
+    };
+    while let Some(event) = receiver.recv().await {
+        // envelope 格式：信封头、条目头、事件各占一行
+        let envelope = format!(
+            "{}\n{}\n{}\n",
+            serde_json::json!({"event_id": event["event_id"], "dsn": dsn}),
+            serde_json::json!({"type": "event"}),
+            event
+        );
+        let result = client
+            .post(&endpoint)
+            .header("X-Sentry-Auth", &auth)
+            .header("Content-Type", "application/x-sentry-envelope")
+            .body(envelope)
+            .send()
+            .await;
+        match result {
+            Ok(resp) if resp.status().is_success() => {}
+            Ok(resp) => log::debug!("Sentry 上报被拒绝: {}", resp.status()),
+            Err(e) => log::debug!("Sentry 上报失败: {}", e),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+// ==================== Server-Timing 响应头 ====================
+//
+// 启用后在代理的响应上附加 Server-Timing 响应头，列出代理测得的各阶段耗时：路由匹配、排队、
+// 每次上游调用到达响应头的时间（含建立连接，HTTP客户端不单独公开连接耗时）、读取响应体与总耗时，
+// 浏览器开发者工具的 Timing 面板可以直接显示；上游自身返回的 Server-Timing 保留在前
+
+use crate::access_log::RequestInfo;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, web};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+// Server-Timing 配置：对应配置文件中的 [server_timing]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ServerTimingConfig {
+    #[serde(default)]
+    pub enabled: bool, // 是否附加 Server-Timing 响应头
+    #[serde(default)]
+    pub timing_allow_origin: Option<String>, // Timing-Allow-Origin 响应头，跨源请求的页面需要它才能读取耗时，如 "*"
+}
+
+// Server-Timing 中间件：需要注册在访问日志中间件的内层，访问日志才能记录该响应头
+pub async fn middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let config = req
+        .app_data::<web::Data<crate::AppState>>()
+        .map(|state| state.server_timing.load())
+        .filter(|config| config.enabled);
+    let Some(config) = config else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_boxed_body);
+    };
+    let started = Instant::now();
+    let http_req = req.request().clone();
+    let mut res = match next.call(req).await {
+        Ok(res) => res.map_into_boxed_body(),
+        Err(e) => ServiceResponse::from_err(e, http_req),
+    };
+    let info = res
+        .request()
+        .extensions()
+        .get::<RequestInfo>()
+        .cloned()
+        .unwrap_or_default();
+    let value = render(&info, started.elapsed());
+    let headers = res.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(&value) {
+        headers.append(HeaderName::from_static("server-timing"), value);
+    }
+    if let Some(origin) = &config.timing_allow_origin
+        && let Ok(origin) = HeaderValue::from_str(origin)
+    {
+        headers.insert(HeaderName::from_static("timing-allow-origin"), origin);
+    }
+    Ok(res)
+}
+
+// 按 Server-Timing 语法渲染各阶段耗时(毫秒)，未经过的阶段不输出；响应头只能是ASCII，描述使用英文
+fn render(info: &RequestInfo, total: Duration) -> String {
+    let mut metrics = Vec::new();
+    let mut metric = |name: &str, time: Duration, desc: &str| {
+        let mut metric = format!("{};dur={:.3}", name, time.as_secs_f64() * 1000.0);
+        if !desc.is_empty() {
+            let _ = write!(metric, ";desc=\"{}\"", desc);
+        }
+        metrics.push(metric);
+    };
+    if let Some(time) = info.route_match {
+        metric("route", time, "route match");
+    }
+    if let Some(time) = info.queue {
+        metric("queue", time, "concurrency queue");
+    }
+    // 每次上游调用一项（故障转移、对冲时有多项），描述中不包含上游地址
+    for (i, upstream) in info.upstreams.iter().enumerate() {
+        let desc = match upstream.status {
+            Some(status) => format!("upstream #{} {}", i + 1, status),
+            None => format!("upstream #{} failed", i + 1),
+        };
+        metric("upstream", upstream.time, &desc);
+    }
+    if let Some(time) = info.body {
+        metric("body", time, "response body");
+    }
+    metric("total", total, "");
+    metrics.join(", ")
+}
@@ -0,0 +1,192 @@
+// ==================== 自适应降载 ====================
+//
+// 监控代理自身的在途请求数和滚动平均延迟，超过阈值即视为饱和；
+// 饱和程度越高，按越大的比例随机拒绝低优先级请求(503)，高优先级请求始终放行
+
+use crate::{AppState, ProxyError, metrics};
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::web;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// 降载配置：对应配置文件中的 [load_shedding]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SheddingConfig {
+    #[serde(default)]
+    pub enabled: bool, // 是否启用降载
+    #[serde(default = "default_max_inflight")]
+    pub max_inflight: usize, // 在途请求数超过该值视为饱和
+    #[serde(default = "default_target_latency_ms")]
+    pub target_latency_ms: u64, // 滚动平均延迟超过该值视为饱和
+    #[serde(default = "default_max_shed_fraction")]
+    pub max_shed_fraction: f64, // 低优先级请求的最大拒绝比例
+    #[serde(default = "default_priority_header")]
+    pub priority_header: String, // 标记请求优先级的请求头
+    #[serde(default = "default_low_priority_values")]
+    pub low_priority_values: Vec<String>, // 表示低优先级的请求头取值
+    #[serde(default)]
+    pub low_priority_routes: Vec<String>, // 所有请求都视为低优先级的路由名称
+}
+
+impl Default for SheddingConfig {
+    fn default() -> Self {
+        SheddingConfig {
+            enabled: false,
+            max_inflight: default_max_inflight(),
+            target_latency_ms: default_target_latency_ms(),
+            max_shed_fraction: default_max_shed_fraction(),
+            priority_header: default_priority_header(),
+            low_priority_values: default_low_priority_values(),
+            low_priority_routes: Vec::new(),
+        }
+    }
+}
+
+// 以下函数为降载配置提供默认值
+fn default_max_inflight() -> usize {
+    1000
+}
+
+fn default_target_latency_ms() -> u64 {
+    1000
+}
+
+fn default_max_shed_fraction() -> f64 {
+    0.9
+}
+
+fn default_priority_header() -> String {
+    "X-Priority".to_string()
+}
+
+fn default_low_priority_values() -> Vec<String> {
+    vec!["low".to_string()]
+}
+
+// 滚动平均延迟的平滑系数
+const EWMA_ALPHA: f64 = 0.1;
+
+// 降载器
+pub struct LoadShedder {
+    config: SheddingConfig,
+    inflight: AtomicUsize,  // 当前在途请求数
+    latency_ms: Mutex<f64>, // 请求延迟的指数加权移动平均(毫秒)
+}
+
+impl LoadShedder {
+    pub fn new(config: &SheddingConfig) -> Self {
+        LoadShedder {
+            config: config.clone(),
+            inflight: AtomicUsize::new(0),
+            latency_ms: Mutex::new(0.0),
+        }
+    }
+
+    // 饱和程度：在途请求数与延迟相对阈值的较大比值，大于1表示饱和
+    fn pressure(&self) -> f64 {
+        let inflight =
+            self.inflight.load(Ordering::Relaxed) as f64 / self.config.max_inflight.max(1) as f64;
+        let latency =
+            *self.latency_ms.lock().unwrap() / self.config.target_latency_ms.max(1) as f64;
+        inflight.max(latency)
+    }
+
+    // 当前低优先级请求的拒绝概率：饱和度从1到2线性增加到最大比例
+    fn shed_probability(&self) -> f64 {
+        ((self.pressure() - 1.0).clamp(0.0, 1.0)) * self.config.max_shed_fraction
+    }
+
+    // 判断请求是否为低优先级
+    fn is_low_priority(&self, req: &ServiceRequest, route: Option<&str>) -> bool {
+        let by_header = req
+            .headers()
+            .get(self.config.priority_header.as_str())
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| {
+                self.config
+                    .low_priority_values
+                    .iter()
+                    .any(|low| low.eq_ignore_ascii_case(v))
+            });
+        let by_route =
+            route.is_some_and(|name| self.config.low_priority_routes.iter().any(|r| r == name));
+        by_header || by_route
+    }
+
+    // 计入一个在途请求，返回的守卫释放时（包括处理过程中 panic）减去
+    fn enter(self: &Arc<Self>) -> InflightGuard {
+        self.inflight.fetch_add(1, Ordering::Relaxed);
+        InflightGuard {
+            shedder: self.clone(),
+        }
+    }
+
+    // 记录请求完成后的延迟
+    fn record_latency(&self, elapsed_ms: f64) {
+        let mut avg = self.latency_ms.lock().unwrap();
+        *avg = *avg * (1.0 - EWMA_ALPHA) + elapsed_ms * EWMA_ALPHA;
+    }
+}
+
+// 在途请求的计数，释放时减一
+struct InflightGuard {
+    shedder: Arc<LoadShedder>,
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        self.shedder.inflight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// 降载中间件
+pub async fn middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let Some(state) = req.app_data::<web::Data<AppState>>().cloned() else {
+        return next.call(req).await;
+    };
+    let shedder = state.shedder.load();
+    if !shedder.config.enabled || !state.features.enabled(crate::Feature::LoadShedding) {
+        return next.call(req).await;
+    }
+
+    // 1. 饱和时按概率拒绝低优先级请求
+    let route = crate::listeners::find_route(req.request(), &state.routes.load());
+    let route_name = route.as_ref().map(|r| r.name.as_str());
+    let probability = shedder.shed_probability();
+    if probability > 0.0
+        && shedder.is_low_priority(&req, route_name)
+        && rand::thread_rng().gen_bool(probability)
+    {
+        let route_name = route_name.unwrap_or("-");
+        log::warn!(
+            "代理负载过高(饱和度{:.2})，拒绝低优先级请求 {}",
+            shedder.pressure(),
+            req.path()
+        );
+        metrics::counter_inc("proxy_shed_total", &[("route", route_name)]);
+        state.audit.record(
+            req.request(),
+            "load_shedding",
+            "load_shedding.low_priority",
+            route_name,
+            503,
+        );
+        return Err(ProxyError::Overloaded("负载过高，已拒绝低优先级请求".to_string()).into());
+    }
+
+    // 2. 统计在途请求数与延迟
+    let guard = shedder.enter();
+    let started = Instant::now();
+    let result = next.call(req).await;
+    drop(guard);
+    shedder.record_latency(started.elapsed().as_secs_f64() * 1000.0);
+    result
+}
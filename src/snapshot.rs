@@ -0,0 +1,70 @@
+// ==================== 响应快照 ====================
+//
+// 保存完整的响应（状态码、响应头、响应体），用于降级、幂等重放等需要重复返回同一响应的场景
+
+use actix_web::HttpResponse;
+use actix_web::body::to_bytes;
+use actix_web::http::StatusCode;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::web;
+
+// 响应快照
+#[derive(Debug, Clone)]
+pub struct ResponseSnapshot {
+    pub status: StatusCode,                      // 状态码
+    pub headers: Vec<(HeaderName, HeaderValue)>, // 响应头（不含长度和传输编码）
+    pub body: web::Bytes,                        // 响应体
+}
+
+// 快照中不保存的响应头，由服务器在发送时重新生成
+fn is_framing_header(name: &HeaderName) -> bool {
+    name == "content-length" || name == "transfer-encoding"
+}
+
+impl ResponseSnapshot {
+    // 由上游响应头和已读取的响应体创建快照
+    pub fn new(
+        status: StatusCode,
+        headers: &reqwest::header::HeaderMap,
+        body: &web::Bytes,
+    ) -> Self {
+        ResponseSnapshot {
+            status,
+            headers: headers
+                .iter()
+                .filter(|(k, _)| !is_framing_header(k))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            body: body.clone(),
+        }
+    }
+
+    // 读取一个已构建的响应并创建快照，同时返回原样重建的响应
+    pub async fn capture(resp: HttpResponse) -> (Self, HttpResponse) {
+        let (head, body) = resp.into_parts();
+        let body = to_bytes(body).await.unwrap_or_default();
+        let snapshot = ResponseSnapshot {
+            status: head.status(),
+            headers: head
+                .headers()
+                .iter()
+                .filter(|(k, _)| !is_framing_header(k))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            body: body.clone(),
+        };
+        (snapshot, head.set_body(body).map_into_boxed_body())
+    }
+
+    // 由快照生成响应，extra为额外附加的响应头
+    pub fn to_response(&self, extra: Option<(&'static str, &'static str)>) -> HttpResponse {
+        let mut resp = HttpResponse::build(self.status);
+        for header in &self.headers {
+            resp.append_header(header.clone());
+        }
+        if let Some(header) = extra {
+            resp.insert_header(header);
+        }
+        resp.body(self.body.clone())
+    }
+}
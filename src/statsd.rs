@@ -0,0 +1,200 @@
+// ==================== StatsD 指标推送 ====================
+//
+// 在Prometheus拉取之外，把每次指标更新以 StatsD 协议通过UDP推送：计数器为 |c，仪表为 |g，
+// 直方图观测（秒）转换为毫秒计时 |ms；启用 DogStatsD 标签时标签写作 |#k:v，
+// 否则标签值依次追加到指标名后。发送在后台批量进行，队列满时丢弃
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+// StatsD 配置：对应配置文件中的 [metrics.statsd]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatsdConfig {
+    #[serde(default)]
+    pub enabled: bool, // 是否启用推送
+    #[serde(default = "default_address")]
+    pub address: String, // StatsD/DogStatsD 代理地址
+    #[serde(default)]
+    pub prefix: String, // 指标名前缀，如 "rust_proxy."
+    #[serde(default = "default_true")]
+    pub dogstatsd_tags: bool, // 是否使用 DogStatsD 标签扩展
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>, // 附加到所有指标的标签，如 { env = "prod" }
+    #[serde(default = "default_flush_interval_ms")]
+    pub flush_interval_ms: u64, // 发送间隔(毫秒)
+    #[serde(default = "default_max_packet_bytes")]
+    pub max_packet_bytes: usize, // 每个UDP包的最大字节数
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize, // 等待发送的最大指标行数，超出时丢弃
+}
+
+impl Default for StatsdConfig {
+    fn default() -> Self {
+        StatsdConfig {
+            enabled: false,
+            address: default_address(),
+            prefix: String::new(),
+            dogstatsd_tags: true,
+            tags: BTreeMap::new(),
+            flush_interval_ms: default_flush_interval_ms(),
+            max_packet_bytes: default_max_packet_bytes(),
+            queue_size: default_queue_size(),
+        }
+    }
+}
+
+// 以下函数为 StatsD 配置提供默认值
+fn default_address() -> String {
+    "127.0.0.1:8125".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_flush_interval_ms() -> u64 {
+    1000
+}
+
+fn default_max_packet_bytes() -> usize {
+    1432
+}
+
+fn default_queue_size() -> usize {
+    10000
+}
+
+// 一次指标更新
+#[derive(Debug, Clone, Copy)]
+pub enum Sample {
+    Count(u64),  // 计数器增量
+    Gauge(f64),  // 仪表的当前值
+    Timing(f64), // 直方图观测值(秒)
+}
+
+// 运行中的推送器
+struct Exporter {
+    config: StatsdConfig,
+    sender: mpsc::Sender<String>,
+}
+
+static EXPORTER: OnceLock<Exporter> = OnceLock::new();
+
+// 启用时在后台启动推送任务
+pub fn start(config: &StatsdConfig) {
+    if !config.enabled {
+        return;
+    }
+    let (sender, receiver) = mpsc::channel(config.queue_size.max(1));
+    let exporter = Exporter {
+        config: config.clone(),
+        sender,
+    };
+    if EXPORTER.set(exporter).is_ok() {
+        log::info!("StatsD 推送: {}", config.address);
+        tokio::spawn(export(config.clone(), receiver));
+    }
+}
+
+// 推送一次指标更新，未启用时直接返回
+pub fn emit(name: &str, labels: &[(String, String)], sample: Sample) {
+    let Some(exporter) = EXPORTER.get() else {
+        return;
+    };
+    let config = &exporter.config;
+    let mut line = format!("{}{}", config.prefix, name);
+    if !config.dogstatsd_tags {
+        for (_, value) in labels {
+            line.push('.');
+            line.extend(value.chars().map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            }));
+        }
+    }
+    let _ = match sample {
+        Sample::Count(value) => write!(line, ":{}|c", value),
+        Sample::Gauge(value) => write!(line, ":{}|g", value),
+        Sample::Timing(seconds) => write!(line, ":{:.3}|ms", seconds * 1000.0),
+    };
+    if config.dogstatsd_tags && (!labels.is_empty() || !config.tags.is_empty()) {
+        let tags = labels
+            .iter()
+            .map(|(key, value)| (key, value))
+            .chain(config.tags.iter())
+            .map(|(key, value)| format!("{}:{}", key, value.replace([',', '|', '#'], "_")))
+            .collect::<Vec<_>>();
+        let _ = write!(line, "|#{}", tags.join(","));
+    }
+    // 队列已满时丢弃，不阻塞请求处理
+    let _ = exporter.sender.try_send(line);
+}
+
+// 后台推送任务：把指标行拼成不超过 max_packet_bytes 的UDP包，包满或到达发送间隔时发送
+async fn export(config: StatsdConfig, mut receiver: mpsc::Receiver<String>) {
+    let mut socket = None;
+    let mut packet = String::new();
+    let mut ticker = tokio::time::interval(Duration::from_millis(config.flush_interval_ms.max(1)));
+    loop {
+        tokio::select! {
+            line = receiver.recv() => match line {
+                Some(line) => {
+                    if !packet.is_empty() && packet.len() + 1 + line.len() > config.max_packet_bytes {
+                        send(&mut socket, &config.address, &mut packet).await;
+                    }
+                    if !packet.is_empty() {
+                        packet.push('\n');
+                    }
+                    packet.push_str(&line);
+                }
+                None => {
+                    send(&mut socket, &config.address, &mut packet).await;
+                    return;
+                }
+            },
+            _ = ticker.tick() => send(&mut socket, &config.address, &mut packet).await,
+        }
+    }
+}
+
+// 创建连接到 StatsD 代理的UDP套接字，本地地址与目标的地址族一致，IPv6 目标（如 [::1]:8125）绑定 [::]
+async fn connect(address: &str) -> std::io::Result<tokio::net::UdpSocket> {
+    let target = tokio::net::lookup_host(address)
+        .await?
+        .next()
+        .ok_or_else(|| std::io::Error::other(format!("无法解析地址 {}", address)))?;
+    let local = match target {
+        std::net::SocketAddr::V4(_) => "0.0.0.0:0",
+        std::net::SocketAddr::V6(_) => "[::]:0",
+    };
+    let socket = tokio::net::UdpSocket::bind(local).await?;
+    socket.connect(target).await?;
+    Ok(socket)
+}
+
+// 发送一个UDP包，失败时记录日志并丢弃；套接字在需要时创建，发送失败后下次重新解析地址并创建
+async fn send(socket: &mut Option<tokio::net::UdpSocket>, address: &str, packet: &mut String) {
+    if packet.is_empty() {
+        return;
+    }
+    if socket.is_none() {
+        match connect(address).await {
+            Ok(connected) => *socket = Some(connected),
+            Err(e) => log::debug!("StatsD 套接字创建失败({}): {}", address, e),
+        }
+    }
+    if let Some(connected) = socket
+        && let Err(e) = connected.send(packet.as_bytes()).await
+    {
+        log::debug!("发送 StatsD 指标到 {} 失败: {}", address, e);
+        *socket = None;
+    }
+    packet.clear();
+}
@@ -0,0 +1,130 @@
+// ==================== 响应体替换 ====================
+//
+// 代理 Web 界面时，上游页面与脚本中常有指向自身地址的绝对链接（如 http://10.0.0.8:8080/static/app.js），
+// 浏览器会绕过代理直接访问。[routes.sub_filter] 对指定内容类型的响应体按顺序做字符串替换（类似 nginx
+// 的 sub_filter），to 中的 $scheme 与 $host 为客户端访问代理时使用的协议与 Host。
+// 所有规则在一遍扫描中完成，替换结果不会被后面的规则再次匹配；响应体由代理完整读取后再替换，
+// 被网络分成多个数据块的匹配同样生效。上游的压缩响应无法替换，启用后发往上游的请求去掉 Accept-Encoding
+
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde::{Deserialize, Serialize};
+
+// 响应体替换配置：对应配置文件中的 [routes.sub_filter]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubFilterConfig {
+    #[serde(default)]
+    pub replace: Vec<Replacement>, // 替换规则，同一位置按顺序使用第一条匹配的规则
+    #[serde(default = "default_types")]
+    pub types: Vec<String>, // 进行替换的内容类型，不含参数
+}
+
+// 替换规则
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Replacement {
+    pub from: String, // 查找的字符串，如 "http://10.0.0.8:8080"
+    pub to: String,   // 替换为的字符串，可以使用 $scheme 与 $host，如 "$scheme://$host"
+}
+
+// 以下函数为响应体替换提供默认值
+fn default_types() -> Vec<String> {
+    vec![
+        "text/html".to_string(),
+        "text/css".to_string(),
+        "text/javascript".to_string(),
+        "application/javascript".to_string(),
+    ]
+}
+
+// 编译后的响应体替换规则
+#[derive(Debug)]
+pub struct SubFilter {
+    replace: Vec<Replacement>,
+    types: Vec<String>, // 小写
+}
+
+impl SubFilter {
+    // 从配置构建，查找的字符串为空时启动失败
+    pub fn new(config: &SubFilterConfig) -> Result<Self, String> {
+        if let Some(empty) = config.replace.iter().find(|r| r.from.is_empty()) {
+            return Err(format!(
+                "响应体替换规则的 from 不能为空（to = {:?}）",
+                empty.to
+            ));
+        }
+        Ok(SubFilter {
+            replace: config.replace.clone(),
+            types: config
+                .types
+                .iter()
+                .map(|t| t.trim().to_ascii_lowercase())
+                .collect(),
+        })
+    }
+
+    // 响应是否需要替换：内容类型匹配且没有压缩
+    pub fn applies(&self, headers: &HeaderMap) -> bool {
+        !encoded(headers) && self.types.contains(&media_type(headers))
+    }
+
+    // 替换响应体；没有任何匹配时返回 None
+    pub fn apply(&self, body: &[u8], scheme: &str, host: &str) -> Option<Vec<u8>> {
+        let replacements: Vec<(&[u8], Vec<u8>)> = self
+            .replace
+            .iter()
+            .map(|r| {
+                let to = r.to.replace("$scheme", scheme).replace("$host", host);
+                (r.from.as_bytes(), to.into_bytes())
+            })
+            .collect();
+        let mut out = Vec::with_capacity(body.len());
+        let mut matched = false;
+        let mut i = 0;
+        while i < body.len() {
+            match replacements
+                .iter()
+                .find(|(from, _)| body[i..].starts_with(from))
+            {
+                Some((from, to)) => {
+                    out.extend_from_slice(to);
+                    i += from.len();
+                    matched = true;
+                }
+                None => {
+                    out.push(body[i]);
+                    i += 1;
+                }
+            }
+        }
+        matched.then_some(out)
+    }
+}
+
+// 响应的媒体类型，小写且不含 charset 等参数；没有 Content-Type 时为空
+pub fn media_type(headers: &HeaderMap) -> String {
+    headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+// 响应体是否经过压缩
+pub fn encoded(headers: &HeaderMap) -> bool {
+    headers
+        .get(reqwest::header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| !v.trim().eq_ignore_ascii_case("identity"))
+}
+
+// 响应体被替换后原来的强 ETag 不再成立，改为弱 ETag
+pub fn weaken_etag(etag: &HeaderValue) -> HeaderValue {
+    match etag.as_bytes().starts_with(b"W/") {
+        true => etag.clone(),
+        false => {
+            let mut weak = b"W/".to_vec();
+            weak.extend_from_slice(etag.as_bytes());
+            HeaderValue::from_bytes(&weak).unwrap_or_else(|_| etag.clone())
+        }
+    }
+}
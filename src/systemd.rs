@@ -0,0 +1,201 @@
+// ==================== systemd 集成 ====================
+//
+// socket activation：systemd 通过 LISTEN_PID/LISTEN_FDS/LISTEN_FDNAMES 传入已经在监听的套接字（从描述符 3 开始），
+// FileDescriptorName=admin 的套接字交给管理服务，与 [[listeners]] 名称相同的交给该监听，没有配置 [[listeners]] 时
+// 其余的都交给代理服务；使用传入的套接字时忽略配置中的监听地址。
+// sd_notify：设置了 NOTIFY_SOCKET 时，启动完成后发送 READY=1，热加载时发送 RELOADING=1 与 READY=1
+// （支持 Type=notify-reload），设置了 WatchdogSec 时按一半的间隔发送 WATCHDOG=1；平滑升级启动的新进程
+// 通过 MAINPID= 接替主进程。不是由 systemd 启动时都不生效
+
+use std::net::TcpListener;
+
+// systemd 传入的第一个描述符
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+// systemd 传入且尚未被服务取走的套接字(名称, 描述符)；None 表示尚未读取环境变量
+#[cfg(unix)]
+static SOCKETS: std::sync::Mutex<Option<Vec<(String, i32)>>> = std::sync::Mutex::new(None);
+
+// 读取 systemd 传入的套接字；LISTEN_PID 不是本进程时视为没有传入
+#[cfg(unix)]
+fn activated() -> Vec<(String, i32)> {
+    let pid = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|p| p.parse::<u32>().ok());
+    if pid != Some(std::process::id()) {
+        return Vec::new();
+    }
+    let count = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|n| n.parse::<i32>().ok())
+        .unwrap_or(0);
+    let names = std::env::var("LISTEN_FDNAMES").unwrap_or_default();
+    let names: Vec<&str> = names.split(':').collect();
+    (0..count)
+        .map(|i| {
+            let name = names.get(i as usize).copied().unwrap_or_default();
+            let name = if name.is_empty() { "unknown" } else { name };
+            (name.to_string(), LISTEN_FDS_START + i)
+        })
+        .collect()
+}
+
+// 取走属于 service 的套接字：名为 admin 的属于管理服务，[[listeners]] 中的监听取走与其名称相同的套接字，
+// 没有配置 [[listeners]] 时的 proxy 监听取走其余所有套接字
+pub fn take(service: &str) -> Vec<TcpListener> {
+    #[cfg(unix)]
+    {
+        use std::os::fd::FromRawFd;
+        let mut guard = SOCKETS.lock().unwrap();
+        let sockets = guard.get_or_insert_with(activated);
+        let (mine, rest): (Vec<_>, Vec<_>) =
+            sockets.drain(..).partition(|(name, _)| match service {
+                crate::listeners::DEFAULT_NAME => name != "admin",
+                service => name == service,
+            });
+        *sockets = rest;
+        mine.into_iter()
+            .filter_map(|(name, fd)| {
+                if let Err(e) = crate::upgrade::set_cloexec(fd, true) {
+                    log::warn!("systemd 传入的描述符 {}({}) 无效: {}", fd, name, e);
+                    return None;
+                }
+                // 描述符由 systemd 传入，只在这里取得所有权一次
+                let listener = unsafe { TcpListener::from_raw_fd(fd) };
+                let stream = socket2::SockRef::from(&listener)
+                    .r#type()
+                    .is_ok_and(|t| t == socket2::Type::STREAM);
+                match listener.local_addr() {
+                    Ok(addr) if stream => {
+                        log::info!(
+                            "{} 使用 systemd 传入的套接字 {}({})，忽略配置的监听地址",
+                            service,
+                            addr,
+                            name
+                        );
+                        Some(listener)
+                    }
+                    _ => {
+                        log::warn!(
+                            "systemd 传入的描述符 {}({}) 不是 TCP 套接字，已关闭",
+                            fd,
+                            name
+                        );
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = service;
+        Vec::new()
+    }
+}
+
+// 向 systemd 发送状态通知；没有设置 NOTIFY_SOCKET 时不做任何事
+pub fn notify(state: &str) {
+    #[cfg(unix)]
+    {
+        let Ok(path) = std::env::var("NOTIFY_SOCKET") else {
+            return;
+        };
+        if let Err(e) = send(&path, state) {
+            log::warn!("向 systemd 发送通知失败: {}", e);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+// 发送一个数据报到通知套接字，@ 开头的路径为 Linux 抽象命名空间
+#[cfg(unix)]
+fn send(path: &str, state: &str) -> std::io::Result<()> {
+    let socket = std::os::unix::net::UnixDatagram::unbound()?;
+    match path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => return Err(std::io::Error::other("当前平台不支持抽象命名空间的套接字")),
+        None => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    }
+    Ok(())
+}
+
+// 启动完成：通知 systemd 服务就绪并启动 watchdog 心跳；upgraded 表示本进程由平滑升级启动，
+// 同时把主进程改为本进程（需要 NotifyAccess=all）
+pub fn ready(upgraded: bool) {
+    #[cfg(unix)]
+    for (name, fd) in SOCKETS.lock().unwrap().take().unwrap_or_default() {
+        log::warn!("systemd 传入的套接字 {}(描述符 {}) 没有服务使用", name, fd);
+    }
+    let state = match upgraded {
+        true => format!("MAINPID={}\nREADY=1", std::process::id()),
+        false => "READY=1".to_string(),
+    };
+    notify(&state);
+    watchdog(upgraded);
+}
+
+// 设置了 WatchdogSec 时，按超时的一半定期发送心跳，心跳由主事件循环发出
+fn watchdog(upgraded: bool) {
+    let Some(usec) = std::env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|u| u.parse::<u64>().ok())
+    else {
+        return;
+    };
+    // WATCHDOG_PID 指定了其他进程时不发送；平滑升级启动的进程接替了旧的主进程
+    let pid = std::env::var("WATCHDOG_PID")
+        .ok()
+        .and_then(|p| p.parse::<u32>().ok());
+    if !upgraded && pid.is_some_and(|pid| pid != std::process::id()) {
+        return;
+    }
+    let interval = std::time::Duration::from_micros(usec / 2);
+    log::info!("systemd watchdog 已启用，每 {:?} 发送一次心跳", interval);
+    tokio::spawn(async move {
+        loop {
+            notify("WATCHDOG=1");
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+// 开始热加载，MONOTONIC_USEC 为 Type=notify-reload 所要求的单调时钟时间
+pub fn reloading() {
+    #[cfg(unix)]
+    {
+        if std::env::var_os("NOTIFY_SOCKET").is_none() {
+            return;
+        }
+        let mut now = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+        let usec = now.tv_sec as u64 * 1_000_000 + now.tv_nsec as u64 / 1000;
+        notify(&format!("RELOADING=1\nMONOTONIC_USEC={}", usec));
+    }
+}
+
+// 热加载结束，status 为显示在 systemctl status 中的结果
+pub fn reloaded(status: &str) {
+    notify(&format!("READY=1\nSTATUS={}", status));
+}
+
+// 平滑升级启动新进程时，去掉只属于本进程的 systemd 环境变量
+#[cfg(unix)]
+pub fn child_env(command: &mut std::process::Command) {
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES", "WATCHDOG_PID"] {
+        command.env_remove(name);
+    }
+}
@@ -0,0 +1,152 @@
+// ==================== 实时流量查看 ====================
+//
+// 管理接口 GET /tap 以 Server-Sent Events 推送经过代理的每个请求的摘要（方法、路径、状态码、耗时等），
+// 可按查询参数过滤，无需重启即可像 tcpdump 一样观察 HTTP 流量；没有订阅者时不产生任何开销
+
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::web::Bytes;
+use serde::Deserialize;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+// 最多同时订阅的客户端数
+const MAX_SUBSCRIBERS: usize = 16;
+
+// 每个订阅者等待发送的最大事件数，客户端读取太慢时丢弃新事件
+const SUBSCRIBER_QUEUE: usize = 1024;
+
+// 一个请求的摘要
+#[derive(Debug)]
+pub struct Event<'a> {
+    pub method: &'a str,           // 请求方法
+    pub path: &'a str,             // 路径和查询参数
+    pub status: u16,               // 响应状态码
+    pub duration_ms: u128,         // 处理时间(毫秒)
+    pub route: Option<&'a str>,    // 匹配的路由
+    pub upstream: Option<&'a str>, // 最后一次调用的上游
+    pub client_ip: Option<String>, // 客户端IP
+}
+
+// 订阅过滤条件，对应 /tap 的查询参数，未设置的条件不过滤
+#[derive(Debug, Deserialize, Default)]
+pub struct Filter {
+    method: Option<String>, // 请求方法，如 GET
+    path: Option<String>,   // 路径前缀
+    route: Option<String>,  // 路由名称
+    status: Option<String>, // 状态码，如 404，或状态类别，如 5xx
+    min_ms: Option<u128>,   // 最小处理时间(毫秒)
+}
+
+impl Filter {
+    fn matches(&self, event: &Event) -> bool {
+        if let Some(method) = &self.method
+            && !method.eq_ignore_ascii_case(event.method)
+        {
+            return false;
+        }
+        if let Some(path) = &self.path
+            && !event.path.starts_with(path.as_str())
+        {
+            return false;
+        }
+        if let Some(route) = &self.route
+            && event.route != Some(route.as_str())
+        {
+            return false;
+        }
+        if let Some(status) = &self.status {
+            let actual = event.status.to_string();
+            let matched = match status.to_ascii_lowercase().strip_suffix("xx") {
+                Some(class) => actual.starts_with(class),
+                None => *status == actual,
+            };
+            if !matched {
+                return false;
+            }
+        }
+        self.min_ms.is_none_or(|min| event.duration_ms >= min)
+    }
+}
+
+struct Subscriber {
+    filter: Filter,
+    sender: mpsc::Sender<Bytes>,
+}
+
+// 流量订阅中心
+#[derive(Default)]
+pub struct Tap {
+    subscribers: Mutex<Vec<Subscriber>>,
+    count: AtomicUsize, // 订阅者数，请求路径上只读取该值
+}
+
+impl Tap {
+    // 是否有订阅者，没有时调用方可以跳过构造事件
+    pub fn active(&self) -> bool {
+        self.count.load(Ordering::Relaxed) > 0
+    }
+
+    // 新增订阅，订阅数已达上限时返回None
+    pub fn subscribe(&self, filter: Filter) -> Option<EventStream> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|s| !s.sender.is_closed());
+        if subscribers.len() >= MAX_SUBSCRIBERS {
+            return None;
+        }
+        let (sender, receiver) = mpsc::channel(SUBSCRIBER_QUEUE);
+        // 先发送一个注释行，客户端可以立即确认连接已建立
+        let _ = sender.try_send(Bytes::from_static(b": connected\n\n"));
+        subscribers.push(Subscriber { filter, sender });
+        self.count.store(subscribers.len(), Ordering::Relaxed);
+        Some(EventStream { receiver })
+    }
+
+    // 把请求摘要推送给过滤条件匹配的订阅者，并清理已断开的订阅
+    pub fn publish(&self, event: &Event) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|s| !s.sender.is_closed());
+        self.count.store(subscribers.len(), Ordering::Relaxed);
+        let mut data = None;
+        for subscriber in subscribers.iter().filter(|s| s.filter.matches(event)) {
+            let data = data.get_or_insert_with(|| {
+                let json = serde_json::json!({
+                    "timestamp": chrono::Utc::now().to_rfc3339(),
+                    "request_id": crate::request_id::current(),
+                    "method": event.method,
+                    "path": event.path,
+                    "status": event.status,
+                    "duration_ms": event.duration_ms,
+                    "route": event.route,
+                    "upstream": event.upstream,
+                    "client_ip": event.client_ip,
+                });
+                Bytes::from(format!("data: {}\n\n", json))
+            });
+            // 客户端读取太慢时丢弃该事件
+            let _ = subscriber.sender.try_send(data.clone());
+        }
+    }
+}
+
+// SSE 响应体：逐个输出订阅到的事件，客户端断开时随响应一起释放
+pub struct EventStream {
+    receiver: mpsc::Receiver<Bytes>,
+}
+
+impl MessageBody for EventStream {
+    type Error = std::convert::Infallible;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        self.receiver.poll_recv(cx).map(|event| event.map(Ok))
+    }
+}
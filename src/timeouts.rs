@@ -0,0 +1,94 @@
+// ==================== 超时控制 ====================
+//
+// 将单一的总超时拆分为：连接超时、等待响应头超时、响应体两个数据块之间的空闲超时和总超时，
+// 全局值在 [request] 中设置，每个路由可以在 [routes.timeouts] 中单独覆盖
+
+use crate::{ProxyError, RequestConfig};
+use actix_web::web;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// 路由级超时覆盖，未设置的项沿用 [request] 中的全局值
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TimeoutConfig {
+    #[serde(default)]
+    pub total_ms: Option<u64>, // 总超时(毫秒)，从发出请求到读完响应体
+    #[serde(default)]
+    pub connect_ms: Option<u64>, // 建立连接的超时(毫秒)
+    #[serde(default)]
+    pub header_ms: Option<u64>, // 等待响应头的超时(毫秒)
+    #[serde(default)]
+    pub idle_ms: Option<u64>, // 响应体数据块之间的空闲超时(毫秒)
+}
+
+// 合并全局配置与路由覆盖后的超时设置
+#[derive(Debug, Clone)]
+pub struct Timeouts {
+    pub total: Duration,           // 总超时
+    pub connect: Option<Duration>, // 连接超时
+    pub header: Option<Duration>,  // 响应头超时
+    pub idle: Option<Duration>,    // 空闲超时
+}
+
+impl Timeouts {
+    // 以全局配置为基础，应用路由覆盖
+    pub fn resolve(request: &RequestConfig, route: Option<&TimeoutConfig>) -> Self {
+        let route = route.cloned().unwrap_or_default();
+        let ms = Duration::from_millis;
+        Timeouts {
+            total: route
+                .total_ms
+                .map(ms)
+                .unwrap_or(Duration::from_secs(request.timeout)),
+            connect: route.connect_ms.or(request.connect_timeout_ms).map(ms),
+            header: route.header_ms.or(request.header_timeout_ms).map(ms),
+            idle: route.idle_ms.or(request.idle_timeout_ms).map(ms),
+        }
+    }
+}
+
+// 发送请求并在限定时间内等待响应头
+pub async fn send(
+    request: reqwest::RequestBuilder,
+    header_timeout: Option<Duration>,
+) -> Result<reqwest::Response, ProxyError> {
+    // FastCGI、uwsgi 目标与路由要求发送 PROXY 协议头的请求改用独立的连接发送；前两者不发送 PROXY 协议头
+    let (client, request) = request.build_split();
+    let mut request = request?;
+    let sending = async {
+        let upstream = crate::proxy_protocol::take(&mut request);
+        match (crate::cgi::take(&mut request), upstream) {
+            (Some(marked), _) => crate::cgi::send(marked, request).await,
+            (None, Some(upstream)) => upstream.send(request).await,
+            (None, None) => Ok(client.execute(request).await?),
+        }
+    };
+    match header_timeout {
+        Some(limit) => tokio::time::timeout(limit, sending).await.map_err(|_| {
+            ProxyError::UpstreamTimeout(format!("{}毫秒内未收到响应头", limit.as_millis()))
+        })?,
+        None => sending.await,
+    }
+}
+
+// 逐块读取响应体，两个数据块之间的间隔超过空闲超时即放弃
+pub async fn read_body(
+    mut response: reqwest::Response,
+    idle_timeout: Option<Duration>,
+) -> Result<web::Bytes, ProxyError> {
+    let Some(limit) = idle_timeout else {
+        return Ok(response.bytes().await?);
+    };
+    let mut body = Vec::new();
+    loop {
+        let chunk = tokio::time::timeout(limit, response.chunk())
+            .await
+            .map_err(|_| {
+                ProxyError::UpstreamTimeout(format!("响应体读取空闲超过{}毫秒", limit.as_millis()))
+            })??;
+        match chunk {
+            Some(chunk) => body.extend_from_slice(&chunk),
+            None => return Ok(web::Bytes::from(body)),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+// ==================== 入站 TLS ====================
+//
+// HTTPS 监听的握手与 ALPN 协商。native-tls 的接受器不能声明 ALPN，客户端只能使用 HTTP/1.1，
+// 因此入站 TLS 直接使用系统的 OpenSSL：按客户端的列表优先选择 h2，其次 http/1.1，
+// 握手后由监听按协商结果选择 HTTP/2 或 HTTP/1.1。OpenSSL 的流是同步读写的，
+// 这里把异步连接包装为遇到 Pending 时返回 WouldBlock 的同步读写，唤醒器在每次读写前更新
+
+use openssl::pkey::PKey;
+use openssl::ssl::{self, AlpnError, ErrorCode, Ssl, SslAcceptor, SslMethod, SslStream};
+use openssl::x509::X509;
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+// 服务端声明的协议，ALPN 的编码格式：每个协议名前加一字节长度
+const ALPN: &[u8] = b"\x02h2\x08http/1.1";
+
+// TLS 接受器
+#[derive(Clone)]
+pub struct Acceptor(SslAcceptor);
+
+impl Acceptor {
+    // 读取 PEM 格式的证书链与私钥
+    pub fn new(cert: &str, key: &str) -> Result<Self, String> {
+        let chain = std::fs::read(cert).map_err(|e| format!("无法读取证书 {}: {}", cert, e))?;
+        let pkey = std::fs::read(key).map_err(|e| format!("无法读取私钥 {}: {}", key, e))?;
+        let mut chain = X509::stack_from_pem(&chain)
+            .map_err(|e| format!("证书无效: {}", e))?
+            .into_iter();
+        let leaf = chain
+            .next()
+            .ok_or(format!("证书文件 {} 中没有证书", cert))?;
+        let pkey = PKey::private_key_from_pem(&pkey)
+            .map_err(|e| format!("私钥无效（私钥应为 PKCS#8 PEM 格式）: {}", e))?;
+        let build = || -> Result<SslAcceptor, openssl::error::ErrorStack> {
+            let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())?;
+            builder.set_certificate(&leaf)?;
+            for cert in chain {
+                builder.add_extra_chain_cert(cert)?;
+            }
+            builder.set_private_key(&pkey)?;
+            builder.check_private_key()?;
+            builder.set_alpn_select_callback(|_, client| {
+                ssl::select_next_proto(ALPN, client).ok_or(AlpnError::NOACK)
+            });
+            Ok(builder.build())
+        };
+        build()
+            .map(Acceptor)
+            .map_err(|e| format!("无法创建 TLS 接受器（证书与私钥可能不匹配）: {}", e))
+    }
+
+    // 在连接上完成服务端握手
+    pub async fn accept<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        io: S,
+    ) -> Result<TlsStream<S>, String> {
+        let ssl = Ssl::new(self.0.context()).map_err(|e| e.to_string())?;
+        let bridge = Bridge {
+            io,
+            waker: Waker::noop().clone(),
+        };
+        let mut stream = SslStream::new(ssl, bridge).map_err(|e| e.to_string())?;
+        std::future::poll_fn(|cx| {
+            stream.get_mut().waker = cx.waker().clone();
+            match stream.accept() {
+                Ok(()) => Poll::Ready(Ok(())),
+                Err(e) if e.code() == ErrorCode::WANT_READ || e.code() == ErrorCode::WANT_WRITE => {
+                    Poll::Pending
+                }
+                Err(e) => Poll::Ready(Err(e.to_string())),
+            }
+        })
+        .await?;
+        Ok(TlsStream(stream))
+    }
+}
+
+// 以同步读写的方式访问异步连接，Pending 转换为 WouldBlock
+struct Bridge<S> {
+    io: S,
+    waker: Waker, // 当前读写所在任务的唤醒器
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Bridge<S> {
+    fn poll<R>(
+        &mut self,
+        f: impl FnOnce(Pin<&mut S>, &mut Context<'_>) -> Poll<io::Result<R>>,
+    ) -> io::Result<R> {
+        let mut cx = Context::from_waker(&self.waker);
+        match f(Pin::new(&mut self.io), &mut cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Read for Bridge<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut buf = ReadBuf::new(buf);
+        self.poll(|io, cx| io.poll_read(cx, &mut buf))?;
+        Ok(buf.filled().len())
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Write for Bridge<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.poll(|io, cx| io.poll_write(cx, buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.poll(|io, cx| io.poll_flush(cx))
+    }
+}
+
+// 握手完成的 TLS 连接
+pub struct TlsStream<S>(SslStream<Bridge<S>>);
+
+impl<S: AsyncRead + AsyncWrite + Unpin> TlsStream<S> {
+    // 客户端是否通过 ALPN 选择了 HTTP/2
+    pub fn is_h2(&self) -> bool {
+        self.0.ssl().selected_alpn_protocol() == Some(b"h2")
+    }
+
+    // 以当前任务的唤醒器执行一次同步读写
+    fn with_context<R>(
+        &mut self,
+        cx: &mut Context<'_>,
+        f: impl FnOnce(&mut SslStream<Bridge<S>>) -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        self.0.get_mut().waker = cx.waker().clone();
+        match f(&mut self.0) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Poll::Pending,
+            result => Poll::Ready(result),
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for TlsStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let read = std::task::ready!(
+            self.get_mut()
+                .with_context(cx, |s| s.read(buf.initialize_unfilled()))
+        )?;
+        buf.advance(read);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for TlsStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().with_context(cx, |s| s.write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().with_context(cx, |s| s.flush())
+    }
+
+    // 先发送 close_notify，再关闭底层连接
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        std::task::ready!(this.with_context(cx, |s| match s.shutdown() {
+            Ok(_) => Ok(()),
+            Err(e) if e.code() == ErrorCode::ZERO_RETURN => Ok(()),
+            Err(e) => Err(e.into_io_error().unwrap_or_else(io::Error::other)),
+        }))?;
+        Pin::new(&mut this.0.get_mut().io).poll_shutdown(cx)
+    }
+}
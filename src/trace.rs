@@ -0,0 +1,386 @@
+// ==================== 分布式追踪 ====================
+//
+// 为每个代理请求创建一个服务端span，发往上游的调用作为其子span；
+// 按 W3C Trace Context 读取客户端传入的 traceparent 并向上游传递，返回给客户端的响应（含错误响应）
+// 同样带上服务端span的 traceparent 与客户端传入的 tracestate，调用方可以按追踪ID找到本次请求；
+// span 在后台批量通过 OTLP/HTTP(JSON) 导出到配置的收集器
+
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+use rand::Rng;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+// 追踪配置：对应配置文件中的 [tracing]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TracingConfig {
+    #[serde(default)]
+    pub enabled: bool, // 是否启用追踪
+    #[serde(default = "default_endpoint")]
+    pub endpoint: String, // OTLP/HTTP 收集器地址
+    #[serde(default)]
+    pub headers: HashMap<String, String>, // 导出时附加的请求头，如认证令牌
+    #[serde(default = "default_service_name")]
+    pub service_name: String, // 上报的服务名
+    #[serde(default = "default_sample_ratio")]
+    pub sample_ratio: f64, // 没有上游追踪上下文时的采样比例
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize, // 每批导出的最大span数
+    #[serde(default = "default_flush_interval_ms")]
+    pub flush_interval_ms: u64, // 导出间隔(毫秒)
+    #[serde(default = "default_max_queue")]
+    pub max_queue: usize, // 等待导出的最大span数，超出时丢弃
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        TracingConfig {
+            enabled: false,
+            endpoint: default_endpoint(),
+            headers: HashMap::new(),
+            service_name: default_service_name(),
+            sample_ratio: default_sample_ratio(),
+            batch_size: default_batch_size(),
+            flush_interval_ms: default_flush_interval_ms(),
+            max_queue: default_max_queue(),
+        }
+    }
+}
+
+// 以下函数为追踪配置提供默认值
+fn default_endpoint() -> String {
+    "http://127.0.0.1:4318/v1/traces".to_string()
+}
+
+fn default_service_name() -> String {
+    "rust_proxy".to_string()
+}
+
+fn default_sample_ratio() -> f64 {
+    1.0
+}
+
+fn default_batch_size() -> usize {
+    512
+}
+
+fn default_flush_interval_ms() -> u64 {
+    5000
+}
+
+fn default_max_queue() -> usize {
+    2048
+}
+
+// OTLP 中的span类型
+const SPAN_KIND_SERVER: u8 = 2;
+const SPAN_KIND_CLIENT: u8 = 3;
+
+// 追踪上下文，对应 traceparent 中的字段
+#[derive(Debug, Clone, Copy)]
+pub struct SpanContext {
+    pub trace_id: u128, // 追踪ID
+    pub span_id: u64,   // 当前span的ID
+    pub sampled: bool,  // 是否采样
+}
+
+impl SpanContext {
+    // 解析 traceparent：版本-追踪ID-父spanID-标志位
+    fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let span_id = parts.next()?;
+        let flags = parts.next()?;
+        if version.len() != 2 || version == "ff" || trace_id.len() != 32 || span_id.len() != 16 {
+            return None;
+        }
+        let trace_id = u128::from_str_radix(trace_id, 16).ok()?;
+        let span_id = u64::from_str_radix(span_id, 16).ok()?;
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+        // 全零的ID无效
+        (trace_id != 0 && span_id != 0).then_some(SpanContext {
+            trace_id,
+            span_id,
+            sampled: flags & 1 == 1,
+        })
+    }
+
+    // 生成 traceparent 请求头的值
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, self.span_id, self.sampled as u8
+        )
+    }
+}
+
+// span属性值
+#[derive(Debug, Clone)]
+enum AttrValue {
+    Str(String),
+    Int(i64),
+}
+
+// 进行中的span，调用 end 后导出
+#[derive(Debug)]
+pub struct Span {
+    context: SpanContext,
+    parent_span_id: Option<u64>,
+    name: String,
+    kind: u8,
+    start: SystemTime,
+    attributes: Vec<(&'static str, AttrValue)>,
+    error: Option<String>,
+    sender: Option<mpsc::Sender<serde_json::Value>>, // 未启用或未采样时为None
+}
+
+impl Span {
+    // span的追踪上下文
+    pub fn context(&self) -> SpanContext {
+        self.context
+    }
+
+    // 设置字符串属性
+    pub fn set_str(&mut self, key: &'static str, value: impl Into<String>) {
+        self.attributes.push((key, AttrValue::Str(value.into())));
+    }
+
+    // 设置整数属性
+    pub fn set_int(&mut self, key: &'static str, value: i64) {
+        self.attributes.push((key, AttrValue::Int(value)));
+    }
+
+    // 标记span出错
+    pub fn set_error(&mut self, message: impl Into<String>) {
+        self.error = Some(message.into());
+    }
+
+    // 结束span并放入导出队列，队列已满时丢弃
+    pub fn end(self) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        if sender.try_send(self.to_otlp()).is_err() {
+            log::debug!("追踪导出队列已满，丢弃span {}", self.name);
+        }
+    }
+
+    // 转换为 OTLP JSON 格式
+    fn to_otlp(&self) -> serde_json::Value {
+        let attributes: Vec<serde_json::Value> = self
+            .attributes
+            .iter()
+            .map(|(key, value)| match value {
+                AttrValue::Str(s) => json!({"key": key, "value": {"stringValue": s}}),
+                AttrValue::Int(i) => json!({"key": key, "value": {"intValue": i.to_string()}}),
+            })
+            .collect();
+        let status = match &self.error {
+            Some(message) => json!({"code": 2, "message": message}),
+            None => json!({"code": 0}),
+        };
+        let mut span = json!({
+            "traceId": format!("{:032x}", self.context.trace_id),
+            "spanId": format!("{:016x}", self.context.span_id),
+            "name": self.name,
+            "kind": self.kind,
+            "startTimeUnixNano": unix_nanos(self.start).to_string(),
+            "endTimeUnixNano": unix_nanos(SystemTime::now()).to_string(),
+            "attributes": attributes,
+            "status": status,
+        });
+        if let Some(parent) = self.parent_span_id {
+            span["parentSpanId"] = json!(format!("{:016x}", parent));
+        }
+        span
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+// 生成非零的随机spanID
+fn new_span_id() -> u64 {
+    rand::thread_rng().gen_range(1..=u64::MAX)
+}
+
+// 追踪器
+#[derive(Debug)]
+pub struct Tracer {
+    config: TracingConfig,
+    sender: Option<mpsc::Sender<serde_json::Value>>, // 未启用时为None
+}
+
+impl Tracer {
+    // 创建追踪器，启用时在后台启动导出任务
+    pub fn new(config: &TracingConfig) -> Result<Self, String> {
+        if !config.enabled {
+            return Ok(Tracer {
+                config: config.clone(),
+                sender: None,
+            });
+        }
+        let mut headers = HeaderMap::new();
+        for (name, value) in &config.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("无效的追踪导出请求头: {}", name))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| format!("追踪导出请求头 {} 的值无效", name))?;
+            headers.insert(name, value);
+        }
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .default_headers(headers)
+            .build()
+            .map_err(|e| format!("追踪导出客户端构建失败: {}", e))?;
+        let (sender, receiver) = mpsc::channel(config.max_queue.max(1));
+        tokio::spawn(export(config.clone(), client, receiver));
+        Ok(Tracer {
+            config: config.clone(),
+            sender: Some(sender),
+        })
+    }
+
+    // 为进入的请求创建服务端span：有合法的 traceparent 时沿用其追踪ID和采样决定
+    pub fn server_span(&self, req: &HttpRequest, name: String) -> Option<Span> {
+        self.sender.as_ref()?;
+        let parent = req
+            .headers()
+            .get("traceparent")
+            .and_then(|v| v.to_str().ok())
+            .and_then(SpanContext::parse);
+        let context = match parent {
+            Some(parent) => SpanContext {
+                trace_id: parent.trace_id,
+                span_id: new_span_id(),
+                sampled: parent.sampled,
+            },
+            None => SpanContext {
+                trace_id: rand::thread_rng().gen_range(1..=u128::MAX),
+                span_id: new_span_id(),
+                sampled: rand::thread_rng().gen_bool(self.config.sample_ratio.clamp(0.0, 1.0)),
+            },
+        };
+        let mut span = self.span(context, parent.map(|p| p.span_id), name, SPAN_KIND_SERVER);
+        span.set_str("http.request.method", req.method().as_str());
+        span.set_str("url.path", req.path());
+        if let Some(ip) = crate::addr::peer_ip(req) {
+            span.set_str("client.address", ip.to_string());
+        }
+        Some(span)
+    }
+
+    // 创建发往上游的子span
+    pub fn client_span(&self, parent: &SpanContext, name: String) -> Span {
+        let context = SpanContext {
+            span_id: new_span_id(),
+            ..*parent
+        };
+        self.span(context, Some(parent.span_id), name, SPAN_KIND_CLIENT)
+    }
+
+    fn span(
+        &self,
+        context: SpanContext,
+        parent_span_id: Option<u64>,
+        name: String,
+        kind: u8,
+    ) -> Span {
+        Span {
+            context,
+            parent_span_id,
+            name,
+            kind,
+            start: SystemTime::now(),
+            attributes: Vec::new(),
+            error: None,
+            sender: self.sender.clone().filter(|_| context.sampled),
+        }
+    }
+}
+
+// 为代理请求写入子span的 traceparent，覆盖客户端传入的值
+pub fn inject(span: &Span, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(&span.context.traceparent()) {
+        headers.insert("traceparent", value);
+    }
+    request.headers(headers)
+}
+
+// 为返回给客户端的响应写入服务端span的 traceparent，覆盖上游返回的值；客户端传入的 tracestate 原样带回
+pub fn respond(context: &SpanContext, req: &HttpRequest, resp: &mut HttpResponse) {
+    let headers = resp.headers_mut();
+    if let Ok(value) = header::HeaderValue::from_str(&context.traceparent()) {
+        headers.insert(header::HeaderName::from_static("traceparent"), value);
+    }
+    let name = header::HeaderName::from_static("tracestate");
+    match req.headers().get(&name) {
+        Some(state) => headers.insert(name, state.clone()),
+        None => headers.remove(name),
+    };
+}
+
+// 后台导出任务：攒够一批或到达导出间隔时发送到收集器
+async fn export(
+    config: TracingConfig,
+    client: reqwest::Client,
+    mut receiver: mpsc::Receiver<serde_json::Value>,
+) {
+    let mut batch = Vec::new();
+    let mut ticker = tokio::time::interval(Duration::from_millis(config.flush_interval_ms.max(1)));
+    loop {
+        tokio::select! {
+            span = receiver.recv() => match span {
+                Some(span) => {
+                    batch.push(span);
+                    if batch.len() >= config.batch_size {
+                        flush(&config, &client, &mut batch).await;
+                    }
+                }
+                None => {
+                    flush(&config, &client, &mut batch).await;
+                    return;
+                }
+            },
+            _ = ticker.tick() => flush(&config, &client, &mut batch).await,
+        }
+    }
+}
+
+// 发送一批span，失败时记录日志并丢弃
+async fn flush(
+    config: &TracingConfig,
+    client: &reqwest::Client,
+    batch: &mut Vec<serde_json::Value>,
+) {
+    if batch.is_empty() {
+        return;
+    }
+    let spans = std::mem::take(batch);
+    let count = spans.len();
+    let body = json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [{"key": "service.name", "value": {"stringValue": config.service_name}}]
+            },
+            "scopeSpans": [{
+                "scope": {"name": "rust_proxy"},
+                "spans": spans,
+            }]
+        }]
+    });
+    match client.post(&config.endpoint).json(&body).send().await {
+        Ok(resp) if resp.status().is_success() => log::debug!("已导出 {} 个span", count),
+        Ok(resp) => log::warn!("导出 {} 个span失败: 收集器返回 {}", count, resp.status()),
+        Err(e) => log::warn!("导出 {} 个span失败: {}", count, e),
+    }
+}
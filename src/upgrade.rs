@@ -0,0 +1,298 @@
+// ==================== 平滑升级 ====================
+//
+// 收到 SIGUSR2 时以相同的命令行参数启动新的可执行文件，并把正在监听的套接字通过文件描述符继承交给它。
+// 新进程直接在继承的套接字上接受连接，启动完成后向旧进程发送 SIGTERM；旧进程停止接受新连接，
+// 处理完已接受的请求后退出（最长 [server] shutdown_timeout_secs 秒）。监听套接字始终没有关闭，未被接受的连接留在同一个监听队列中
+// 由新进程接受，因此升级过程中不会拒绝或断开连接。新进程启动失败时旧进程继续运行。
+// 新进程按服务和地址匹配继承的套接字，配置中修改了的监听地址重新绑定，不再使用的套接字关闭；
+// systemd 传入的套接字不按地址匹配，原样交给新进程。只支持 Unix
+
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{SocketAddr, TcpListener};
+
+// 继承的监听套接字，"服务=地址=描述符"以逗号分隔，如 proxy=127.0.0.1:3000=3,admin=127.0.0.1:9090=4；
+// 地址为 * 表示不按地址匹配（来自 systemd 的套接字）
+#[cfg(unix)]
+const FDS_ENV: &str = "RUST_PROXY_LISTEN_FDS";
+// 发起升级的旧进程的 pid，新进程启动完成后通知它退出
+#[cfg(unix)]
+const PARENT_ENV: &str = "RUST_PROXY_UPGRADE_PARENT";
+
+// 本进程正在监听的一个套接字
+#[cfg(unix)]
+#[derive(Debug, Clone)]
+struct Listening {
+    service: String,          // 所属服务：监听名称或 admin
+    addr: Option<SocketAddr>, // 匹配用的地址，None 表示不按地址匹配
+    fd: i32,                  // 文件描述符
+}
+
+// 本进程正在监听的套接字，升级时交给新进程
+#[cfg(unix)]
+static LISTENING: std::sync::Mutex<Vec<Listening>> = std::sync::Mutex::new(Vec::new());
+
+// 升级进行中（新进程已启动但尚未接管），避免重复的 SIGUSR2 启动多个新进程
+#[cfg(unix)]
+static UPGRADING: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+// 服务（监听名称或 admin）在 host 列出的所有主机上监听 port。依次使用 systemd 传入的套接字、
+// 旧进程交接的套接字，都没有时新建；主机名解析到多个地址时全部监听，与 actix-web 的 bind 一样，
+// 至少一个地址监听成功即可；backlog 为新建套接字的监听队列长度
+pub fn listen(
+    service: &str,
+    host: &str,
+    port: u16,
+    backlog: u32,
+) -> std::io::Result<Vec<TcpListener>> {
+    let activated = crate::systemd::take(service);
+    if !activated.is_empty() {
+        return Ok(record(service, None, activated));
+    }
+    let handed = inherited(service, None);
+    if !handed.is_empty() {
+        return Ok(record(service, None, handed));
+    }
+
+    let addrs = crate::addr::resolve(host, port)?;
+    // 同时监听 IPv4 地址时 IPv6 套接字只接受 IPv6 连接，否则 :: 与 0.0.0.0 会冲突
+    let v6only = addrs.iter().any(|a| a.is_ipv4());
+    let mut listeners = Vec::new();
+    let mut last_error = None;
+    for addr in addrs {
+        let listener = match inherited(service, Some(addr)).pop() {
+            Some(listener) => listener,
+            None => match bind(addr, v6only, backlog) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    last_error = Some(e);
+                    continue;
+                }
+            },
+        };
+        listeners.extend(record(service, Some(addr), vec![listener]));
+    }
+    match (listeners.is_empty(), last_error) {
+        (true, Some(e)) => Err(e),
+        (true, None) => Err(std::io::Error::other(format!(
+            "无法解析监听地址: {}",
+            crate::addr::join(host, port)
+        ))),
+        _ => Ok(listeners),
+    }
+}
+
+// 新建监听套接字，选项与 actix-web 的 bind 相同；IPv6 套接字显式设置是否双栈，不依赖系统默认值
+fn bind(addr: SocketAddr, v6only: bool, backlog: u32) -> std::io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(v6only)?;
+    }
+    socket.bind(&addr.into())?;
+    socket.listen(backlog.min(i32::MAX as u32) as i32)?;
+    Ok(socket.into())
+}
+
+// 记录正在监听的套接字，供升级时交接
+#[cfg(unix)]
+fn record(
+    service: &str,
+    addr: Option<SocketAddr>,
+    listeners: Vec<TcpListener>,
+) -> Vec<TcpListener> {
+    use std::os::fd::AsRawFd;
+    let mut listening = LISTENING.lock().unwrap();
+    for listener in &listeners {
+        listening.push(Listening {
+            service: service.to_string(),
+            addr,
+            fd: listener.as_raw_fd(),
+        });
+    }
+    listeners
+}
+
+#[cfg(not(unix))]
+fn record(
+    _service: &str,
+    _addr: Option<SocketAddr>,
+    listeners: Vec<TcpListener>,
+) -> Vec<TcpListener> {
+    listeners
+}
+
+// 设置或清除描述符的 close-on-exec 标志
+#[cfg(unix)]
+pub fn set_cloexec(fd: i32, on: bool) -> std::io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    let flags = match on {
+        true => flags | libc::FD_CLOEXEC,
+        false => flags & !libc::FD_CLOEXEC,
+    };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// 旧进程交给本进程的套接字
+#[cfg(unix)]
+fn inherited_fds() -> Vec<Listening> {
+    let Ok(value) = std::env::var(FDS_ENV) else {
+        return Vec::new();
+    };
+    value
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.splitn(3, '=');
+            let (service, addr, fd) = (parts.next()?, parts.next()?, parts.next()?);
+            Some(Listening {
+                service: service.to_string(),
+                addr: match addr {
+                    "*" => None,
+                    addr => Some(addr.parse().ok()?),
+                },
+                fd: fd.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+// 继承的属于 service、匹配地址为 addr 且尚未使用的套接字
+#[cfg(unix)]
+fn inherited(service: &str, addr: Option<SocketAddr>) -> Vec<TcpListener> {
+    use std::os::fd::FromRawFd;
+    let used: Vec<i32> = LISTENING.lock().unwrap().iter().map(|l| l.fd).collect();
+    let mut listeners = Vec::new();
+    for item in inherited_fds() {
+        if item.service != service || item.addr != addr || used.contains(&item.fd) {
+            continue;
+        }
+        // 恢复 close-on-exec，避免再传给其他子进程（再次升级时会重新清除）；描述符无效时跳过
+        if let Err(e) = set_cloexec(item.fd, true) {
+            log::warn!("旧进程交接的描述符 {} 无效: {}", item.fd, e);
+            continue;
+        }
+        // 描述符由旧进程在启动本进程前保留，只在这里取得所有权一次
+        let listener = unsafe { TcpListener::from_raw_fd(item.fd) };
+        match listener.local_addr() {
+            Ok(local) => log::info!("{} 使用旧进程交接的监听套接字: {}", service, local),
+            Err(_) => log::info!("{} 使用旧进程交接的监听套接字", service),
+        }
+        listeners.push(listener);
+    }
+    listeners
+}
+
+#[cfg(not(unix))]
+fn inherited(_service: &str, _addr: Option<SocketAddr>) -> Vec<TcpListener> {
+    Vec::new()
+}
+
+// 本进程由平滑升级启动时，发起升级的旧进程的 pid
+#[cfg(unix)]
+pub fn parent() -> Option<u32> {
+    let pid = std::env::var(PARENT_ENV).ok()?.parse::<u32>().ok()?;
+    (pid == std::os::unix::process::parent_id()).then_some(pid)
+}
+
+// 所有服务的监听套接字都已就绪：关闭未使用的继承套接字，通知 systemd 与发起升级的旧进程
+pub fn ready() {
+    #[cfg(unix)]
+    {
+        let listening = LISTENING.lock().unwrap().clone();
+        for item in inherited_fds() {
+            if !listening.iter().any(|l| l.fd == item.fd) {
+                log::info!("旧进程的 {} 套接字已不在配置中，关闭该套接字", item.service);
+                unsafe { libc::close(item.fd) };
+            }
+        }
+        let parent = parent();
+        crate::systemd::ready(parent.is_some());
+        if let Some(pid) = parent {
+            log::info!("已接管监听套接字，通知旧进程(pid {})退出", pid);
+            unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+        }
+    }
+    #[cfg(not(unix))]
+    crate::systemd::ready(false);
+    crate::daemon::ready(); // 写入 PID 文件，通知 --daemon 的前台进程退出
+}
+
+// 启动升级：监听 SIGUSR2
+pub fn start() {
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use std::sync::atomic::Ordering;
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut usr2 = match signal(SignalKind::user_defined2()) {
+            Ok(usr2) => usr2,
+            Err(e) => {
+                log::warn!("无法监听 SIGUSR2，平滑升级不可用: {}", e);
+                return;
+            }
+        };
+        while usr2.recv().await.is_some() {
+            if UPGRADING.swap(true, Ordering::SeqCst) {
+                log::warn!("收到 SIGUSR2，但上一次升级尚未完成，忽略");
+                continue;
+            }
+            log::info!("收到 SIGUSR2，启动新进程接管监听套接字");
+            if let Err(e) = spawn() {
+                UPGRADING.store(false, Ordering::SeqCst);
+                log::error!("平滑升级失败，继续运行: {}", e);
+            }
+        }
+    });
+}
+
+// 以相同的参数启动新进程，监听套接字的描述符在新进程中保持打开
+#[cfg(unix)]
+fn spawn() -> std::io::Result<()> {
+    use std::os::unix::process::CommandExt;
+    let listening = LISTENING.lock().unwrap().clone();
+    let fds = listening
+        .iter()
+        .map(|l| {
+            let addr = l.addr.map(|a| a.to_string()).unwrap_or("*".to_string());
+            format!("{}={}={}", l.service, addr, l.fd)
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    // 使用启动时的程序路径而不是 current_exe：可执行文件被新版本替换后，
+    // /proc/self/exe 指向的是已删除的旧文件
+    let mut args = std::env::args_os();
+    let program = match args.next() {
+        Some(program) => program,
+        None => std::env::current_exe()?.into_os_string(),
+    };
+    let raw: Vec<i32> = listening.iter().map(|l| l.fd).collect();
+    let mut command = std::process::Command::new(&program);
+    command
+        .args(args)
+        .env(FDS_ENV, fds)
+        .env(PARENT_ENV, std::process::id().to_string());
+    crate::systemd::child_env(&mut command);
+    // 在新进程 exec 之前清除监听套接字的 close-on-exec 标志；fcntl 在 fork 之后调用是安全的
+    unsafe {
+        command.pre_exec(move || {
+            for &fd in &raw {
+                set_cloexec(fd, false)?;
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn()?;
+    let pid = child.id();
+    log::info!("新进程已启动(pid {})，接管监听套接字后本进程将退出", pid);
+    // 新进程启动失败退出时恢复，允许再次升级；接管成功时本进程收到 SIGTERM 先退出
+    std::thread::spawn(move || {
+        let status = child.wait();
+        UPGRADING.store(false, std::sync::atomic::Ordering::SeqCst);
+        match status {
+            Ok(status) => log::error!("新进程(pid {})已退出({})，继续运行", pid, status),
+            Err(e) => log::error!("等待新进程(pid {})失败: {}", pid, e),
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,180 @@
+// ==================== 上游代理 ====================
+//
+// 只能经过企业 HTTP/HTTPS 代理访问外部网络时，[request] 的 proxy_url 让发往目标服务器的请求
+// 经过该代理（HTTPS 目标使用 CONNECT 隧道），地址中的用户名与密码用于代理的 Basic 认证。
+// 也可以是 SOCKS5 代理（socks5:// 在本地解析目标的域名，socks5h:// 交给代理解析），
+// 用于经 SSH 动态转发或 Tor 访问后端，用户名与密码用于 SOCKS5 的用户名密码认证。
+// no_proxy 中的目标直接连接：主机名匹配自身及其子域名（.corp.example 与 corp.example 相同），
+// IP 地址可以写成网段，* 表示全部。路由与单个目标可以用自己的 proxy_url 覆盖全局设置，
+// 写 "direct" 表示直接连接，目标的设置优先于路由。
+// 都没有配置时沿用 HTTP 客户端的默认行为，读取 HTTP_PROXY 与 HTTPS_PROXY 环境变量
+
+use crate::AppConfig;
+use crate::acl::IpNet;
+use crate::compose::Compose;
+use reqwest::Url;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+// 目标服务器配置中表示直接连接的 proxy_url
+const DIRECT: &str = "direct";
+
+// 不经代理的目标
+#[derive(Debug)]
+enum Bypass {
+    All,            // *
+    Domain(String), // 主机名及其子域名，小写
+    Net(IpNet),     // IP 网段
+}
+
+impl Bypass {
+    fn parse(rule: &str) -> Result<Self, String> {
+        let rule = rule.trim().to_ascii_lowercase();
+        if rule == "*" {
+            return Ok(Bypass::All);
+        }
+        if let Some(net) = IpNet::parse(rule.trim_start_matches('[').trim_end_matches(']')) {
+            return Ok(Bypass::Net(net));
+        }
+        let domain = rule.trim_start_matches('*').trim_start_matches('.');
+        match domain.is_empty() || domain.contains(['/', ':', '*']) {
+            true => Err(format!("无效的 no_proxy 规则: {:?}", rule)),
+            false => Ok(Bypass::Domain(domain.to_string())),
+        }
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            Bypass::All => true,
+            Bypass::Domain(domain) => {
+                host == domain
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|s| s.ends_with('.'))
+            }
+            Bypass::Net(net) => host.parse::<IpAddr>().is_ok_and(|ip| net.contains(ip)),
+        }
+    }
+}
+
+// 上游代理的选择规则
+#[derive(Debug)]
+pub struct UpstreamProxy {
+    default: Option<Url>,                          // 全局代理
+    bypass: Vec<Bypass>,                           // 不经全局代理的目标
+    targets: BTreeMap<(String, u16), Option<Url>>, // 按主机与端口覆盖，None 表示直接连接
+    explicit: bool,                                // 路由指定了代理或直接连接
+}
+
+impl UpstreamProxy {
+    // 从配置构建，代理地址或 no_proxy 规则无效、同一目标配置了不同的代理时启动失败
+    pub fn new(config: &AppConfig) -> Result<Self, String> {
+        let default = config.request.proxy_url.as_deref().map(parse).transpose()?;
+        let bypass = config
+            .request
+            .no_proxy
+            .iter()
+            .map(|r| Bypass::parse(r))
+            .collect::<Result<_, _>>()?;
+        let mut targets = BTreeMap::new();
+        let configured = config.routes.iter().flat_map(|r| {
+            r.target
+                .iter()
+                .chain(&r.targets)
+                .chain(r.failover_target.iter())
+                .chain(r.compose.iter().flat_map(Compose::targets))
+        });
+        for target in std::iter::once(&config.target).chain(configured) {
+            let Some(proxy_url) = &target.proxy_url else {
+                continue;
+            };
+            let proxy = match proxy_url.trim().eq_ignore_ascii_case(DIRECT) {
+                true => None,
+                false => Some(parse(proxy_url)?),
+            };
+            match targets.insert(key(&target.host, target.port), proxy.clone()) {
+                Some(existing) if existing != proxy => {
+                    return Err(format!(
+                        "目标 {} 配置了不同的上游代理",
+                        crate::addr::join(&target.host, target.port)
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(UpstreamProxy {
+            default,
+            bypass,
+            targets,
+            explicit: false,
+        })
+    }
+
+    // 路由的 proxy_url 代替全局代理，no_proxy 不再生效；"direct" 表示直接连接
+    pub fn for_route(mut self, proxy_url: Option<&str>) -> Result<Self, String> {
+        let Some(proxy_url) = proxy_url else {
+            return Ok(self);
+        };
+        self.default = match proxy_url.trim().eq_ignore_ascii_case(DIRECT) {
+            true => None,
+            false => Some(parse(proxy_url)?),
+        };
+        self.bypass.clear();
+        self.explicit = true;
+        Ok(self)
+    }
+
+    // 是否配置了上游代理；没有配置时不设置，HTTP 客户端按环境变量选择代理
+    pub fn is_configured(&self) -> bool {
+        self.explicit || self.default.is_some() || !self.targets.is_empty()
+    }
+
+    // 选择请求使用的代理，None 表示直接连接
+    pub fn select(&self, url: &Url) -> Option<Url> {
+        let host = url
+            .host_str()?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_ascii_lowercase();
+        let port = url.port_or_known_default()?;
+        if let Some(proxy) = self.targets.get(&(host.clone(), port)) {
+            return proxy.clone();
+        }
+        let default = self.default.as_ref()?;
+        match self.bypass.iter().any(|b| b.matches(&host)) {
+            true => None,
+            false => Some(default.clone()),
+        }
+    }
+
+    // 用于日志的说明，隐藏代理地址中的密码
+    pub fn describe(&self) -> String {
+        let default = self
+            .default
+            .as_ref()
+            .map_or("无".to_string(), crate::redact::url);
+        match self.targets.len() {
+            0 => default,
+            n => format!("{}，{} 个目标单独配置", default, n),
+        }
+    }
+}
+
+// 目标的主机与端口，主机为小写且不带 IPv6 的方括号
+fn key(host: &str, port: u16) -> (String, u16) {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    (host.to_ascii_lowercase(), port)
+}
+
+// 解析代理地址，只接受 http、https、socks5 与 socks5h
+fn parse(proxy_url: &str) -> Result<Url, String> {
+    let url = Url::parse(proxy_url.trim()).map_err(|e| format!("无效的上游代理地址: {}", e))?;
+    if !matches!(url.scheme(), "http" | "https" | "socks5" | "socks5h") || url.host_str().is_none()
+    {
+        return Err(format!(
+            "上游代理地址应为 http://、https://、socks5:// 或 socks5h:// 加主机与端口: {}",
+            crate::redact::url(&url)
+        ));
+    }
+    Ok(url)
+}
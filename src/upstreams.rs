@@ -0,0 +1,182 @@
+// ==================== 运行时注册的上游 ====================
+//
+// 管理接口可以在运行时为路由注册或注销目标服务器，编排脚本不修改配置文件即可管理后端池。
+// 记录的是相对于配置的增减：路由生效的目标服务器为配置中的目标去掉已注销的，再加上已注册的；
+// 热加载后对新的路由表重新应用。配置了 state_file 时每次修改都先写入该文件，重启后恢复。
+// 路由至少保留一个目标服务器
+
+use crate::TargetConfig;
+use crate::routes::{Route, RouteTable};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+// 运行时上游配置：对应配置文件中的 [upstreams]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UpstreamsConfig {
+    #[serde(default)]
+    pub state_file: Option<String>, // 保存运行时增减的 JSON 文件，缺省只保存在内存中
+}
+
+// 一个路由相对于配置的增减
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Changes {
+    #[serde(default)]
+    pub added: Vec<TargetConfig>, // 通过管理接口注册的目标服务器
+    #[serde(default)]
+    pub removed: Vec<TargetConfig>, // 通过管理接口注销的配置中的目标服务器
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+
+    // 应用到配置中的目标服务器；配置修改后可能全部被注销，此时保留配置中的目标
+    fn resolve(&self, configured: &[TargetConfig]) -> Vec<TargetConfig> {
+        let mut targets: Vec<TargetConfig> = configured
+            .iter()
+            .filter(|t| !self.removed.contains(t))
+            .cloned()
+            .collect();
+        for target in &self.added {
+            if !targets.contains(target) {
+                targets.push(target.clone());
+            }
+        }
+        match targets.is_empty() {
+            true => configured.to_vec(),
+            false => targets,
+        }
+    }
+}
+
+// 所有路由的运行时增减，按路由名称索引
+pub struct Registry {
+    state_file: Option<String>,
+    changes: Mutex<BTreeMap<String, Changes>>,
+}
+
+impl Registry {
+    // 创建注册表，state_file 存在时读取其中保存的增减
+    pub fn new(config: &UpstreamsConfig) -> Result<Self, String> {
+        let changes = match &config.state_file {
+            Some(path) if std::path::Path::new(path).exists() => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| format!("无法读取上游状态文件 {}: {}", path, e))?;
+                serde_json::from_str(&text)
+                    .map_err(|e| format!("无法解析上游状态文件 {}: {}", path, e))?
+            }
+            _ => BTreeMap::new(),
+        };
+        Ok(Registry {
+            state_file: config.state_file.clone(),
+            changes: Mutex::new(changes),
+        })
+    }
+
+    // 把记录的增减应用到路由表中的每个路由，启动与热加载时调用
+    pub fn apply(&self, table: &RouteTable) {
+        let changes = self.changes.lock().unwrap();
+        for route in table.iter() {
+            let targets = changes
+                .get(&route.name)
+                .map(|c| c.resolve(&route.configured))
+                .unwrap_or_else(|| route.configured.clone());
+            route.set_targets(targets);
+        }
+        for name in changes.keys() {
+            if !table.iter().any(|r| r.name == *name) {
+                log::warn!("运行时注册的上游所属的路由 {} 已不在配置中，暂不生效", name);
+            }
+        }
+    }
+
+    // 路由当前的增减
+    pub fn changes(&self, route: &str) -> Changes {
+        self.changes
+            .lock()
+            .unwrap()
+            .get(route)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // 注册目标服务器，已经生效时返回 false
+    pub fn add(&self, route: &Route, target: TargetConfig) -> Result<bool, String> {
+        if route.targets().contains(&target) {
+            return Ok(false);
+        }
+        self.update(route, |changes| {
+            match changes.removed.iter().position(|t| *t == target) {
+                Some(i) => {
+                    changes.removed.remove(i);
+                }
+                None => changes.added.push(target.clone()),
+            }
+            Ok(())
+        })?;
+        log::warn!(
+            "管理接口为路由 {} 注册上游 {}",
+            route.name,
+            target.base_url()
+        );
+        Ok(true)
+    }
+
+    // 注销目标服务器，不在生效的目标中时返回 false；不能注销最后一个
+    pub fn remove(&self, route: &Route, target: &TargetConfig) -> Result<bool, String> {
+        let targets = route.targets();
+        if !targets.contains(target) {
+            return Ok(false);
+        }
+        if targets.len() == 1 {
+            return Err(format!("路由 {} 至少需要保留一个目标服务器", route.name));
+        }
+        self.update(route, |changes| {
+            match changes.added.iter().position(|t| t == target) {
+                Some(i) => {
+                    changes.added.remove(i);
+                }
+                None => changes.removed.push(target.clone()),
+            }
+            Ok(())
+        })?;
+        log::warn!(
+            "管理接口为路由 {} 注销上游 {}",
+            route.name,
+            target.base_url()
+        );
+        Ok(true)
+    }
+
+    // 修改路由的增减：先写入状态文件，成功后才替换内存中的记录与路由的目标服务器
+    fn update(
+        &self,
+        route: &Route,
+        change: impl FnOnce(&mut Changes) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let mut guard = self.changes.lock().unwrap();
+        let mut changes = guard.clone();
+        let entry = changes.entry(route.name.clone()).or_default();
+        change(entry)?;
+        let targets = entry.resolve(&route.configured);
+        changes.retain(|_, c| !c.is_empty());
+        self.save(&changes)?;
+        *guard = changes;
+        route.set_targets(targets);
+        Ok(())
+    }
+
+    // 写入状态文件：先写临时文件再改名，避免进程中途退出留下不完整的文件
+    fn save(&self, changes: &BTreeMap<String, Changes>) -> Result<(), String> {
+        let Some(path) = &self.state_file else {
+            return Ok(());
+        };
+        let text = serde_json::to_string_pretty(changes).map_err(|e| e.to_string())?;
+        let temp = format!("{}.tmp", path);
+        std::fs::write(&temp, text)
+            .and_then(|_| std::fs::rename(&temp, path))
+            .map_err(|e| format!("无法写入上游状态文件 {}: {}", path, e))
+    }
+}
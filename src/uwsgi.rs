@@ -0,0 +1,186 @@
+// ==================== uwsgi ====================
+//
+// 目标服务器的 protocol 为 "uwsgi" 时，请求按 uWSGI 的 uwsgi 二进制协议发给应用服务器（uwsgi --socket），
+// Django、Flask 等 Python 应用不必再在前面部署 nginx。请求转换为 WSGI 环境变量（见 cgi.rs），
+// 与请求体一起作为一个 uwsgi 数据包发送，应用服务器返回的 HTTP 响应原样转换后返回客户端。
+// 路由 [routes.uwsgi] 的 script_name 为应用的挂载路径：请求路径去掉它之后作为 PATH_INFO，
+// 应用生成的链接会带上这个前缀
+
+use crate::ProxyError;
+use crate::cgi::{self, Marked, Param};
+use actix_web::HttpRequest;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+// uwsgi 配置：对应配置文件中的 [routes.uwsgi]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UwsgiConfig {
+    #[serde(default)]
+    pub script_name: String, // 应用的挂载路径，如 "/app"，默认为空（挂载在根路径）
+    #[serde(default)]
+    pub socket: Option<String>, // 应用服务器的 Unix 套接字，配置后代替目标的主机与端口
+    #[serde(default)]
+    pub params: Vec<Param>, // 附加或覆盖的环境变量，如 UWSGI_SCRIPT
+}
+
+// 目标服务器配置中表示 uwsgi 的协议
+pub const PROTOCOL: &str = "uwsgi";
+
+// uwsgi 数据包中环境变量部分的最大长度
+const MAX_VARS: usize = u16::MAX as usize;
+
+// 路由的 uwsgi 设置
+#[derive(Debug)]
+pub struct Uwsgi {
+    script_name: String, // 不含末尾的 /
+    socket: Option<String>,
+    params: Vec<(String, String)>,
+}
+
+impl Uwsgi {
+    // 从配置构建，挂载路径不以 / 开头、参数名为空或当前平台不支持 Unix 套接字时启动失败
+    pub fn new(config: &UwsgiConfig) -> Result<Self, String> {
+        let script_name = config.script_name.trim_end_matches('/');
+        if !script_name.is_empty() && !script_name.starts_with('/') {
+            return Err(format!(
+                "uwsgi 的 script_name 必须以/开头: {}",
+                config.script_name
+            ));
+        }
+        cgi::check_socket(&config.socket)?;
+        Ok(Uwsgi {
+            script_name: script_name.to_string(),
+            socket: config.socket.clone(),
+            params: cgi::extra_params(&config.params).map_err(|e| format!("uwsgi {}", e))?,
+        })
+    }
+
+    // 为发往 uwsgi 目标的请求加上标记，url 为发往上游的地址
+    pub fn mark(
+        &self,
+        builder: reqwest::RequestBuilder,
+        req: &HttpRequest,
+        url: &reqwest::Url,
+        forwarded: &crate::forwarded::ForwardedPolicy,
+    ) -> reqwest::RequestBuilder {
+        let path = cgi::decode_path(url.path());
+        let path_info = match path.strip_prefix(&self.script_name) {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => rest.to_string(),
+            _ => path.clone(),
+        };
+        let mut params = cgi::server_params(req, forwarded);
+        params.extend([
+            ("SCRIPT_NAME", self.script_name.clone()),
+            ("PATH_INFO", path_info),
+        ]);
+        let marked = Marked::new(self.socket.clone(), params, self.params.clone());
+        cgi::mark(builder, req, marked)
+    }
+}
+
+// 建立连接并完成一次请求，返回应用服务器的 HTTP 响应
+pub async fn send(
+    marked: &Marked,
+    request: &reqwest::Request,
+) -> Result<reqwest::Response, ProxyError> {
+    let mut vars = Vec::new();
+    for (name, value) in &marked.params(request) {
+        for part in [name.as_bytes(), value.as_bytes()] {
+            vars.extend_from_slice(&(part.len().min(MAX_VARS) as u16).to_le_bytes());
+            vars.extend_from_slice(part);
+        }
+    }
+    if vars.len() > MAX_VARS {
+        return Err(ProxyError::InvalidHeader(format!(
+            "uwsgi 环境变量共 {} 字节，超过 {} 字节",
+            vars.len(),
+            MAX_VARS
+        )));
+    }
+    // 数据包头：modifier1（0 为 WSGI）、环境变量长度（小端）与 modifier2
+    let mut packet = vec![0];
+    packet.extend_from_slice(&(vars.len() as u16).to_le_bytes());
+    packet.push(0);
+    packet.extend_from_slice(&vars);
+    packet.extend_from_slice(cgi::body(request));
+
+    let mut stream = cgi::connect(marked, request.url()).await?;
+    stream
+        .write_all(&packet)
+        .await
+        .map_err(|e| ProxyError::InvalidAppResponse(format!("发送请求失败: {}", e)))?;
+    let mut raw = Vec::new();
+    stream
+        .read_to_end(&mut raw)
+        .await
+        .map_err(|e| ProxyError::InvalidAppResponse(format!("读取响应失败: {}", e)))?;
+    response(&raw).map_err(ProxyError::InvalidAppResponse)
+}
+
+// 解析应用服务器返回的 HTTP/1.x 响应；分块编码的响应体在这里解码
+fn response(raw: &[u8]) -> Result<reqwest::Response, String> {
+    let Some(end) = raw.windows(4).position(|w| w == b"\r\n\r\n") else {
+        return Err(match raw.is_empty() {
+            true => "应用服务器没有返回响应".to_string(),
+            false => "应用服务器的响应中没有完整的响应头".to_string(),
+        });
+    };
+    let head = std::str::from_utf8(&raw[..end]).map_err(|_| "响应头不是文本".to_string())?;
+    let mut body = raw[end + 4..].to_vec();
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or_default();
+    let status = match status_line.split(' ').collect::<Vec<_>>().as_slice() {
+        [version, code, ..] if version.starts_with("HTTP/") => code
+            .parse::<u16>()
+            .map_err(|_| format!("无效的状态行: {:?}", status_line))?,
+        _ => return Err(format!("无效的状态行: {:?}", status_line)),
+    };
+    let mut builder = hyper::Response::builder().status(status);
+    let mut chunked = false;
+    let mut length = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(format!("无效的响应头: {:?}", line));
+        };
+        let (name, value) = (name.trim().to_ascii_lowercase(), value.trim());
+        match name.as_str() {
+            "transfer-encoding" if value.eq_ignore_ascii_case("chunked") => chunked = true,
+            "content-length" => length = value.parse::<usize>().ok(),
+            _ => builder = builder.header(name.as_str(), value),
+        }
+    }
+    if chunked {
+        body = dechunk(&body)?;
+    } else if let Some(length) = length {
+        if body.len() < length {
+            return Err(format!("响应体只有 {} 字节，少于 {}", body.len(), length));
+        }
+        body.truncate(length);
+    }
+    let response = builder.body(body).map_err(|e| e.to_string())?;
+    Ok(reqwest::Response::from(response))
+}
+
+// 解码分块编码的响应体，忽略块扩展与尾部
+fn dechunk(mut data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = data
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or("分块编码的响应体不完整")?;
+        let size = std::str::from_utf8(&data[..line_end])
+            .ok()
+            .and_then(|line| usize::from_str_radix(line.split(';').next()?.trim(), 16).ok())
+            .ok_or("无效的块大小")?;
+        data = &data[line_end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        if data.len() < size + 2 {
+            return Err("分块编码的响应体不完整".to_string());
+        }
+        body.extend_from_slice(&data[..size]);
+        data = &data[size + 2..];
+    }
+}
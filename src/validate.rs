@@ -0,0 +1,349 @@
+// ==================== 配置校验 ====================
+//
+// check 子命令使用：配置能够解析之后，再检查端口范围、URL 语法、正则表达式和引用的文件，
+// 每个问题带上出错配置项的位置（如 routes[1].targets[0].port），一次列出全部问题，便于在 CI 中使用。
+// 端口范围在反序列化之前就检查，避免超出范围的数值被截断成另一个端口
+
+use crate::{AppConfig, TargetConfig};
+use config::{Map, Value, ValueKind};
+use std::fmt::Display;
+use std::path::Path;
+
+// 收集到的问题，每项为 "位置: 说明"
+#[derive(Debug, Default)]
+pub struct Problems(Vec<String>);
+
+impl Problems {
+    pub fn add(&mut self, location: impl Display, message: impl Display) {
+        // 多行的说明（如正则的错误提示）合并为一行
+        let message = message.to_string();
+        let message = message.split_whitespace().collect::<Vec<_>>().join(" ");
+        self.0.push(format!("{}: {}", location, message));
+    }
+
+    // 记录 Result 中的错误
+    pub fn check<T>(&mut self, location: impl Display, result: Result<T, String>) {
+        if let Err(e) = result {
+            self.add(location, e);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn into_vec(self) -> Vec<String> {
+        self.0
+    }
+}
+
+// 检查配置树中所有名为 port 或以 _port 结尾的整数项是否超出 u16 范围；加载配置时调用，
+// 为 0 的端口能正常解析，由 check 报告
+pub fn port_ranges(table: &Map<String, Value>) -> Problems {
+    fn walk(problems: &mut Problems, path: &str, value: &Value) {
+        match &value.kind {
+            ValueKind::Table(table) => {
+                for (key, child) in table {
+                    let child_path = match path.is_empty() {
+                        true => key.clone(),
+                        false => format!("{}.{}", path, key),
+                    };
+                    walk(problems, &child_path, child);
+                }
+            }
+            ValueKind::Array(array) => {
+                for (index, child) in array.iter().enumerate() {
+                    walk(problems, &format!("{}[{}]", path, index), child);
+                }
+            }
+            _ if path == "port" || path.ends_with(".port") || path.ends_with("_port") => {
+                if let Ok(port) = value.clone().into_int()
+                    && !(0..=65535).contains(&port)
+                {
+                    problems.add(path, format!("端口必须在 1-65535 之间: {}", port));
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut problems = Problems::default();
+    walk(
+        &mut problems,
+        "",
+        &Value::new(None, ValueKind::Table(table.clone())),
+    );
+    problems
+}
+
+// 检查整个配置
+pub fn check(config: &AppConfig) -> Problems {
+    let mut problems = Problems::default();
+    problems.check("server.port", port(config.server.port));
+    problems.check("server.host", hosts(&config.server.host));
+    if config.server.workers == Some(0) {
+        problems.add("server.workers", "工作线程数必须大于 0");
+    }
+    if config.server.max_connections == 0 {
+        problems.add("server.max_connections", "最大连接数必须大于 0");
+    }
+    if config.server.backlog == 0 {
+        problems.add("server.backlog", "监听队列长度必须大于 0");
+    }
+    target(&mut problems, "target", &config.target);
+    if config.routes.is_empty() {
+        problems.check("proxy.path_prefix", path_prefix(&config.proxy.path_prefix));
+    }
+
+    for (i, route) in config.routes.iter().enumerate() {
+        let at = format!("routes[{}]", i);
+        if route.name.is_empty() {
+            problems.add(format!("{}.name", at), "路由名称不能为空");
+        } else if config.routes[..i].iter().any(|r| r.name == route.name) {
+            problems.add(
+                format!("{}.name", at),
+                format!("路由名称重复: {}", route.name),
+            );
+        }
+        problems.check(
+            format!("{}.path_prefix", at),
+            path_prefix(&route.path_prefix),
+        );
+        if let Some(t) = &route.target {
+            target(&mut problems, &format!("{}.target", at), t);
+        }
+        for (j, t) in route.targets.iter().enumerate() {
+            target(&mut problems, &format!("{}.targets[{}]", at, j), t);
+        }
+        if let Some(t) = &route.failover_target {
+            target(&mut problems, &format!("{}.failover_target", at), t);
+        }
+        for (j, sub) in route.compose.iter().flat_map(|c| &c.requests).enumerate() {
+            if let Some(t) = &sub.target {
+                target(
+                    &mut problems,
+                    &format!("{}.compose.requests[{}].target", at, j),
+                    t,
+                );
+            }
+        }
+        if let Some(file) = route.fallback.as_ref().and_then(|f| f.file.as_ref()) {
+            problems.check(format!("{}.fallback.file", at), existing_file(file));
+        }
+        if let Some(cache) = &route.cache {
+            if let Some(disk) = &cache.disk {
+                problems.check(format!("{}.cache.disk.path", at), directory(&disk.path));
+            }
+            if let Some(redis) = &cache.redis {
+                problems.check(
+                    format!("{}.cache.redis.address", at),
+                    address(&redis.address),
+                );
+            }
+        }
+    }
+
+    for (i, listener) in config.listeners.iter().enumerate() {
+        let at = format!("listeners[{}]", i);
+        problems.check(format!("{}.name", at), listener_name(&listener.name));
+        if config.listeners[..i]
+            .iter()
+            .any(|l| l.name == listener.name)
+        {
+            problems.add(
+                format!("{}.name", at),
+                format!("监听名称重复: {}", listener.name),
+            );
+        }
+        problems.check(format!("{}.port", at), port(listener.port));
+        problems.check(format!("{}.host", at), hosts(&listener.host));
+        if let Some(tls) = &listener.tls {
+            problems.check(format!("{}.tls.cert", at), existing_file(&tls.cert));
+            problems.check(format!("{}.tls.key", at), existing_file(&tls.key));
+        }
+        for name in &listener.routes {
+            let exists = match config.routes.is_empty() {
+                true => name == "default",
+                false => config.routes.iter().any(|r| &r.name == name),
+            };
+            if !exists {
+                problems.add(format!("{}.routes", at), format!("路由不存在: {}", name));
+            }
+        }
+        if let Some(redirect) = listener.redirect_https {
+            problems.check(format!("{}.redirect_https", at), port(redirect));
+        }
+    }
+
+    if config.admin.enabled {
+        problems.check("admin.port", port(config.admin.port));
+        problems.check("admin.host", hosts(&config.admin.host));
+        if config.admin.port != 0 && config.admin.port == config.server.port {
+            problems.add(
+                "admin.port",
+                format!("与 server.port 相同({})", config.server.port),
+            );
+        }
+    }
+    if config.startup_probe.enabled && config.startup_probe.timeout_ms == 0 {
+        problems.add("startup_probe.timeout_ms", "探测超时必须大于 0");
+    }
+    if let Some(file) = &config.upstreams.state_file {
+        problems.check("upstreams.state_file", writable_file(file));
+    }
+    if config.audit.enabled {
+        problems.check("audit.path", writable_file(&config.audit.path));
+    }
+    if let Some(file) = &config.access_log.file {
+        problems.check("access_log.file", writable_file(file));
+    }
+    if config.tracing.enabled {
+        problems.check("tracing.endpoint", http_url(&config.tracing.endpoint));
+    }
+    if let Some(dsn) = &config.sentry.dsn {
+        problems.check("sentry.dsn", crate::sentry::parse_dsn(dsn).map(|_| ()));
+    }
+    if config.metrics.statsd.enabled {
+        problems.check(
+            "metrics.statsd.address",
+            address(&config.metrics.statsd.address),
+        );
+    }
+
+    problems.check("log.level", crate::log_level::validate(&config.log.level));
+    for (i, sink) in config.log.sinks.iter().enumerate() {
+        let at = format!("log.sinks[{}]", i);
+        match sink.kind {
+            crate::log_sink::SinkKind::Http => match &sink.url {
+                Some(url) => problems.check(format!("{}.url", at), http_url(url)),
+                None => problems.add(format!("{}.url", at), "http 输出需要设置 url"),
+            },
+            _ => match &sink.address {
+                Some(_) if matches!(sink.protocol, crate::log_sink::SyslogProtocol::Unix) => {}
+                Some(value) => problems.check(format!("{}.address", at), address(value)),
+                None => problems.add(format!("{}.address", at), "需要设置 address"),
+            },
+        }
+    }
+    for (i, pattern) in config.capture.redact_patterns.iter().enumerate() {
+        problems.check(
+            format!("capture.redact_patterns[{}]", i),
+            regex::Regex::new(pattern)
+                .map(|_| ())
+                .map_err(|e| format!("无效的正则: {}", e)),
+        );
+    }
+    problems
+}
+
+// 检查目标服务器的协议、主机和端口
+fn target(problems: &mut Problems, at: &str, target: &TargetConfig) {
+    if !matches!(
+        target.protocol.as_str(),
+        "http" | "https" | "fastcgi" | "uwsgi"
+    ) {
+        problems.add(
+            format!("{}.protocol", at),
+            format!("只支持 http、https、fastcgi 或 uwsgi: {}", target.protocol),
+        );
+    }
+    problems.check(format!("{}.host", at), host(&target.host));
+    problems.check(format!("{}.port", at), port(target.port));
+}
+
+// 监听名称用于 systemd 套接字名称与平滑升级时的交接，只能包含字母、数字、- 和 _
+fn listener_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    match (valid, name) {
+        (false, _) => Err(format!("监听名称只能包含字母、数字、- 和 _: {:?}", name)),
+        (true, "admin") => Err("admin 是管理服务使用的名称".to_string()),
+        _ => Ok(()),
+    }
+}
+
+fn port(port: u16) -> Result<(), String> {
+    match port {
+        0 => Err("端口必须在 1-65535 之间".to_string()),
+        _ => Ok(()),
+    }
+}
+
+// 主机名或 IP 地址，IPv6 地址可以带方括号
+fn host(host: &str) -> Result<(), String> {
+    let bare = host.trim_start_matches('[').trim_end_matches(']');
+    let valid = bare.parse::<std::net::IpAddr>().is_ok()
+        || (!host.is_empty()
+            && !host.contains(['/', ':', '@', '?', '#', ' '])
+            && reqwest::Url::parse(&format!("http://{}/", host)).is_ok());
+    match valid {
+        true => Ok(()),
+        false => Err(format!("无效的主机名: {:?}", host)),
+    }
+}
+
+// 监听主机列表：逗号分隔的一个或多个主机名或 IP 地址
+fn hosts(value: &str) -> Result<(), String> {
+    let list = crate::addr::hosts(value);
+    if list.is_empty() {
+        return Err("监听地址不能为空".to_string());
+    }
+    list.into_iter().try_for_each(host)
+}
+
+fn path_prefix(prefix: &str) -> Result<(), String> {
+    match prefix.is_empty() || prefix.starts_with('/') {
+        true => Ok(()),
+        false => Err(format!("路径前缀必须以/开头: {}", prefix)),
+    }
+}
+
+// host:port 形式的地址
+fn address(value: &str) -> Result<(), String> {
+    let (h, p) = value
+        .rsplit_once(':')
+        .ok_or(format!("地址应为 host:port 形式: {}", value))?;
+    host(h).map_err(|e| format!("{}: {}", value, e))?;
+    match p.parse::<u16>() {
+        Ok(p) if p != 0 => Ok(()),
+        _ => Err(format!("无效的端口: {}", value)),
+    }
+}
+
+fn http_url(value: &str) -> Result<(), String> {
+    let url = reqwest::Url::parse(value).map_err(|e| format!("无效的URL {}: {}", value, e))?;
+    match url.scheme() {
+        "http" | "https" if url.host_str().is_some() => Ok(()),
+        _ => Err(format!("URL 应以 http:// 或 https:// 开头: {}", value)),
+    }
+}
+
+fn existing_file(path: &str) -> Result<(), String> {
+    match Path::new(path).is_file() {
+        true => Ok(()),
+        false => Err(format!("文件不存在: {}", path)),
+    }
+}
+
+// 目录可以尚不存在（启动时创建），但不能是普通文件
+fn directory(path: &str) -> Result<(), String> {
+    match Path::new(path).is_file() {
+        true => Err(format!("不是目录: {}", path)),
+        false => Ok(()),
+    }
+}
+
+// 追加写入的文件：所在目录必须存在，路径本身不能是目录
+fn writable_file(path: &str) -> Result<(), String> {
+    let file = Path::new(path);
+    if file.is_dir() {
+        return Err(format!("是一个目录: {}", path));
+    }
+    match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() && !dir.is_dir() => {
+            Err(format!("所在目录不存在: {}", dir.display()))
+        }
+        _ => Ok(()),
+    }
+}
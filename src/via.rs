@@ -0,0 +1,149 @@
+// ==================== Via 与转发环路检测 ====================
+//
+// 启用 [via] 后，发往上游的请求与返回客户端的响应都在 Via 末尾追加本跳（RFC 9110 第 7.6.3 节），
+// 如 "1.1 proxy-a"，协议版本为该方向上收到的报文的版本；可选同时设置 X-Proxied-By，排查问题时可以看出
+// 请求经过了哪个代理。收到的请求的 Via 中已有本代理的标识时，说明请求被转发回了自己（例如上游配置成了
+// 代理自身的地址），直接返回 508，不再转发。同一条链路上的每个实例需要使用不同的 pseudonym
+
+use crate::ProxyError;
+use actix_web::HttpRequest;
+use actix_web::http::Version;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+
+// Via 配置：对应配置文件中的 [via]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ViaConfig {
+    #[serde(default)]
+    pub enabled: bool, // 是否追加 Via
+    #[serde(default = "default_pseudonym")]
+    pub pseudonym: String, // 本代理在 Via 中的标识，缺省为主机名
+    #[serde(default)]
+    pub proxied_by: bool, // 是否同时设置 X-Proxied-By
+    #[serde(default = "default_true")]
+    pub reject_loops: bool, // 请求的 Via 中已有本代理的标识时是否返回 508
+}
+
+impl Default for ViaConfig {
+    fn default() -> Self {
+        ViaConfig {
+            enabled: false,
+            pseudonym: default_pseudonym(),
+            proxied_by: false,
+            reject_loops: true,
+        }
+    }
+}
+
+// 以下函数为 Via 配置提供默认值
+fn default_pseudonym() -> String {
+    match crate::log_sink::hostname() {
+        "-" => "rust_proxy".to_string(),
+        host => host.to_string(),
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+const VIA: HeaderName = HeaderName::from_static("via");
+const X_PROXIED_BY: HeaderName = HeaderName::from_static("x-proxied-by");
+
+// Via 策略
+#[derive(Debug)]
+pub struct ViaPolicy {
+    config: ViaConfig,
+}
+
+impl ViaPolicy {
+    // 从配置构建策略，标识不是合法的 token 时启动失败
+    pub fn new(config: &ViaConfig) -> Result<Self, String> {
+        let token = !config.pseudonym.is_empty()
+            && config
+                .pseudonym
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~:".contains(c));
+        if config.enabled && !token {
+            return Err(format!(
+                "无效的 Via 标识: {:?}（只能包含字母、数字与 -._ 等符号，不能有空格与逗号）",
+                config.pseudonym
+            ));
+        }
+        Ok(ViaPolicy {
+            config: config.clone(),
+        })
+    }
+
+    // 请求头是否由本策略重写，复制客户端请求头时跳过
+    pub fn rewrites(&self, name: &HeaderName) -> bool {
+        self.config.enabled && (*name == VIA || (self.config.proxied_by && *name == X_PROXIED_BY))
+    }
+
+    // 检查转发环路：请求的 Via 中已有本代理的标识时返回错误
+    pub fn check(&self, req: &HttpRequest) -> Result<(), ProxyError> {
+        if !self.config.enabled || !self.config.reject_loops {
+            return Ok(());
+        }
+        let looped = req
+            .headers()
+            .get_all(VIA)
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .filter_map(|hop| hop.split_whitespace().nth(1))
+            .any(|received_by| received_by.eq_ignore_ascii_case(&self.config.pseudonym));
+        match looped {
+            true => Err(ProxyError::LoopDetected(self.config.pseudonym.clone())),
+            false => Ok(()),
+        }
+    }
+
+    // 发往上游的 Via 与 X-Proxied-By；未启用时为空
+    pub fn request_headers(&self, req: &HttpRequest) -> HeaderMap {
+        self.headers(req.headers().get_all(VIA), req.version())
+    }
+
+    // 返回客户端的 Via 与 X-Proxied-By，追加在上游响应的 Via 之后；未启用时为空
+    pub fn response_headers(&self, upstream: &HeaderMap, version: Version) -> HeaderMap {
+        self.headers(upstream.get_all(VIA), version)
+    }
+
+    fn headers<'a>(
+        &self,
+        received: impl IntoIterator<Item = &'a HeaderValue>,
+        version: Version,
+    ) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if !self.config.enabled {
+            return headers;
+        }
+        let mut hops: Vec<&str> = received
+            .into_iter()
+            .filter_map(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .collect();
+        let ours = format!("{} {}", protocol(version), self.config.pseudonym);
+        hops.push(&ours);
+        if let Ok(value) = HeaderValue::from_str(&hops.join(", ")) {
+            headers.insert(VIA, value);
+        }
+        if self.config.proxied_by
+            && let Ok(value) = HeaderValue::from_str(&self.config.pseudonym)
+        {
+            headers.insert(X_PROXIED_BY, value);
+        }
+        headers
+    }
+}
+
+// Via 中的协议版本，HTTP 可以省略协议名
+fn protocol(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "0.9",
+        Version::HTTP_10 => "1.0",
+        Version::HTTP_2 => "2",
+        Version::HTTP_3 => "3",
+        _ => "1.1",
+    }
+}
@@ -0,0 +1,186 @@
+// ==================== Windows 服务 ====================
+//
+// install-service 把程序注册为自动启动的 Windows 服务（名称 rust_proxy），启动参数为 run-service 与
+// 安装时的 --config（转换为绝对路径）、--profile 等选项；uninstall-service 停止并删除服务。
+// 服务控制管理器以 run-service 启动进程，监听全部就绪后报告为运行中，收到停止或关机通知时
+// 与 Ctrl+C 一样平滑停止。服务的标准输出不可见，日志应通过 [[log.sinks]] 输出
+
+use crate::cli::Cli;
+use std::ffi::OsString;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, OnceLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{
+    self, ServiceControlHandlerResult, ServiceStatusHandle,
+};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+
+// 服务名称
+pub const SERVICE_NAME: &str = "rust_proxy";
+
+// 服务控制管理器的状态句柄，由 run-service 启动时设置
+static STATUS: OnceLock<ServiceStatusHandle> = OnceLock::new();
+
+// 收到停止或关机通知
+static STOPPING: AtomicBool = AtomicBool::new(false);
+static STOP: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+define_windows_service!(ffi_service_main, service_main);
+
+// run-service：连接服务控制管理器并运行服务，服务停止后返回
+pub fn run() -> Result<(), String> {
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main).map_err(|e| {
+        format!(
+            "无法连接服务控制管理器（run-service 只能由服务控制管理器启动）: {}",
+            e
+        )
+    })
+}
+
+// 服务线程：注册控制处理函数，在本线程上运行代理直到停止
+fn service_main(_arguments: Vec<OsString>) {
+    let handler = service_control_handler::register(SERVICE_NAME, |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            set_status(ServiceState::StopPending, ServiceExitCode::Win32(0));
+            STOPPING.store(true, Ordering::SeqCst);
+            STOP.notify_waiters();
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    });
+    let Ok(handle) = handler else {
+        return;
+    };
+    let _ = STATUS.set(handle);
+    set_status(ServiceState::StartPending, ServiceExitCode::Win32(0));
+    let result = actix_web::rt::System::new().block_on(crate::run());
+    let code = match result {
+        Ok(()) => 0,
+        Err(_) => 1,
+    };
+    set_status(
+        ServiceState::Stopped,
+        ServiceExitCode::ServiceSpecific(code),
+    );
+}
+
+// 报告服务状态；不是由服务控制管理器启动时不做任何事
+fn set_status(state: ServiceState, exit_code: ServiceExitCode) {
+    let Some(handle) = STATUS.get() else {
+        return;
+    };
+    let controls_accepted = match state {
+        ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        _ => ServiceControlAccept::empty(),
+    };
+    let status = ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted,
+        exit_code,
+        checkpoint: 0,
+        wait_hint: Duration::from_secs(30),
+        process_id: None,
+    };
+    if let Err(e) = handle.set_service_status(status) {
+        log::warn!("无法向服务控制管理器报告状态: {}", e);
+    }
+}
+
+// 所有服务都已监听，报告为运行中
+pub fn running() {
+    set_status(ServiceState::Running, ServiceExitCode::Win32(0));
+}
+
+// 收到服务控制管理器的停止通知时平滑停止 server
+pub fn stop_with(server: actix_web::dev::ServerHandle) {
+    actix_web::rt::spawn(async move {
+        let notified = STOP.notified();
+        if !STOPPING.load(Ordering::SeqCst) {
+            notified.await;
+        }
+        server.stop(true).await;
+    });
+}
+
+// install-service：注册服务，返回服务的启动参数
+pub fn install(args: &Cli) -> Result<String, String> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .map_err(|e| format!("无法连接服务控制管理器（需要以管理员身份运行）: {}", e))?;
+    let executable_path =
+        std::env::current_exe().map_err(|e| format!("无法取得程序路径: {}", e))?;
+    // 服务的当前目录为系统目录，配置文件转换为绝对路径
+    let config = std::path::absolute(&crate::config_file().0)
+        .map_err(|e| format!("无法取得配置文件的绝对路径: {}", e))?;
+    let mut launch_arguments = vec![
+        OsString::from("run-service"),
+        OsString::from("--config"),
+        config.into_os_string(),
+    ];
+    let options = [
+        ("--profile", args.profile.clone()),
+        ("--port", args.port.map(|p| p.to_string())),
+        ("--target", args.target.clone()),
+        ("--log-level", args.log_level.clone()),
+        ("--pid-file", args.pid_file.clone()),
+    ];
+    for (name, value) in options {
+        if let Some(value) = value {
+            launch_arguments.push(OsString::from(name));
+            launch_arguments.push(OsString::from(value));
+        }
+    }
+    let display = launch_arguments
+        .iter()
+        .map(|a| a.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from("rust_proxy"),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path,
+        launch_arguments,
+        dependencies: vec![],
+        account_name: None, // LocalSystem
+        account_password: None,
+    };
+    let service = manager
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG)
+        .map_err(|e| format!("无法创建服务 {}: {}", SERVICE_NAME, e))?;
+    service
+        .set_description("rust_proxy HTTP 反向代理")
+        .map_err(|e| format!("无法设置服务描述: {}", e))?;
+    Ok(display)
+}
+
+// uninstall-service：停止并删除服务
+pub fn uninstall() -> Result<(), String> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .map_err(|e| format!("无法连接服务控制管理器（需要以管理员身份运行）: {}", e))?;
+    let service = manager
+        .open_service(
+            SERVICE_NAME,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+        )
+        .map_err(|e| format!("无法打开服务 {}: {}", SERVICE_NAME, e))?;
+    let status = service
+        .query_status()
+        .map_err(|e| format!("无法查询服务状态: {}", e))?;
+    if status.current_state != ServiceState::Stopped {
+        service.stop().map_err(|e| format!("无法停止服务: {}", e))?;
+    }
+    service.delete().map_err(|e| format!("无法删除服务: {}", e))
+}
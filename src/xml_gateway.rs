@@ -0,0 +1,366 @@
+// ==================== XML 网关 ====================
+//
+// 让只支持 XML/SOAP 的旧后端以 JSON API 的形式提供服务。路由配置 [routes.xml_gateway] 后，
+// 客户端的 JSON 请求按 request_template（或 request_file）渲染为 XML 请求体再转发，模板中
+// ${body.路径} 取请求体中的字段、${query.名称} 取查询参数、${header.名称} 取请求头，值按 XML 转义，
+// 对象与数组渲染为子元素；上游返回的 XML 响应转换为 JSON：元素名去掉命名空间前缀，属性为 "@名称"，
+// 同时有属性或子元素的文本为 "#text"，重复的元素合并为数组。response_root 选出其中的一部分，
+// response_template 按模板（见 json_template.rs）重新组织字段，${路径|number}、|bool、|array 转换类型。
+// 不是 XML 的响应（如网关的 HTML 错误页）原样转发
+
+use crate::ProxyError;
+use crate::json_template::{Conversion, JsonTemplate, Segment, Template, convert, lookup};
+use actix_web::HttpRequest;
+use actix_web::web::Bytes;
+use quick_xml::events::{BytesStart, Event};
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// XML 网关配置：对应配置文件中的 [routes.xml_gateway]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct XmlGatewayConfig {
+    #[serde(default)]
+    pub request_template: Option<String>, // XML 请求体模板，缺省时请求体原样转发
+    #[serde(default)]
+    pub request_file: Option<String>, // 从文件读取 XML 请求体模板
+    #[serde(default)]
+    pub method: Option<String>, // 发往上游的请求方法，如 SOAP 使用的 POST；缺省与客户端相同
+    #[serde(default = "default_content_type")]
+    pub content_type: String, // 发往上游的 Content-Type
+    #[serde(default)]
+    pub soap_action: Option<String>, // SOAPAction 请求头
+    #[serde(default)]
+    pub response_root: Option<String>, // 返回的部分，如 "Envelope.Body.GetOrderResponse"
+    #[serde(default)]
+    pub arrays: Vec<String>, // 总是转换为数组的元素名，只出现一次时也是数组
+    #[serde(default)]
+    pub response_template: Option<Value>, // 响应模板，其中的字符串按模板渲染
+}
+
+// 以下函数为 XML 网关提供默认值
+fn default_content_type() -> String {
+    "text/xml; charset=utf-8".to_string()
+}
+
+// XML 网关
+#[derive(Debug)]
+pub struct XmlGateway {
+    request: Option<Template>,
+    method: Option<reqwest::Method>,
+    content_type: HeaderValue,
+    soap_action: Option<HeaderValue>,
+    response_root: Vec<String>, // 为空时返回整个文档
+    arrays: Vec<String>,
+    response: Option<JsonTemplate>,
+}
+
+impl XmlGateway {
+    // 从配置构建，模板文件在启动与热加载时读取，模板或方法无效时启动失败
+    pub fn new(config: &XmlGatewayConfig) -> Result<Self, String> {
+        let source = match (&config.request_template, &config.request_file) {
+            (Some(_), Some(_)) => {
+                return Err("xml_gateway 的 request_template 与 request_file 只能设置一个".into());
+            }
+            (Some(template), None) => Some(template.clone()),
+            (None, Some(file)) => Some(
+                std::fs::read_to_string(file)
+                    .map_err(|e| format!("无法读取 XML 模板 {}: {}", file, e))?,
+            ),
+            (None, None) => None,
+        };
+        let request = source.as_deref().map(Template::parse).transpose()?;
+        if let Some(template) = &request
+            && let Some(path) = template
+                .fields()
+                .find(|path| !matches!(path[0].as_str(), "body" | "query" | "header"))
+        {
+            return Err(format!(
+                "XML 模板中的字段 ${{{}}} 必须以 body.、query. 或 header. 开头",
+                path.join(".")
+            ));
+        }
+        let method = config
+            .method
+            .as_deref()
+            .map(|m| {
+                reqwest::Method::from_bytes(m.trim().to_ascii_uppercase().as_bytes())
+                    .map_err(|_| format!("无效的请求方法: {}", m))
+            })
+            .transpose()?;
+        let header = |name: &str, value: &str| {
+            HeaderValue::from_str(value).map_err(|_| format!("无效的 {}: {:?}", name, value))
+        };
+        Ok(XmlGateway {
+            request,
+            method,
+            content_type: header("content_type", &config.content_type)?,
+            soap_action: config
+                .soap_action
+                .as_deref()
+                .map(|a| header("soap_action", a))
+                .transpose()?,
+            response_root: config
+                .response_root
+                .iter()
+                .flat_map(|root| root.split('.'))
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+                .collect(),
+            arrays: config.arrays.clone(),
+            response: config
+                .response_template
+                .as_ref()
+                .map(JsonTemplate::parse)
+                .transpose()?,
+        })
+    }
+
+    // 是否生成 XML 请求体，生成时客户端的 Content-Type 不转发
+    pub fn translates_request(&self) -> bool {
+        self.request.is_some()
+    }
+
+    // 发往上游的请求方法
+    pub fn method(&self) -> Option<&reqwest::Method> {
+        self.method.as_ref()
+    }
+
+    // 发往上游的 Content-Type 与 SOAPAction
+    pub fn request_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if self.request.is_some() {
+            headers.insert(reqwest::header::CONTENT_TYPE, self.content_type.clone());
+        }
+        if let Some(action) = &self.soap_action {
+            headers.insert("soapaction", action.clone());
+        }
+        headers
+    }
+
+    // 按模板把请求渲染为 XML 请求体；没有模板时原样返回
+    pub fn request(&self, req: &HttpRequest, body: &Bytes) -> Result<Bytes, ProxyError> {
+        let Some(template) = &self.request else {
+            return Ok(body.clone());
+        };
+        let uses_body = template.fields().any(|path| path[0] == "body");
+        let json: Value = match uses_body && !body.is_empty() {
+            true => serde_json::from_slice(body).map_err(|e| {
+                ProxyError::TranslationFailed(format!("请求体不是有效的 JSON: {}", e))
+            })?,
+            false => Value::Null,
+        };
+        let query = actix_web::web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+            .map(|q| q.into_inner())
+            .unwrap_or_default();
+        let mut out = String::new();
+        for segment in &template.segments {
+            let (path, conversion) = match segment {
+                Segment::Literal(s) => {
+                    out.push_str(s);
+                    continue;
+                }
+                Segment::Field(path, conversion) => (path, *conversion),
+            };
+            let missing = || ProxyError::TranslationFailed(format!("缺少 {}", path.join(".")));
+            match path[0].as_str() {
+                "body" => {
+                    let value = lookup(&json, &path[1..]).ok_or_else(missing)?;
+                    let value = match conversion {
+                        Conversion::None => value.clone(),
+                        conversion => convert(Some(value), conversion),
+                    };
+                    write_xml(&value, &mut out);
+                }
+                "query" => {
+                    let name = path[1..].join(".");
+                    let (_, value) = query.iter().find(|(k, _)| *k == name).ok_or_else(missing)?;
+                    out.push_str(&crate::error_pages::escape_html(value));
+                }
+                _ => {
+                    let value = req
+                        .headers()
+                        .get(path[1..].join("."))
+                        .and_then(|v| v.to_str().ok())
+                        .ok_or_else(missing)?;
+                    out.push_str(&crate::error_pages::escape_html(value));
+                }
+            }
+        }
+        Ok(Bytes::from(out))
+    }
+
+    // 上游响应是否为需要转换的 XML
+    pub fn applies(&self, headers: &HeaderMap) -> bool {
+        let media_type = crate::sub_filter::media_type(headers);
+        !crate::sub_filter::encoded(headers)
+            && (media_type == "text/xml"
+                || media_type == "application/xml"
+                || media_type.ends_with("+xml"))
+    }
+
+    // 把 XML 响应体转换为 JSON
+    pub fn response(&self, body: &[u8]) -> Result<Vec<u8>, ProxyError> {
+        let document = self.parse(body).map_err(ProxyError::InvalidUpstreamXml)?;
+        // 找不到 response_root 时（如 SOAP Fault）返回整个文档，不套用响应模板
+        let value = match lookup(&document, &self.response_root) {
+            Some(root) => match &self.response {
+                Some(template) => template.render(root),
+                None => root.clone(),
+            },
+            None => {
+                log::debug!(
+                    "XML 响应中没有 {}，返回整个文档",
+                    self.response_root.join(".")
+                );
+                document
+            }
+        };
+        Ok(value.to_string().into_bytes())
+    }
+
+    // 解析 XML 文档，返回以根元素名为唯一字段的对象
+    fn parse(&self, body: &[u8]) -> Result<Value, String> {
+        let text = std::str::from_utf8(body).map_err(|_| "响应体不是 UTF-8".to_string())?;
+        let mut reader = quick_xml::Reader::from_str(text);
+        reader.config_mut().trim_text(true);
+        // 正在解析的元素：名称、属性与子元素、文本
+        let mut stack: Vec<(String, Map<String, Value>, String)> = Vec::new();
+        let mut document = Map::new();
+        loop {
+            let event = reader
+                .read_event()
+                .map_err(|e| format!("位置 {}: {}", reader.buffer_position(), e))?;
+            match event {
+                Event::Start(start) => {
+                    let (name, map) = open(&start)?;
+                    stack.push((name, map, String::new()));
+                }
+                Event::Empty(start) => {
+                    let (name, map) = open(&start)?;
+                    let value = finish(map, String::new());
+                    let parent = stack.last_mut().map(|(_, map, _)| map);
+                    self.insert(parent.unwrap_or(&mut document), name, value);
+                }
+                Event::Text(t) => {
+                    if let Some((_, _, text)) = stack.last_mut() {
+                        text.push_str(&t.unescape().map_err(|e| e.to_string())?);
+                    }
+                }
+                Event::CData(c) => {
+                    if let Some((_, _, text)) = stack.last_mut() {
+                        text.push_str(&String::from_utf8_lossy(&c.into_inner()));
+                    }
+                }
+                Event::End(_) => {
+                    let Some((name, map, text)) = stack.pop() else {
+                        return Err("多余的结束标签".to_string());
+                    };
+                    let value = finish(map, text);
+                    let parent = stack.last_mut().map(|(_, map, _)| map);
+                    self.insert(parent.unwrap_or(&mut document), name, value);
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        if !stack.is_empty() || document.is_empty() {
+            return Err("XML 文档不完整".to_string());
+        }
+        Ok(Value::Object(document))
+    }
+
+    // 把子元素加入父元素，重复的元素合并为数组
+    fn insert(&self, parent: &mut Map<String, Value>, name: String, value: Value) {
+        match parent.get_mut(&name) {
+            Some(Value::Array(items)) => items.push(value),
+            Some(existing) => {
+                let first = existing.take();
+                *existing = Value::Array(vec![first, value]);
+            }
+            None if self.arrays.contains(&name) => {
+                parent.insert(name, Value::Array(vec![value]));
+            }
+            None => {
+                parent.insert(name, value);
+            }
+        }
+    }
+}
+
+// 元素名（去掉命名空间前缀）与属性；命名空间声明不保留，xsi:nil="true" 记为 null
+fn open(start: &BytesStart) -> Result<(String, Map<String, Value>), String> {
+    let name = String::from_utf8_lossy(start.local_name().as_ref()).into_owned();
+    let mut map = Map::new();
+    for attr in start.attributes() {
+        let attr = attr.map_err(|e| e.to_string())?;
+        if attr.key.as_namespace_binding().is_some() {
+            continue;
+        }
+        let key = String::from_utf8_lossy(attr.key.local_name().as_ref()).into_owned();
+        let value = attr.unescape_value().map_err(|e| e.to_string())?;
+        if key == "nil" && value == "true" {
+            map.insert("#nil".to_string(), Value::Null);
+            continue;
+        }
+        map.insert(format!("@{}", key), Value::String(value.into_owned()));
+    }
+    Ok((name, map))
+}
+
+// 结束一个元素：只有文本时为字符串，否则为对象，文本记在 "#text" 中
+fn finish(mut map: Map<String, Value>, text: String) -> Value {
+    if map.remove("#nil").is_some() && map.is_empty() && text.is_empty() {
+        return Value::Null;
+    }
+    if map.is_empty() {
+        return Value::String(text);
+    }
+    if !text.is_empty() {
+        map.insert("#text".to_string(), Value::String(text));
+    }
+    Value::Object(map)
+}
+
+// 把 JSON 值写为 XML：标量为转义后的文本，对象的字段为子元素，数组的元素重复外层的元素名
+fn write_xml(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => {}
+        Value::String(s) => out.push_str(&crate::error_pages::escape_html(s)),
+        Value::Object(map) => {
+            // 不是合法 XML 元素名的字段（如含有 < 或空格）不写出，避免拼接出额外的元素
+            for (key, value) in map.iter().filter(|(key, _)| valid_name(key)) {
+                match value {
+                    Value::Array(items) => {
+                        for item in items {
+                            write_element(key, item, out);
+                        }
+                    }
+                    value => write_element(key, value, out),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                write_xml(item, out);
+            }
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':'))
+}
+
+fn write_element(name: &str, value: &Value, out: &mut String) {
+    out.push('<');
+    out.push_str(name);
+    out.push('>');
+    write_xml(value, out);
+    out.push_str("</");
+    out.push_str(name);
+    out.push('>');
+}
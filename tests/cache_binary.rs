@@ -0,0 +1,76 @@
+// 二进制响应体（不是合法的 UTF-8）经代理与缓存后保持原样
+
+mod common;
+
+use common::{Proxy, Reply, Upstream, client};
+
+const CONFIG: &str = r#"
+version = 2
+
+[server]
+host = "127.0.0.1"
+port = {port}
+
+[target]
+protocol = "http"
+host = "127.0.0.1"
+port = {upstream_port}
+
+[request]
+timeout = 5
+accept_invalid_certs = false
+
+[log]
+level = "warn"
+
+[[routes]]
+name = "memory"
+path_prefix = "/memory"
+cache = { ttl_secs = 300 }
+
+[[routes]]
+name = "disk"
+path_prefix = "/disk"
+
+[routes.cache]
+ttl_secs = 300
+
+[routes.cache.disk]
+path = "{dir}/cache"
+min_object_kb = 1
+"#;
+
+// PNG 文件头加上覆盖全部字节值的内容，大小超过磁盘缓存的 min_object_kb
+fn png() -> Vec<u8> {
+    let mut body = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+    body.extend((0..4096u32).map(|i| (i * 7 % 256) as u8));
+    body
+}
+
+#[tokio::test]
+async fn binary_body_round_trips_through_memory_and_disk_cache() {
+    let upstream = Upstream::start(|_| Reply::new(200, png()).header("Content-Type", "image/png"));
+    let proxy = Proxy::start(CONFIG, upstream.port);
+    let client = client();
+
+    for path in ["/memory/logo.png", "/disk/logo.png"] {
+        for expected in ["MISS", "HIT"] {
+            let resp = client.get(proxy.url(path)).send().await.unwrap();
+            assert_eq!(resp.status(), 200, "{}", path);
+            assert_eq!(resp.headers()["x-cache"], expected, "{}", path);
+            assert_eq!(resp.headers()["content-type"], "image/png");
+            assert_eq!(
+                resp.bytes().await.unwrap().as_ref(),
+                png(),
+                "{} {}",
+                path,
+                expected
+            );
+        }
+    }
+    assert_eq!(upstream.received().len(), 2);
+
+    // 大于 min_object_kb 的响应保存在磁盘缓存目录中
+    let files = std::fs::read_dir(proxy.dir.join("cache")).unwrap().count();
+    assert!(files > 0, "磁盘缓存目录为空");
+}
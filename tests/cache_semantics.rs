@@ -0,0 +1,237 @@
+// 响应缓存遵循 RFC 7234：新鲜期、Vary 变体、304 重新验证，以及 no-store/private 不缓存；
+// 缓存键包含请求的主机
+
+mod common;
+
+use common::{Proxy, Received, Reply, Upstream, client};
+use std::time::Duration;
+
+const CONFIG: &str = r#"
+version = 2
+
+[server]
+host = "127.0.0.1"
+port = {port}
+
+[target]
+protocol = "http"
+host = "127.0.0.1"
+port = {upstream_port}
+
+[request]
+timeout = 5
+accept_invalid_certs = false
+
+[log]
+level = "warn"
+
+[[routes]]
+name = "vhosts"
+path_prefix = "/site"
+host_header = "preserve"
+cache = { ttl_secs = 300 }
+
+[[routes]]
+name = "default"
+path_prefix = "/"
+cache = { ttl_secs = 300 }
+"#;
+
+// 按路径决定上游响应的缓存控制；/etag 在验证器匹配时返回 304
+fn reply(req: &Received) -> Reply {
+    let path = req.path.split('?').next().unwrap_or_default();
+    match path {
+        "/max-age" => Reply::new(200, "max-age").header("Cache-Control", "max-age=1"),
+        "/s-maxage" => {
+            Reply::new(200, "s-maxage").header("Cache-Control", "max-age=300, s-maxage=1")
+        }
+        "/age" => Reply::new(200, "age")
+            .header("Cache-Control", "max-age=300")
+            .header("Age", "299"),
+        "/vary" => Reply::new(200, req.header("accept-language").unwrap_or("none"))
+            .header("Cache-Control", "max-age=300")
+            .header("Vary", "Accept-Language"),
+        "/vary-star" => Reply::new(200, "star")
+            .header("Cache-Control", "max-age=300")
+            .header("Vary", "*"),
+        "/etag" if req.header("if-none-match") == Some("\"v1\"") => {
+            Reply::new(304, "").header("Cache-Control", "max-age=1")
+        }
+        "/etag" => Reply::new(200, "tagged")
+            .header("Cache-Control", "max-age=1")
+            .header("ETag", "\"v1\""),
+        "/no-store" => Reply::new(200, "no-store").header("Cache-Control", "no-store"),
+        "/private" => Reply::new(200, "private").header("Cache-Control", "private, max-age=300"),
+        "/site" => Reply::new(200, req.header("host").unwrap_or_default()),
+        _ => Reply::new(200, "default"),
+    }
+}
+
+// 发送请求，返回 X-Cache 与响应体
+async fn get(proxy: &Proxy, path: &str, headers: &[(&str, &str)]) -> (String, String) {
+    let mut request = client().get(proxy.url(path));
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let resp = request.send().await.unwrap();
+    assert_eq!(resp.status(), 200, "{}", path);
+    let cache = resp
+        .headers()
+        .get("x-cache")
+        .map(|v| v.to_str().unwrap().to_string())
+        .unwrap_or_default();
+    (cache, resp.text().await.unwrap())
+}
+
+// 上游收到的路径为 path 的请求数
+fn calls(upstream: &Upstream, path: &str) -> usize {
+    upstream
+        .received()
+        .iter()
+        .filter(|r| r.path == path)
+        .count()
+}
+
+#[tokio::test]
+async fn freshness_follows_max_age_and_s_maxage() {
+    let upstream = Upstream::start(reply);
+    let proxy = Proxy::start(CONFIG, upstream.port);
+
+    for path in ["/max-age", "/s-maxage"] {
+        assert_eq!(get(&proxy, path, &[]).await.0, "MISS", "{}", path);
+        assert_eq!(get(&proxy, path, &[]).await.0, "HIT", "{}", path);
+    }
+    // 上游给出的 Age 计入条目的年龄：剩余的新鲜期只有 1 秒
+    assert_eq!(get(&proxy, "/age", &[]).await.0, "MISS");
+    assert_eq!(get(&proxy, "/age", &[]).await.0, "HIT");
+
+    // 新鲜期结束后重新请求上游；s-maxage 优先于 max-age
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    for path in ["/max-age", "/s-maxage", "/age"] {
+        assert_eq!(get(&proxy, path, &[]).await.0, "MISS", "{}", path);
+        assert_eq!(calls(&upstream, path), 2, "{}", path);
+    }
+
+    // 请求的 max-age 收紧可接受的新鲜度
+    assert_eq!(get(&proxy, "/max-age", &[]).await.0, "HIT");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let (cache, _) = get(&proxy, "/s-maxage", &[("Cache-Control", "max-age=0")]).await;
+    assert_eq!(cache, "MISS");
+    assert_eq!(calls(&upstream, "/s-maxage"), 3);
+}
+
+#[tokio::test]
+async fn vary_keeps_variants_apart() {
+    let upstream = Upstream::start(reply);
+    let proxy = Proxy::start(CONFIG, upstream.port);
+
+    for (language, expected) in [("en", "MISS"), ("de", "MISS"), ("en", "HIT"), ("de", "HIT")] {
+        let (cache, body) = get(&proxy, "/vary", &[("Accept-Language", language)]).await;
+        assert_eq!(cache, expected, "{}", language);
+        assert_eq!(body, language);
+    }
+    // 没有该请求头也是一个变体
+    assert_eq!(
+        get(&proxy, "/vary", &[]).await,
+        ("MISS".into(), "none".into())
+    );
+    assert_eq!(
+        get(&proxy, "/vary", &[]).await,
+        ("HIT".into(), "none".into())
+    );
+    assert_eq!(calls(&upstream, "/vary"), 3);
+
+    // Vary: * 不缓存
+    for _ in 0..2 {
+        assert_eq!(get(&proxy, "/vary-star", &[]).await.0, "MISS");
+    }
+    assert_eq!(calls(&upstream, "/vary-star"), 2);
+}
+
+#[tokio::test]
+async fn expired_entries_are_revalidated_with_304() {
+    let upstream = Upstream::start(reply);
+    let proxy = Proxy::start(CONFIG, upstream.port);
+
+    assert_eq!(
+        get(&proxy, "/etag", &[]).await,
+        ("MISS".into(), "tagged".into())
+    );
+    assert_eq!(get(&proxy, "/etag", &[]).await.0, "HIT");
+
+    // 客户端的条件请求与缓存条目匹配时由代理直接返回 304
+    let resp = client()
+        .get(proxy.url("/etag"))
+        .header("If-None-Match", "\"v1\"")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 304);
+    assert_eq!(calls(&upstream, "/etag"), 1);
+
+    // 过期后带 If-None-Match 重新验证，上游返回 304 时继续使用缓存的响应体
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(
+        get(&proxy, "/etag", &[]).await,
+        ("REVALIDATED".into(), "tagged".into())
+    );
+    let received = upstream.received();
+    assert_eq!(received.len(), 2);
+    assert_eq!(received[1].header("if-none-match"), Some("\"v1\""));
+    // 304 更新了条目的新鲜期
+    assert_eq!(
+        get(&proxy, "/etag", &[]).await,
+        ("HIT".into(), "tagged".into())
+    );
+    assert_eq!(calls(&upstream, "/etag"), 2);
+
+    // no-cache 请求强制重新验证
+    let (cache, _) = get(&proxy, "/etag", &[("Cache-Control", "no-cache")]).await;
+    assert_eq!(cache, "REVALIDATED");
+    assert_eq!(calls(&upstream, "/etag"), 3);
+}
+
+#[tokio::test]
+async fn no_store_and_private_bypass_the_cache() {
+    let upstream = Upstream::start(reply);
+    let proxy = Proxy::start(CONFIG, upstream.port);
+
+    // no-store、private 的响应不保存
+    for path in ["/no-store", "/private"] {
+        for _ in 0..2 {
+            let (cache, body) = get(&proxy, path, &[]).await;
+            assert_eq!(cache, "MISS", "{}", path);
+            assert_eq!(body, &path[1..]);
+        }
+        assert_eq!(calls(&upstream, path), 2, "{}", path);
+    }
+
+    // 请求的 no-store 跳过已有的条目，也不替换它
+    assert_eq!(get(&proxy, "/default", &[]).await.0, "MISS");
+    assert_eq!(get(&proxy, "/default", &[]).await.0, "HIT");
+    let (cache, body) = get(&proxy, "/default", &[("Cache-Control", "no-store")]).await;
+    assert_ne!(cache, "HIT");
+    assert_eq!(body, "default");
+    assert_eq!(calls(&upstream, "/default"), 2);
+    assert_eq!(get(&proxy, "/default", &[]).await.0, "HIT");
+    assert_eq!(calls(&upstream, "/default"), 2);
+}
+
+#[tokio::test]
+async fn hosts_are_cached_separately() {
+    let upstream = Upstream::start(reply);
+    let proxy = Proxy::start(CONFIG, upstream.port);
+
+    // 上游按 Host 区分虚拟主机，一个主机缓存的响应不会返回给另一个主机
+    for (host, expected) in [
+        ("a.example.com", "MISS"),
+        ("b.example.com", "MISS"),
+        ("a.example.com", "HIT"),
+        ("B.Example.com", "HIT"),
+    ] {
+        let (cache, body) = get(&proxy, "/site", &[("Host", host)]).await;
+        assert_eq!(cache, expected, "{}", host);
+        assert_eq!(body, host.to_ascii_lowercase(), "{}", host);
+    }
+    assert_eq!(calls(&upstream, "/site"), 2);
+}
@@ -0,0 +1,258 @@
+// ==================== 集成测试的公共部分 ====================
+//
+// 集成测试以子进程运行编译好的代理，上游是测试内启动的简单 HTTP/1.1 服务器，
+// 每个测试按自己的需要生成配置文件；配置中的 {port}、{admin_port}、{upstream_port} 与 {dir}
+// 替换为分配的端口和临时目录。代理与上游在测试结束时随 Drop 一起退出
+#![allow(dead_code)] // 各测试文件只用到其中一部分
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// 上游收到的请求
+#[derive(Debug, Clone)]
+pub struct Received {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>, // 名称为小写
+    pub body: Vec<u8>,
+    pub at: Instant,                   // 收到请求头的时间
+    pub proxy_header: Option<Vec<u8>>, // 连接开头的 PROXY 协议 v2 头
+}
+
+impl Received {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+// 上游的应答
+#[derive(Debug, Clone)]
+pub struct Reply {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub delay: Duration, // 发送响应之前等待的时间
+}
+
+impl Reply {
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Reply {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+type Handler = dyn Fn(&Received) -> Reply + Send + Sync;
+
+// 测试用的上游服务器，每个连接一个线程，响应后关闭连接
+pub struct Upstream {
+    pub port: u16,
+    received: Arc<Mutex<Vec<Received>>>,
+}
+
+impl Upstream {
+    pub fn start(handler: impl Fn(&Received) -> Reply + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("绑定上游端口");
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+        let log = received.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let handler = handler.clone();
+                let log = log.clone();
+                std::thread::spawn(move || serve(stream, &*handler, &log));
+            }
+        });
+        Upstream { port, received }
+    }
+
+    // 到目前为止收到的请求
+    pub fn received(&self) -> Vec<Received> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+// PROXY 协议 v2 的签名
+pub const PROXY_V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+fn serve(stream: TcpStream, handler: &Handler, log: &Mutex<Vec<Received>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let proxy_header = proxy_header(&mut reader);
+    let mut line = String::new();
+    if reader.read_line(&mut line).unwrap_or(0) == 0 {
+        return;
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    let length = headers
+        .iter()
+        .find(|(n, _)| n == "content-length")
+        .and_then(|(_, v)| v.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    let _ = reader.read_exact(&mut body);
+    let request = Received {
+        method,
+        path,
+        headers,
+        body,
+        at: Instant::now(),
+        proxy_header,
+    };
+    log.lock().unwrap().push(request.clone());
+    let reply = handler(&request);
+    std::thread::sleep(reply.delay);
+    let mut head = format!("HTTP/1.1 {} Test\r\n", reply.status);
+    for (name, value) in &reply.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        reply.body.len()
+    ));
+    let mut stream = stream;
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(&reply.body);
+}
+
+// 读取连接开头的 PROXY 协议 v2 头（签名、命令、地址族与地址块），没有时不消耗数据
+fn proxy_header(reader: &mut BufReader<TcpStream>) -> Option<Vec<u8>> {
+    let buffered = reader.fill_buf().ok()?;
+    if !buffered.starts_with(PROXY_V2_SIGNATURE) {
+        return None;
+    }
+    let mut header = vec![0; 16];
+    reader.read_exact(&mut header).ok()?;
+    let length = u16::from_be_bytes([header[14], header[15]]) as usize;
+    let mut addresses = vec![0; length];
+    reader.read_exact(&mut addresses).ok()?;
+    header.extend(addresses);
+    Some(header)
+}
+
+// 运行中的代理进程
+pub struct Proxy {
+    child: Child,
+    pub port: u16,
+    pub admin_port: u16,
+    pub dir: PathBuf,
+}
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+impl Proxy {
+    // 按配置模板启动代理，等待代理（与管理接口，如果启用了）开始监听
+    pub fn start(template: &str, upstream_port: u16) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "rust_proxy_test_{}_{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let port = free_port();
+        let admin_port = free_port();
+        let config = template
+            .replace("{port}", &port.to_string())
+            .replace("{admin_port}", &admin_port.to_string())
+            .replace("{upstream_port}", &upstream_port.to_string())
+            .replace("{dir}", dir.to_str().unwrap());
+        let path = dir.join("config.toml");
+        std::fs::write(&path, &config).unwrap();
+        let child = Command::new(env!("CARGO_BIN_EXE_rust_proxy"))
+            .arg("--config")
+            .arg(&path)
+            .current_dir(&dir)
+            .env_remove("RUST_LOG") // 日志级别由配置决定
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("启动代理");
+        let proxy = Proxy {
+            child,
+            port,
+            admin_port,
+            dir,
+        };
+        wait_listening(port);
+        if template.contains("{admin_port}") {
+            wait_listening(admin_port);
+        }
+        proxy
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, path)
+    }
+
+    pub fn admin_url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.admin_port, path)
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+// 不经系统代理、不自动解压的 HTTP 客户端
+pub fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .no_proxy()
+        .timeout(Duration::from_secs(20))
+        .build()
+        .unwrap()
+}
+
+// 分配一个当前空闲的端口
+pub fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn wait_listening(port: u16) {
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        assert!(Instant::now() < deadline, "代理没有在端口 {} 上监听", port);
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,94 @@
+// 上游已经压缩的响应原样返回：保留 Content-Encoding，响应压缩不会再压缩一次
+
+mod common;
+
+use common::{Proxy, Reply, Upstream, client};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::io::{Read, Write};
+
+const CONFIG: &str = r#"
+version = 2
+
+[server]
+host = "127.0.0.1"
+port = {port}
+
+[target]
+protocol = "http"
+host = "127.0.0.1"
+port = {upstream_port}
+
+[request]
+timeout = 5
+accept_invalid_certs = false
+
+[log]
+level = "warn"
+
+[compression]
+enabled = true
+min_size = 16
+
+[[routes]]
+name = "default"
+path_prefix = "/"
+"#;
+
+fn text() -> Vec<u8> {
+    "rust_proxy 压缩测试 ".repeat(200).into_bytes()
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[tokio::test]
+async fn compressed_upstream_response_passes_through() {
+    let upstream = Upstream::start(|req| match req.path.as_str() {
+        "/gzip" => Reply::new(200, gzip(&text()))
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .header("Content-Encoding", "gzip"),
+        _ => Reply::new(200, text()).header("Content-Type", "text/plain; charset=utf-8"),
+    });
+    let proxy = Proxy::start(CONFIG, upstream.port);
+    let client = client();
+
+    let resp = client
+        .get(proxy.url("/gzip"))
+        .header("Accept-Encoding", "br, gzip")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let encodings: Vec<_> = resp
+        .headers()
+        .get_all("content-encoding")
+        .iter()
+        .map(|v| v.to_str().unwrap().to_string())
+        .collect();
+    assert_eq!(encodings, ["gzip"]);
+    assert_eq!(resp.bytes().await.unwrap().as_ref(), gzip(&text()));
+    // 路由不改写响应体时客户端的 Accept-Encoding 转发给上游
+    assert_eq!(
+        upstream.received()[0].header("accept-encoding"),
+        Some("br, gzip")
+    );
+
+    // 上游没有压缩的响应由代理压缩
+    let resp = client
+        .get(proxy.url("/plain"))
+        .header("Accept-Encoding", "gzip")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-encoding"], "gzip");
+    let mut decoded = Vec::new();
+    flate2::read::GzDecoder::new(resp.bytes().await.unwrap().as_ref())
+        .read_to_end(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, text());
+}
@@ -0,0 +1,268 @@
+// 正向代理：转发绝对地址的 HTTP 请求与 CONNECT 隧道，显式启用后解密 HTTPS
+
+mod common;
+
+use common::{Proxy, Reply, Upstream, client, free_port};
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNum;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::x509::extension::{BasicConstraints, KeyUsage, SubjectAlternativeName};
+use openssl::x509::{X509, X509NameBuilder};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const CONFIG: &str = r#"
+version = 2
+
+[server]
+host = "127.0.0.1"
+port = {port}
+
+[target]
+protocol = "http"
+host = "127.0.0.1"
+port = {upstream_port}
+
+[request]
+timeout = 5
+accept_invalid_certs = false
+
+[log]
+level = "warn"
+
+[forward_proxy]
+enabled = true
+port = {forward_port}
+connect_ports = [{connect_port}]
+{mitm}
+
+[[routes]]
+name = "default"
+path_prefix = "/"
+"#;
+
+// 启动启用了正向代理的代理，返回代理与正向代理的端口
+fn start(upstream_port: u16, connect_port: u16, mitm: &str) -> (Proxy, u16) {
+    let forward_port = free_port();
+    let config = CONFIG
+        .replace("{forward_port}", &forward_port.to_string())
+        .replace("{connect_port}", &connect_port.to_string())
+        .replace("{mitm}", mitm);
+    let proxy = Proxy::start(&config, upstream_port);
+    let deadline = std::time::Instant::now() + Duration::from_secs(20);
+    while TcpStream::connect(("127.0.0.1", forward_port)).is_err() {
+        assert!(std::time::Instant::now() < deadline, "正向代理没有监听");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    (proxy, forward_port)
+}
+
+// 在连接上发送请求头并读到连接关闭
+fn exchange(stream: &mut TcpStream, request: &str) -> String {
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response);
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+#[tokio::test]
+async fn forwards_absolute_requests_and_tunnels_connect() {
+    let upstream = Upstream::start(|_| Reply::new(200, "ok"));
+    let (_proxy, forward_port) = start(upstream.port, upstream.port, "");
+    let proxied = reqwest::Client::builder()
+        .proxy(reqwest::Proxy::http(format!("http://127.0.0.1:{}", forward_port)).unwrap())
+        .build()
+        .unwrap();
+
+    // 绝对地址的请求转发到请求中的主机，逐跳请求头不转发
+    let resp = proxied
+        .get(format!("http://127.0.0.1:{}/hello?x=1", upstream.port))
+        .header("Proxy-Connection", "keep-alive")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.text().await.unwrap(), "ok");
+    let received = upstream.received();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].path, "/hello?x=1");
+    assert_eq!(
+        received[0].header("host"),
+        Some(format!("127.0.0.1:{}", upstream.port).as_str())
+    );
+    assert_eq!(received[0].header("proxy-connection"), None);
+
+    // 不是绝对地址的请求不转发
+    let resp = client()
+        .get(format!("http://127.0.0.1:{}/hello", forward_port))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    // CONNECT 只能连接 connect_ports 中的端口
+    let mut stream = TcpStream::connect(("127.0.0.1", forward_port)).unwrap();
+    let response = exchange(
+        &mut stream,
+        "CONNECT 127.0.0.1:22 HTTP/1.1\r\nHost: 127.0.0.1:22\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 403"), "{}", response);
+
+    // 隧道原样转发两个方向的数据
+    let mut stream = TcpStream::connect(("127.0.0.1", forward_port)).unwrap();
+    let target = format!("127.0.0.1:{}", upstream.port);
+    stream
+        .write_all(format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", target).as_bytes())
+        .unwrap();
+    let mut established = [0u8; 39];
+    stream.read_exact(&mut established).unwrap();
+    assert!(
+        established.starts_with(b"HTTP/1.1 200 Connection Established\r\n\r\n"),
+        "{}",
+        String::from_utf8_lossy(&established)
+    );
+    let response = exchange(
+        &mut stream,
+        &format!("GET /tunnel HTTP/1.1\r\nHost: {}\r\n\r\n", target),
+    );
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.ends_with("ok"), "{}", response);
+    assert_eq!(upstream.received()[1].path, "/tunnel");
+}
+
+const MITM: &str = r#"
+[forward_proxy.mitm]
+enabled = true
+ca_cert = "{ca_dir}/ca.pem"
+ca_key = "{ca_dir}/ca-key.pem"
+hosts = ["localhost"]
+accept_invalid_certs = true
+request_headers = { set = { "X-Inspected" = "$client_ip" } }
+response_headers = { set = { "X-Decrypted" = "1" }, remove = ["server"] }
+"#;
+
+fn key() -> PKey<Private> {
+    PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap()
+}
+
+// 自签名证书；ca 为 true 时可以签发其他证书
+fn self_signed(name: &str, key: &PKey<Private>, ca: bool) -> X509 {
+    let mut subject = X509NameBuilder::new().unwrap();
+    subject.append_entry_by_text("CN", name).unwrap();
+    let subject = subject.build();
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    let serial = BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap();
+    builder.set_serial_number(&serial).unwrap();
+    builder.set_subject_name(&subject).unwrap();
+    builder.set_issuer_name(&subject).unwrap();
+    builder.set_pubkey(key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(30).unwrap())
+        .unwrap();
+    match ca {
+        true => {
+            let constraints = BasicConstraints::new().critical().ca().build().unwrap();
+            builder.append_extension(constraints).unwrap();
+            let usage = KeyUsage::new().key_cert_sign().crl_sign().build().unwrap();
+            builder.append_extension(usage).unwrap();
+        }
+        false => {
+            let alt_name = SubjectAlternativeName::new()
+                .dns(name)
+                .build(&builder.x509v3_context(None, None))
+                .unwrap();
+            builder.append_extension(alt_name).unwrap();
+        }
+    }
+    builder.sign(key, MessageDigest::sha256()).unwrap();
+    builder.build()
+}
+
+// 测试用的 HTTPS 目标，证书为自签名；记录收到的请求头
+fn https_upstream() -> (u16, Arc<Mutex<Vec<String>>>) {
+    let key = key();
+    let cert = self_signed("localhost", &key, false);
+    let identity = native_tls::Identity::from_pkcs8(
+        &cert.to_pem().unwrap(),
+        &key.private_key_to_pem_pkcs8().unwrap(),
+    )
+    .unwrap();
+    let acceptor = native_tls::TlsAcceptor::new(identity).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let log = received.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let Ok(mut stream) = acceptor.accept(stream) else {
+                continue;
+            };
+            let mut head = Vec::new();
+            let mut byte = [0u8; 1];
+            while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap_or(0) == 1 {
+                head.push(byte[0]);
+            }
+            log.lock()
+                .unwrap()
+                .push(String::from_utf8_lossy(&head).to_ascii_lowercase());
+            let _ = stream.write_all(
+                b"HTTP/1.1 200 OK\r\nServer: upstream\r\nContent-Length: 6\r\nConnection: close\r\n\r\nsecret",
+            );
+            let _ = stream.shutdown();
+        }
+    });
+    (port, received)
+}
+
+#[tokio::test]
+async fn mitm_decrypts_configured_hosts() {
+    let ca_dir = std::env::temp_dir().join(format!("rust_proxy_mitm_ca_{}", std::process::id()));
+    std::fs::create_dir_all(&ca_dir).unwrap();
+    let ca_key = key();
+    let ca = self_signed("rust_proxy test CA", &ca_key, true);
+    let write = |name: &str, pem: Vec<u8>| std::fs::write(Path::new(&ca_dir).join(name), pem);
+    write("ca.pem", ca.to_pem().unwrap()).unwrap();
+    write("ca-key.pem", ca_key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+
+    let (tls_port, received) = https_upstream();
+    let mitm = MITM.replace("{ca_dir}", ca_dir.to_str().unwrap());
+    let (_proxy, forward_port) = start(free_port(), tls_port, &mitm);
+    // 只信任测试 CA：能完成握手说明证书由代理签发
+    let proxied = reqwest::Client::builder()
+        .proxy(reqwest::Proxy::https(format!("http://127.0.0.1:{}", forward_port)).unwrap())
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(reqwest::Certificate::from_pem(&ca.to_pem().unwrap()).unwrap())
+        .build()
+        .unwrap();
+
+    let resp = proxied
+        .get(format!("https://localhost:{}/secret", tls_port))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["x-decrypted"], "1");
+    assert!(!resp.headers().contains_key("server"));
+    assert_eq!(resp.text().await.unwrap(), "secret");
+    let head = received.lock().unwrap()[0].clone();
+    assert!(head.starts_with("get /secret http/1.1\r\n"), "{}", head);
+    assert!(head.contains("\r\nx-inspected: 127.0.0.1\r\n"), "{}", head);
+
+    // 不在 hosts 中的主机只建立隧道，客户端看到的是目标自己的证书
+    let result = proxied
+        .get(format!("https://127.0.0.1:{}/secret", tls_port))
+        .send()
+        .await;
+    assert!(result.is_err(), "{:?}", result);
+
+    let _ = std::fs::remove_dir_all(&ca_dir);
+}
@@ -0,0 +1,352 @@
+// 客户端发送的内部标记请求头（x-rust-proxy-*）被忽略，不会改变代理连接上游的方式
+
+mod common;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use common::{PROXY_V2_SIGNATURE, Proxy, Reply, Upstream, client, free_port};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+const CONFIG: &str = r#"
+version = 2
+
+[server]
+host = "127.0.0.1"
+port = {port}
+
+[target]
+protocol = "http"
+host = "127.0.0.1"
+port = {upstream_port}
+
+[request]
+timeout = 5
+accept_invalid_certs = false
+
+[log]
+level = "warn"
+
+[[routes]]
+name = "proxy_protocol"
+path_prefix = "/pp"
+send_proxy_protocol = true
+
+[[routes]]
+name = "plain"
+path_prefix = "/"
+"#;
+
+const FORGED: &str = "6.6.6.6:1234 10.0.0.1:80 false";
+
+#[tokio::test]
+async fn client_proxy_protocol_marker_is_ignored() {
+    let upstream = Upstream::start(|_| Reply::new(200, "ok"));
+    let proxy = Proxy::start(CONFIG, upstream.port);
+    let client = client();
+
+    for path in ["/plain", "/pp"] {
+        let resp = client
+            .get(proxy.url(path))
+            .header("X-Rust-Proxy-Send-Proxy-Protocol", FORGED)
+            .header("X-Rust-Proxy-Other", "1")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200, "{}", path);
+    }
+
+    let received = upstream.received();
+    assert_eq!(received.len(), 2);
+    for request in &received {
+        assert!(
+            request
+                .headers
+                .iter()
+                .all(|(name, _)| !name.starts_with("x-rust-proxy-")),
+            "{:?}",
+            request.headers
+        );
+    }
+
+    // 普通路由不发送协议头
+    assert_eq!(received[0].path, "/plain");
+    assert_eq!(received[0].proxy_header, None);
+
+    // 要求发送协议头的路由使用真实的客户端地址
+    assert_eq!(received[1].path, "/pp");
+    let header = received[1]
+        .proxy_header
+        .as_ref()
+        .expect("缺少 PROXY 协议头");
+    assert!(header.starts_with(PROXY_V2_SIGNATURE));
+    assert_eq!(&header[12..16], &[0x21, 0x11, 0, 12]);
+    assert_eq!(&header[16..20], &[127, 0, 0, 1], "{:?}", header);
+    assert_ne!(&header[24..26], &1234u16.to_be_bytes(), "源端口为伪造的值");
+}
+
+const CGI_CONFIG: &str = r#"
+version = 2
+
+[server]
+host = "127.0.0.1"
+port = {port}
+
+[target]
+protocol = "http"
+host = "127.0.0.1"
+port = {upstream_port}
+
+[request]
+timeout = 5
+accept_invalid_certs = false
+
+[log]
+level = "warn"
+
+[[routes]]
+name = "php"
+path_prefix = "/php"
+target = { protocol = "fastcgi", host = "127.0.0.1", port = {fastcgi_port} }
+
+[routes.fastcgi]
+root = "/srv"
+script = "/index.php"
+
+[[routes]]
+name = "django"
+path_prefix = "/py"
+target = { protocol = "uwsgi", host = "127.0.0.1", port = {uwsgi_port} }
+uwsgi = { script_name = "/py" }
+
+[[routes]]
+name = "plain"
+path_prefix = "/"
+"#;
+
+// 收到的各个请求的参数
+type Params = Arc<Mutex<Vec<Vec<(String, String)>>>>;
+
+// 测试用的应用服务器：每个连接交给 handle 读取请求并写入响应，记录它解析出的参数
+fn app_server(handle: fn(&mut TcpStream) -> Vec<(String, String)>) -> (u16, Params) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let params = Params::default();
+    let log = params.clone();
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let params = handle(&mut stream);
+            log.lock().unwrap().push(params);
+        }
+    });
+    (port, params)
+}
+
+// FastCGI：读取到空的 STDIN 记录为止，返回 text/plain 的 "ok"
+fn fastcgi(stream: &mut TcpStream) -> Vec<(String, String)> {
+    let mut encoded = Vec::new();
+    loop {
+        let mut header = [0u8; 8];
+        stream.read_exact(&mut header).unwrap();
+        let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+        let mut content = vec![0; len + header[6] as usize];
+        stream.read_exact(&mut content).unwrap();
+        content.truncate(len);
+        match header[1] {
+            4 => encoded.extend(content),
+            5 if len == 0 => break,
+            _ => {}
+        }
+    }
+    let record = |kind: u8, content: &[u8]| {
+        let mut out = vec![1, kind, 0, 1];
+        out.extend((content.len() as u16).to_be_bytes());
+        out.extend([0, 0]);
+        out.extend(content);
+        out
+    };
+    let _ = stream.write_all(&record(6, b"Content-Type: text/plain\r\n\r\nok"));
+    let _ = stream.write_all(&record(3, &[0; 8]));
+    let mut params = Vec::new();
+    let mut rest = encoded.as_slice();
+    let length = |rest: &mut &[u8]| match rest[0] >> 7 {
+        0 => {
+            let n = rest[0] as usize;
+            *rest = &rest[1..];
+            n
+        }
+        _ => {
+            let n = u32::from_be_bytes([rest[0] & 0x7f, rest[1], rest[2], rest[3]]) as usize;
+            *rest = &rest[4..];
+            n
+        }
+    };
+    while !rest.is_empty() {
+        let name_len = length(&mut rest);
+        let value_len = length(&mut rest);
+        let name = String::from_utf8_lossy(&rest[..name_len]).into_owned();
+        let value = String::from_utf8_lossy(&rest[name_len..name_len + value_len]).into_owned();
+        rest = &rest[name_len + value_len..];
+        params.push((name, value));
+    }
+    params
+}
+
+// uwsgi：读取数据包头、环境变量与请求体，返回 "ok"
+fn uwsgi(stream: &mut TcpStream) -> Vec<(String, String)> {
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).unwrap();
+    let mut vars = vec![0; u16::from_le_bytes([header[1], header[2]]) as usize];
+    stream.read_exact(&mut vars).unwrap();
+    let mut params = Vec::new();
+    let mut rest = vars.as_slice();
+    let string = |rest: &mut &[u8]| {
+        let len = u16::from_le_bytes([rest[0], rest[1]]) as usize;
+        let value = String::from_utf8_lossy(&rest[2..2 + len]).into_owned();
+        *rest = &rest[2 + len..];
+        value
+    };
+    while !rest.is_empty() {
+        let name = string(&mut rest);
+        let value = string(&mut rest);
+        params.push((name, value));
+    }
+    let length = params
+        .iter()
+        .find(|(n, _)| n == "CONTENT_LENGTH")
+        .and_then(|(_, v)| v.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    let _ = stream.read_exact(&mut body);
+    let _ = stream
+        .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 2\r\n\r\nok");
+    params
+}
+
+// 旧版本的标记格式：Base64 编码的 JSON，指定 Unix 套接字与参数
+fn forged_cgi_marker(socket: &str) -> String {
+    let json = serde_json::json!({
+        "socket": socket,
+        "params": [["SCRIPT_FILENAME", "/tmp/evil.php"]],
+        "extra": [["PHP_VALUE", "auto_prepend_file=/etc/passwd"]],
+    });
+    STANDARD.encode(json.to_string())
+}
+
+// 伪造的标记指向的 Unix 套接字，检查是否有连接
+struct Trap {
+    listener: std::os::unix::net::UnixListener,
+    path: std::path::PathBuf,
+}
+
+impl Trap {
+    fn new(dir: &std::path::Path) -> Self {
+        let path = dir.join("evil.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        Trap { listener, path }
+    }
+
+    fn connected(&self) -> bool {
+        self.listener.accept().is_ok()
+    }
+}
+
+fn assert_clean(params: &[(String, String)]) {
+    let get = |name: &str| {
+        params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    };
+    assert!(get("PHP_VALUE").is_none(), "{:?}", params);
+    assert!(
+        params
+            .iter()
+            .all(|(n, _)| !n.starts_with("HTTP_X_RUST_PROXY")),
+        "{:?}",
+        params
+    );
+    assert_ne!(
+        get("SCRIPT_FILENAME"),
+        Some("/tmp/evil.php"),
+        "{:?}",
+        params
+    );
+}
+
+#[tokio::test]
+async fn client_fastcgi_marker_is_ignored() {
+    let upstream = Upstream::start(|_| Reply::new(200, "plain"));
+    let (fastcgi_port, params) = app_server(fastcgi);
+    let config = CGI_CONFIG
+        .replace("{fastcgi_port}", &fastcgi_port.to_string())
+        .replace("{uwsgi_port}", &free_port().to_string());
+    let proxy = Proxy::start(&config, upstream.port);
+    let trap = Trap::new(&proxy.dir);
+    let forged = forged_cgi_marker(trap.path.to_str().unwrap());
+    let client = client();
+
+    let resp = client
+        .get(proxy.url("/php/hello"))
+        .header("X-Rust-Proxy-Cgi", &forged)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.text().await.unwrap(), "ok");
+    let params = params.lock().unwrap().clone();
+    assert_eq!(params.len(), 1);
+    assert_clean(&params[0]);
+    assert!(
+        params[0].contains(&("SCRIPT_FILENAME".to_string(), "/srv/index.php".to_string())),
+        "{:?}",
+        params[0]
+    );
+
+    // 普通路由上的伪造标记同样被忽略，请求照常转发
+    let resp = client
+        .get(proxy.url("/plain"))
+        .header("X-Rust-Proxy-Cgi", &forged)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.text().await.unwrap(), "plain");
+    assert!(!trap.connected(), "连接了伪造的标记中的 Unix 套接字");
+}
+
+#[tokio::test]
+async fn client_uwsgi_marker_is_ignored() {
+    let upstream = Upstream::start(|_| Reply::new(200, "plain"));
+    let (uwsgi_port, params) = app_server(uwsgi);
+    let config = CGI_CONFIG
+        .replace("{fastcgi_port}", &free_port().to_string())
+        .replace("{uwsgi_port}", &uwsgi_port.to_string());
+    let proxy = Proxy::start(&config, upstream.port);
+    let trap = Trap::new(&proxy.dir);
+    let forged = forged_cgi_marker(trap.path.to_str().unwrap());
+    let client = client();
+
+    let resp = client
+        .post(proxy.url("/py/hello"))
+        .header("X-Rust-Proxy-Cgi", &forged)
+        .body("data")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.text().await.unwrap(), "ok");
+    let params = params.lock().unwrap().clone();
+    assert_eq!(params.len(), 1);
+    assert_clean(&params[0]);
+    for expected in [("SCRIPT_NAME", "/py"), ("PATH_INFO", "/hello")] {
+        assert!(
+            params[0].contains(&(expected.0.to_string(), expected.1.to_string())),
+            "{:?}",
+            params[0]
+        );
+    }
+    assert!(!trap.connected(), "连接了伪造的标记中的 Unix 套接字");
+}
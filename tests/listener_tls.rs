@@ -0,0 +1,149 @@
+// HTTPS 监听：按 ALPN 协商 HTTP/2 或 HTTP/1.1，两种协议下上游看到的协议都是 https
+
+mod common;
+
+use common::{Proxy, Reply, Upstream};
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNum;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::x509::extension::SubjectAlternativeName;
+use openssl::x509::{X509, X509NameBuilder};
+use tokio::net::TcpStream;
+
+const CONFIG: &str = r#"
+version = 2
+
+[server]
+host = "127.0.0.1"
+port = {port}
+
+[target]
+protocol = "http"
+host = "127.0.0.1"
+port = {upstream_port}
+
+[request]
+timeout = 5
+accept_invalid_certs = false
+
+[log]
+level = "warn"
+
+[[listeners]]
+name = "https"
+port = {port}
+tls = { cert = "{tls_dir}/cert.pem", key = "{tls_dir}/key.pem" }
+
+[[routes]]
+name = "default"
+path_prefix = "/"
+"#;
+
+// 为 localhost 生成自签名证书，写入 dir
+fn write_certificate(dir: &std::path::Path) {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut subject = X509NameBuilder::new().unwrap();
+    subject.append_entry_by_text("CN", "localhost").unwrap();
+    let subject = subject.build();
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    let serial = BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap();
+    builder.set_serial_number(&serial).unwrap();
+    builder.set_subject_name(&subject).unwrap();
+    builder.set_issuer_name(&subject).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(30).unwrap())
+        .unwrap();
+    let alt_name = SubjectAlternativeName::new()
+        .dns("localhost")
+        .build(&builder.x509v3_context(None, None))
+        .unwrap();
+    builder.append_extension(alt_name).unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+    let cert = builder.build();
+    std::fs::write(dir.join("cert.pem"), cert.to_pem().unwrap()).unwrap();
+    std::fs::write(dir.join("key.pem"), key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+}
+
+// 以给定的 ALPN 列表建立 TLS 连接，返回连接与协商的协议
+async fn connect(
+    port: u16,
+    alpns: &[&str],
+) -> (tokio_native_tls::TlsStream<TcpStream>, Option<Vec<u8>>) {
+    let connector = native_tls::TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .request_alpns(alpns)
+        .build()
+        .unwrap();
+    let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let stream = tokio_native_tls::TlsConnector::from(connector)
+        .connect("localhost", stream)
+        .await
+        .unwrap();
+    let alpn = stream.get_ref().negotiated_alpn().unwrap();
+    (stream, alpn)
+}
+
+#[tokio::test]
+async fn https_listener_negotiates_h2_with_alpn() {
+    let tls_dir = std::env::temp_dir().join(format!("rust_proxy_tls_{}", std::process::id()));
+    std::fs::create_dir_all(&tls_dir).unwrap();
+    write_certificate(&tls_dir);
+    let upstream = Upstream::start(|_| Reply::new(200, "ok"));
+    let config = CONFIG.replace("{tls_dir}", tls_dir.to_str().unwrap());
+    let proxy = Proxy::start(&config, upstream.port);
+
+    // 客户端支持 h2 时使用 HTTP/2
+    let (stream, alpn) = connect(proxy.port, &["h2", "http/1.1"]).await;
+    assert_eq!(alpn.as_deref(), Some(b"h2".as_slice()));
+    let (mut sender, connection) = hyper::client::conn::Builder::new()
+        .http2_only(true)
+        .handshake(stream)
+        .await
+        .unwrap();
+    tokio::spawn(connection);
+    let request = hyper::Request::get(format!("https://localhost:{}/h2", proxy.port))
+        .body(hyper::Body::empty())
+        .unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.version(), hyper::Version::HTTP_2);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(body.as_ref(), b"ok");
+
+    // 只支持 HTTP/1.1 或不发送 ALPN 的客户端使用 HTTP/1.1
+    for alpns in [&["http/1.1"][..], &[]] {
+        let (stream, alpn) = connect(proxy.port, alpns).await;
+        assert_ne!(alpn.as_deref(), Some(b"h2".as_slice()));
+        let (mut sender, connection) = hyper::client::conn::Builder::new()
+            .handshake(stream)
+            .await
+            .unwrap();
+        tokio::spawn(connection);
+        let request = hyper::Request::get("/h1")
+            .header("Host", format!("localhost:{}", proxy.port))
+            .body(hyper::Body::empty())
+            .unwrap();
+        let response = sender.send_request(request).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.version(), hyper::Version::HTTP_11);
+    }
+
+    let received = upstream.received();
+    assert_eq!(received.len(), 3);
+    for request in &received {
+        assert_eq!(request.header("x-forwarded-proto"), Some("https"));
+        assert_eq!(
+            request.header("x-forwarded-host"),
+            Some(format!("localhost:{}", proxy.port).as_str())
+        );
+    }
+
+    let _ = std::fs::remove_dir_all(&tls_dir);
+}
@@ -0,0 +1,236 @@
+// 入站 PROXY 协议头的解析：v1 与 v2 的各种地址族与命令，以及格式错误、截断与超长的协议头
+
+mod common;
+
+use common::{PROXY_V2_SIGNATURE, Proxy, Reply, Upstream};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+const CONFIG: &str = r#"
+version = 2
+
+[server]
+host = "127.0.0.1"
+port = {port}
+proxy_protocol = { trusted = [{trusted}], timeout_ms = 500 }
+
+[target]
+protocol = "http"
+host = "127.0.0.1"
+port = {upstream_port}
+
+[request]
+timeout = 5
+accept_invalid_certs = false
+
+[log]
+level = "warn"
+
+[[routes]]
+name = "default"
+path_prefix = "/"
+"#;
+
+const REQUEST: &[u8] = b"GET /whoami HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n";
+
+// 发送协议头与一个请求，返回响应；连接被关闭时为空
+fn send(port: u16, header: &[u8]) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    let mut data = header.to_vec();
+    data.extend_from_slice(REQUEST);
+    let _ = stream.write_all(&data);
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response);
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+// v2 协议头：签名、版本与命令、地址族与传输协议、长度与地址块
+fn v2(version_command: u8, family: u8, body: &[u8]) -> Vec<u8> {
+    let mut header = PROXY_V2_SIGNATURE.to_vec();
+    header.push(version_command);
+    header.push(family);
+    header.extend((body.len() as u16).to_be_bytes());
+    header.extend_from_slice(body);
+    header
+}
+
+fn ipv4_block() -> Vec<u8> {
+    let mut body = vec![203, 0, 113, 7, 10, 0, 0, 1];
+    body.extend(56324u16.to_be_bytes());
+    body.extend(80u16.to_be_bytes());
+    body
+}
+
+fn ipv6_block() -> Vec<u8> {
+    let source: std::net::Ipv6Addr = "2001:db8::7".parse().unwrap();
+    let destination: std::net::Ipv6Addr = "2001:db8::1".parse().unwrap();
+    let mut body = source.octets().to_vec();
+    body.extend(destination.octets());
+    body.extend(56324u16.to_be_bytes());
+    body.extend(443u16.to_be_bytes());
+    body
+}
+
+// 请求被接受，上游看到的客户端地址为 client
+fn assert_client(proxy: &Proxy, upstream: &Upstream, header: &[u8], client: &str) {
+    let before = upstream.received().len();
+    let response = send(proxy.port, header);
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "{:?}: {}",
+        header,
+        response
+    );
+    let received = upstream.received();
+    assert_eq!(received.len(), before + 1);
+    assert_eq!(
+        received[before].header("x-forwarded-for"),
+        Some(client),
+        "{:?}",
+        header
+    );
+    assert_eq!(received[before].header("x-real-ip"), Some(client));
+}
+
+// 连接被关闭，请求没有到达上游
+fn assert_rejected(proxy: &Proxy, upstream: &Upstream, header: &[u8]) {
+    let before = upstream.received().len();
+    let response = send(proxy.port, header);
+    assert_eq!(response, "", "{:?}", String::from_utf8_lossy(header));
+    assert_eq!(upstream.received().len(), before);
+}
+
+#[tokio::test]
+async fn v1_headers() {
+    let upstream = Upstream::start(|_| Reply::new(200, "ok"));
+    let proxy = Proxy::start(&CONFIG.replace("{trusted}", ""), upstream.port);
+
+    assert_client(
+        &proxy,
+        &upstream,
+        b"PROXY TCP4 203.0.113.7 10.0.0.1 56324 80\r\n",
+        "203.0.113.7",
+    );
+    assert_client(
+        &proxy,
+        &upstream,
+        b"PROXY TCP6 2001:db8::7 2001:db8::1 56324 443\r\n",
+        "2001:db8::7",
+    );
+    // UNKNOWN 保留 TCP 的对端地址，之后的字段忽略
+    assert_client(&proxy, &upstream, b"PROXY UNKNOWN\r\n", "127.0.0.1");
+    assert_client(
+        &proxy,
+        &upstream,
+        b"PROXY UNKNOWN ffff::1 ffff::2 1 2\r\n",
+        "127.0.0.1",
+    );
+
+    // 地址族与地址不符、缺少字段、端口无效
+    assert_rejected(
+        &proxy,
+        &upstream,
+        b"PROXY TCP4 2001:db8::7 10.0.0.1 56324 80\r\n",
+    );
+    assert_rejected(&proxy, &upstream, b"PROXY TCP4 203.0.113.7 10.0.0.1\r\n");
+    assert_rejected(
+        &proxy,
+        &upstream,
+        b"PROXY TCP4 203.0.113.7 10.0.0.1 99999 80\r\n",
+    );
+    assert_rejected(
+        &proxy,
+        &upstream,
+        b"PROXY UDP4 203.0.113.7 10.0.0.1 1 2\r\n",
+    );
+    // 超过 107 字节仍没有 \r\n
+    let mut long = b"PROXY TCP4 ".to_vec();
+    long.extend(std::iter::repeat_n(b'1', 120));
+    long.extend(b"\r\n");
+    assert_rejected(&proxy, &upstream, &long);
+    // 没有协议头的请求
+    assert_rejected(&proxy, &upstream, b"");
+}
+
+#[tokio::test]
+async fn v2_headers() {
+    let upstream = Upstream::start(|_| Reply::new(200, "ok"));
+    let proxy = Proxy::start(&CONFIG.replace("{trusted}", ""), upstream.port);
+
+    // PROXY 命令：IPv4 与 IPv6
+    assert_client(
+        &proxy,
+        &upstream,
+        &v2(0x21, 0x11, &ipv4_block()),
+        "203.0.113.7",
+    );
+    assert_client(
+        &proxy,
+        &upstream,
+        &v2(0x21, 0x21, &ipv6_block()),
+        "2001:db8::7",
+    );
+    // 地址块之后的 TLV 忽略
+    let mut with_tlv = ipv4_block();
+    with_tlv.extend([0x05, 0, 4, b'a', b'b', b'c', b'd']);
+    assert_client(&proxy, &upstream, &v2(0x21, 0x11, &with_tlv), "203.0.113.7");
+    // LOCAL 命令忽略地址块，保留 TCP 的对端地址
+    assert_client(
+        &proxy,
+        &upstream,
+        &v2(0x20, 0x11, &ipv4_block()),
+        "127.0.0.1",
+    );
+    assert_client(&proxy, &upstream, &v2(0x20, 0x00, &[]), "127.0.0.1");
+    // Unix 套接字与 AF_UNSPEC 没有 IP 地址
+    let mut unix = b"/run/haproxy/client.sock".to_vec();
+    unix.resize(216, 0);
+    assert_client(&proxy, &upstream, &v2(0x21, 0x31, &unix), "127.0.0.1");
+    assert_client(&proxy, &upstream, &v2(0x21, 0x00, &[]), "127.0.0.1");
+
+    // 版本与命令无效
+    assert_rejected(&proxy, &upstream, &v2(0x11, 0x11, &ipv4_block()));
+    assert_rejected(&proxy, &upstream, &v2(0x22, 0x11, &ipv4_block()));
+    // 地址块短于地址族的要求
+    assert_rejected(&proxy, &upstream, &v2(0x21, 0x11, &ipv4_block()[..8]));
+    assert_rejected(&proxy, &upstream, &v2(0x21, 0x21, &ipv4_block()));
+    // 签名错误
+    let mut bad_signature = v2(0x21, 0x11, &ipv4_block());
+    bad_signature[11] = b'!';
+    assert_rejected(&proxy, &upstream, &bad_signature);
+    // 签名之后的固定部分不完整：连接关闭前只有 2 字节
+    let mut stream = TcpStream::connect(("127.0.0.1", proxy.port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    stream.write_all(PROXY_V2_SIGNATURE).unwrap();
+    stream.write_all(&[0x21, 0x11]).unwrap();
+    stream.shutdown(std::net::Shutdown::Write).unwrap();
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response);
+    assert!(response.is_empty());
+
+    // 声明的长度超过实际发送的数据：等到 timeout_ms 后关闭，请求数据不会被当作地址块
+    let mut oversized = v2(0x21, 0x11, &ipv4_block());
+    oversized[14..16].copy_from_slice(&u16::MAX.to_be_bytes());
+    let started = Instant::now();
+    assert_rejected(&proxy, &upstream, &oversized);
+    assert!(started.elapsed() >= Duration::from_millis(400));
+}
+
+#[tokio::test]
+async fn untrusted_peer_is_rejected_before_parsing() {
+    let upstream = Upstream::start(|_| Reply::new(200, "ok"));
+    let config = CONFIG.replace("{trusted}", r#""10.0.0.0/8""#);
+    let proxy = Proxy::start(&config, upstream.port);
+    assert_rejected(&proxy, &upstream, &v2(0x21, 0x11, &ipv4_block()));
+    assert_rejected(
+        &proxy,
+        &upstream,
+        b"PROXY TCP4 203.0.113.7 10.0.0.1 56324 80\r\n",
+    );
+}
@@ -0,0 +1,96 @@
+// 热加载：管理接口的令牌立即生效，启动时固定的配置（如 [server]）在重启前保持原值
+
+mod common;
+
+use common::{Proxy, Reply, Upstream, client};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+const CONFIG: &str = r#"
+version = 2
+
+[server]
+host = "127.0.0.1"
+port = {port}
+title_case_headers = {title_case}
+
+[target]
+protocol = "http"
+host = "127.0.0.1"
+port = {upstream_port}
+
+[request]
+timeout = 5
+accept_invalid_certs = false
+
+[log]
+level = "warn"
+
+[admin]
+enabled = true
+port = {admin_port}
+token = "{token}"
+
+[reload]
+watch = true
+poll_interval_ms = 100
+
+[[routes]]
+name = "default"
+path_prefix = "/"
+"#;
+
+fn config(token: &str, title_case: bool) -> String {
+    CONFIG
+        .replace("{token}", token)
+        .replace("{title_case}", &title_case.to_string())
+}
+
+// 以令牌访问管理接口，返回状态码
+async fn admin_status(proxy: &Proxy, token: &str) -> u16 {
+    client()
+        .get(proxy.admin_url("/status"))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+// 原样读取响应头，检查名称的大小写
+fn raw_response(port: u16) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[tokio::test]
+async fn token_reloads_and_fixed_sections_keep_startup_values() {
+    let upstream = Upstream::start(|_| Reply::new(200, "ok").header("x-upstream", "1"));
+    let proxy = Proxy::start(&config("old-token", false), upstream.port);
+    assert_eq!(admin_status(&proxy, "old-token").await, 200);
+    assert!(raw_response(proxy.port).contains("\r\nx-upstream: 1\r\n"));
+
+    // 修改令牌与 [server] 中按请求读取的 title_case_headers
+    let updated = config("new-token", true)
+        .replace("{port}", &proxy.port.to_string())
+        .replace("{admin_port}", &proxy.admin_port.to_string())
+        .replace("{upstream_port}", &upstream.port.to_string());
+    std::fs::write(proxy.dir.join("config.toml"), updated).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while admin_status(&proxy, "new-token").await != 200 {
+        assert!(Instant::now() < deadline, "新的令牌没有生效");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(admin_status(&proxy, "old-token").await, 401);
+
+    // [server] 需要重启才能生效，热加载后仍按原来的配置发送小写的响应头
+    let response = raw_response(proxy.port);
+    assert!(response.contains("\r\nx-upstream: 1\r\n"), "{}", response);
+}
@@ -0,0 +1,74 @@
+// 自行建立连接的请求（发送 PROXY 协议头）连接失败时按 retry_on_connect_error 重试
+
+mod common;
+
+use common::{Proxy, client};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const CONFIG: &str = r#"
+version = 2
+
+[server]
+host = "127.0.0.1"
+port = {port}
+
+[target]
+protocol = "http"
+host = "127.0.0.1"
+port = {upstream_port}
+
+[request]
+timeout = 5
+accept_invalid_certs = false
+
+[log]
+level = "warn"
+
+[[routes]]
+name = "default"
+path_prefix = "/"
+send_proxy_protocol = true
+retry = { max_attempts = 2, base_delay_ms = 10, jitter = false, retry_on_connect_error = {retry} }
+"#;
+
+// 第一个连接接受后立即关闭，之后的连接正常应答；返回端口与连接数
+fn flaky_upstream() -> (u16, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let count = connections.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if count.fetch_add(1, Ordering::SeqCst) == 0 {
+                continue;
+            }
+            // 跳过 PROXY 协议头，再读到请求头结束
+            let mut reader = BufReader::new(&stream);
+            let mut header = [0u8; 16];
+            reader.read_exact(&mut header).unwrap();
+            let mut addresses = vec![0; u16::from_be_bytes([header[14], header[15]]) as usize];
+            reader.read_exact(&mut addresses).unwrap();
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n")
+                && reader.read_until(b'\n', &mut head).unwrap_or(0) > 0
+            {}
+            let _ = (&stream)
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok");
+        }
+    });
+    (port, connections)
+}
+
+#[tokio::test]
+async fn proxy_protocol_connect_errors_are_retried() {
+    for (retry, status, connections) in [(true, 200, 2), (false, 502, 1)] {
+        let (port, count) = flaky_upstream();
+        let proxy = Proxy::start(&CONFIG.replace("{retry}", &retry.to_string()), port);
+        let resp = client().get(proxy.url("/hello")).send().await.unwrap();
+        assert_eq!(resp.status(), status, "retry_on_connect_error = {}", retry);
+        assert_eq!(count.load(Ordering::SeqCst), connections);
+    }
+}
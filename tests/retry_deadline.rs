@@ -0,0 +1,129 @@
+// 重试受请求截止时间约束：单次超时不超过剩余时间，剩余时间不足时不再重试，放弃的重试不占用重试预算
+
+mod common;
+
+use common::{Proxy, Reply, Upstream, client};
+use std::time::{Duration, Instant};
+
+const CONFIG: &str = r#"
+version = 2
+
+[server]
+host = "127.0.0.1"
+port = {port}
+
+[target]
+protocol = "http"
+host = "127.0.0.1"
+port = {upstream_port}
+
+[request]
+timeout = 5
+accept_invalid_certs = false
+
+[log]
+level = "warn"
+
+[deadline]
+propagate = true
+
+[[routes]]
+name = "slow"
+path_prefix = "/"
+timeouts = { total_ms = 1500 }
+retry = { max_attempts = 5, per_try_timeout_ms = 1000, base_delay_ms = 50, jitter = false }
+"#;
+
+#[tokio::test]
+async fn retries_stop_at_request_deadline() {
+    let upstream = Upstream::start(|_| Reply::new(200, "slow").delay(Duration::from_secs(3)));
+    let proxy = Proxy::start(CONFIG, upstream.port);
+
+    let started = Instant::now();
+    let resp = client()
+        .get(proxy.url("/slow"))
+        .header("Content-Type", "application/grpc")
+        .send()
+        .await
+        .unwrap();
+    let elapsed = started.elapsed();
+    assert!(resp.status().is_server_error(), "{}", resp.status());
+    // 不受截止时间约束时 5 次尝试需要 5 秒以上
+    assert!(elapsed < Duration::from_millis(2500), "耗时 {:?}", elapsed);
+
+    // 第一次尝试用满单次超时，第二次只剩不到 500 毫秒，之后不再重试
+    let received = upstream.received();
+    assert_eq!(received.len(), 2, "{:?}", received);
+    let timeouts: Vec<u64> = received
+        .iter()
+        .map(|r| {
+            let value = r.header("grpc-timeout").expect("缺少 grpc-timeout");
+            value.trim_end_matches('m').parse().unwrap()
+        })
+        .collect();
+    assert!(timeouts[0] > 1000, "{:?}", timeouts);
+    assert!(timeouts[1] < 500, "{:?}", timeouts);
+}
+
+const BUDGET_CONFIG: &str = r#"
+version = 2
+
+[server]
+host = "127.0.0.1"
+port = {port}
+
+[target]
+protocol = "http"
+host = "127.0.0.1"
+port = {upstream_port}
+
+[request]
+timeout = 5
+accept_invalid_certs = false
+
+[log]
+level = "warn"
+
+[retry_budget]
+enabled = true
+ratio = 0.0
+window_secs = 10
+min_retries_per_sec = 1
+
+[[routes]]
+name = "short"
+path_prefix = "/short"
+timeouts = { total_ms = 300 }
+retry = { max_attempts = 3, base_delay_ms = 1000, jitter = false }
+
+[[routes]]
+name = "flaky"
+path_prefix = "/flaky"
+retry = { max_attempts = 2, base_delay_ms = 10, jitter = false }
+"#;
+
+#[tokio::test]
+async fn abandoned_retries_do_not_consume_budget() {
+    let flaky = std::sync::atomic::AtomicUsize::new(0);
+    let upstream = Upstream::start(move |req| match req.path.as_str() {
+        "/flaky" if flaky.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 => {
+            Reply::new(503, "down")
+        }
+        "/flaky" => Reply::new(200, "ok"),
+        _ => Reply::new(503, "down"),
+    });
+    let proxy = Proxy::start(BUDGET_CONFIG, upstream.port);
+    let client = client();
+
+    // 退避间隔超过剩余时间，这些失败都不重试；预算为窗口内 10 次，不应被它们占用
+    for _ in 0..12 {
+        let resp = client.get(proxy.url("/short")).send().await.unwrap();
+        assert_eq!(resp.status(), 503);
+    }
+    assert_eq!(upstream.received().len(), 12);
+
+    // 预算仍有余量，真正的重试照常发送
+    let resp = client.get(proxy.url("/flaky")).send().await.unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.text().await.unwrap(), "ok");
+}
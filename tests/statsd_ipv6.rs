@@ -0,0 +1,66 @@
+// StatsD 指标经 UDP 推送到 IPv6 地址
+
+mod common;
+
+use common::{Proxy, Reply, Upstream, client};
+use std::time::Duration;
+
+const CONFIG: &str = r#"
+version = 2
+
+[server]
+host = "127.0.0.1"
+port = {port}
+
+[target]
+protocol = "http"
+host = "127.0.0.1"
+port = {upstream_port}
+
+[request]
+timeout = 5
+accept_invalid_certs = false
+
+[log]
+level = "warn"
+
+[metrics.statsd]
+enabled = true
+address = "{statsd}"
+flush_interval_ms = 100
+
+[[routes]]
+name = "default"
+path_prefix = "/"
+"#;
+
+#[tokio::test]
+async fn statsd_reaches_ipv6_listener() {
+    let Ok(listener) = tokio::net::UdpSocket::bind("[::1]:0").await else {
+        eprintln!("本机不支持 IPv6，跳过");
+        return;
+    };
+    let address = listener.local_addr().unwrap().to_string();
+    let upstream = Upstream::start(|_| Reply::new(200, "ok"));
+    let proxy = Proxy::start(&CONFIG.replace("{statsd}", &address), upstream.port);
+    let resp = client().get(proxy.url("/hello")).send().await.unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let mut buf = vec![0; 65536];
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+    loop {
+        let len = tokio::time::timeout_at(deadline, listener.recv(&mut buf))
+            .await
+            .expect("没有收到请求计数")
+            .unwrap();
+        let packet = String::from_utf8_lossy(&buf[..len]);
+        if let Some(line) = packet
+            .lines()
+            .find(|line| line.starts_with("proxy_requests_total:"))
+        {
+            assert!(line.contains("|c"), "{}", line);
+            assert!(line.contains("route:default"), "{}", line);
+            break;
+        }
+    }
+}
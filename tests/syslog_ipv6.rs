@@ -0,0 +1,58 @@
+// syslog 经 UDP 发送到 IPv6 地址
+
+mod common;
+
+use common::{Proxy, free_port};
+use std::time::Duration;
+
+const CONFIG: &str = r#"
+version = 2
+
+[server]
+host = "127.0.0.1"
+port = {port}
+
+[target]
+protocol = "http"
+host = "127.0.0.1"
+port = {upstream_port}
+
+[request]
+timeout = 5
+accept_invalid_certs = false
+
+[log]
+level = "info"
+
+[[log.sinks]]
+kind = "syslog"
+address = "{syslog}"
+protocol = "udp"
+level = "info"
+
+[[routes]]
+name = "default"
+path_prefix = "/"
+"#;
+
+#[tokio::test]
+async fn syslog_udp_sink_reaches_ipv6_listener() {
+    let Ok(listener) = tokio::net::UdpSocket::bind("[::1]:0").await else {
+        eprintln!("本机不支持 IPv6，跳过");
+        return;
+    };
+    let address = listener.local_addr().unwrap().to_string();
+    // 启动时输出的配置信息转发到 syslog
+    let _proxy = Proxy::start(&CONFIG.replace("{syslog}", &address), free_port());
+
+    let mut buf = vec![0; 8192];
+    let len = tokio::time::timeout(Duration::from_secs(10), listener.recv(&mut buf))
+        .await
+        .expect("没有收到 syslog 消息")
+        .unwrap();
+    let message = String::from_utf8_lossy(&buf[..len]);
+    // RFC 5424：<PRI>1 时间戳 主机 应用名 ...
+    assert!(message.starts_with('<'), "{}", message);
+    assert!(message.contains(">1 "), "{}", message);
+    assert!(message.contains(" rust_proxy "), "{}", message);
+}
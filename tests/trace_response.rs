@@ -0,0 +1,79 @@
+// 启用追踪时响应带上本次请求的 traceparent，沿用客户端传入的追踪ID
+
+mod common;
+
+use common::{Proxy, Reply, Upstream, client, free_port};
+
+const CONFIG: &str = r#"
+version = 2
+
+[server]
+host = "127.0.0.1"
+port = {port}
+
+[target]
+protocol = "http"
+host = "127.0.0.1"
+port = {upstream_port}
+
+[request]
+timeout = 5
+accept_invalid_certs = false
+
+[log]
+level = "warn"
+
+[tracing]
+enabled = true
+endpoint = "http://127.0.0.1:{collector_port}/v1/traces"
+
+[[routes]]
+name = "down"
+path_prefix = "/down"
+target = { protocol = "http", host = "127.0.0.1", port = {down_port} }
+
+[[routes]]
+name = "default"
+path_prefix = "/"
+"#;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_ID: &str = "00f067aa0ba902b7";
+
+#[tokio::test]
+async fn response_traceparent_keeps_incoming_trace_id() {
+    let upstream = Upstream::start(|_| {
+        Reply::new(200, "ok").header(
+            "traceparent",
+            "00-ffffffffffffffffffffffffffffffff-ffffffffffffffff-01",
+        )
+    });
+    let config = CONFIG
+        .replace("{collector_port}", &free_port().to_string())
+        .replace("{down_port}", &free_port().to_string());
+    let proxy = Proxy::start(&config, upstream.port);
+    let client = client();
+
+    for (path, ok) in [("/ok", true), ("/down/x", false)] {
+        let resp = client
+            .get(proxy.url(path))
+            .header("traceparent", format!("00-{}-{}-01", TRACE_ID, PARENT_ID))
+            .header("tracestate", "vendor=abc")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status().is_success(), ok, "{}", path);
+        let traceparent = resp.headers()["traceparent"].to_str().unwrap();
+        let parts: Vec<_> = traceparent.split('-').collect();
+        assert_eq!(parts.len(), 4, "{}", traceparent);
+        assert_eq!(parts[1], TRACE_ID, "{}", path);
+        assert_ne!(parts[2], PARENT_ID, "{}", path);
+        assert_eq!(resp.headers()["tracestate"], "vendor=abc", "{}", path);
+    }
+
+    // 没有传入 traceparent 时响应带上新生成的追踪ID
+    let resp = client.get(proxy.url("/ok")).send().await.unwrap();
+    let traceparent = resp.headers()["traceparent"].to_str().unwrap();
+    assert_eq!(traceparent.len(), 55, "{}", traceparent);
+    assert!(!resp.headers().contains_key("tracestate"));
+}